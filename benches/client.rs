@@ -0,0 +1,83 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use harmony_rust_sdk::api::chat::{
+    self,
+    content::{Content, TextContent},
+    format::Format,
+    FormattedText, Message as RawMessage,
+};
+use ilo_toki::bench;
+
+const GUILD: u64 = 1;
+const CHANNEL: u64 = 2;
+
+/// Creates a page of text messages from a handful of authors.
+fn messages(count: u64) -> Vec<(u64, RawMessage)> {
+    (0..count)
+        .map(|i| {
+            let text = format!("message {} with enough text in it to wrap over a few lines in a narrow terminal window", i);
+            let message = RawMessage {
+                author_id: i % 7,
+                created_at: 1_600_000_000 + i,
+                content: Some(chat::Content::new(Some(Content::new_text_message(TextContent::new(Some(FormattedText::new(text, vec![]))))))),
+                ..Default::default()
+            };
+            (i + 1, message)
+        })
+        .collect()
+}
+
+/// Creates text with bold, italic, monospace, mention, and math formatting, repeated a number of times.
+fn formatted_text(repeats: usize) -> FormattedText {
+    const UNIT: &str = "some bold and italic text for @someone with code and $x^2$ math ";
+    let spans = [
+        ("bold", Format::Bold(chat::format::Bold {})),
+        ("italic", Format::Italic(chat::format::Italic {})),
+        ("code", Format::Monospace(chat::format::Monospace {})),
+        ("@someone", Format::UserMention(chat::format::UserMention { user_id: 3 })),
+    ];
+
+    let mut formats = vec![];
+    for i in 0..repeats {
+        for (word, format) in spans.iter() {
+            formats.push(chat::Format {
+                start: (i * UNIT.len() + UNIT.find(word).unwrap()) as _,
+                length: word.len() as _,
+                format: Some(format.clone()),
+            });
+        }
+    }
+
+    FormattedText::new(UNIT.repeat(repeats), formats)
+}
+
+fn formatting(c: &mut Criterion) {
+    let state = bench::channel_state(GUILD, CHANNEL);
+    let text = formatted_text(40);
+    c.bench_function("format and wrap", |b| {
+        b.iter_batched(
+            || text.clone(),
+            |text| black_box(bench::format(&state, text, 60)),
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+fn ingestion(c: &mut Criterion) {
+    let page = messages(1000);
+    c.bench_function("ingest 1000 messages", |b| {
+        b.iter_batched(
+            || (bench::channel_state(GUILD, CHANNEL), page.clone()),
+            |(mut state, page)| bench::ingest(&mut state, GUILD, CHANNEL, page),
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+fn rendering(c: &mut Criterion) {
+    let mut state = bench::channel_state(GUILD, CHANNEL);
+    bench::ingest(&mut state, GUILD, CHANNEL, messages(10_000));
+    c.bench_function("render frame of 10000 message channel", |b| b.iter(|| bench::render(&state, 160, 50)));
+}
+
+criterion_group!(benches, formatting, ingestion, rendering);
+criterion_main!(benches);
@@ -0,0 +1,733 @@
+use std::{future::Future, pin::Pin};
+
+use harmony_rust_sdk::{
+    api::{
+        about::AboutRequest,
+        auth::{auth_step::Step, AuthStep, BeginAuthRequest, KeyRequest, NextStepRequest},
+        chat::{
+            self, get_channel_messages_request::Direction, permission::Mode, AddGuildRoleRequest, DeleteGuildRoleRequest,
+            DeleteMessageRequest, FormattedText, GetGuildChannelsRequest, GetGuildListRequest, GetGuildMembersRequest, GetGuildRequest,
+            GetGuildRolesRequest, GetPermissionsRequest, JoinGuildRequest, LeaveGuildRequest, Message as RawMessage,
+            ModifyGuildRoleRequest, MoveRoleRequest, Permission, PreviewGuildRequest, QueryHasPermissionRequest, SendMessageRequest,
+            SetPermissionsRequest, TypingRequest, UpdateMessageTextRequest,
+        },
+        harmonytypes::{item_position::Position, ItemPosition},
+        profile::{GetProfileRequest, Profile},
+    },
+    client::{
+        api::{
+            auth::AuthStepResponse,
+            chat::channel::GetChannelMessages,
+            profile::{UpdateProfile, UserStatus},
+        },
+        Client,
+    },
+};
+
+use crate::capabilities::{is_unsupported, Capabilities, Feature};
+
+/// The error returned by a backend.
+pub type BackendError = Box<dyn std::error::Error + Send + Sync>;
+
+/// A future resolving to the result of a backend call.
+pub type BackendFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, BackendError>> + Send + 'a>>;
+
+/// What a server says about itself.
+pub struct ServerInfo {
+    /// The name of the server.
+    pub name: String,
+
+    /// The version of the server software.
+    pub version: String,
+
+    /// The server's description of itself.
+    pub about: String,
+
+    /// The server's message of the day.
+    pub motd: String,
+
+    /// The ways the server lets users log in or register, if it lists them.
+    pub auth_methods: Option<Vec<String>>,
+
+    /// Whether the server talks to other servers.
+    pub federates: bool,
+}
+
+/// What an invite says about the guild it's for.
+pub struct InvitePreview {
+    /// The name of the guild.
+    pub name: String,
+
+    /// The number of members in the guild, if the server says.
+    pub member_count: Option<u64>,
+
+    /// The guild's description, if the server has them.
+    pub description: Option<String>,
+}
+
+/// A message found by searching on the server.
+#[derive(Clone, Debug)]
+pub struct SearchHit {
+    /// The guild the message is in.
+    pub guild_id: u64,
+
+    /// The channel the message is in.
+    pub channel_id: u64,
+
+    /// The id of the message.
+    pub message_id: u64,
+
+    /// The name of the message's author.
+    pub author: String,
+
+    /// The start of the message's contents.
+    pub snippet: String,
+
+    /// The unix timestamp the message was sent at.
+    pub timestamp: u64,
+}
+
+/// Someone having read a message, as the server reports it.
+#[derive(Clone, Debug)]
+pub struct ReadReceipt {
+    /// The user who read the message.
+    pub user_id: u64,
+
+    /// The unix timestamp they read it at.
+    pub timestamp: u64,
+}
+
+/// An account setting changed through a server-driven flow of steps, like logging in.
+#[derive(Clone, Copy, Debug)]
+pub enum AccountSetting {
+    /// Changing the password.
+    Password,
+
+    /// Changing the email address.
+    Email,
+
+    /// Enrolling in TOTP two-factor authentication.
+    EnableTotp,
+
+    /// Removing TOTP two-factor authentication.
+    DisableTotp,
+}
+
+/// Everything the client asks of a chat server.
+/// The UI and state only talk to the server through this, so other backends or mocks can be swapped in.
+pub trait ChatBackend: Send + Sync {
+    /// Sends a message to a channel.
+    fn send_message(&self, guild_id: u64, channel_id: u64, content: chat::Content) -> BackendFuture<()>;
+
+    /// Sends a message shown with another name or avatar, where the user is allowed to.
+    fn send_message_as(&self, guild_id: u64, channel_id: u64, content: chat::Content, overrides: chat::Overrides) -> BackendFuture<()>;
+
+    /// Replaces the text of a message.
+    fn edit_message(&self, guild_id: u64, channel_id: u64, message_id: u64, text: String) -> BackendFuture<()>;
+
+    /// Deletes a message.
+    fn delete_message(&self, guild_id: u64, channel_id: u64, message_id: u64) -> BackendFuture<()>;
+
+    /// Gets up to `count` messages before the given message, or the latest messages if none is given.
+    /// Returns the id and contents of each message, newest first.
+    fn get_messages(&self, guild_id: u64, channel_id: u64, before: Option<u64>, count: u32) -> BackendFuture<Vec<(u64, RawMessage)>>;
+
+    /// Gets a user's profile.
+    fn get_profile(&self, user_id: u64) -> BackendFuture<Option<Profile>>;
+
+    /// Changes the user's status.
+    fn set_status(&self, status: UserStatus) -> BackendFuture<()>;
+
+    /// Sets the user's status text, or clears it.
+    /// Returns false if the server has nowhere to keep it.
+    fn set_status_text(&self, text: Option<String>) -> BackendFuture<bool>;
+
+    /// Tells the channel the user is typing.
+    fn typing(&self, guild_id: u64, channel_id: u64) -> BackendFuture<()>;
+
+    /// Gets the ids of the guilds the user is in.
+    fn guild_list(&self) -> BackendFuture<Vec<u64>>;
+
+    /// Gets a guild's info.
+    fn get_guild(&self, guild_id: u64) -> BackendFuture<Option<chat::Guild>>;
+
+    /// Gets the id and info of each channel in a guild.
+    fn get_channels(&self, guild_id: u64) -> BackendFuture<Vec<(u64, chat::Channel)>>;
+
+    /// Gets the ids of the members of a guild.
+    fn get_members(&self, guild_id: u64) -> BackendFuture<Vec<u64>>;
+
+    /// Joins a guild from an invite, returning the guild's id.
+    fn join_guild(&self, invite: String) -> BackendFuture<u64>;
+
+    /// Gets what an invite says about its guild without joining it.
+    fn preview_invite(&self, invite: String) -> BackendFuture<InvitePreview>;
+
+    /// Leaves a guild.
+    fn leave_guild(&self, guild_id: u64) -> BackendFuture<()>;
+
+    /// Presses a button on a message.
+    fn trigger_action(&self, guild_id: u64, channel_id: u64, message_id: u64, data: Vec<u8>) -> BackendFuture<()>;
+
+    /// Checks whether the user has a permission node, in a channel or the whole guild.
+    fn has_permission(&self, guild_id: u64, channel_id: Option<u64>, node: String) -> BackendFuture<bool>;
+
+    /// Gets the id and info of each role in a guild, highest first.
+    fn get_roles(&self, guild_id: u64) -> BackendFuture<Vec<(u64, chat::Role)>>;
+
+    /// Creates a role, returning its id.
+    fn add_role(&self, guild_id: u64, name: String, color: i32) -> BackendFuture<u64>;
+
+    /// Changes the name or colour of a role.
+    fn modify_role(&self, guild_id: u64, role_id: u64, name: Option<String>, color: Option<i32>) -> BackendFuture<()>;
+
+    /// Moves a role next to another one, after it if `after` is set and before it otherwise.
+    fn move_role(&self, guild_id: u64, role_id: u64, next_to: u64, after: bool) -> BackendFuture<()>;
+
+    /// Deletes a role.
+    fn delete_role(&self, guild_id: u64, role_id: u64) -> BackendFuture<()>;
+
+    /// Gets the permission nodes set for a role, in a channel or the whole guild, as (node, allowed).
+    fn get_permissions(&self, guild_id: u64, channel_id: Option<u64>, role_id: u64) -> BackendFuture<Vec<(String, bool)>>;
+
+    /// Replaces the permission nodes set for a role, in a channel or the whole guild.
+    fn set_permissions(&self, guild_id: u64, channel_id: Option<u64>, role_id: u64, permissions: Vec<(String, bool)>) -> BackendFuture<()>;
+
+    /// Works out which features the server supports and its version.
+    fn capabilities(&self) -> BackendFuture<Capabilities>;
+
+    /// Gets the server's version and what it advertises about itself.
+    fn server_info(&self) -> BackendFuture<ServerInfo>;
+
+    /// Reports a message to the server's moderators.
+    /// Returns false if the server has no way to take reports.
+    fn report_message(&self, guild_id: u64, channel_id: u64, message_id: u64, reason: String) -> BackendFuture<bool>;
+
+    /// Searches the messages of every guild the user is in, newest first.
+    /// Returns `None` if the server has no way to search.
+    fn search_messages(&self, query: String) -> BackendFuture<Option<Vec<SearchHit>>>;
+
+    /// Gets who has read a message, oldest first.
+    /// Returns `None` if the server doesn't keep read receipts.
+    fn read_receipts(&self, guild_id: u64, channel_id: u64, message_id: u64) -> BackendFuture<Option<Vec<ReadReceipt>>>;
+
+    /// Tells the server the user has read a channel up to a message.
+    fn send_read_receipt(&self, guild_id: u64, channel_id: u64, message_id: u64) -> BackendFuture<()>;
+
+    /// Asks the server for an export of everything it keeps about the user.
+    /// Returns where to get it, such as a link or that it's being emailed, or `None` if the server can't export data.
+    fn export_account(&self) -> BackendFuture<Option<String>>;
+
+    /// Deletes the user's account and everything the server keeps about it.
+    /// Returns false if the server has no way to delete accounts.
+    fn delete_account(&self) -> BackendFuture<bool>;
+
+    /// Starts changing an account setting, returning the first step to show.
+    /// Returns `None` if the server can't change the setting.
+    fn begin_account_flow(&self, setting: AccountSetting) -> BackendFuture<Option<AuthStep>>;
+
+    /// Answers the current step of changing an account setting, returning the next one.
+    /// Answering with `AuthStepResponse::Initial` goes back a step. Returns `None` once the change is done.
+    fn next_account_step(&self, response: AuthStepResponse) -> BackendFuture<Option<AuthStep>>;
+}
+
+/// The Harmony backend.
+impl ChatBackend for Client {
+    fn send_message(&self, guild_id: u64, channel_id: u64, content: chat::Content) -> BackendFuture<()> {
+        Box::pin(async move {
+            self.call(SendMessageRequest::new(guild_id, channel_id, Some(content), None, None, None, None)).await?;
+            Ok(())
+        })
+    }
+
+    fn send_message_as(&self, guild_id: u64, channel_id: u64, content: chat::Content, overrides: chat::Overrides) -> BackendFuture<()> {
+        Box::pin(async move {
+            self.call(SendMessageRequest {
+                guild_id,
+                channel_id,
+                content: Some(content),
+                overrides: Some(overrides),
+                ..Default::default()
+            })
+            .await?;
+            Ok(())
+        })
+    }
+
+    fn edit_message(&self, guild_id: u64, channel_id: u64, message_id: u64, text: String) -> BackendFuture<()> {
+        Box::pin(async move {
+            self.call(UpdateMessageTextRequest::new(guild_id, channel_id, message_id, Some(FormattedText::new(text, vec![]))))
+                .await?;
+            Ok(())
+        })
+    }
+
+    fn delete_message(&self, guild_id: u64, channel_id: u64, message_id: u64) -> BackendFuture<()> {
+        Box::pin(async move {
+            self.call(DeleteMessageRequest::new(guild_id, channel_id, message_id)).await?;
+            Ok(())
+        })
+    }
+
+    fn get_messages(&self, guild_id: u64, channel_id: u64, before: Option<u64>, count: u32) -> BackendFuture<Vec<(u64, RawMessage)>> {
+        Box::pin(async move {
+            let mut request = GetChannelMessages::new(guild_id, channel_id)
+                .with_direction(Some(Direction::BeforeUnspecified))
+                .with_count(count);
+            if let Some(message_id) = before {
+                request = request.with_message_id(message_id);
+            }
+
+            let response = self.call(request).await?;
+            Ok(response
+                .messages
+                .into_iter()
+                .filter_map(|v| v.message.map(|message| (v.message_id, message)))
+                .collect())
+        })
+    }
+
+    fn get_profile(&self, user_id: u64) -> BackendFuture<Option<Profile>> {
+        Box::pin(async move { Ok(self.call(GetProfileRequest::new(user_id)).await?.profile) })
+    }
+
+    fn set_status(&self, status: UserStatus) -> BackendFuture<()> {
+        Box::pin(async move {
+            self.call(UpdateProfile::default().with_new_status(status)).await?;
+            Ok(())
+        })
+    }
+
+    // Harmony profiles only have a status, not any text to go with it
+    fn set_status_text(&self, _text: Option<String>) -> BackendFuture<bool> {
+        Box::pin(async move { Ok(false) })
+    }
+
+    fn typing(&self, guild_id: u64, channel_id: u64) -> BackendFuture<()> {
+        Box::pin(async move {
+            self.call(TypingRequest::new(guild_id, channel_id)).await?;
+            Ok(())
+        })
+    }
+
+    fn guild_list(&self) -> BackendFuture<Vec<u64>> {
+        Box::pin(async move {
+            let response = self.call(GetGuildListRequest::default()).await?;
+            Ok(response.guilds.into_iter().map(|v| v.guild_id).collect())
+        })
+    }
+
+    fn get_guild(&self, guild_id: u64) -> BackendFuture<Option<chat::Guild>> {
+        Box::pin(async move { Ok(self.call(GetGuildRequest::new(guild_id)).await?.guild) })
+    }
+
+    fn get_channels(&self, guild_id: u64) -> BackendFuture<Vec<(u64, chat::Channel)>> {
+        Box::pin(async move {
+            let response = self.call(GetGuildChannelsRequest::new(guild_id)).await?;
+            Ok(response
+                .channels
+                .into_iter()
+                .filter_map(|v| v.channel.map(|channel| (v.channel_id, channel)))
+                .collect())
+        })
+    }
+
+    fn get_members(&self, guild_id: u64) -> BackendFuture<Vec<u64>> {
+        Box::pin(async move { Ok(self.call(GetGuildMembersRequest::new(guild_id)).await?.members) })
+    }
+
+    fn join_guild(&self, invite: String) -> BackendFuture<u64> {
+        Box::pin(async move { Ok(self.call(JoinGuildRequest::new(invite)).await?.guild_id) })
+    }
+
+    // Harmony guilds don't have descriptions
+    fn preview_invite(&self, invite: String) -> BackendFuture<InvitePreview> {
+        Box::pin(async move {
+            let preview = self.call(PreviewGuildRequest::new(invite)).await?;
+            Ok(InvitePreview {
+                name: preview.name,
+                member_count: Some(preview.member_count),
+                description: None,
+            })
+        })
+    }
+
+    fn leave_guild(&self, guild_id: u64) -> BackendFuture<()> {
+        Box::pin(async move {
+            self.call(LeaveGuildRequest::new(guild_id)).await?;
+            Ok(())
+        })
+    }
+
+    fn trigger_action(&self, guild_id: u64, channel_id: u64, message_id: u64, data: Vec<u8>) -> BackendFuture<()> {
+        Box::pin(async move {
+            let payload = chat::ActionPayload {
+                payload: Some(chat::action_payload::Payload::Button(chat::action_payload::Button { data })),
+            };
+            self.call(chat::TriggerActionRequest {
+                guild_id,
+                channel_id,
+                message_id,
+                payload: Some(payload),
+            })
+            .await?;
+            Ok(())
+        })
+    }
+
+    fn has_permission(&self, guild_id: u64, channel_id: Option<u64>, node: String) -> BackendFuture<bool> {
+        Box::pin(async move {
+            let request = QueryHasPermissionRequest {
+                guild_id,
+                channel_id,
+                check_for: node,
+                ..Default::default()
+            };
+            Ok(self.call(request).await?.ok)
+        })
+    }
+
+    fn get_roles(&self, guild_id: u64) -> BackendFuture<Vec<(u64, chat::Role)>> {
+        Box::pin(async move {
+            let response = self.call(GetGuildRolesRequest { guild_id }).await?;
+            Ok(response.roles.into_iter().filter_map(|v| v.role.map(|role| (v.role_id, role))).collect())
+        })
+    }
+
+    fn add_role(&self, guild_id: u64, name: String, color: i32) -> BackendFuture<u64> {
+        Box::pin(async move {
+            let request = AddGuildRoleRequest {
+                guild_id,
+                name,
+                color,
+                ..Default::default()
+            };
+            Ok(self.call(request).await?.role_id)
+        })
+    }
+
+    fn modify_role(&self, guild_id: u64, role_id: u64, name: Option<String>, color: Option<i32>) -> BackendFuture<()> {
+        Box::pin(async move {
+            let request = ModifyGuildRoleRequest {
+                guild_id,
+                role_id,
+                new_name: name,
+                new_color: color,
+                ..Default::default()
+            };
+            self.call(request).await?;
+            Ok(())
+        })
+    }
+
+    fn move_role(&self, guild_id: u64, role_id: u64, next_to: u64, after: bool) -> BackendFuture<()> {
+        Box::pin(async move {
+            let position = if after { Position::After } else { Position::BeforeUnspecified };
+            let request = MoveRoleRequest {
+                guild_id,
+                role_id,
+                new_position: Some(ItemPosition {
+                    item_id: next_to,
+                    position: position.into(),
+                }),
+            };
+            self.call(request).await?;
+            Ok(())
+        })
+    }
+
+    fn delete_role(&self, guild_id: u64, role_id: u64) -> BackendFuture<()> {
+        Box::pin(async move {
+            self.call(DeleteGuildRoleRequest { guild_id, role_id }).await?;
+            Ok(())
+        })
+    }
+
+    fn get_permissions(&self, guild_id: u64, channel_id: Option<u64>, role_id: u64) -> BackendFuture<Vec<(String, bool)>> {
+        Box::pin(async move {
+            let request = GetPermissionsRequest {
+                guild_id,
+                channel_id,
+                role_id,
+            };
+            let response = self.call(request).await?;
+            Ok(response.perms.into_iter().map(|v| (v.matches, v.mode != Mode::Deny as i32)).collect())
+        })
+    }
+
+    fn set_permissions(&self, guild_id: u64, channel_id: Option<u64>, role_id: u64, permissions: Vec<(String, bool)>) -> BackendFuture<()> {
+        Box::pin(async move {
+            let perms_to_give = permissions
+                .into_iter()
+                .map(|(matches, allowed)| Permission {
+                    matches,
+                    mode: if allowed { Mode::Allow } else { Mode::Deny } as i32,
+                })
+                .collect();
+            let request = SetPermissionsRequest {
+                guild_id,
+                channel_id,
+                role_id,
+                perms_to_give,
+            };
+            self.call(request).await?;
+            Ok(())
+        })
+    }
+
+    fn capabilities(&self) -> BackendFuture<Capabilities> {
+        Box::pin(async move {
+            // Harmony has no moderation endpoint to send reports to, profiles have no status text, messages can't be
+            // searched on the server, nobody's reading is tracked, and accounts can't be exported, deleted, or changed
+            let mut unsupported = vec![
+                Feature::Reports,
+                Feature::StatusText,
+                Feature::Search,
+                Feature::ReadReceipts,
+                Feature::AccountExport,
+                Feature::AccountDeletion,
+                Feature::AccountSettings,
+            ];
+            let version = match self.call(AboutRequest {}).await {
+                Ok(about) => Some(about.version),
+                Err(e) => {
+                    if is_unsupported(&e.into()) {
+                        unsupported.push(Feature::ServerInfo);
+                    }
+                    None
+                }
+            };
+
+            Ok(Capabilities::new(version, unsupported))
+        })
+    }
+
+    fn server_info(&self) -> BackendFuture<ServerInfo> {
+        Box::pin(async move {
+            let about = self.call(AboutRequest {}).await?;
+
+            // The first step of a new auth session is the choice of how to authenticate
+            let auth_methods = match self.call(BeginAuthRequest {}).await {
+                Ok(auth) => self
+                    .call(NextStepRequest {
+                        auth_id: auth.auth_id,
+                        ..Default::default()
+                    })
+                    .await
+                    .ok()
+                    .and_then(|v| v.step)
+                    .and_then(|v| v.step)
+                    .and_then(|v| match v {
+                        Step::Choice(choice) => Some(choice.options),
+                        _ => None,
+                    }),
+                Err(_) => None,
+            };
+
+            // Only servers that federate hand out the key other servers verify them with
+            let federates = self.call(KeyRequest {}).await.is_ok();
+
+            Ok(ServerInfo {
+                name: about.server_name,
+                version: about.version,
+                about: about.about_server,
+                motd: about.message_of_the_day,
+                auth_methods,
+                federates,
+            })
+        })
+    }
+
+    // Harmony has no moderation endpoint to send reports to
+    fn report_message(&self, _guild_id: u64, _channel_id: u64, _message_id: u64, _reason: String) -> BackendFuture<bool> {
+        Box::pin(async move { Ok(false) })
+    }
+
+    // Harmony has no search endpoint, so only loaded messages can be searched
+    fn search_messages(&self, _query: String) -> BackendFuture<Option<Vec<SearchHit>>> {
+        Box::pin(async move { Ok(None) })
+    }
+
+    // Harmony has no read receipts, so nobody is listed and nothing is sent
+    fn read_receipts(&self, _guild_id: u64, _channel_id: u64, _message_id: u64) -> BackendFuture<Option<Vec<ReadReceipt>>> {
+        Box::pin(async move { Ok(None) })
+    }
+
+    fn send_read_receipt(&self, _guild_id: u64, _channel_id: u64, _message_id: u64) -> BackendFuture<()> {
+        Box::pin(async move { Ok(()) })
+    }
+
+    // Harmony has no data takeout or account deletion endpoints, so both are left to the homeserver's operator
+    fn export_account(&self) -> BackendFuture<Option<String>> {
+        Box::pin(async move { Ok(None) })
+    }
+
+    fn delete_account(&self) -> BackendFuture<bool> {
+        Box::pin(async move { Ok(false) })
+    }
+
+    // Harmony's auth flows only log in and register, so there are no flows for changing account settings
+    fn begin_account_flow(&self, _setting: AccountSetting) -> BackendFuture<Option<AuthStep>> {
+        Box::pin(async move { Ok(None) })
+    }
+
+    fn next_account_step(&self, _response: AuthStepResponse) -> BackendFuture<Option<AuthStep>> {
+        Box::pin(async move { Ok(None) })
+    }
+}
+
+/// Backends for tests.
+#[cfg(test)]
+pub mod mock {
+    use super::*;
+
+    /// A backend whose every call fails as if the homeserver couldn't be reached.
+    pub struct OfflineBackend;
+
+    /// Fails a call.
+    fn offline<'a, T: Send + 'a>() -> BackendFuture<'a, T> {
+        Box::pin(async { Err(BackendError::from("offline")) })
+    }
+
+    impl ChatBackend for OfflineBackend {
+        fn send_message(&self, _guild_id: u64, _channel_id: u64, _content: chat::Content) -> BackendFuture<()> {
+            offline()
+        }
+
+        fn send_message_as(&self, _guild_id: u64, _channel_id: u64, _content: chat::Content, _overrides: chat::Overrides) -> BackendFuture<()> {
+            offline()
+        }
+
+        fn edit_message(&self, _guild_id: u64, _channel_id: u64, _message_id: u64, _text: String) -> BackendFuture<()> {
+            offline()
+        }
+
+        fn delete_message(&self, _guild_id: u64, _channel_id: u64, _message_id: u64) -> BackendFuture<()> {
+            offline()
+        }
+
+        fn get_messages(&self, _guild_id: u64, _channel_id: u64, _before: Option<u64>, _count: u32) -> BackendFuture<Vec<(u64, RawMessage)>> {
+            offline()
+        }
+
+        fn get_profile(&self, _user_id: u64) -> BackendFuture<Option<Profile>> {
+            offline()
+        }
+
+        fn set_status(&self, _status: UserStatus) -> BackendFuture<()> {
+            offline()
+        }
+
+        fn set_status_text(&self, _text: Option<String>) -> BackendFuture<bool> {
+            offline()
+        }
+
+        fn typing(&self, _guild_id: u64, _channel_id: u64) -> BackendFuture<()> {
+            offline()
+        }
+
+        fn guild_list(&self) -> BackendFuture<Vec<u64>> {
+            offline()
+        }
+
+        fn get_guild(&self, _guild_id: u64) -> BackendFuture<Option<chat::Guild>> {
+            offline()
+        }
+
+        fn get_channels(&self, _guild_id: u64) -> BackendFuture<Vec<(u64, chat::Channel)>> {
+            offline()
+        }
+
+        fn get_members(&self, _guild_id: u64) -> BackendFuture<Vec<u64>> {
+            offline()
+        }
+
+        fn join_guild(&self, _invite: String) -> BackendFuture<u64> {
+            offline()
+        }
+
+        fn preview_invite(&self, _invite: String) -> BackendFuture<InvitePreview> {
+            offline()
+        }
+
+        fn leave_guild(&self, _guild_id: u64) -> BackendFuture<()> {
+            offline()
+        }
+
+        fn trigger_action(&self, _guild_id: u64, _channel_id: u64, _message_id: u64, _data: Vec<u8>) -> BackendFuture<()> {
+            offline()
+        }
+
+        fn has_permission(&self, _guild_id: u64, _channel_id: Option<u64>, _node: String) -> BackendFuture<bool> {
+            offline()
+        }
+
+        fn get_roles(&self, _guild_id: u64) -> BackendFuture<Vec<(u64, chat::Role)>> {
+            offline()
+        }
+
+        fn add_role(&self, _guild_id: u64, _name: String, _color: i32) -> BackendFuture<u64> {
+            offline()
+        }
+
+        fn modify_role(&self, _guild_id: u64, _role_id: u64, _name: Option<String>, _color: Option<i32>) -> BackendFuture<()> {
+            offline()
+        }
+
+        fn move_role(&self, _guild_id: u64, _role_id: u64, _next_to: u64, _after: bool) -> BackendFuture<()> {
+            offline()
+        }
+
+        fn delete_role(&self, _guild_id: u64, _role_id: u64) -> BackendFuture<()> {
+            offline()
+        }
+
+        fn get_permissions(&self, _guild_id: u64, _channel_id: Option<u64>, _role_id: u64) -> BackendFuture<Vec<(String, bool)>> {
+            offline()
+        }
+
+        fn set_permissions(&self, _guild_id: u64, _channel_id: Option<u64>, _role_id: u64, _permissions: Vec<(String, bool)>) -> BackendFuture<()> {
+            offline()
+        }
+
+        fn capabilities(&self) -> BackendFuture<Capabilities> {
+            offline()
+        }
+
+        fn server_info(&self) -> BackendFuture<ServerInfo> {
+            offline()
+        }
+
+        fn report_message(&self, _guild_id: u64, _channel_id: u64, _message_id: u64, _reason: String) -> BackendFuture<bool> {
+            offline()
+        }
+
+        fn search_messages(&self, _query: String) -> BackendFuture<Option<Vec<SearchHit>>> {
+            offline()
+        }
+
+        fn read_receipts(&self, _guild_id: u64, _channel_id: u64, _message_id: u64) -> BackendFuture<Option<Vec<ReadReceipt>>> {
+            offline()
+        }
+
+        fn send_read_receipt(&self, _guild_id: u64, _channel_id: u64, _message_id: u64) -> BackendFuture<()> {
+            offline()
+        }
+
+        fn export_account(&self) -> BackendFuture<Option<String>> {
+            offline()
+        }
+
+        fn delete_account(&self) -> BackendFuture<bool> {
+            offline()
+        }
+
+        fn begin_account_flow(&self, _setting: AccountSetting) -> BackendFuture<Option<AuthStep>> {
+            offline()
+        }
+
+        fn next_account_step(&self, _response: AuthStepResponse) -> BackendFuture<Option<AuthStep>> {
+            offline()
+        }
+    }
+}
@@ -0,0 +1,58 @@
+//! Entry points for the benchmarks in `benches/`, which can't reach the client's internals otherwise.
+
+use std::collections::{HashMap, HashSet};
+
+use harmony_rust_sdk::api::chat::{FormattedText, Message as RawMessage};
+use tui::{backend::TestBackend, Terminal};
+
+use crate::{convert_formatted_text_to_rich_text, draw, handle_message, wrap_plain, AppState, Channel, Guild, History};
+
+/// Creates a state with one guild and one channel, both selected.
+pub fn channel_state(guild_id: u64, channel_id: u64) -> AppState {
+    let mut state = AppState::default();
+    let mut guild = Guild {
+        id: guild_id,
+        channels_list: vec![channel_id],
+        channels_select: Some(0),
+        channels_map: HashMap::new(),
+        name: String::from("bench"),
+        current_channel: Some(channel_id),
+        position: 0,
+        last_activity: 0,
+        unseen_channels: HashSet::new(),
+        member_count: None,
+        permissions: HashMap::new(),
+    };
+    guild.channels_map.insert(channel_id, Channel {
+        history: History::Loaded,
+        ..Channel::new(guild_id, channel_id, String::from("bench"))
+    });
+
+    state.guilds_list.push(guild_id);
+    state.guilds_map.insert(guild_id, guild);
+    state.guilds_select = Some(0);
+    state.current_guild = Some(guild_id);
+    state
+}
+
+/// Adds messages to a channel the way fetched history is added.
+pub fn ingest(state: &mut AppState, guild_id: u64, channel_id: u64, messages: Vec<(u64, RawMessage)>) {
+    for (message_id, message) in messages {
+        handle_message(state, message, guild_id, channel_id, message_id);
+    }
+}
+
+/// Formats text and wraps it to the given width, returning the wrapped lines.
+pub fn format(state: &AppState, text: FormattedText, width: usize) -> Vec<String> {
+    let rich = convert_formatted_text_to_rich_text(text, &state.theme);
+    wrap_plain(&rich.contents, width)
+}
+
+/// Draws a frame of the app onto an offscreen terminal of the given size.
+pub fn render(state: &AppState, width: u16, height: u16) {
+    let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+    terminal.draw(|f| {
+        draw(f, state);
+    })
+    .unwrap();
+}
@@ -0,0 +1,50 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::vault;
+
+/// A saved message.
+#[derive(Deserialize, Serialize)]
+pub struct Bookmark {
+    /// The guild the message is in.
+    pub guild_id: u64,
+
+    /// The channel the message is in.
+    pub channel_id: u64,
+
+    /// The id of the message.
+    pub message_id: u64,
+
+    /// The name of the message's author when it was saved.
+    pub author: String,
+
+    /// A snippet of the message's contents when it was saved.
+    pub snippet: String,
+
+    /// The unix timestamp the message was sent at.
+    pub timestamp: u64,
+}
+
+/// Gets the path of the given user's bookmarks.
+fn path(user_id: u64) -> Option<PathBuf> {
+    dirs::data_dir().map(|v| v.join(format!("ilo-toki/bookmarks-{}.json", user_id)))
+}
+
+/// Loads the given user's bookmarks from the data directory.
+pub fn load(user_id: u64) -> Vec<Bookmark> {
+    path(user_id)
+        .and_then(|v| vault::read_to_string(&v).ok())
+        .and_then(|v| serde_json::from_str(&v).ok())
+        .unwrap_or_default()
+}
+
+/// Saves the given user's bookmarks to the data directory.
+pub fn save(user_id: u64, bookmarks: &[Bookmark]) {
+    if let (Some(data_dir), Some(path)) = (dirs::data_dir(), path(user_id)) {
+        if let Ok(json) = serde_json::to_string(bookmarks) {
+            std::fs::create_dir(data_dir.join("ilo-toki/")).ok();
+            vault::write(&path, &json).ok();
+        }
+    }
+}
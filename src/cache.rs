@@ -0,0 +1,146 @@
+use std::{
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        OnceLock,
+    },
+    time::SystemTime,
+};
+
+/// The most bytes the media cache can hold before the least recently used files are removed.
+static LIMIT: AtomicU64 = AtomicU64::new(u64::MAX);
+
+/// The private directory used instead of the cache directory for this session, while saved data is encrypted.
+static PRIVATE: OnceLock<PathBuf> = OnceLock::new();
+
+/// Gets the directory downloaded attachments and thumbnails are cached in.
+pub fn dir() -> Option<PathBuf> {
+    match PRIVATE.get() {
+        Some(private) => Some(private.clone()),
+        None => dirs::cache_dir().map(|v| v.join("ilo-toki/media")),
+    }
+}
+
+/// Keeps attachments in a private temporary directory for this session only, after emptying the usual cache.
+/// Programs opening attachments need them unencrypted, so this is how they're kept off the disk when saved data is
+/// encrypted. The directory is removed by `remove_private`.
+pub fn use_private() -> io::Result<()> {
+    clear()?;
+    let private = tempfile::Builder::new().prefix("ilo-toki-media").tempdir()?.into_path();
+    let _ = PRIVATE.set(private);
+    Ok(())
+}
+
+/// Removes the private directory attachments were kept in this session, if there is one.
+pub fn remove_private() {
+    if let Some(private) = PRIVATE.get() {
+        std::fs::remove_dir_all(private).ok();
+    }
+}
+
+/// Parses a size such as `500MiB`, `500MB`, `2G`, or `1024` (bytes). Units are powers of 1024 however they're written.
+/// `off` means no limit.
+pub fn parse_size(text: &str) -> Option<u64> {
+    let text = text.trim();
+    if text == "off" {
+        return Some(u64::MAX);
+    }
+
+    let split = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: u64 = number.parse().ok()?;
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().trim_end_matches("ib").trim_end_matches('b') {
+        "" => 1,
+        "k" => 1 << 10,
+        "m" => 1 << 20,
+        "g" => 1 << 30,
+        _ => return None,
+    };
+    number.checked_mul(multiplier)
+}
+
+/// Sets how many bytes the cache can hold, from the `cache_size` setting.
+pub fn set_limit(size: &str) {
+    LIMIT.store(parse_size(size).unwrap_or(u64::MAX), Ordering::Relaxed);
+}
+
+/// The files in the cache with their size and when they were last used, leaving out downloads still in progress.
+fn entries() -> Vec<(PathBuf, u64, SystemTime)> {
+    let entries = match dir().and_then(|v| std::fs::read_dir(v).ok()) {
+        Some(entries) => entries,
+        None => return vec![],
+    };
+
+    entries
+        .filter_map(Result::ok)
+        .filter(|v| v.path().extension().map(|v| v != "part").unwrap_or(true))
+        .filter_map(|v| {
+            let metadata = v.metadata().ok().filter(|v| v.is_file())?;
+            Some((v.path(), metadata.len(), metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH)))
+        })
+        .collect()
+}
+
+/// Marks a cached file as just used, so it's the last to be removed.
+/// The modification time stands in for the access time, which many systems don't keep up to date.
+pub fn touch(path: &Path) {
+    if let Ok(file) = File::options().write(true).open(path) {
+        file.set_modified(SystemTime::now()).ok();
+    }
+}
+
+/// Removes the least recently used files until the cache fits in its limit.
+/// The file given was just used, so it stays even if it's bigger than the limit.
+pub fn evict(keep: &Path) {
+    let limit = LIMIT.load(Ordering::Relaxed);
+    let mut entries = entries();
+    let mut total: u64 = entries.iter().map(|(_, size, _)| size).sum();
+    if total <= limit {
+        return;
+    }
+
+    entries.sort_by_key(|(_, _, used)| *used);
+    for (path, size, _) in entries {
+        if total <= limit {
+            break;
+        }
+
+        if path != keep && std::fs::remove_file(&path).is_ok() {
+            total -= size;
+        }
+    }
+}
+
+/// How much is in the cache.
+pub struct Stats {
+    /// The number of files.
+    pub files: usize,
+
+    /// Their total size in bytes.
+    pub bytes: u64,
+
+    /// The most bytes the cache holds, or `u64::MAX` if there's no limit.
+    pub limit: u64,
+}
+
+/// Counts the files in the cache and their size.
+pub fn stats() -> Stats {
+    let entries = entries();
+    Stats {
+        files: entries.len(),
+        bytes: entries.iter().map(|(_, size, _)| size).sum(),
+        limit: LIMIT.load(Ordering::Relaxed),
+    }
+}
+
+/// Removes everything in the cache, returning how many files were removed.
+pub fn clear() -> io::Result<usize> {
+    let mut removed = 0;
+    for (path, _, _) in entries() {
+        std::fs::remove_file(path)?;
+        removed += 1;
+    }
+    Ok(removed)
+}
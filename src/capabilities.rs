@@ -0,0 +1,113 @@
+use std::collections::HashSet;
+
+use crate::backend::BackendError;
+
+/// A feature of the client that not every homeserver supports.
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Feature {
+    /// Describing the server with `:serverinfo`.
+    ServerInfo,
+
+    /// Managing roles and permissions with `:roleedit`.
+    Roles,
+
+    /// Reporting messages to moderators with `:report`.
+    Reports,
+
+    /// Pressing buttons on embeds.
+    Actions,
+
+    /// Showing a status text with `:status`.
+    StatusText,
+
+    /// Searching every guild's messages on the server with `:search`.
+    Search,
+
+    /// Showing who read messages and telling others what the user read.
+    ReadReceipts,
+
+    /// Exporting what the server keeps about the user with `:account export`.
+    AccountExport,
+
+    /// Deleting the user's account with `:account delete`.
+    AccountDeletion,
+
+    /// Changing the password, email, or two-factor authentication with `:account`.
+    AccountSettings,
+}
+
+impl Feature {
+    /// Gets the name of the feature shown to the user.
+    fn name(self) -> &'static str {
+        match self {
+            Feature::ServerInfo => "server info",
+            Feature::Roles => "managing roles",
+            Feature::Reports => "reporting messages",
+            Feature::Actions => "embed buttons",
+            Feature::StatusText => "status text",
+            Feature::Search => "server-side search",
+            Feature::ReadReceipts => "read receipts",
+            Feature::AccountExport => "data export",
+            Feature::AccountDeletion => "account deletion",
+            Feature::AccountSettings => "account settings",
+        }
+    }
+}
+
+/// What the homeserver supports, worked out when connecting and refined as features are used.
+#[derive(Default)]
+pub struct Capabilities {
+    /// The version of the server software, if it says.
+    pub version: Option<String>,
+
+    /// The features the homeserver doesn't support.
+    unsupported: HashSet<Feature>,
+}
+
+impl Capabilities {
+    /// Creates capabilities for a server that supports everything but the given features.
+    pub fn new(version: Option<String>, unsupported: impl IntoIterator<Item = Feature>) -> Capabilities {
+        Capabilities {
+            version,
+            unsupported: unsupported.into_iter().collect(),
+        }
+    }
+
+    /// Checks whether the homeserver supports a feature, as far as is known.
+    pub fn supports(&self, feature: Feature) -> bool {
+        !self.unsupported.contains(&feature)
+    }
+
+    /// Gets the message shown when the user tries to use a feature the homeserver doesn't support.
+    pub fn unsupported_message(&self, feature: Feature) -> String {
+        match &self.version {
+            Some(version) => format!("{} is not supported by this homeserver ({})", feature.name(), version),
+            None => format!("{} is not supported by this homeserver", feature.name()),
+        }
+    }
+
+    /// Gets the names of the features the homeserver doesn't support.
+    pub fn unsupported_names(&self) -> Vec<&'static str> {
+        let mut names: Vec<_> = self.unsupported.iter().map(|v| v.name()).collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// Describes an error from using a feature. If the error shows the homeserver doesn't have the feature's
+    /// endpoints, the feature is remembered as unsupported so it isn't tried again.
+    pub fn describe_error(&mut self, feature: Feature, error: &BackendError) -> String {
+        if is_unsupported(error) {
+            self.unsupported.insert(feature);
+            self.unsupported_message(feature)
+        } else {
+            format!("{} failed: {}", feature.name(), error)
+        }
+    }
+}
+
+/// Checks whether an error means the homeserver doesn't implement the endpoint that was called.
+/// Older homeservers answer unknown endpoints with a 404 or an unimplemented status rather than a proper error.
+pub fn is_unsupported(error: &BackendError) -> bool {
+    let error = error.to_string().to_lowercase();
+    error.contains("unimplemented") || error.contains("not implemented") || error.contains("404")
+}
@@ -0,0 +1,136 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use chrono::{DateTime, Local};
+use tui::{
+    backend::Backend,
+    layout::{Constraint, Direction, Layout, Rect},
+    text::{Span, Spans},
+    widgets::{BarChart, Block, Borders, Paragraph, Sparkline},
+    Frame,
+};
+
+use crate::{message_author, theme::Theme, AppState, Channel, MessageContent};
+
+/// How many of the most active posters are listed.
+const TOP_POSTERS: usize = 5;
+
+/// The widest a poster's bar is drawn.
+const POSTER_BAR_WIDTH: usize = 30;
+
+/// Activity statistics for the loaded history of a channel, shown by `:chanstats`.
+pub struct ChannelStats {
+    /// How many messages were counted.
+    messages: usize,
+
+    /// How many messages were sent on each day from the first loaded message to the last, oldest first.
+    days: Vec<u64>,
+
+    /// The names of the most active posters and how many messages each sent, most active first.
+    posters: Vec<(String, u64)>,
+
+    /// How many messages were sent in each hour of the day, in local time.
+    hours: [u64; 24],
+}
+
+impl ChannelStats {
+    /// Counts the loaded messages of a channel, leaving out notices made by the client.
+    pub fn compute(state: &AppState, channel: &Channel) -> ChannelStats {
+        let mut days: Vec<u64> = vec![];
+        let mut first_day = None;
+        let mut posters: HashMap<String, u64> = HashMap::new();
+        let mut hours = [0; 24];
+        let mut messages = 0;
+
+        for message in channel.messages_list.iter().filter_map(|v| channel.messages_map.get(v)) {
+            if matches!(message.content, MessageContent::Notice(_)) {
+                continue;
+            }
+            messages += 1;
+
+            // Messages are in order of timestamp, so days only ever grow at the end
+            let time: DateTime<Local> = DateTime::from(UNIX_EPOCH + Duration::from_secs(message.timestamp));
+            let local = message.timestamp as i64 + time.offset().local_minus_utc() as i64;
+            let day = local.div_euclid(86400);
+            let first = *first_day.get_or_insert(day);
+            let index = (day - first).max(0) as usize;
+            if days.len() <= index {
+                days.resize(index + 1, 0);
+            }
+            days[index] += 1;
+            hours[(local.rem_euclid(86400) / 3600) as usize] += 1;
+
+            *posters.entry(message_author(state, message)).or_default() += 1;
+        }
+
+        let mut posters: Vec<_> = posters.into_iter().collect();
+        posters.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        posters.truncate(TOP_POSTERS);
+
+        ChannelStats {
+            messages,
+            days,
+            posters,
+            hours,
+        }
+    }
+
+    /// Draws the statistics as charts in the given area.
+    pub fn render<B: Backend>(&self, f: &mut Frame<B>, area: Rect, theme: &Theme) {
+        if self.messages == 0 {
+            f.render_widget(Paragraph::new("(no messages loaded)"), area);
+            return;
+        }
+
+        let sections = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(1),
+                Constraint::Length(5),
+                Constraint::Length(self.posters.len() as u16 + 1),
+                Constraint::Min(5),
+            ])
+            .split(area);
+
+        let plural = if self.days.len() == 1 { "" } else { "s" };
+        let summary = format!("{} loaded messages over {} day{}", self.messages, self.days.len(), plural);
+        f.render_widget(Paragraph::new(summary), sections[0]);
+
+        // Messages per day, keeping the newest days when there are more than fit
+        let block = Block::default().borders(Borders::TOP).title("messages per day");
+        let width = block.inner(sections[1]).width as usize;
+        let days = &self.days[self.days.len().saturating_sub(width)..];
+        f.render_widget(Sparkline::default().block(block).data(days).style(theme.highlight), sections[1]);
+
+        // Top posters as bars scaled to the most active one
+        let most = self.posters.first().map(|v| v.1).unwrap_or(1).max(1);
+        let name_width = self.posters.iter().map(|v| v.0.chars().count()).max().unwrap_or(0);
+        let mut lines = vec![];
+        for (name, count) in self.posters.iter() {
+            let filled = (*count as usize * POSTER_BAR_WIDTH / most as usize).max(1);
+            lines.push(Spans::from(vec![
+                Span::raw(format!("{:width$} ", name, width = name_width)),
+                Span::styled("█".repeat(filled), theme.highlight),
+                Span::raw(format!(" {}", count)),
+            ]));
+        }
+        let block = Block::default().borders(Borders::TOP).title("top posters");
+        f.render_widget(Paragraph::new(lines).block(block), sections[2]);
+
+        // Busiest hours of the day
+        let labels: Vec<String> = (0..24).map(|v| format!("{:02}", v)).collect();
+        let data: Vec<(&str, u64)> = labels.iter().map(String::as_str).zip(self.hours.iter().copied()).collect();
+        let block = Block::default().borders(Borders::TOP).title("messages by hour");
+        let bar_width = ((block.inner(sections[3]).width / 24).saturating_sub(1)).max(1);
+        let chart = BarChart::default()
+            .block(block)
+            .data(&data)
+            .bar_width(bar_width)
+            .bar_gap(1)
+            .bar_style(theme.highlight)
+            .value_style(theme.selected);
+        f.render_widget(chart, sections[3]);
+    }
+}
@@ -0,0 +1,36 @@
+use std::io::{self, Write};
+
+use crate::{daemon, multiplexer};
+
+/// The characters used to encode base64.
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encodes bytes as padded base64.
+fn base64(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &v)| n | ((v as u32) << (16 - 8 * i)));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(BASE64[((n >> (18 - 6 * i)) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Copies text to the system clipboard through the terminal with OSC 52, which also works over ssh.
+/// Terminals that don't support it ignore it, so there's no way to tell whether it worked.
+/// Inside tmux or screen the sequence is wrapped so it reaches the terminal outside.
+pub fn copy(text: &str) -> io::Result<()> {
+    if !multiplexer::clipboard() {
+        return Err(io::Error::new(io::ErrorKind::Unsupported, "the clipboard is turned off"));
+    }
+
+    let sequence = format!("\x1b]52;c;{}\x07", base64(text.as_bytes()));
+    let mut stdout = daemon::stdout();
+    stdout.write_all(multiplexer::passthrough(&sequence).as_bytes())?;
+    stdout.flush()
+}
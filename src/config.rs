@@ -0,0 +1,456 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+/// Represents the user's configuration, loaded from `config.toml` in the config directory.
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// The name of the built in theme to use.
+    pub theme: String,
+
+    /// How the guild list is sorted.
+    pub guild_sort: GuildSort,
+
+    /// Settings for translating messages.
+    pub translate: TranslateConfig,
+
+    /// Words that highlight messages.
+    pub highlight: HighlightConfig,
+
+    /// Settings for desktop notifications.
+    pub notifications: NotificationConfig,
+
+    /// Settings for do not disturb.
+    pub dnd: DndConfig,
+
+    /// Settings for automatically going idle.
+    pub idle: IdleConfig,
+
+    /// Settings for setting the status text from another program.
+    pub rich_presence: RichPresenceConfig,
+
+    /// Settings for the lock screen.
+    pub lock: LockConfig,
+
+    /// Settings for opening attachments.
+    pub media: MediaConfig,
+
+    /// Settings for how connections to the homeserver are made.
+    pub network: NetworkConfig,
+
+    /// Settings for running inside tmux or screen.
+    pub multiplexer: MultiplexerConfig,
+
+    /// Settings for the websocket API other frontends use the running session through.
+    pub relay: RelayConfig,
+
+    /// Settings for the metrics endpoint served in daemon mode.
+    pub metrics: MetricsConfig,
+
+    /// Settings for fetching message history.
+    pub messages: MessagesConfig,
+
+    /// Whether to use less bandwidth by fetching smaller pages of messages and skipping thumbnail downloads.
+    /// Also enabled by the `--low-data` flag.
+    pub low_data: bool,
+
+    /// Whether to encrypt what's saved in the data directory with a passphrase asked for at startup.
+    /// Only read at startup; turning it off decrypts everything the next time the passphrase is typed.
+    pub encrypt_data: bool,
+
+    /// Text inserted in place of a trigger word, keyed by the trigger.
+    pub snippets: HashMap<String, String>,
+
+    /// The channel opened when a guild is selected, keyed by guild id or name.
+    pub default_channels: HashMap<String, String>,
+}
+
+/// Where message times are shown.
+#[derive(Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Timestamps {
+    /// After the author's name, with the date.
+    Inline,
+
+    /// Right-aligned in a column to the left of each message, so every message's text starts at the same place.
+    Column,
+}
+
+impl Default for Timestamps {
+    fn default() -> Self {
+        Self::Inline
+    }
+}
+
+/// The order guilds are shown in.
+#[derive(Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum GuildSort {
+    /// The order the guilds were received from the server or joined in.
+    Manual,
+
+    /// The guild with the most recent activity first.
+    Activity,
+}
+
+impl Default for GuildSort {
+    fn default() -> Self {
+        Self::Manual
+    }
+}
+
+/// Settings for the translation backend.
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct TranslateConfig {
+    /// The url of the LibreTranslate instance to use.
+    pub url: String,
+
+    /// The api key for the LibreTranslate instance, if it requires one.
+    pub api_key: Option<String>,
+
+    /// The language to translate to if none is given.
+    pub language: String,
+}
+
+impl Default for TranslateConfig {
+    fn default() -> Self {
+        Self {
+            url: String::from("https://libretranslate.com"),
+            api_key: None,
+            language: String::from("en"),
+        }
+    }
+}
+
+/// Settings for highlighted words.
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct HighlightConfig {
+    /// The words that highlight a message.
+    pub words: Vec<String>,
+
+    /// Per guild overrides of the highlight words, keyed by guild id or name.
+    pub guilds: HashMap<String, Vec<String>>,
+}
+
+impl HighlightConfig {
+    /// Gets the highlight words for the given guild.
+    pub fn words_for(&self, guild_id: u64, guild_name: &str) -> &[String] {
+        self.guilds
+            .get(&guild_id.to_string())
+            .or_else(|| self.guilds.get(guild_name))
+            .unwrap_or(&self.words)
+    }
+}
+
+/// Settings for desktop notifications.
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct NotificationConfig {
+    /// Whether desktop notifications are shown at all.
+    pub enabled: bool,
+
+    /// The notification level of channels without an override.
+    pub default_level: NotifyLevel,
+
+    /// The sound file played when the user is mentioned, `bell` to ring the terminal bell, or `None` for silence.
+    pub sound: Option<String>,
+
+    /// The command that plays sound files. `{}` is replaced with the file, which is otherwise added at the end.
+    /// Defaults to `afplay` on macOS and `paplay` elsewhere.
+    pub sound_command: Option<String>,
+
+    /// Per guild or channel overrides of the sound, keyed by guild id or name, or `guild#channel`. `off` silences them.
+    pub sounds: HashMap<String, String>,
+}
+
+impl NotificationConfig {
+    /// Gets the sound for a channel, if it has one.
+    pub fn sound_for(&self, guild_id: u64, guild_name: &str, channel_name: &str) -> Option<&str> {
+        self.sounds
+            .get(&format!("{}#{}", guild_name, channel_name))
+            .or_else(|| self.sounds.get(&format!("{}#{}", guild_id, channel_name)))
+            .or_else(|| self.sounds.get(&guild_id.to_string()))
+            .or_else(|| self.sounds.get(guild_name))
+            .map(String::as_str)
+            .or(self.sound.as_deref())
+            .filter(|v| *v != "off")
+    }
+}
+
+impl Default for NotificationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            default_level: NotifyLevel::default(),
+            sound: None,
+            sound_command: None,
+            sounds: HashMap::new(),
+        }
+    }
+}
+
+/// Settings for do not disturb.
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct DndConfig {
+    /// A daily schedule during which do not disturb is on, such as `23:00-08:00`.
+    pub schedule: Option<String>,
+}
+
+/// Settings for automatically going idle.
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct IdleConfig {
+    /// How long without input before the user's status becomes idle, such as `10m`. `off` disables it.
+    pub timeout: String,
+}
+
+impl Default for IdleConfig {
+    fn default() -> Self {
+        Self {
+            timeout: String::from("10m"),
+        }
+    }
+}
+
+/// Settings for the lock screen.
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct LockConfig {
+    /// The Argon2 hash of the passphrase that unlocks the lock screen, as a PHC string such as `$argon2id$v=19$...`.
+    pub passphrase: Option<String>,
+
+    /// How long without input before the lock screen comes up, such as `15m`. `off` disables it.
+    pub timeout: String,
+}
+
+impl Default for LockConfig {
+    fn default() -> Self {
+        Self {
+            passphrase: None,
+            timeout: String::from("off"),
+        }
+    }
+}
+
+/// Settings for setting the status text from another program, such as the song that's playing.
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct RichPresenceConfig {
+    /// A shell command whose first line of output becomes the status text, or `None` to turn rich presence off.
+    pub command: Option<String>,
+
+    /// How often the command is run, such as `30s`.
+    pub interval: String,
+}
+
+impl Default for RichPresenceConfig {
+    fn default() -> Self {
+        Self {
+            command: None,
+            interval: String::from("30s"),
+        }
+    }
+}
+
+/// Settings for fetching message history.
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct MessagesConfig {
+    /// How many messages are fetched at a time.
+    pub page_size: u32,
+
+    /// How close to the oldest loaded message scrolling gets before the previous page is fetched.
+    pub prefetch: usize,
+
+    /// Whether messages are numbered by their distance from the selected message, for use with counts in scroll mode.
+    pub relative_numbers: bool,
+
+    /// The widest message text can be, with the messages centered in the space left over.
+    pub max_width: Option<u16>,
+
+    /// Where message times are shown.
+    pub timestamps: Timestamps,
+
+    /// Whether a line above the messages shows the guild, channel, member count, and what the user can do there.
+    pub header: bool,
+
+    /// Whether the homeserver is told which messages the user has read, on homeservers with read receipts.
+    pub read_receipts: bool,
+}
+
+impl Default for MessagesConfig {
+    fn default() -> Self {
+        Self {
+            page_size: 50,
+            prefetch: 10,
+            relative_numbers: false,
+            max_width: None,
+            timestamps: Timestamps::Inline,
+            header: false,
+            read_receipts: true,
+        }
+    }
+}
+
+/// Settings for opening attachments.
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct MediaConfig {
+    /// Commands to open attachments with, keyed by mime type (`image/png`), category (`image/*`), or `*`.
+    pub handlers: HashMap<String, String>,
+
+    /// How big the cache of downloaded attachments and thumbnails can get, such as `500MiB`. `off` disables the limit.
+    pub cache_size: String,
+}
+
+impl Default for MediaConfig {
+    fn default() -> Self {
+        Self {
+            handlers: HashMap::new(),
+            cache_size: String::from("500MiB"),
+        }
+    }
+}
+
+/// The terminal multiplexer escape sequences are adjusted for.
+#[derive(Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum MultiplexerKind {
+    /// Detected from the environment tmux and screen set.
+    Auto,
+
+    /// tmux, which passes sequences through with `allow-passthrough` on.
+    Tmux,
+
+    /// GNU screen.
+    Screen,
+
+    /// Running straight in a terminal.
+    None,
+}
+
+impl Default for MultiplexerKind {
+    fn default() -> Self {
+        MultiplexerKind::Auto
+    }
+}
+
+/// Settings for running inside a terminal multiplexer. Features left unset are turned on unless the multiplexer is
+/// known to break them.
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct MultiplexerConfig {
+    /// The multiplexer to adjust escape sequences for.
+    pub kind: MultiplexerKind,
+
+    /// Whether the cursor changes shape between modes.
+    pub cursor_shape: Option<bool>,
+
+    /// Whether copying goes to the system clipboard through OSC 52.
+    pub clipboard: Option<bool>,
+
+    /// Whether thumbnails are drawn over the terminal with ueberzug.
+    pub thumbnails: Option<bool>,
+}
+
+/// Settings for the relay, a websocket API serving JSON that lets other frontends use the running session.
+#[derive(Clone, Default, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct RelayConfig {
+    /// The address to listen on, such as `127.0.0.1:9001`, or `None` to not run the relay.
+    pub listen: Option<String>,
+
+    /// The password frontends authenticate with. The relay doesn't start without one.
+    pub password: Option<String>,
+
+    /// Whether the relay may listen on addresses other than loopback ones.
+    pub allow_remote: bool,
+}
+
+/// Settings for the metrics endpoint, which serves counters in the Prometheus text format when running as a daemon.
+#[derive(Clone, Default, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct MetricsConfig {
+    /// The address to serve `/metrics` on, such as `127.0.0.1:9091`, or `None` to not serve metrics.
+    pub listen: Option<String>,
+
+    /// Whether metrics may be served on addresses other than loopback ones.
+    pub allow_remote: bool,
+}
+
+/// Settings for how connections to the homeserver are made.
+#[derive(Clone, Default, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct NetworkConfig {
+    /// A PEM file of extra certificate authorities to trust. Not supported yet, so the config is rejected if it's set.
+    pub ca_bundle: Option<PathBuf>,
+
+    /// SHA-256 fingerprints of server certificates to accept. Not supported yet, so the config is rejected if any are
+    /// set.
+    pub pinned_certs: Vec<String>,
+}
+
+/// How much a channel notifies the user.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum NotifyLevel {
+    /// Every message notifies and marks the channel as unread.
+    All,
+
+    /// Only highlighted messages and mentions notify, but every message marks the channel as unread.
+    Mentions,
+
+    /// Nothing notifies and the channel is never marked as unread.
+    Nothing,
+}
+
+impl Default for NotifyLevel {
+    fn default() -> Self {
+        Self::Mentions
+    }
+}
+
+impl std::str::FromStr for NotifyLevel {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "all" => Ok(Self::All),
+            "mentions" => Ok(Self::Mentions),
+            "nothing" | "none" => Ok(Self::Nothing),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Config {
+    /// Gets the path of the config file.
+    pub fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|v| v.join("ilo-toki/config.toml"))
+    }
+
+    /// Loads the config file, describing what's wrong with it if it's invalid.
+    /// A missing config file is the same as an empty one.
+    pub fn try_load() -> Result<Config, String> {
+        match Self::path().map(std::fs::read_to_string) {
+            Some(Ok(text)) => toml::from_str(&text).map_err(|e| e.to_string()).and_then(Config::validate),
+            _ => Ok(Config::default()),
+        }
+    }
+
+    /// Rejects settings that parse but can't be honoured, rather than quietly ignoring them.
+    fn validate(self) -> Result<Config, String> {
+        // The harmony client can't be given a tls config, so these would leave the homeserver connection unpinned
+        if self.network.ca_bundle.is_some() || !self.network.pinned_certs.is_empty() {
+            return Err(String::from(
+                "`ca_bundle` and `pinned_certs` in [network] aren't supported: the homeserver connection can't use them yet",
+            ));
+        }
+
+        Ok(self)
+    }
+}
@@ -0,0 +1,528 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{self, Write},
+    os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Mutex, PoisonError,
+    },
+};
+
+use crossterm::event::{Event, KeyCode, KeyModifiers};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{unix::OwnedWriteHalf, UnixListener, UnixStream},
+    sync::mpsc,
+};
+use tokio_util::sync::CancellationToken;
+use tui::{
+    backend::{Backend, CrosstermBackend},
+    buffer::Cell,
+    layout::Rect,
+};
+
+use crate::{
+    diff::DiffCache,
+    edit::Edit,
+    input::{self, Source},
+    modal::Modal,
+    registers::RegisterPrompt,
+    shutdown,
+    text_buffer::TextBuffer,
+    AppMode, AppState, Thread,
+};
+
+/// How many frames are kept for a frontend that's slow to read them before it's sent a whole new screen instead.
+const FRAME_BUFFER: usize = 64;
+
+/// The size a frontend is drawn at until it says how big its terminal is.
+const DEFAULT_SIZE: (u16, u16) = (80, 24);
+
+/// A terminal attached to the daemon.
+struct Frontend {
+    /// The size of the terminal, as its width and height.
+    size: (u16, u16),
+
+    /// Set when the terminal attaches, is resized, or falls behind, so its next frame redraws the whole screen.
+    redraw: bool,
+
+    /// Where frames drawn for the terminal go.
+    frames: mpsc::Sender<Vec<u8>>,
+}
+
+/// Set once this process is a daemon drawing for frontends rather than its own terminal.
+static LISTENING: AtomicBool = AtomicBool::new(false);
+
+/// The terminals attached to the daemon, by id.
+static FRONTENDS: Mutex<BTreeMap<Source, Frontend>> = Mutex::new(BTreeMap::new());
+
+/// The terminal whose view is in the app state, which escape sequences written outside of a frame go to.
+static ACTIVE: AtomicU64 = AtomicU64::new(input::LOCAL);
+
+/// Locks the attached terminals, which is still safe if a thread panicked holding the lock since it's only a map.
+fn frontends() -> std::sync::MutexGuard<'static, BTreeMap<Source, Frontend>> {
+    FRONTENDS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Gets the socket frontends attach to the daemon through. Without a runtime directory it goes in a directory of the
+/// temporary directory named after the user, which [`listen`] only uses if no one else can get into it.
+pub fn socket_path() -> Option<PathBuf> {
+    match dirs::runtime_dir() {
+        Some(dir) => Some(dir.join("ilo-toki.sock")),
+        None => Some(std::env::temp_dir().join(format!("ilo-toki-{}", own_uid().ok()?)).join("daemon.sock")),
+    }
+}
+
+/// Gets the id of the user running the program, from the credentials of a socket connected to itself.
+fn own_uid() -> io::Result<u32> {
+    let (socket, _) = UnixStream::pair()?;
+    Ok(socket.peer_cred()?.uid())
+}
+
+/// Checks whether this process is a daemon drawing for frontends rather than its own terminal.
+pub fn is_daemon() -> bool {
+    LISTENING.load(Ordering::Relaxed)
+}
+
+/// Gets the ids of the attached terminals.
+pub fn attached() -> Vec<Source> {
+    frontends().keys().copied().collect()
+}
+
+/// Checks whether a terminal needs its whole screen drawn again since its last frame, clearing the flag.
+pub fn take_redraw(id: Source) -> bool {
+    frontends().get_mut(&id).map_or(false, |v| std::mem::take(&mut v.redraw))
+}
+
+/// Sends a frame to an attached terminal. One too slow to keep up misses it, so it's sent the whole screen instead
+/// once it has room again.
+fn send_frame(id: Source, frame: Vec<u8>) {
+    if let Some(frontend) = frontends().get_mut(&id) {
+        if frontend.frames.try_send(frame).is_err() {
+            frontend.redraw = true;
+        }
+    }
+}
+
+/// What each attached terminal has of its own, so terminals can be in different places and type different things
+/// while sharing the connection and everything loaded. The view of the terminal whose input is being handled lives
+/// in the app state itself; the others are kept here.
+#[derive(Default)]
+pub struct Views {
+    /// The terminal whose view is in the app state.
+    active: Source,
+
+    /// The views of the other terminals.
+    stored: HashMap<Source, View>,
+}
+
+impl Views {
+    /// Gets the terminal whose view is in the app state.
+    pub fn active(&self) -> Source {
+        self.active
+    }
+}
+
+/// A terminal's own part of the app state. Each field is the app state field of the same name.
+#[derive(Default)]
+struct View {
+    mode: AppMode,
+    command_return: AppMode,
+    status: Option<String>,
+    modals: Vec<Modal>,
+    completion: Option<(String, usize)>,
+    action_select: usize,
+    list_filter: Option<String>,
+    jump_list: Vec<(u64, u64)>,
+    jump_index: usize,
+    scroll_count: Option<usize>,
+    thread: Option<Thread>,
+    show_stats: bool,
+    guilds_select: Option<usize>,
+    current_guild: Option<u64>,
+    editing: bool,
+    input: TextBuffer,
+    old_input: TextBuffer,
+    edit_diff: DiffCache,
+    command: TextBuffer,
+    register_prompt: Option<RegisterPrompt>,
+    register: Option<char>,
+    last_edit: Option<Edit>,
+    edit_joins: bool,
+
+    /// The channel open and the channel picked in the channel list for each guild.
+    guilds: HashMap<u64, (Option<u64>, Option<usize>)>,
+
+    /// The selected message, the message at the bottom of the pane and how many of its lines are scrolled below it, and
+    /// how many messages arrived below the selection for each channel.
+    channels: HashMap<u64, (Option<u64>, u64, usize, usize)>,
+}
+
+impl View {
+    /// Creates the view of a newly attached terminal, which starts in the same guild and channels as the active one
+    /// but with nothing typed or open.
+    fn new(state: &AppState) -> View {
+        let guilds = state.guilds_map.values();
+        View {
+            current_guild: state.current_guild,
+            guilds_select: state.guilds_select,
+            guilds: guilds.clone().map(|v| (v.id, (v.current_channel, v.channels_select))).collect(),
+            channels: guilds
+                .flat_map(|v| v.channels_map.values())
+                .map(|v| (v.id, (v.selected, v.view_bottom.load(Ordering::Relaxed), v.view_offset.load(Ordering::Relaxed), v.new_below)))
+                .collect(),
+            ..View::default()
+        }
+    }
+
+    /// Swaps this view with the one in the app state.
+    fn swap(&mut self, state: &mut AppState) {
+        use std::mem::swap;
+
+        swap(&mut self.mode, &mut state.mode);
+        swap(&mut self.command_return, &mut state.command_return);
+        swap(&mut self.status, &mut state.status);
+        swap(&mut self.modals, &mut state.modals);
+        swap(&mut self.completion, &mut state.completion);
+        swap(&mut self.action_select, &mut state.action_select);
+        swap(&mut self.list_filter, &mut state.list_filter);
+        swap(&mut self.jump_list, &mut state.jump_list);
+        swap(&mut self.jump_index, &mut state.jump_index);
+        swap(&mut self.scroll_count, &mut state.scroll_count);
+        swap(&mut self.thread, &mut state.thread);
+        swap(&mut self.show_stats, &mut state.show_stats);
+        swap(&mut self.guilds_select, &mut state.guilds_select);
+        swap(&mut self.current_guild, &mut state.current_guild);
+        swap(&mut self.editing, &mut state.editing);
+        swap(&mut self.input, &mut state.input);
+        swap(&mut self.old_input, &mut state.old_input);
+        swap(&mut self.edit_diff, &mut state.edit_diff);
+        swap(&mut self.command, &mut state.command);
+        swap(&mut self.register_prompt, &mut state.register_prompt);
+        swap(&mut self.register, &mut state.register);
+        swap(&mut self.last_edit, &mut state.last_edit);
+        swap(&mut self.edit_joins, &mut state.edit_joins);
+
+        // Guilds and channels loaded since the view was last used start out with nothing open or selected
+        for guild in state.guilds_map.values_mut() {
+            let (channel, select) = self.guilds.entry(guild.id).or_default();
+            swap(channel, &mut guild.current_channel);
+            swap(select, &mut guild.channels_select);
+
+            for channel in guild.channels_map.values_mut() {
+                let (selected, bottom, offset, below) = self.channels.entry(channel.id).or_default();
+                swap(selected, &mut channel.selected);
+                swap(bottom, channel.view_bottom.get_mut());
+                swap(offset, channel.view_offset.get_mut());
+                swap(below, &mut channel.new_below);
+            }
+        }
+    }
+}
+
+/// Puts a terminal's view in the app state, keeping the one that was there for its own terminal. Nothing changes
+/// outside of a daemon, where every event comes from the one terminal.
+pub fn switch(state: &mut AppState, id: Source) {
+    if state.views.active == id {
+        return;
+    }
+
+    let mut view = match state.views.stored.remove(&id) {
+        Some(view) => view,
+        None => View::new(state),
+    };
+    view.swap(state);
+    let previous = std::mem::replace(&mut state.views.active, id);
+    state.views.stored.insert(previous, view);
+    ACTIVE.store(id, Ordering::Relaxed);
+
+    // Views of terminals that detached aren't needed anymore
+    let attached = frontends();
+    state.views.stored.retain(|id, _| attached.contains_key(id));
+}
+
+/// Where escape sequences for the terminal are written: stdout, or an attached terminal when running as a daemon.
+/// Anything written goes out on flush, so a frame reaches the terminal in one piece.
+pub enum Output {
+    /// The terminal this process runs in.
+    Terminal(io::Stdout),
+
+    /// What's been written since the last flush, for the attached terminal with the given id.
+    Frontend(Source, Vec<u8>),
+}
+
+/// Gets where to write escape sequences, which is the terminal whose input is being handled instead of stdout when
+/// running as a daemon.
+pub fn stdout() -> Output {
+    if is_daemon() {
+        Output::Frontend(ACTIVE.load(Ordering::Relaxed), vec![])
+    } else {
+        Output::Terminal(io::stdout())
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Output::Terminal(stdout) => stdout.write(buf),
+            Output::Frontend(_, buffer) => {
+                buffer.extend_from_slice(buf);
+                Ok(buf.len())
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Terminal(stdout) => stdout.flush(),
+            Output::Frontend(id, buffer) => {
+                // A terminal that detached isn't an error, the frame is just dropped
+                if !buffer.is_empty() {
+                    send_frame(*id, std::mem::take(buffer));
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Draws the UI for one attached terminal, at its size.
+pub struct FrontendBackend {
+    /// Writes the escape sequences, which go to the terminal since this only exists in the daemon.
+    inner: CrosstermBackend<Output>,
+
+    /// The id of the terminal.
+    id: Source,
+
+    /// Where the cursor was last put, since the terminal can't be asked.
+    cursor: (u16, u16),
+}
+
+impl FrontendBackend {
+    /// Creates a backend drawing for the attached terminal with the given id.
+    pub fn new(id: Source) -> FrontendBackend {
+        FrontendBackend {
+            inner: CrosstermBackend::new(Output::Frontend(id, vec![])),
+            id,
+            cursor: (0, 0),
+        }
+    }
+}
+
+impl Backend for FrontendBackend {
+    fn draw<'a, I>(&mut self, content: I) -> io::Result<()>
+    where
+        I: Iterator<Item = (u16, u16, &'a Cell)>,
+    {
+        self.inner.draw(content)
+    }
+
+    fn hide_cursor(&mut self) -> io::Result<()> {
+        self.inner.hide_cursor()
+    }
+
+    fn show_cursor(&mut self) -> io::Result<()> {
+        self.inner.show_cursor()
+    }
+
+    fn get_cursor(&mut self) -> io::Result<(u16, u16)> {
+        Ok(self.cursor)
+    }
+
+    fn set_cursor(&mut self, x: u16, y: u16) -> io::Result<()> {
+        self.cursor = (x, y);
+        self.inner.set_cursor(x, y)
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        self.inner.clear()
+    }
+
+    fn size(&self) -> io::Result<Rect> {
+        let (width, height) = frontends().get(&self.id).map_or(DEFAULT_SIZE, |v| v.size);
+        Ok(Rect::new(0, 0, width, height))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Starts listening for frontends on the socket, returning the input they send along with which terminal sent it.
+/// From then on the UI is drawn for them instead of this terminal, which is given back to the shell.
+pub fn listen(path: &Path, shutdown: CancellationToken) -> io::Result<mpsc::Receiver<(Source, Event)>> {
+    // The socket goes in a directory only this user can get into, made private before anything is put in it
+    let uid = own_uid()?;
+    let dir = path.parent().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "the socket has no directory"))?;
+    if let Err(e) = std::fs::DirBuilder::new().mode(0o700).create(dir) {
+        if e.kind() != io::ErrorKind::AlreadyExists {
+            return Err(e);
+        }
+    }
+    let metadata = std::fs::symlink_metadata(dir)?;
+    if !metadata.is_dir() || metadata.uid() != uid || metadata.mode() & 0o077 != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{} is not a directory only you can open", dir.display()),
+        ));
+    }
+
+    // A socket left behind by a daemon that didn't stop cleanly is replaced, but a running daemon isn't
+    if std::os::unix::net::UnixStream::connect(path).is_ok() {
+        return Err(io::Error::new(io::ErrorKind::AddrInUse, "a daemon is already running"));
+    }
+    std::fs::remove_file(path).ok();
+    let listener = UnixListener::bind(path)?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+
+    LISTENING.store(true, Ordering::Relaxed);
+    let (tx, rx) = mpsc::channel(input::INPUT_BUFFER);
+    tokio::spawn(accept(listener, path.to_owned(), uid, tx, shutdown));
+    Ok(rx)
+}
+
+/// Accepts frontends run by the same user until the daemon stops, then removes the socket.
+async fn accept(listener: UnixListener, path: PathBuf, uid: u32, input: mpsc::Sender<(Source, Event)>, shutdown: CancellationToken) {
+    // The terminal the daemon was started from has the local id, so attached ones count up from after it
+    let mut next_id = input::LOCAL;
+    loop {
+        let stream = tokio::select! {
+            stream = listener.accept() => stream,
+            _ = shutdown.cancelled() => break,
+        };
+
+        // Other users can't get to the socket, but they're turned away here too in case its permissions change
+        let stream = match stream {
+            Ok((stream, _)) if stream.peer_cred().map_or(false, |v| v.uid() == uid) => stream,
+            _ => continue,
+        };
+
+        next_id += 1;
+        tokio::spawn(serve(stream, next_id, input.clone(), shutdown.clone()));
+    }
+
+    std::fs::remove_file(path).ok();
+}
+
+/// Passes a frontend's input to the UI and the frames drawn for it back, until it detaches or the daemon stops.
+async fn serve(stream: UnixStream, id: Source, input: mpsc::Sender<(Source, Event)>, shutdown: CancellationToken) {
+    let (read, write) = stream.into_split();
+    let (frames, receiver) = mpsc::channel(FRAME_BUFFER);
+    frontends().insert(
+        id,
+        Frontend {
+            size: DEFAULT_SIZE,
+            redraw: true,
+            frames,
+        },
+    );
+    let sending = tokio::spawn(send_frames(write, receiver));
+
+    // Each line is an event as JSON
+    let mut lines = BufReader::new(read).lines();
+    loop {
+        let line = tokio::select! {
+            line = lines.next_line() => line,
+            _ = shutdown.cancelled() => break,
+        };
+
+        let event = match line {
+            Ok(Some(line)) => match serde_json::from_str(&line) {
+                Ok(event) => event,
+                Err(_) => continue,
+            },
+            Ok(None) | Err(_) => break,
+        };
+
+        if let Event::Resize(width, height) = event {
+            if let Some(frontend) = frontends().get_mut(&id) {
+                frontend.size = (width, height);
+                frontend.redraw = true;
+            }
+        }
+
+        if input.send((id, event)).await.is_err() {
+            break;
+        }
+    }
+
+    frontends().remove(&id);
+    sending.abort();
+}
+
+/// Writes frames to a frontend as they're drawn for it.
+async fn send_frames(mut write: OwnedWriteHalf, mut frames: mpsc::Receiver<Vec<u8>>) {
+    while let Some(frame) = frames.recv().await {
+        if write.write_all(&frame).await.is_err() {
+            break;
+        }
+    }
+}
+
+/// Sends an event to the daemon as a line of JSON.
+async fn send_event(write: &mut OwnedWriteHalf, event: &Event) -> io::Result<()> {
+    let mut line = serde_json::to_string(event).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    line.push('\n');
+    write.write_all(line.as_bytes()).await
+}
+
+/// Attaches this terminal to a running daemon, passing keys to it and drawing what it sends until ctrl+\ detaches or
+/// the daemon stops. Returns false without touching the terminal if no daemon is running.
+pub async fn attach(path: &Path, shutdown: &CancellationToken) -> io::Result<bool> {
+    let stream = match UnixStream::connect(path).await {
+        Ok(stream) => stream,
+        Err(e) if matches!(e.kind(), io::ErrorKind::NotFound | io::ErrorKind::ConnectionRefused) => return Ok(false),
+        Err(e) => return Err(e),
+    };
+
+    // Keys typed here, such as the passphrase, only go to a daemon run by the same user
+    if stream.peer_cred()?.uid() != own_uid()? {
+        return Err(io::Error::new(io::ErrorKind::PermissionDenied, "the daemon's socket belongs to another user"));
+    }
+    let (mut read, mut write) = stream.into_split();
+
+    crossterm::terminal::enable_raw_mode()?;
+    let mut events = input::spawn(shutdown.clone());
+    let (width, height) = crossterm::terminal::size()?;
+    send_event(&mut write, &Event::Resize(width, height)).await?;
+
+    // Draw whatever the daemon sends straight to the terminal
+    let mut drawing = tokio::spawn(async move {
+        let mut stdout = tokio::io::stdout();
+        let mut buffer = vec![0; 64 * 1024];
+        loop {
+            match read.read(&mut buffer).await {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    if stdout.write_all(&buffer[..n]).await.is_err() || stdout.flush().await.is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+
+    loop {
+        let event = tokio::select! {
+            event = events.recv() => event,
+            _ = &mut drawing => break,
+            _ = shutdown.cancelled() => break,
+        };
+
+        match event {
+            // Detach, leaving the daemon running
+            Some(Event::Key(key)) if key.code == KeyCode::Char('\\') && key.modifiers == KeyModifiers::CONTROL => break,
+            Some(event) => {
+                if send_event(&mut write, &event).await.is_err() {
+                    break;
+                }
+            }
+            None => break,
+        }
+    }
+
+    drawing.abort();
+    shutdown::restore_terminal();
+    Ok(true)
+}
@@ -0,0 +1,274 @@
+use std::{collections::VecDeque, fmt::Debug, sync::{Arc, Mutex}, time::Instant};
+
+use chrono::Local;
+use harmony_rust_sdk::{
+    api::{
+        auth::AuthStep,
+        chat::{self, Message as RawMessage},
+        profile::Profile,
+    },
+    client::api::{auth::AuthStepResponse, profile::UserStatus},
+};
+
+use crate::{
+    backend::{AccountSetting, BackendFuture, ChatBackend, InvitePreview, ReadReceipt, SearchHit, ServerInfo},
+    capabilities::Capabilities,
+    stats::Stats,
+};
+
+/// The most entries kept in the debug log.
+const DEBUG_LOG_SIZE: usize = 500;
+
+/// A log of the calls made to the homeserver and the events received from it, shown by `:debug`.
+/// Only names and ids are logged, never message contents or tokens.
+#[derive(Default)]
+pub struct DebugLog {
+    /// The entries, oldest first.
+    entries: Mutex<VecDeque<String>>,
+}
+
+impl DebugLog {
+    /// Adds an entry, dropping the oldest one if the log is full.
+    pub fn push(&self, entry: String) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= DEBUG_LOG_SIZE {
+            entries.pop_front();
+        }
+        entries.push_back(format!("{} {}", Local::now().format("%H:%M:%S%.3f"), entry));
+    }
+
+    /// Gets the newest entries, oldest first.
+    pub fn last(&self, count: usize) -> Vec<String> {
+        let entries = self.entries.lock().unwrap();
+        entries.iter().skip(entries.len().saturating_sub(count)).cloned().collect()
+    }
+
+    /// Removes every entry.
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+/// Gets the name of an enum's variant (and the variant inside it, if any) from its debug output, leaving out
+/// everything it contains.
+pub fn variant_name(value: &impl Debug) -> String {
+    let debug = format!("{:?}", value);
+    debug
+        .split(|c: char| c == '(' || c == ' ' || c == '{')
+        .take(2)
+        .filter(|v| !v.is_empty())
+        .collect::<Vec<_>>()
+        .join("::")
+}
+
+/// A backend that logs every call made through it before passing it on, and counts the calls still pending and how
+/// long finished ones took.
+pub struct LoggingBackend {
+    /// The backend the calls are passed on to.
+    inner: Arc<dyn ChatBackend>,
+
+    /// Where the calls are logged.
+    log: Arc<DebugLog>,
+
+    /// Where the calls are counted and timed.
+    stats: Arc<Stats>,
+}
+
+impl LoggingBackend {
+    /// Creates a backend logging calls to the given backend.
+    pub fn new(inner: Arc<dyn ChatBackend>, log: Arc<DebugLog>, stats: Arc<Stats>) -> LoggingBackend {
+        LoggingBackend { inner, log, stats }
+    }
+
+    /// Logs a call, and its error once it finishes if it fails.
+    fn logged<'a, T: Send + 'a>(&'a self, call: String, future: BackendFuture<'a, T>) -> BackendFuture<'a, T> {
+        self.log.push(format!("→ {}", call));
+        Box::pin(async move {
+            let _pending = self.stats.start_request();
+            let start = Instant::now();
+            let result = future.await;
+            let name = call.split(' ').next().unwrap_or_default();
+            self.stats.record_request(name, start.elapsed(), result.is_ok());
+            if let Err(e) = &result {
+                self.log.push(format!("← {} failed: {}", call, e));
+            }
+            result
+        })
+    }
+}
+
+impl ChatBackend for LoggingBackend {
+    fn send_message(&self, guild_id: u64, channel_id: u64, content: chat::Content) -> BackendFuture<()> {
+        let kind = content.content.as_ref().map(variant_name).unwrap_or_default();
+        let call = format!("send_message guild={} channel={} content={}", guild_id, channel_id, kind);
+        let send = self.inner.send_message(guild_id, channel_id, content);
+        self.logged(call, Box::pin(async move {
+            let result = send.await;
+            if result.is_ok() {
+                self.stats.record_sent();
+            }
+            result
+        }))
+    }
+
+    fn send_message_as(&self, guild_id: u64, channel_id: u64, content: chat::Content, overrides: chat::Overrides) -> BackendFuture<()> {
+        let kind = content.content.as_ref().map(variant_name).unwrap_or_default();
+        let call = format!("send_message_as guild={} channel={} content={}", guild_id, channel_id, kind);
+        let send = self.inner.send_message_as(guild_id, channel_id, content, overrides);
+        self.logged(call, Box::pin(async move {
+            let result = send.await;
+            if result.is_ok() {
+                self.stats.record_sent();
+            }
+            result
+        }))
+    }
+
+    fn edit_message(&self, guild_id: u64, channel_id: u64, message_id: u64, text: String) -> BackendFuture<()> {
+        let call = format!("edit_message guild={} channel={} message={} text=<{} bytes>", guild_id, channel_id, message_id, text.len());
+        self.logged(call, self.inner.edit_message(guild_id, channel_id, message_id, text))
+    }
+
+    fn delete_message(&self, guild_id: u64, channel_id: u64, message_id: u64) -> BackendFuture<()> {
+        let call = format!("delete_message guild={} channel={} message={}", guild_id, channel_id, message_id);
+        self.logged(call, self.inner.delete_message(guild_id, channel_id, message_id))
+    }
+
+    fn get_messages(&self, guild_id: u64, channel_id: u64, before: Option<u64>, count: u32) -> BackendFuture<Vec<(u64, RawMessage)>> {
+        let call = format!("get_messages guild={} channel={} before={:?} count={}", guild_id, channel_id, before, count);
+        self.logged(call, self.inner.get_messages(guild_id, channel_id, before, count))
+    }
+
+    fn get_profile(&self, user_id: u64) -> BackendFuture<Option<Profile>> {
+        self.logged(format!("get_profile user={}", user_id), self.inner.get_profile(user_id))
+    }
+
+    fn set_status(&self, status: UserStatus) -> BackendFuture<()> {
+        self.logged(format!("set_status {:?}", status), self.inner.set_status(status))
+    }
+
+    fn set_status_text(&self, text: Option<String>) -> BackendFuture<bool> {
+        let call = format!("set_status_text text=<{} bytes>", text.as_ref().map_or(0, String::len));
+        self.logged(call, self.inner.set_status_text(text))
+    }
+
+    fn typing(&self, guild_id: u64, channel_id: u64) -> BackendFuture<()> {
+        self.logged(format!("typing guild={} channel={}", guild_id, channel_id), self.inner.typing(guild_id, channel_id))
+    }
+
+    fn guild_list(&self) -> BackendFuture<Vec<u64>> {
+        self.logged(String::from("guild_list"), self.inner.guild_list())
+    }
+
+    fn get_guild(&self, guild_id: u64) -> BackendFuture<Option<chat::Guild>> {
+        self.logged(format!("get_guild guild={}", guild_id), self.inner.get_guild(guild_id))
+    }
+
+    fn get_channels(&self, guild_id: u64) -> BackendFuture<Vec<(u64, chat::Channel)>> {
+        self.logged(format!("get_channels guild={}", guild_id), self.inner.get_channels(guild_id))
+    }
+
+    fn get_members(&self, guild_id: u64) -> BackendFuture<Vec<u64>> {
+        self.logged(format!("get_members guild={}", guild_id), self.inner.get_members(guild_id))
+    }
+
+    // Invites can be secret, so they aren't logged
+    fn join_guild(&self, invite: String) -> BackendFuture<u64> {
+        self.logged(String::from("join_guild invite=<redacted>"), self.inner.join_guild(invite))
+    }
+
+    fn preview_invite(&self, invite: String) -> BackendFuture<InvitePreview> {
+        self.logged(String::from("preview_invite invite=<redacted>"), self.inner.preview_invite(invite))
+    }
+
+    fn leave_guild(&self, guild_id: u64) -> BackendFuture<()> {
+        self.logged(format!("leave_guild guild={}", guild_id), self.inner.leave_guild(guild_id))
+    }
+
+    fn trigger_action(&self, guild_id: u64, channel_id: u64, message_id: u64, data: Vec<u8>) -> BackendFuture<()> {
+        let call = format!("trigger_action guild={} channel={} message={} data=<{} bytes>", guild_id, channel_id, message_id, data.len());
+        self.logged(call, self.inner.trigger_action(guild_id, channel_id, message_id, data))
+    }
+
+    fn has_permission(&self, guild_id: u64, channel_id: Option<u64>, node: String) -> BackendFuture<bool> {
+        let call = format!("has_permission guild={} channel={:?} node={}", guild_id, channel_id, node);
+        self.logged(call, self.inner.has_permission(guild_id, channel_id, node))
+    }
+
+    fn get_roles(&self, guild_id: u64) -> BackendFuture<Vec<(u64, chat::Role)>> {
+        self.logged(format!("get_roles guild={}", guild_id), self.inner.get_roles(guild_id))
+    }
+
+    fn add_role(&self, guild_id: u64, name: String, color: i32) -> BackendFuture<u64> {
+        self.logged(format!("add_role guild={} color={:06x}", guild_id, color), self.inner.add_role(guild_id, name, color))
+    }
+
+    fn modify_role(&self, guild_id: u64, role_id: u64, name: Option<String>, color: Option<i32>) -> BackendFuture<()> {
+        let call = format!("modify_role guild={} role={} rename={} color={:?}", guild_id, role_id, name.is_some(), color);
+        self.logged(call, self.inner.modify_role(guild_id, role_id, name, color))
+    }
+
+    fn move_role(&self, guild_id: u64, role_id: u64, next_to: u64, after: bool) -> BackendFuture<()> {
+        let call = format!("move_role guild={} role={} next_to={} after={}", guild_id, role_id, next_to, after);
+        self.logged(call, self.inner.move_role(guild_id, role_id, next_to, after))
+    }
+
+    fn delete_role(&self, guild_id: u64, role_id: u64) -> BackendFuture<()> {
+        self.logged(format!("delete_role guild={} role={}", guild_id, role_id), self.inner.delete_role(guild_id, role_id))
+    }
+
+    fn get_permissions(&self, guild_id: u64, channel_id: Option<u64>, role_id: u64) -> BackendFuture<Vec<(String, bool)>> {
+        let call = format!("get_permissions guild={} channel={:?} role={}", guild_id, channel_id, role_id);
+        self.logged(call, self.inner.get_permissions(guild_id, channel_id, role_id))
+    }
+
+    fn set_permissions(&self, guild_id: u64, channel_id: Option<u64>, role_id: u64, permissions: Vec<(String, bool)>) -> BackendFuture<()> {
+        let call = format!("set_permissions guild={} channel={:?} role={} nodes={}", guild_id, channel_id, role_id, permissions.len());
+        self.logged(call, self.inner.set_permissions(guild_id, channel_id, role_id, permissions))
+    }
+
+    fn capabilities(&self) -> BackendFuture<Capabilities> {
+        self.logged(String::from("capabilities"), self.inner.capabilities())
+    }
+
+    fn server_info(&self) -> BackendFuture<ServerInfo> {
+        self.logged(String::from("server_info"), self.inner.server_info())
+    }
+
+    fn report_message(&self, guild_id: u64, channel_id: u64, message_id: u64, reason: String) -> BackendFuture<bool> {
+        let call = format!("report_message guild={} channel={} message={}", guild_id, channel_id, message_id);
+        self.logged(call, self.inner.report_message(guild_id, channel_id, message_id, reason))
+    }
+
+    // Searches can say as much as messages, so only their length is logged
+    fn search_messages(&self, query: String) -> BackendFuture<Option<Vec<SearchHit>>> {
+        let call = format!("search_messages query=<{} bytes>", query.len());
+        self.logged(call, self.inner.search_messages(query))
+    }
+
+    fn read_receipts(&self, guild_id: u64, channel_id: u64, message_id: u64) -> BackendFuture<Option<Vec<ReadReceipt>>> {
+        let call = format!("read_receipts guild={} channel={} message={}", guild_id, channel_id, message_id);
+        self.logged(call, self.inner.read_receipts(guild_id, channel_id, message_id))
+    }
+
+    fn send_read_receipt(&self, guild_id: u64, channel_id: u64, message_id: u64) -> BackendFuture<()> {
+        let call = format!("send_read_receipt guild={} channel={} message={}", guild_id, channel_id, message_id);
+        self.logged(call, self.inner.send_read_receipt(guild_id, channel_id, message_id))
+    }
+
+    fn export_account(&self) -> BackendFuture<Option<String>> {
+        self.logged(String::from("export_account"), self.inner.export_account())
+    }
+
+    fn delete_account(&self) -> BackendFuture<bool> {
+        self.logged(String::from("delete_account"), self.inner.delete_account())
+    }
+
+    fn begin_account_flow(&self, setting: AccountSetting) -> BackendFuture<Option<AuthStep>> {
+        self.logged(format!("begin_account_flow {:?}", setting), self.inner.begin_account_flow(setting))
+    }
+
+    fn next_account_step(&self, response: AuthStepResponse) -> BackendFuture<Option<AuthStep>> {
+        self.logged(String::from("next_account_step"), self.inner.next_account_step(response))
+    }
+}
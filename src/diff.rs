@@ -0,0 +1,136 @@
+use std::sync::{Mutex, PoisonError};
+
+/// A change between two texts.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change<T> {
+    /// Text present in both.
+    Same(T),
+
+    /// Text only present in the old text.
+    Removed(T),
+
+    /// Text only present in the new text.
+    Added(T),
+}
+
+impl Change<&str> {
+    /// Copies the text of the change so it can be kept after the texts are gone.
+    fn into_owned(self) -> Change<String> {
+        match self {
+            Change::Same(v) => Change::Same(v.to_owned()),
+            Change::Removed(v) => Change::Removed(v.to_owned()),
+            Change::Added(v) => Change::Added(v.to_owned()),
+        }
+    }
+}
+
+/// Keeps the last word level diff, so drawing the same texts every frame doesn't compute it again.
+#[derive(Default)]
+pub struct DiffCache {
+    /// The old and new texts of the last diff, and the diff itself.
+    last: Mutex<Option<(String, String, Vec<Change<String>>)>>,
+}
+
+impl DiffCache {
+    /// Gets the word level diff between two texts, only computing it if either changed since the last call.
+    pub fn diff_words(&self, old: &str, new: &str) -> Vec<Change<String>> {
+        let mut last = self.last.lock().unwrap_or_else(PoisonError::into_inner);
+        match &*last {
+            Some((last_old, last_new, changes)) if last_old == old && last_new == new => changes.clone(),
+            _ => {
+                let changes: Vec<_> = diff_words(old, new).into_iter().map(Change::into_owned).collect();
+                *last = Some((old.to_owned(), new.to_owned(), changes.clone()));
+                changes
+            }
+        }
+    }
+}
+
+/// Splits text into words and the whitespace between them.
+fn tokenize(text: &str) -> Vec<&str> {
+    let mut result = vec![];
+    let mut start = 0;
+    let mut last_whitespace = None;
+
+    for (i, c) in text.char_indices() {
+        let whitespace = c.is_whitespace();
+        if last_whitespace.is_some() && last_whitespace != Some(whitespace) {
+            result.push(&text[start..i]);
+            start = i;
+        }
+        last_whitespace = Some(whitespace);
+    }
+
+    if start < text.len() {
+        result.push(&text[start..]);
+    }
+
+    result
+}
+
+/// Computes a word level diff between two texts using the longest common subsequence of words.
+pub fn diff_words<'a>(old: &'a str, new: &'a str) -> Vec<Change<&'a str>> {
+    let old = tokenize(old);
+    let new = tokenize(new);
+
+    // lcs[i][j] is the length of the longest common subsequence of old[i..] and new[j..]
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut result = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            result.push(Change::Same(old[i]));
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            result.push(Change::Removed(old[i]));
+            i += 1;
+        } else {
+            result.push(Change::Added(new[j]));
+            j += 1;
+        }
+    }
+
+    result.extend(old[i..].iter().map(|v| Change::Removed(v)));
+    result.extend(new[j..].iter().map(|v| Change::Added(v)));
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diffs_words() {
+        let changes = diff_words("the quick fox", "the slow fox");
+        assert_eq!(
+            changes,
+            vec![
+                Change::Same("the"),
+                Change::Same(" "),
+                Change::Removed("quick"),
+                Change::Added("slow"),
+                Change::Same(" "),
+                Change::Same("fox"),
+            ]
+        );
+    }
+
+    #[test]
+    fn cache_follows_the_texts() {
+        let cache = DiffCache::default();
+        assert_eq!(cache.diff_words("a", "a b"), vec![Change::Same(String::from("a")), Change::Added(String::from(" ")), Change::Added(String::from("b"))]);
+        assert_eq!(cache.diff_words("a", "a b"), vec![Change::Same(String::from("a")), Change::Added(String::from(" ")), Change::Added(String::from("b"))]);
+        assert_eq!(cache.diff_words("a", "b"), vec![Change::Removed(String::from("a")), Change::Added(String::from("b"))]);
+    }
+}
@@ -0,0 +1,44 @@
+use std::time::Duration;
+
+/// The longest duration accepted, in seconds, so adding one to the current time can't overflow.
+pub const MAX_SECS: u64 = 100 * 365 * 24 * 60 * 60;
+
+/// Parses a human readable duration such as `90`, `30s`, `15m`, `2h`, `1d`, or `1h30m`.
+/// A plain number is treated as minutes. Durations longer than [`MAX_SECS`] are rejected.
+pub fn parse_duration(text: &str) -> Option<Duration> {
+    let text = text.trim();
+    if text.is_empty() {
+        return None;
+    }
+
+    // Plain numbers are minutes
+    if let Ok(minutes) = text.parse::<u64>() {
+        return minutes.checked_mul(60).filter(|&v| v <= MAX_SECS).map(Duration::from_secs);
+    }
+
+    let mut total = 0;
+    let mut number = String::new();
+    for c in text.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+        } else {
+            let value: u64 = number.parse().ok()?;
+            number.clear();
+            let unit = match c {
+                's' => 1,
+                'm' => 60,
+                'h' => 60 * 60,
+                'd' => 60 * 60 * 24,
+                'w' => 60 * 60 * 24 * 7,
+                _ => return None,
+            };
+            total = value.checked_mul(unit).and_then(|v| v.checked_add(total)).filter(|&v| v <= MAX_SECS)?;
+        }
+    }
+
+    if number.is_empty() {
+        Some(Duration::from_secs(total))
+    } else {
+        None
+    }
+}
@@ -0,0 +1,80 @@
+use crate::AppState;
+
+/// A change to the input box made from the keyboard, kept so `.` can make it again.
+#[derive(Clone, Debug)]
+pub enum Edit {
+    /// Text was typed or pasted at the cursor. Everything typed in one go in insert mode is a single edit.
+    Insert(String),
+
+    /// The grapheme cluster before the cursor was deleted.
+    Backspace,
+
+    /// The grapheme cluster under the cursor was deleted.
+    Delete,
+
+    /// Everything from the cursor to the end was deleted.
+    DeleteToEnd,
+
+    /// Everything before the cursor was deleted.
+    DeleteToStart,
+
+    /// The word before the cursor was deleted.
+    DeleteWord,
+
+    /// A register was pasted after the cursor, or at it if `after` is false.
+    Paste { register: Option<char>, after: bool },
+}
+
+/// Makes a change to the input box and remembers it as the last change.
+/// This is the only place keys change the draft, so `.` makes exactly the same change again.
+pub fn apply(state: &mut AppState, edit: Edit) {
+    match &edit {
+        Edit::Insert(text) => state.input.insert(text),
+
+        Edit::Backspace => {
+            state.input.backspace();
+        }
+
+        // Deleted text goes to a register
+        Edit::Delete => {
+            let deleted = state.input.delete().unwrap_or_default();
+            state.yank(deleted);
+        }
+        Edit::DeleteToEnd => {
+            let deleted = state.input.delete_to_end();
+            state.yank(deleted);
+        }
+        Edit::DeleteToStart => {
+            let deleted = state.input.delete_to_start();
+            state.yank(deleted);
+        }
+        Edit::DeleteWord => {
+            let deleted = state.input.delete_word();
+            state.yank(deleted);
+        }
+
+        Edit::Paste { register, after } => {
+            if *after {
+                state.input.move_right();
+            }
+            state.paste(*register);
+        }
+    }
+
+    // Text typed since entering insert mode joins into one change
+    match (&mut state.last_edit, edit) {
+        (Some(Edit::Insert(last)), Edit::Insert(text)) if state.edit_joins => last.push_str(&text),
+        (last, edit) => {
+            state.edit_joins = matches!(edit, Edit::Insert(_));
+            *last = Some(edit);
+        }
+    }
+}
+
+/// Makes the last change again at the cursor, if there was one.
+pub fn repeat(state: &mut AppState) {
+    state.edit_joins = false;
+    if let Some(edit) = state.last_edit.clone() {
+        apply(state, edit);
+    }
+}
@@ -0,0 +1,168 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use tui::text::Text;
+
+use crate::{
+    message_author, message_snippet,
+    modal::{ListKind, Modal, ModalKind},
+    search, vault, AppState, Guild, Message,
+};
+
+/// A saved filter, shown in the sidebar like a channel that collects the loaded messages matching it from every guild.
+#[derive(Deserialize, Serialize)]
+pub struct SavedFilter {
+    /// The name of the filter.
+    pub name: String,
+
+    /// Only messages from the user with this name or id match, if set.
+    pub from: Option<String>,
+
+    /// Only messages in the guild with this name match, if set.
+    pub guild: Option<String>,
+
+    /// Only messages containing this text, ignoring case, match, if set.
+    pub contains: Option<String>,
+
+    /// How many matching messages arrived since the filter was last opened.
+    #[serde(skip)]
+    pub unseen: usize,
+}
+
+impl SavedFilter {
+    /// Parses a filter from its criteria: `from:<user>` and `in:<guild>` words, with the rest of the words being text
+    /// the message has to contain.
+    pub fn parse(name: &str, criteria: &str) -> Result<SavedFilter, String> {
+        let mut filter = SavedFilter {
+            name: name.to_owned(),
+            from: None,
+            guild: None,
+            contains: None,
+            unseen: 0,
+        };
+
+        let mut text = vec![];
+        for word in criteria.split_whitespace() {
+            if let Some(user) = word.strip_prefix("from:") {
+                filter.from = Some(user.to_owned());
+            } else if let Some(guild) = word.strip_prefix("in:") {
+                filter.guild = Some(guild.to_owned());
+            } else {
+                text.push(word);
+            }
+        }
+        if !text.is_empty() {
+            filter.contains = Some(text.join(" "));
+        }
+
+        if filter.from.is_none() && filter.guild.is_none() && filter.contains.is_none() {
+            Err(String::from("a filter needs from:<user>, in:<guild>, or some text to match"))
+        } else {
+            Ok(filter)
+        }
+    }
+
+    /// Describes the criteria of the filter the way they're typed.
+    pub fn criteria(&self) -> String {
+        let mut words = vec![];
+        if let Some(from) = &self.from {
+            words.push(format!("from:{}", from));
+        }
+        if let Some(guild) = &self.guild {
+            words.push(format!("in:{}", guild));
+        }
+        if let Some(contains) = &self.contains {
+            words.push(contains.clone());
+        }
+        words.join(" ")
+    }
+
+    /// Checks whether a message in a guild matches the filter.
+    fn matches(&self, state: &AppState, guild: &Guild, message: &Message) -> bool {
+        let from = self.from.as_ref().map(|from| {
+            from.parse::<u64>().ok() == Some(message.author_id) || message_author(state, message).eq_ignore_ascii_case(from)
+        });
+        let guild = self.guild.as_ref().map(|name| guild.name.eq_ignore_ascii_case(name));
+        let contains = self
+            .contains
+            .as_ref()
+            .map(|text| message_snippet(message, usize::MAX).to_lowercase().contains(&text.to_lowercase()));
+        from.unwrap_or(true) && guild.unwrap_or(true) && contains.unwrap_or(true)
+    }
+}
+
+/// Gets the path of the given user's saved filters.
+fn path(user_id: u64) -> Option<PathBuf> {
+    dirs::data_dir().map(|v| v.join(format!("ilo-toki/filters-{}.json", user_id)))
+}
+
+/// Loads the given user's saved filters from the data directory.
+pub fn load(user_id: u64) -> Vec<SavedFilter> {
+    path(user_id)
+        .and_then(|v| vault::read_to_string(&v).ok())
+        .and_then(|v| serde_json::from_str(&v).ok())
+        .unwrap_or_default()
+}
+
+/// Saves the given user's saved filters to the data directory.
+pub fn save(user_id: u64, filters: &[SavedFilter]) {
+    if let (Some(data_dir), Some(path)) = (dirs::data_dir(), path(user_id)) {
+        if let Ok(json) = serde_json::to_string(filters) {
+            std::fs::create_dir(data_dir.join("ilo-toki/")).ok();
+            vault::write(&path, &json).ok();
+        }
+    }
+}
+
+/// Creates the popup showing the loaded messages matching a filter, grouped by guild and channel.
+pub fn view(state: &AppState, filter: &SavedFilter) -> Modal {
+    let mut hits = search::find_loaded(state, |guild, message| filter.matches(state, guild, message));
+    let items = search::grouped_items(state, &mut hits);
+    let title = format!("filter: {} ({} messages)", filter.name, hits.len());
+    Modal::list(title, Text::default(), items, ListKind::Filter(filter.name.clone(), hits))
+}
+
+/// Counts a message that just arrived against every filter it matches. A filter being viewed is shown again with the
+/// message in it instead, keeping its selection.
+pub fn message_received(state: &mut AppState, guild_id: u64, channel_id: u64, message_id: u64) {
+    let matching: Vec<_> = {
+        let guild = match state.guilds_map.get(&guild_id) {
+            Some(guild) => guild,
+            None => return,
+        };
+        let message = match guild.channels_map.get(&channel_id).and_then(|v| v.messages_map.get(&message_id)) {
+            Some(message) => message,
+            None => return,
+        };
+        state
+            .filters
+            .iter()
+            .enumerate()
+            .filter(|(_, filter)| filter.matches(state, guild, message))
+            .map(|(i, _)| i)
+            .collect()
+    };
+
+    let viewing = match state.modals.last().map(|v| &v.kind) {
+        Some(ModalKind::List {
+            list: ListKind::Filter(name, _),
+            selected,
+            ..
+        }) => Some((name.clone(), *selected)),
+        _ => None,
+    };
+
+    for i in matching {
+        match &viewing {
+            Some((name, selected)) if *name == state.filters[i].name => {
+                let mut popup = view(state, &state.filters[i]);
+                if let ModalKind::List { selected: new, .. } = &mut popup.kind {
+                    *new = *selected;
+                }
+                state.modals.pop();
+                state.modals.push(popup);
+            }
+            _ => state.filters[i].unseen += 1,
+        }
+    }
+}
@@ -0,0 +1,243 @@
+use std::{future::Future, pin::Pin};
+
+use tui::{style::Style, text::Span};
+
+/// The result of fuzzy matching a pattern against some text.
+pub struct Match {
+    /// How well the pattern matched. Higher is better.
+    pub score: i64,
+
+    /// The indices of the characters in the text that matched the pattern.
+    pub indices: Vec<usize>,
+}
+
+/// The most a gap between matched characters costs.
+const MAX_GAP_PENALTY: usize = 5;
+
+/// Fuzzy matches a pattern against some text, ignoring case.
+/// Every character of the pattern has to appear in order in the text. Matches at the start of a word and
+/// consecutive matches score higher, and gaps between matched characters score lower. Of all the ways the pattern
+/// fits in the text, the one scoring highest is used, preferring the earliest on a tie.
+pub fn score(pattern: &str, text: &str) -> Option<Match> {
+    let pattern: Vec<char> = pattern.chars().flat_map(char::to_lowercase).collect();
+    if pattern.is_empty() {
+        return Some(Match { score: 0, indices: vec![] });
+    }
+
+    let mut previous: Option<char> = None;
+    let text: Vec<(Option<char>, i64)> = text
+        .chars()
+        .map(|c| {
+            let word_start = previous.map(|v| !v.is_alphanumeric()).unwrap_or(true);
+            previous = Some(c);
+            (c.to_lowercase().next(), if word_start { 9 } else { 1 })
+        })
+        .collect();
+
+    // The best score for each prefix of the pattern ending on each character of the text, and where the character
+    // before it matched
+    let mut best: Vec<Option<i64>> = text
+        .iter()
+        .enumerate()
+        .map(|(i, &(c, bonus))| pattern.first().filter(|&&p| c == Some(p)).map(|_| bonus - i.min(10) as i64))
+        .collect();
+    let mut from: Vec<Vec<usize>> = vec![];
+
+    for &p in pattern.iter().skip(1) {
+        let mut next = vec![None; text.len()];
+        let mut links = vec![0; text.len()];
+
+        // Gaps past the longest penalty all cost the same, so only the best of those is kept
+        let mut far: Option<(i64, usize)> = None;
+        for (i, &(c, bonus)) in text.iter().enumerate() {
+            if let Some(k) = i.checked_sub(MAX_GAP_PENALTY + 1) {
+                if let Some(score) = best[k].filter(|&v| far.map(|f| v > f.0).unwrap_or(true)) {
+                    far = Some((score, k));
+                }
+            }
+            if c != Some(p) {
+                continue;
+            }
+
+            let mut chosen = far.map(|(score, k)| (score - MAX_GAP_PENALTY as i64, k));
+            for k in i.saturating_sub(MAX_GAP_PENALTY)..i {
+                if let Some(score) = best[k] {
+                    let score = score + if k + 1 == i { 5 } else { -((i - k - 1) as i64) };
+                    if chosen.map(|v| score > v.0).unwrap_or(true) {
+                        chosen = Some((score, k));
+                    }
+                }
+            }
+
+            if let Some((score, k)) = chosen {
+                next[i] = Some(score + bonus);
+                links[i] = k;
+            }
+        }
+
+        best = next;
+        from.push(links);
+    }
+
+    // Follow the best alignment back from its last character
+    let (mut i, score) = best
+        .iter()
+        .enumerate()
+        .filter_map(|(i, v)| v.map(|v| (i, v)))
+        .fold(None, |top: Option<(usize, i64)>, v| if top.map(|t| v.1 > t.1).unwrap_or(true) { Some(v) } else { top })?;
+    let mut indices = vec![i];
+    for links in from.iter().rev() {
+        i = links[i];
+        indices.push(i);
+    }
+    indices.reverse();
+
+    Some(Match { score, indices })
+}
+
+/// Checks whether all characters of the pattern appear in order in the text, ignoring case.
+pub fn matches(pattern: &str, text: &str) -> bool {
+    score(pattern, text).is_some()
+}
+
+/// Splits text into spans, styling the characters at the given indices.
+pub fn highlight<'a>(text: &'a str, indices: &[usize], style: Style) -> Vec<Span<'a>> {
+    let mut spans = vec![];
+    let mut start = 0;
+    let mut highlighted = false;
+
+    for (i, (byte, _)) in text.char_indices().enumerate() {
+        let matched = indices.contains(&i);
+        if matched != highlighted {
+            if start != byte {
+                spans.push(if highlighted { Span::styled(&text[start..byte], style) } else { Span::raw(&text[start..byte]) });
+            }
+            start = byte;
+            highlighted = matched;
+        }
+    }
+
+    if start != text.len() {
+        spans.push(if highlighted { Span::styled(&text[start..], style) } else { Span::raw(&text[start..]) });
+    }
+
+    spans
+}
+
+/// A future resolving to the candidates of a source.
+pub type Candidates = Pin<Box<dyn Future<Output = Vec<String>> + Send>>;
+
+/// Something that provides candidates to fuzzy match against, such as members, commands, or emoji.
+pub trait CandidateSource: Send + Sync {
+    /// Gets the candidates for the given query. Sources backed by the server can use the query to narrow down their request.
+    fn candidates(&self, query: &str) -> Candidates;
+}
+
+/// A fixed list of candidates.
+impl CandidateSource for Vec<String> {
+    fn candidates(&self, _query: &str) -> Candidates {
+        let candidates = self.clone();
+        Box::pin(async move { candidates })
+    }
+}
+
+/// Fuzzy matches queries against the candidates of several sources.
+#[derive(Default)]
+pub struct Matcher {
+    /// Where candidates come from.
+    sources: Vec<Box<dyn CandidateSource>>,
+}
+
+impl Matcher {
+    /// Adds a source of candidates.
+    pub fn source(mut self, source: impl CandidateSource + 'static) -> Matcher {
+        self.sources.push(Box::new(source));
+        self
+    }
+
+    /// Gets the candidates matching the query, best match first.
+    pub async fn search(&self, query: &str) -> Vec<(String, Match)> {
+        let mut results = vec![];
+        for source in self.sources.iter() {
+            for candidate in source.candidates(query).await {
+                if let Some(matched) = score(query, &candidate) {
+                    results.push((candidate, matched));
+                }
+            }
+        }
+
+        results.sort_by(|a, b| b.1.score.cmp(&a.1.score).then(a.0.len().cmp(&b.0.len())).then(a.0.cmp(&b.0)));
+        results.dedup_by(|a, b| a.0 == b.0);
+        results
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tui::style::Modifier;
+
+    use super::*;
+
+    /// Gets the text and whether it's highlighted for each span.
+    fn spans(text: &str, indices: &[usize]) -> Vec<(String, bool)> {
+        let style = Style::default().add_modifier(Modifier::BOLD);
+        highlight(text, indices, style).into_iter().map(|v| (v.content.into_owned(), v.style == style)).collect()
+    }
+
+    #[test]
+    fn better_matches_score_higher() {
+        let general = score("gen", "general").unwrap().score;
+        let engine = score("gen", "big-engine").unwrap().score;
+        let scattered = score("gen", "gaze on").unwrap().score;
+        assert!(general > engine);
+        assert!(general > scattered);
+        assert!(score("gen", "nope").is_none());
+        assert!(score("ba", "ab").is_none());
+    }
+
+    #[test]
+    fn finds_the_best_alignment() {
+        // Taking the first t would leave a gap before the o
+        let matched = score("to", "tab topic").unwrap();
+        assert_eq!(matched.indices, vec![4, 5]);
+        assert_eq!(matched.score, 11);
+
+        let matched = score("ilo", "lilo ilo").unwrap();
+        assert_eq!(matched.indices, vec![5, 6, 7]);
+    }
+
+    #[test]
+    fn ignores_case() {
+        let lower = score("gen", "GENERAL").unwrap();
+        let upper = score("GEN", "general").unwrap();
+        assert_eq!(lower.indices, vec![0, 1, 2]);
+        assert_eq!(upper.indices, lower.indices);
+        assert_eq!(upper.score, lower.score);
+    }
+
+    #[test]
+    fn empty_pattern_matches_everything() {
+        let matched = score("", "general").unwrap();
+        assert_eq!(matched.score, 0);
+        assert!(matched.indices.is_empty());
+    }
+
+    #[test]
+    fn highlights_matched_characters() {
+        let matched = score("to", "tab topic").unwrap();
+        assert_eq!(
+            spans("tab topic", &matched.indices),
+            vec![(String::from("tab "), false), (String::from("to"), true), (String::from("pic"), false)]
+        );
+        assert_eq!(spans("ab", &[0, 1]), vec![(String::from("ab"), true)]);
+        assert_eq!(spans("ab", &[]), vec![(String::from("ab"), false)]);
+    }
+
+    #[test]
+    fn highlights_by_character_not_byte() {
+        assert_eq!(
+            spans("ñandú", &[0, 4]),
+            vec![(String::from("ñ"), true), (String::from("and"), false), (String::from("ú"), true)]
+        );
+    }
+}
@@ -0,0 +1,76 @@
+use std::sync::Arc;
+
+use tokio::{
+    sync::{mpsc, RwLock},
+    time::Duration,
+};
+use tokio_util::sync::CancellationToken;
+use tui::text::{Span, Spans};
+
+use crate::{AppState, ClientEvent};
+
+/// The permission nodes summarized in the header, with what they let the user do.
+pub const NODES: &[(&str, &str)] = &[
+    ("messages.send", "send"),
+    ("messages.manage.delete", "delete messages"),
+    ("channels.manage.change-information", "manage channels"),
+    ("roles.manage", "manage roles"),
+    ("invites.manage.create", "invite"),
+];
+
+/// How often the header checks whether a different channel was opened.
+const CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Background task that fetches the member count and permissions of each channel opened while the header is shown.
+/// Channels are opened from many places, so this watches for them instead of each place asking.
+pub async fn header_task(state: Arc<RwLock<AppState>>, tx: mpsc::Sender<ClientEvent>, shutdown: CancellationToken) {
+    let mut fetched = None;
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(CHECK_INTERVAL) => (),
+            _ = shutdown.cancelled() => break,
+        }
+
+        let current = {
+            let state = state.read().await;
+            state
+                .current_channel()
+                .filter(|_| state.config.messages.header)
+                .map(|v| (v.guild_id, v.id))
+        };
+
+        if current.is_some() && current != fetched {
+            fetched = current;
+            if let Some((guild_id, channel_id)) = current {
+                let _ = tx.send(ClientEvent::GetChannelInfo(guild_id, channel_id)).await;
+            }
+        }
+    }
+}
+
+/// Gets the line shown in the header for the current channel.
+pub fn line(state: &AppState) -> Spans<'static> {
+    let (guild, channel) = match (state.current_guild(), state.current_channel()) {
+        (Some(guild), Some(channel)) => (guild, channel),
+        _ => return Spans::default(),
+    };
+
+    let mut spans = vec![Span::styled(format!("{} › #{}", guild.name, channel.name), state.theme.header)];
+    if let Some(count) = guild.member_count {
+        spans.push(Span::raw(format!(" · {} member{}", count, if count == 1 { "" } else { "s" })));
+    }
+
+    // Only what the user is allowed to do is listed, once it's known
+    let allowed: Vec<_> = NODES
+        .iter()
+        .filter(|(node, _)| guild.permissions.get(&(channel.id, *node)).copied().unwrap_or(false))
+        .map(|(_, name)| *name)
+        .collect();
+    if !allowed.is_empty() {
+        spans.push(Span::styled(format!(" · you can {}", allowed.join(", ")), state.theme.translation));
+    } else if NODES.iter().all(|(node, _)| guild.permissions.contains_key(&(channel.id, *node))) {
+        spans.push(Span::styled(" · read only", state.theme.translation));
+    }
+
+    Spans::from(spans)
+}
@@ -0,0 +1,100 @@
+use std::{
+    sync::Arc,
+    time::Instant,
+};
+
+use tokio::{sync::RwLock, time::Duration};
+use tokio_util::sync::CancellationToken;
+use tui::{style::Style, text::Span};
+
+use crate::{backend::ChatBackend, theme::Theme, AppState};
+
+/// How often the homeserver is pinged.
+const PING_INTERVAL: Duration = Duration::from_secs(15);
+
+/// How long a ping can take before the homeserver counts as unreachable.
+const PING_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Latency above which the connection counts as slow.
+const SLOW_LATENCY: Duration = Duration::from_millis(500);
+
+/// How many times the usual latency a ping has to take to count as a spike.
+const SPIKE_FACTOR: u32 = 3;
+
+/// The health of the connection to the homeserver.
+#[derive(Default)]
+pub struct Health {
+    /// The round trip time of the last ping, or `None` if it failed or there hasn't been one yet.
+    pub latency: Option<Duration>,
+
+    /// A moving average of the latency, used to detect spikes.
+    average: Option<Duration>,
+
+    /// Whether the last ping failed.
+    pub unreachable: bool,
+
+    /// The number of event streams that stopped unexpectedly.
+    pub stalled_streams: usize,
+}
+
+impl Health {
+    /// Gets the connection indicator for the status bar.
+    pub fn indicator(&self, theme: &Theme) -> Span<'static> {
+        let (text, style): (_, Style) = if self.unreachable {
+            (String::from("● offline"), theme.connection_down)
+        } else if self.stalled_streams > 0 {
+            (String::from("● stalled"), theme.connection_down)
+        } else {
+            match self.latency {
+                Some(latency) if latency > SLOW_LATENCY => (format!("● {}ms", latency.as_millis()), theme.connection_slow),
+                Some(latency) => (format!("● {}ms", latency.as_millis()), theme.connection_good),
+                None => (String::from("● …"), Style::default()),
+            }
+        };
+
+        Span::styled(text, style)
+    }
+}
+
+/// Background task that pings the homeserver and records the connection health, warning in the status bar
+/// when the homeserver becomes unreachable or latency spikes.
+pub async fn health_task(state: Arc<RwLock<AppState>>, backend: Arc<dyn ChatBackend>, user_id: u64, shutdown: CancellationToken) {
+    while !shutdown.is_cancelled() {
+        let start = Instant::now();
+        let result = tokio::time::timeout(PING_TIMEOUT, backend.get_profile(user_id)).await;
+        let latency = start.elapsed();
+
+        {
+            let mut state = state.write().await;
+            let state = &mut *state;
+            let health = &mut state.health;
+            match result {
+                Ok(Ok(_)) => {
+                    if health.unreachable {
+                        state.status = Some(String::from("reconnected to homeserver"));
+                    } else if matches!(health.average, Some(average) if latency > average * SPIKE_FACTOR && latency > SLOW_LATENCY) {
+                        state.status = Some(format!("warning: latency spiked to {}ms", latency.as_millis()));
+                    }
+
+                    health.unreachable = false;
+                    health.latency = Some(latency);
+                    health.average = Some(match health.average {
+                        Some(average) => (average * 3 + latency) / 4,
+                        None => latency,
+                    });
+                }
+
+                _ => {
+                    if !health.unreachable {
+                        state.status = Some(String::from("warning: homeserver is unreachable"));
+                    }
+
+                    health.unreachable = true;
+                    health.latency = None;
+                }
+            }
+        }
+
+        tokio::time::sleep(PING_INTERVAL).await;
+    }
+}
@@ -0,0 +1,184 @@
+use crate::{config::NotifyLevel, message_author, message_snippet, AppState, SMALL_GUILD_SIZE};
+
+/// The most entries kept in the inbox, dropping the oldest first.
+const INBOX_SIZE: usize = 500;
+
+/// Why something is in the inbox.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InboxKind {
+    /// A message that mentions the user or has one of their highlight words.
+    Mention,
+
+    /// A message in a guild small enough to stand in for direct messages.
+    DirectMessage,
+
+    /// An invite to a guild, with the invite id used to join it.
+    Invite(String),
+}
+
+/// Something that happened while the user might not have been looking.
+#[derive(Clone, Debug)]
+pub struct InboxEntry {
+    /// The id of the entry, which is unique for the session.
+    pub id: u64,
+
+    /// Why it's in the inbox.
+    pub kind: InboxKind,
+
+    /// The guild the message was sent in, or zero for invites.
+    pub guild_id: u64,
+
+    /// The channel the message was sent in, or zero for invites.
+    pub channel_id: u64,
+
+    /// The message, or zero for invites.
+    pub message_id: u64,
+
+    /// The name of whoever sent the message or invite.
+    pub author: String,
+
+    /// The start of the message, or where the invite is from.
+    pub snippet: String,
+
+    /// The unix timestamp it arrived at.
+    pub timestamp: u64,
+
+    /// Whether the user has seen it.
+    pub read: bool,
+}
+
+/// Mentions, direct messages, and invites from every guild, newest last, shown by `:inbox`.
+#[derive(Default)]
+pub struct Inbox {
+    /// The entries, oldest first.
+    entries: Vec<InboxEntry>,
+
+    /// The id the next entry gets.
+    next_id: u64,
+}
+
+impl Inbox {
+    /// Adds an entry, dropping the oldest one if the inbox is full.
+    fn push(&mut self, mut entry: InboxEntry) {
+        entry.id = self.next_id;
+        self.next_id += 1;
+        if self.entries.len() >= INBOX_SIZE {
+            self.entries.remove(0);
+        }
+        self.entries.push(entry);
+    }
+
+    /// Gets the entries, newest first.
+    pub fn newest_first(&self) -> impl Iterator<Item = &InboxEntry> {
+        self.entries.iter().rev()
+    }
+
+    /// Gets an entry by its id.
+    pub fn get(&self, id: u64) -> Option<&InboxEntry> {
+        self.entries.iter().find(|v| v.id == id)
+    }
+
+    /// Counts the entries the user hasn't seen.
+    pub fn unread(&self) -> usize {
+        self.entries.iter().filter(|v| !v.read).count()
+    }
+
+    /// Marks an entry as seen.
+    pub fn mark_read(&mut self, id: u64) {
+        if let Some(entry) = self.entries.iter_mut().find(|v| v.id == id) {
+            entry.read = true;
+        }
+    }
+
+    /// Marks every entry as seen.
+    pub fn mark_all_read(&mut self) {
+        for entry in self.entries.iter_mut() {
+            entry.read = true;
+        }
+    }
+
+    /// Marks the messages in a channel as seen, once the user opens it.
+    pub fn mark_channel_read(&mut self, guild_id: u64, channel_id: u64) {
+        for entry in self.entries.iter_mut().filter(|v| v.guild_id == guild_id && v.channel_id == channel_id) {
+            entry.read = true;
+        }
+    }
+
+    /// Removes an entry.
+    pub fn remove(&mut self, id: u64) {
+        self.entries.retain(|v| v.id != id);
+    }
+
+    /// Removes the entries from a guild, when its local data is purged.
+    pub fn remove_guild(&mut self, guild_id: u64) {
+        self.entries.retain(|v| v.guild_id != guild_id);
+    }
+
+    /// Removes every entry.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+/// Adds a message that just arrived to the inbox if it mentions the user or is in a small guild. Messages from the
+/// user and in channels that never notify are left out, and ones in the channel being viewed arrive already read.
+pub fn message_received(state: &mut AppState, guild_id: u64, channel_id: u64, message_id: u64) {
+    if state.notify_level(guild_id, channel_id) == NotifyLevel::Nothing {
+        return;
+    }
+
+    let guild = match state.guilds_map.get(&guild_id) {
+        Some(guild) => guild,
+        None => return,
+    };
+    let message = match guild.channels_map.get(&channel_id).and_then(|v| v.messages_map.get(&message_id)) {
+        Some(message) if message.author_id != state.current_user => message,
+        _ => return,
+    };
+
+    let kind = if message.highlighted {
+        InboxKind::Mention
+    } else if guild.member_count.map(|v| v <= SMALL_GUILD_SIZE).unwrap_or(false) {
+        InboxKind::DirectMessage
+    } else {
+        return;
+    };
+
+    let viewing = state.current_guild == Some(guild_id) && guild.current_channel == Some(channel_id);
+    let entry = InboxEntry {
+        id: 0,
+        kind,
+        guild_id,
+        channel_id,
+        message_id,
+        author: message_author(state, message),
+        snippet: message_snippet(message, 80),
+        timestamp: message.timestamp,
+        read: viewing,
+    };
+    state.inbox.push(entry);
+}
+
+/// Adds an invite the user received to the inbox.
+pub fn invite_received(state: &mut AppState, invite_id: String, server_id: Option<String>, inviter_id: u64, timestamp: u64) {
+    let author = state
+        .users
+        .get(&inviter_id)
+        .map(|v| v.name.clone())
+        .unwrap_or_else(|| String::from("<unknown user>"));
+    let entry = InboxEntry {
+        id: 0,
+        snippet: match &server_id {
+            Some(server) => format!("invite {} on {}", invite_id, server),
+            None => format!("invite {}", invite_id),
+        },
+        kind: InboxKind::Invite(invite_id),
+        guild_id: 0,
+        channel_id: 0,
+        message_id: 0,
+        author,
+        timestamp,
+        read: false,
+    };
+    state.inbox.push(entry);
+}
@@ -0,0 +1,137 @@
+use crossterm::event::{Event, EventStream, KeyCode, KeyEvent, KeyModifiers};
+use futures_util::StreamExt;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+/// The most input events buffered while the UI is busy, so held keys pile up instead of being dropped.
+pub const INPUT_BUFFER: usize = 256;
+
+/// Which terminal an event came from, so a daemon can tell the terminals attached to it apart.
+pub type Source = u64;
+
+/// The source of events from the terminal this process runs in.
+pub const LOCAL: Source = 0;
+
+/// Starts reading input from the terminal in the background, returning the events as they arrive.
+/// There is only one reader for the whole program, so no key is read by a handler that has already stopped.
+pub fn spawn(shutdown: CancellationToken) -> mpsc::Receiver<Event> {
+    let (tx, rx) = mpsc::channel(INPUT_BUFFER);
+    tokio::spawn(input_task(tx, shutdown));
+    rx
+}
+
+/// Marks events from the terminal this process runs in as coming from it, for the UI's event loop.
+pub fn from_local(mut events: mpsc::Receiver<Event>) -> mpsc::Receiver<(Source, Event)> {
+    let (tx, rx) = mpsc::channel(INPUT_BUFFER);
+    tokio::spawn(async move {
+        while let Some(event) = events.recv().await {
+            if tx.send((LOCAL, event)).await.is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+/// Reads events from the terminal until the program stops or nothing is listening anymore.
+async fn input_task(tx: mpsc::Sender<Event>, shutdown: CancellationToken) {
+    let mut events = EventStream::new();
+    loop {
+        let event = tokio::select! {
+            event = events.next() => event,
+            _ = shutdown.cancelled() => break,
+        };
+
+        let event = match event {
+            Some(Ok(Event::Key(key))) => Event::Key(normalize(key)),
+            Some(Ok(event)) => event,
+            Some(Err(_)) | None => break,
+        };
+
+        if tx.send(event).await.is_err() {
+            break;
+        }
+    }
+}
+
+/// Normalizes a key press so a key combination compares the same however the terminal reports it.
+/// Held keys arrive as repeated presses, which are kept in order by the buffer like any other key.
+fn normalize(mut key: KeyEvent) -> KeyEvent {
+    match key.code {
+        // The character already says whether shift was held, and some terminals send ctrl+shift+letter as uppercase
+        KeyCode::Char(c) => {
+            key.modifiers.remove(KeyModifiers::SHIFT);
+            if key.modifiers.contains(KeyModifiers::CONTROL) {
+                key.code = KeyCode::Char(c.to_ascii_lowercase());
+            }
+        }
+
+        // Some terminals send shift+tab as tab with shift held
+        KeyCode::Tab if key.modifiers.contains(KeyModifiers::SHIFT) => {
+            key.code = KeyCode::BackTab;
+            key.modifiers.remove(KeyModifiers::SHIFT);
+        }
+
+        KeyCode::BackTab => key.modifiers.remove(KeyModifiers::SHIFT),
+
+        _ => (),
+    }
+
+    key
+}
+
+/// Checks whether a key press types text rather than being a shortcut.
+/// Ctrl+alt counts as text since that's how some terminals send characters typed with AltGr.
+pub fn is_text(key: &KeyEvent) -> bool {
+    let shortcut = KeyModifiers::CONTROL | KeyModifiers::ALT;
+    let held = key.modifiers & shortcut;
+    held.is_empty() || held == shortcut
+}
+
+/// Takes the text keys already waiting behind a typed character, which is how pasted text arrives without bracketed
+/// paste, so it can be inserted all at once. Crossterm 0.20 can't report the terminal's paste markers, so this is the
+/// only way to tell a paste from typing.
+///
+/// A pasted line break arrives as enter. If `multiline` is set, an enter with more text queued behind it is taken as
+/// a line break in the text, so pasting several lines doesn't send each one as its own message. An enter at the end
+/// of the burst is still a key press.
+///
+/// Keys from another terminal attached to the daemon end the burst, since they can't be part of the same paste.
+///
+/// Returns the text and the events after it, which are handled next in order.
+pub fn take_burst(
+    first: char,
+    source: Source,
+    events: &mut mpsc::Receiver<(Source, Event)>,
+    multiline: bool,
+) -> (String, Vec<(Source, Event)>) {
+    let mut text = String::from(first);
+    let mut queued = vec![];
+    while let Ok((from, event)) = events.try_recv() {
+        if from != source {
+            queued.push((from, event));
+            return (text, queued);
+        }
+
+        match event {
+            Event::Key(key) if multiline && key.code == KeyCode::Enter && key.modifiers.is_empty() => queued.push((from, event)),
+            Event::Key(key) => match key.code {
+                KeyCode::Char(c) if is_text(&key) => {
+                    for _ in queued.drain(..) {
+                        text.push('\n');
+                    }
+                    text.push(c);
+                }
+                _ => {
+                    queued.push((from, event));
+                    return (text, queued);
+                }
+            },
+            _ => {
+                queued.push((from, event));
+                return (text, queued);
+            }
+        }
+    }
+    (text, queued)
+}
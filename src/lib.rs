@@ -0,0 +1,7474 @@
+use std::{
+    borrow::Cow,
+    collections::{hash_map::Entry, BTreeSet, HashMap, HashSet, VecDeque},
+    sync::{atomic::{AtomicU64, AtomicUsize, Ordering}, Arc},
+    time::{Instant, UNIX_EPOCH}, ops::Range,
+};
+
+use chrono::{DateTime, Local};
+use crossterm::{event::{Event, KeyCode, KeyEvent, KeyModifiers}, execute};
+
+use harmony_rust_sdk::{
+    api::{
+        auth::{AuthStep, Session, auth_step::Step, next_step_request::form_fields::Field},
+        chat::{
+            self,
+            content::{Content, TextContent},
+            EventSource, FormattedText,
+            Message as RawMessage, format::{Format, color}, overrides,
+        },
+        profile::Profile,
+    },
+    client::{
+        api::{
+            profile::UserStatus, auth::AuthStepResponse,
+        },
+        error::ClientResult,
+        Client,
+    },
+};
+
+use tokio::sync::{mpsc, RwLock};
+use tokio::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tui::{
+    backend::CrosstermBackend,
+    layout,
+    text::{Span, Spans, Text},
+    widgets, Terminal, style::{Style, Modifier},
+};
+
+mod backend;
+#[cfg(any(test, feature = "bench"))]
+#[doc(hidden)]
+pub mod bench;
+mod config;
+mod bookmarks;
+mod cache;
+mod capabilities;
+mod chanstats;
+mod clipboard;
+mod daemon;
+mod debug;
+mod diff;
+mod duration;
+mod edit;
+mod filters;
+mod fuzzy;
+mod header;
+mod health;
+mod inbox;
+mod input;
+mod lock;
+mod math;
+mod media;
+mod metrics;
+mod modal;
+mod multiplexer;
+mod net;
+mod notifications;
+mod payload;
+mod presence;
+mod receipts;
+mod redact;
+mod reducer;
+mod registers;
+mod relay;
+mod reload;
+mod roles;
+mod schedule;
+mod search;
+mod settings;
+mod shutdown;
+mod snippets;
+mod splash;
+mod stats;
+mod substitute;
+mod supervisor;
+mod text_buffer;
+mod theme;
+mod transfers;
+mod translate;
+mod vault;
+mod voice;
+mod wrap;
+
+use backend::{AccountSetting, BackendError, ChatBackend, ReadReceipt};
+use config::{Config, GuildSort, NotifyLevel, Timestamps};
+use bookmarks::Bookmark;
+use capabilities::{Capabilities, Feature};
+use debug::{DebugLog, LoggingBackend};
+use edit::Edit;
+use filters::SavedFilter;
+use fuzzy::Matcher;
+use inbox::{Inbox, InboxKind};
+use lock::{LockEvent, LockScreen};
+use media::Attachment;
+use modal::{ConfirmAction, ListKind, Modal, ModalEvent, ModalKind};
+use registers::{RegisterPrompt, Registers};
+use roles::{RoleEditor, RoleRequest};
+use schedule::{Reminder, ScheduledMessage};
+use snippets::SnippetContext;
+use splash::{Splash, Step};
+use stats::Stats;
+use supervisor::Supervisor;
+use text_buffer::TextBuffer;
+use theme::Theme;
+use transfers::Transfer;
+
+/// How long a typing notification lasts.
+const TYPING_DURATION: Duration = Duration::from_secs(5);
+
+/// The most messages fetched at a time in low data mode.
+const LOW_DATA_MESSAGE_PAGE: u32 = 20;
+
+/// The most channels remembered in the jump list.
+const JUMP_LIST_SIZE: usize = 100;
+
+/// The most lines the input box grows to before scrolling.
+const MAX_INPUT_LINES: u16 = 8;
+
+/// The frames of the spinner shown while a channel's messages load.
+const SPINNER: [char; 8] = ['⠋', '⠙', '⠸', '⠴', '⠦', '⠇', '⠏', '⠛'];
+
+/// How long each frame of the spinner is shown for.
+const SPINNER_FRAME_MILLIS: u128 = 100;
+
+/// The width of the column message times are shown in, including the space after them.
+const TIME_COLUMN_WIDTH: u16 = 6;
+
+/// The number of channels shown in the recent channels popup, one for each number key.
+const RECENT_CHANNELS: usize = 9;
+
+/// The most members a guild can have for its members to be listed by `:online`.
+/// Harmony has no direct messages, so guilds this small stand in for them.
+const SMALL_GUILD_SIZE: usize = 10;
+
+/// The most pages of history loaded looking for a message to jump to.
+const JUMP_PAGES: usize = 20;
+
+/// The settings only read at startup, named in the status bar when a reload changes them.
+const STARTUP_SETTINGS: &str = "encrypt_data, relay, and metrics";
+
+/// The most images `:gallery` downloads for thumbnails when it opens, newest first.
+const GALLERY_PREFETCH: usize = 24;
+
+/// The shrug added by `/shrug`.
+const SHRUG: &str = r"¯\_(ツ)_/¯";
+
+/// The commands available in command mode, used for completion.
+const COMMANDS: &[&str] = &[
+    "account", "as", "broadcast", "cache", "chanstats", "copy-link", "dnd", "filter", "forward", "gallery", "goto-offset", "header", "history", "inbox", "join", "leave", "lock", "max-width", "mute", "notifylevel", "numbers", "online", "open-math", "purge-local", "quit", "raw-send", "readers", "readonly", "remind", "report", "roleedit", "saved", "schedule",
+    "scheduled", "search", "serverinfo", "settings", "snippet", "sort", "stats", "status", "streamer-mode", "transfers", "translate", "translate-key", "voice-message",
+];
+
+/// Represents an event sent by the user from the UI to other parts of the program.
+enum ClientEvent {
+    /// Quits the program.
+    Quit,
+
+    /// Sends a text message to the current channel.
+    Send(String),
+
+    /// Sends a text message to the given channel.
+    /// arg0 - guild id
+    /// arg1 - channel id
+    /// arg2 - message
+    SendTo(u64, u64, String),
+
+    /// Sends an uploaded file to the given channel.
+    /// arg0 - guild id
+    /// arg1 - channel id
+    /// arg2 - the uploaded file
+    /// arg3 - size of the file in bytes
+    SendAttachment(u64, u64, Attachment, usize),
+
+    /// Sends a forwarded message to the given channel, followed by its files if it had any.
+    /// arg0 - guild id
+    /// arg1 - channel id
+    /// arg2 - the text of the message, including who it was forwarded from
+    /// arg3 - the files of the message, already uploaded to the homeserver
+    Forward(u64, u64, String, Vec<Attachment>),
+
+    /// Sends a message built from its raw content to the given channel, shown with another name or avatar if
+    /// overrides are given.
+    /// arg0 - guild id
+    /// arg1 - channel id
+    /// arg2 - the content of the message
+    /// arg3 - the name and avatar to show instead of the user's
+    SendContent(u64, u64, chat::Content, Option<chat::Overrides>),
+
+    /// Gets more messages from the current channel.
+    /// arg0 - message id
+    GetMoreMessages(Option<u64>),
+
+    /// Gets pages of messages from the current channel until the given time is loaded, then selects the first
+    /// message at or after it.
+    /// arg0 - unix timestamp
+    LoadUntil(u64),
+
+    /// Gets pages of messages from the current channel until the given message is loaded, then selects it.
+    /// arg0 - message id
+    LoadMessage(u64),
+
+    /// Deletes a message in the current channel.
+    Delete(u64),
+
+    /// Edits a message in the current channel.
+    Edit(u64, String),
+
+    /// Gets the channels of a guild, merging them with the ones already known.
+    GetChannels(u64),
+
+    /// Gets a user's profile from their id.
+    GetUser(u64),
+
+    /// Leaves the given guild.
+    LeaveGuild(u64),
+
+    /// Joins a guild given an invite.
+    JoinGuild(String),
+
+    /// Shows the guild an invite is for and asks whether to join it.
+    PreviewInvite(String),
+
+    /// Translates a message in the current channel.
+    /// arg0 - message id
+    /// arg1 - target language
+    Translate(u64, String),
+
+    /// Renders math sources to an image and opens it.
+    OpenMath(Vec<String>),
+
+    /// Sets the user's status.
+    SetStatus(UserStatus),
+
+    /// Sets or clears the user's status text.
+    SetStatusText(Option<String>),
+
+    /// Notifies the current channel that the user is typing.
+    Typing,
+
+    /// Refreshes a guild's info and channels after it changed (such as a room being upgraded to a guild)
+    /// and makes sure its events are subscribed to.
+    RefreshGuild(u64),
+
+    /// Presses a button on an embed in the current channel.
+    /// arg0 - message id
+    /// arg1 - data of the button
+    TriggerAction(u64, Vec<u8>),
+
+    /// Opens the role editor for a guild if the user can manage its roles.
+    OpenRoleEditor(u64),
+
+    /// Refreshes the roles shown in the role editor of a guild.
+    GetRoles(u64),
+
+    /// Makes a change to a guild's roles requested from the role editor.
+    EditRoles(u64, RoleRequest),
+
+    /// Reports a message to the homeserver's moderators, or copies the report to the clipboard if they can't take
+    /// reports.
+    /// arg0 - guild id
+    /// arg1 - channel id
+    /// arg2 - message id
+    /// arg3 - the reason given for the report
+    /// arg4 - the report written out for submitting it manually
+    Report(u64, u64, u64, String, String),
+
+    /// Shows what the homeserver says about itself.
+    ServerInfo,
+
+    /// Asks the homeserver for an export of everything it keeps about the user.
+    ExportAccount,
+
+    /// Deletes the user's account, then wipes what's kept locally and quits.
+    DeleteAccount,
+
+    /// Starts changing an account setting, showing the homeserver's first step in the open account popup.
+    BeginAccountFlow(AccountSetting),
+
+    /// Answers the current step of changing an account setting, showing the next one or closing the account popup
+    /// once it's done.
+    AccountStep(AuthStepResponse),
+
+    /// Searches every guild's messages on the server, or the loaded ones if the server can't search.
+    /// arg0 - the text to search for
+    Search(String),
+
+    /// Fetches who has read a message, showing them in a popup if asked to.
+    /// arg0 - guild id
+    /// arg1 - channel id
+    /// arg2 - message id
+    /// arg3 - whether to show the readers
+    GetReadReceipts(u64, u64, u64, bool),
+
+    /// Tells the homeserver the user has read a channel up to a message.
+    /// arg0 - guild id
+    /// arg1 - channel id
+    /// arg2 - message id
+    SendReadReceipt(u64, u64, u64),
+
+    /// Shows the users the user shares small guilds with and their status.
+    ShowOnline,
+
+    /// Fetches the member count and permissions shown in the header.
+    /// arg1 - guild id
+    /// arg2 - channel id
+    GetChannelInfo(u64, u64),
+
+    /// Sends an action message (`/me`) to the current channel.
+    SendAction(String),
+}
+
+#[derive(Copy, Clone)]
+/// The current mode of the application.
+enum AppMode {
+    /// Normal mode for text.
+    TextNormal,
+
+    /// Insert mode for text.
+    TextInsert,
+
+    /// Command mode to enter commands.
+    Command,
+
+    /// Scroll mode to scroll through messages.
+    Scroll,
+
+    /// Guild select mode to select a guild.
+    GuildSelect,
+
+    /// Channel select mode to select a channel.
+    ChannelSelect,
+}
+
+impl AppMode {
+    /// Gets the pane that has focus in this mode, or `None` for command mode, which keeps the focus it started with.
+    fn pane(self) -> Option<Pane> {
+        match self {
+            AppMode::TextNormal | AppMode::TextInsert => Some(Pane::Input),
+            AppMode::Scroll => Some(Pane::Messages),
+            AppMode::GuildSelect => Some(Pane::Guilds),
+            AppMode::ChannelSelect => Some(Pane::Channels),
+            AppMode::Command => None,
+        }
+    }
+}
+
+/// A pane of the UI, which gets key presses and a highlighted border while it has focus.
+#[derive(Copy, Clone, PartialEq)]
+enum Pane {
+    /// The guild list in the sidebar.
+    Guilds,
+
+    /// The channel list in the sidebar.
+    Channels,
+
+    /// The messages in the current channel.
+    Messages,
+
+    /// The input box.
+    Input,
+}
+
+impl Default for AppMode {
+    fn default() -> Self {
+        Self::TextNormal
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum FormatMetadata {
+    Bold,
+    Italic,
+    Underline,
+    Monospace, // lol
+    Superscript, // oh no
+    Subscript, // *oh no*
+    CodeBlock,
+    UserMention,
+    RoleMention,
+    ChannelMention,
+    GuildMention,
+    Emoji,
+    Color,
+    Localisation,
+    Compose(Vec<FormatMetadata>),
+
+    /// Rendered LaTeX math; contains the original source with delimiters.
+    Math(String),
+}
+
+#[derive(Debug)]
+struct RichText {
+    contents: String,
+    formats: Vec<(Range<usize>, Style, FormatMetadata)>,
+}
+
+impl RichText {
+    /// Estimates the memory the rich text uses on the heap, for `:stats`.
+    fn heap_size(&self) -> usize {
+        self.contents.capacity() + self.formats.capacity() * std::mem::size_of::<(Range<usize>, Style, FormatMetadata)>()
+    }
+
+    /// Gets the source text of the rich text, undoing any rendering done on the client side.
+    fn source(&self) -> String {
+        let mut result = String::new();
+        let mut last = 0;
+        for (span, _, meta) in self.formats.iter() {
+            if let FormatMetadata::Math(source) = meta {
+                result.push_str(&self.contents[last..span.start]);
+                result.push_str(source);
+                last = span.end;
+            }
+        }
+        result.push_str(&self.contents[last..]);
+        result
+    }
+}
+
+/// Represents an interactive button on an embed.
+struct EmbedAction {
+    /// The text on the button.
+    text: String,
+
+    /// The data sent back to the bot when the button is pressed.
+    data: Vec<u8>,
+
+    /// If present, the button opens this url instead of notifying the bot.
+    url: Option<String>,
+}
+
+/// Represents a field of an embed.
+struct EmbedField {
+    /// The title of the field.
+    title: String,
+
+    /// The subtitle of the field.
+    subtitle: Option<String>,
+
+    /// The body of the field.
+    body: Option<RichText>,
+}
+
+/// Represents an embed.
+struct Embed {
+    /// The title of the embed.
+    title: String,
+
+    /// The body of the embed.
+    body: Option<RichText>,
+
+    /// The fields of the embed.
+    fields: Vec<EmbedField>,
+
+    /// The buttons of all fields in the embed.
+    actions: Vec<EmbedAction>,
+}
+
+/// Represents the contents of a received message.
+enum MessageContent {
+    /// A message composed of text.
+    Text(RichText),
+
+    /// A message composed of an embed.
+    Embed(Embed),
+
+    /// A message composed of files or photos.
+    Files(Vec<Attachment>),
+
+    /// A notice generated by the client, such as an action being performed.
+    Notice(String),
+}
+
+/// Represents a received message.
+struct Message {
+    /// The id of the message.
+    id: u64,
+
+    /// The user id of the author.
+    author_id: u64,
+
+    /// If an override is present, sets the username to this string.
+    override_username: Option<String>,
+
+    /// The content of the message.
+    content: MessageContent,
+
+    /// The timestamp the message was created at.
+    timestamp: u64,
+
+    /// The timestamp the message was edited at.
+    edited_timestamp: Option<u64>,
+
+    /// The cached translation of the message and the language it was translated to.
+    translation: Option<(String, String)>,
+
+    /// Whether the message contains one of the user's highlight words.
+    highlighted: bool,
+
+    /// The id of the message this one replies to, if any.
+    in_reply_to: Option<u64>,
+
+    /// Whether the message was sent through a bridge from another chat service, or `None` if it has no override
+    /// saying why its author is overridden.
+    bridged: Option<bool>,
+}
+
+impl Message {
+    /// Estimates the memory the message uses on the heap, for `:stats`. Attachments are counted without their urls.
+    fn heap_size(&self) -> usize {
+        let content = match &self.content {
+            MessageContent::Text(text) => text.heap_size(),
+            MessageContent::Embed(embed) => {
+                let fields: usize = embed
+                    .fields
+                    .iter()
+                    .map(|v| v.title.capacity() + v.subtitle.as_ref().map_or(0, String::capacity) + v.body.as_ref().map_or(0, RichText::heap_size))
+                    .sum();
+                let actions: usize = embed
+                    .actions
+                    .iter()
+                    .map(|v| v.text.capacity() + v.data.capacity() + v.url.as_ref().map_or(0, String::capacity))
+                    .sum();
+                embed.title.capacity()
+                    + embed.body.as_ref().map_or(0, RichText::heap_size)
+                    + embed.fields.capacity() * std::mem::size_of::<EmbedField>()
+                    + embed.actions.capacity() * std::mem::size_of::<EmbedAction>()
+                    + fields
+                    + actions
+            }
+            MessageContent::Files(files) => files.capacity() * std::mem::size_of::<Attachment>(),
+            MessageContent::Notice(notice) => notice.capacity(),
+        };
+
+        content
+            + self.override_username.as_ref().map_or(0, String::capacity)
+            + self.translation.as_ref().map_or(0, |(text, language)| text.capacity() + language.capacity())
+    }
+
+    /// Gets the text of an action message (`/me`) and whether it already starts with the author's name.
+    /// Actions are sent as wholly italic text, and bridges send them as `* name does thing`.
+    fn action(&self) -> Option<(&str, bool)> {
+        let text = match &self.content {
+            MessageContent::Text(text) => text,
+            _ => return None,
+        };
+
+        let italic = text
+            .formats
+            .iter()
+            .any(|(span, _, meta)| *meta == FormatMetadata::Italic && span.start == 0 && span.end >= text.contents.len());
+        if italic && !text.contents.is_empty() {
+            return Some((text.contents.as_str(), false));
+        }
+
+        let name = self.override_username.as_deref().filter(|_| self.bridged != Some(false))?;
+        text.contents
+            .strip_prefix("* ")
+            .filter(|v| v.starts_with(name))
+            .map(|_| (text.contents.as_str(), true))
+    }
+}
+
+/// Represents a member of a guild.
+struct Member {
+    /// The name of the member
+    name: String,
+
+    /// Whether the member is a bot or not.
+    is_bot: bool,
+
+    /// The HMC of the member's avatar, if they have one.
+    avatar: Option<String>,
+
+    /// The member's online status, kept up to date by profile events.
+    status: UserStatus,
+}
+
+/// Represents a channel.
+struct Channel {
+    /// The id of the channel.
+    id: u64,
+
+    /// The id of the guild that contains this channel.
+    guild_id: u64,
+
+    /// The name of the channel.
+    name: String,
+
+    /// The selected message, or `None` to follow the newest message as messages arrive.
+    selected: Option<u64>,
+
+    /// The message at the bottom of the message pane, so messages arriving or being deleted while a message is
+    /// selected don't move what's being read. It's atomic since it's kept up to date while drawing.
+    view_bottom: AtomicU64,
+
+    /// How many lines of the message at the bottom of the pane are scrolled out of view below it, so a message taller
+    /// than the pane stays where it was too. Kept up to date while drawing, like `view_bottom`.
+    view_offset: AtomicUsize,
+
+    /// The number of messages that arrived while a message was selected, shown until the selection returns to the
+    /// newest message.
+    new_below: usize,
+
+    /// The map of messages in the channel.
+    messages_map: HashMap<u64, Message>,
+
+    /// The list of messages in the channel.
+    messages_list: Vec<u64>,
+
+    /// Slash commands seen in the channel, used for completion.
+    slash_commands: BTreeSet<String>,
+
+    /// The users currently typing in the channel and when their typing notification expires.
+    typing: HashMap<u64, Instant>,
+
+    /// The message the last page of history was requested before, so the same page isn't requested twice.
+    requested_before: Option<u64>,
+
+    /// The timestamp of the first message that arrived while the user wasn't viewing the channel.
+    first_unread: Option<u64>,
+
+    /// How far loading the channel's latest messages has got.
+    history: History,
+
+    /// The timestamp of the last message sent in the channel.
+    last_activity: u64,
+
+    /// When the user last switched to the channel.
+    last_visited: u64,
+
+    /// Who has read each of the user's messages that receipts were fetched for, by message id.
+    readers: HashMap<u64, Vec<ReadReceipt>>,
+}
+
+/// How far loading a channel's latest messages has got.
+#[derive(Clone, PartialEq)]
+enum History {
+    /// The latest messages haven't been requested yet.
+    Unloaded,
+
+    /// The latest messages are being fetched.
+    Loading,
+
+    /// The latest messages were fetched, even if there weren't any.
+    Loaded,
+
+    /// Fetching the latest messages failed with the given error.
+    Failed(String),
+}
+
+/// A view of a channel showing only a message and the replies to it.
+struct Thread {
+    /// The id of the channel the thread is in.
+    channel_id: u64,
+
+    /// The message the thread starts from.
+    root: u64,
+
+    /// The channel's selected message before the thread was opened, restored when it's closed.
+    channel_selected: Option<u64>,
+}
+
+/// Represents a guild.
+struct Guild {
+    /// The id of the guild.
+    id: u64,
+
+    /// The list of channels.
+    channels_list: Vec<u64>,
+
+    /// The current channel selected.
+    channels_select: Option<usize>,
+
+    /// The map of channels.
+    channels_map: HashMap<u64, Channel>,
+
+    /// The name of the guild.
+    name: String,
+
+    /// The current channel being viewed.
+    current_channel: Option<u64>,
+
+    /// The position of the guild in the manually sorted guild list.
+    position: usize,
+
+    /// The timestamp of the last message sent in the guild.
+    last_activity: u64,
+
+    /// The channels with activity the user hasn't seen yet.
+    unseen_channels: HashSet<u64>,
+
+    /// How many members the guild has, once it's been fetched for the header.
+    member_count: Option<usize>,
+
+    /// Whether the user has each of the permission nodes summarized in the header, by channel.
+    permissions: HashMap<(u64, &'static str), bool>,
+}
+
+impl Guild {
+    fn current_channel(&self) -> Option<&Channel> {
+        self.current_channel.and_then(|v| self.channels_map.get(&v))
+    }
+
+    fn current_channel_mut(&mut self) -> Option<&mut Channel> {
+        self.current_channel.and_then(|v| self.channels_map.get_mut(&v))
+    }
+
+    /// Adds a channel, or renames it if it's already known so its messages are kept.
+    fn add_channel(&mut self, channel_id: u64, name: String) {
+        if let Some(existing) = self.channels_map.get_mut(&channel_id) {
+            existing.name = name;
+            return;
+        }
+
+        self.channels_list.push(channel_id);
+        self.channels_map.insert(channel_id, Channel::new(self.id, channel_id, name));
+    }
+
+    /// Removes a channel, deselecting it if it was selected.
+    fn remove_channel(&mut self, channel_id: u64) {
+        self.channels_map.remove(&channel_id);
+        self.unseen_channels.remove(&channel_id);
+        if self.current_channel == Some(channel_id) {
+            self.current_channel = None;
+        }
+
+        if let Some(i) = self.channels_list.iter().position(|&v| v == channel_id) {
+            self.channels_list.remove(i);
+            self.channels_select = match self.channels_select {
+                Some(select) if select == i => None,
+                Some(select) if select > i => Some(select - 1),
+                select => select,
+            };
+        }
+    }
+
+    /// Merges a fetched channel list into the guild, so fetching the same list again changes nothing.
+    /// Channels missing from the list were deleted and are removed.
+    fn merge_channels(&mut self, channels: Vec<(u64, chat::Channel)>) {
+        let fetched: HashSet<u64> = channels.iter().map(|(id, _)| *id).collect();
+        for channel_id in self.channels_list.clone() {
+            if !fetched.contains(&channel_id) {
+                self.remove_channel(channel_id);
+            }
+        }
+
+        for (channel_id, channel) in channels {
+            self.add_channel(channel_id, channel.channel_name);
+        }
+    }
+}
+
+impl Channel {
+    /// Creates a channel with no history loaded yet, following the newest message.
+    fn new(guild_id: u64, channel_id: u64, name: String) -> Channel {
+        Channel {
+            id: channel_id,
+            guild_id,
+            name,
+            selected: None,
+            view_bottom: AtomicU64::new(0),
+            view_offset: AtomicUsize::new(0),
+            new_below: 0,
+            messages_map: HashMap::new(),
+            messages_list: vec![],
+            slash_commands: BTreeSet::new(),
+            typing: HashMap::new(),
+            requested_before: None,
+            first_unread: None,
+            history: History::Unloaded,
+            last_activity: 0,
+            last_visited: 0,
+            readers: HashMap::new(),
+        }
+    }
+
+    /// Gets how many messages above the newest message the selected message is.
+    fn scroll_selected(&self) -> usize {
+        self.selected
+            .and_then(|id| self.index_of(id))
+            .map(|i| self.messages_list.len() - 1 - i)
+            .unwrap_or(0)
+    }
+
+    /// Gets the position of a loaded message in the list. The list is kept in order of timestamp, then id, so this is
+    /// a binary search rather than a scan.
+    fn index_of(&self, message_id: u64) -> Option<usize> {
+        let key = self.messages_map.get(&message_id).map(|v| (v.timestamp, v.id))?;
+        let messages_map = &self.messages_map;
+        let index = self
+            .messages_list
+            .partition_point(|v| messages_map.get(v).map(|v| (v.timestamp, v.id) < key).unwrap_or(true));
+        Some(index).filter(|&i| self.messages_list.get(i) == Some(&message_id))
+    }
+
+    /// Gets the message currently selected by scrolling.
+    fn selected_message(&self) -> Option<&Message> {
+        self.messages_list
+            .len()
+            .checked_sub(self.scroll_selected() + 1)
+            .and_then(|i| self.messages_list.get(i))
+            .and_then(|v| self.messages_map.get(v))
+    }
+
+    /// Adds a message in order of timestamp, then id. A message that is already present is updated in place instead,
+    /// so replayed events and overlapping pages never produce duplicates.
+    fn insert_message(&mut self, message: Message) {
+        let id = message.id;
+        if let Some(existing) = self.messages_map.get_mut(&id) {
+            *existing = Message {
+                translation: existing.translation.take(),
+                ..message
+            };
+            return;
+        }
+
+        let key = (message.timestamp, id);
+        let messages_map = &self.messages_map;
+        let index = self
+            .messages_list
+            .partition_point(|v| messages_map.get(v).map(|v| (v.timestamp, v.id) < key).unwrap_or(true));
+        self.messages_list.insert(index, id);
+        self.messages_map.insert(id, message);
+
+        // Count new messages arriving below what's being read
+        if self.selected.is_some() && index + 1 == self.messages_list.len() {
+            self.new_below += 1;
+        }
+    }
+
+    /// Counts the messages from other users that arrived while the user wasn't viewing the channel.
+    fn unread_count(&self, current_user: u64) -> usize {
+        match self.first_unread {
+            Some(first) => self.messages_map.values().filter(|v| v.timestamp >= first && v.author_id != current_user).count(),
+            None => 0,
+        }
+    }
+
+    /// Selects the message with the given id if it's loaded. Selecting the newest message follows new messages as
+    /// they arrive.
+    fn select(&mut self, message_id: u64) {
+        if self.messages_list.last() == Some(&message_id) {
+            self.selected = None;
+            self.new_below = 0;
+        } else if self.messages_map.contains_key(&message_id) {
+            self.selected = Some(message_id);
+        }
+    }
+
+    /// Gets the given message and every message replying to it, directly or through other replies, oldest first.
+    fn thread(&self, root: u64) -> Vec<u64> {
+        let mut members = HashSet::from([root]);
+        self.messages_list
+            .iter()
+            .copied()
+            .filter(|&id| {
+                let parent = self.messages_map.get(&id).and_then(|v| v.in_reply_to);
+                let member = id == root || matches!(parent, Some(parent) if members.contains(&parent));
+                if member {
+                    members.insert(id);
+                }
+                member
+            })
+            .collect()
+    }
+
+    /// Moves the selection by the given number of messages, up towards older messages if positive, staying in bounds.
+    fn scroll_by(&mut self, offset: isize) {
+        let last = self.messages_list.len().saturating_sub(1) as isize;
+        let target = (self.scroll_selected() as isize + offset).clamp(0, last) as usize;
+        if let Some(&id) = self.messages_list.iter().rev().nth(target) {
+            self.select(id);
+        }
+    }
+
+    /// Gets the message to fetch the previous page of history before, if the selected message is within
+    /// `prefetch` messages of the oldest loaded message and that page hasn't been requested yet.
+    fn prefetch_anchor(&mut self, prefetch: usize) -> Option<u64> {
+        if self.scroll_selected() + prefetch < self.messages_list.len() {
+            return None;
+        }
+
+        let oldest = self.messages_list.first().and_then(|v| self.messages_map.get(v)).map(|v| v.id)?;
+        if self.requested_before == Some(oldest) {
+            return None;
+        }
+
+        self.requested_before = Some(oldest);
+        Some(oldest)
+    }
+}
+
+#[cfg(test)]
+mod channel_tests {
+    use super::*;
+
+    /// Creates an empty channel with all its history loaded.
+    fn channel() -> Channel {
+        Channel { history: History::Loaded, ..Channel::new(1, 2, String::from("test")) }
+    }
+
+    /// Creates a message with the given id, timestamp, and text.
+    fn message(id: u64, timestamp: u64, text: &str) -> Message {
+        Message {
+            id,
+            author_id: 7,
+            override_username: None,
+            content: MessageContent::Notice(text.to_owned()),
+            timestamp,
+            edited_timestamp: None,
+            translation: None,
+            highlighted: false,
+            in_reply_to: None,
+            bridged: None,
+        }
+    }
+
+    /// Gets the text of a message in the channel.
+    fn text(channel: &Channel, id: u64) -> &str {
+        match &channel.messages_map[&id].content {
+            MessageContent::Notice(text) => text,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn replayed_event_updates_in_place() {
+        let mut channel = channel();
+        channel.insert_message(message(1, 10, "first"));
+        channel.messages_map.get_mut(&1).unwrap().translation = Some((String::from("unua"), String::from("tok")));
+
+        channel.insert_message(message(1, 10, "edited"));
+
+        assert_eq!(channel.messages_list, vec![1]);
+        assert_eq!(channel.messages_map.len(), 1);
+        assert_eq!(text(&channel, 1), "edited");
+        assert!(channel.messages_map[&1].translation.is_some());
+    }
+
+    #[test]
+    fn overlapping_pages_have_no_duplicates() {
+        let mut channel = channel();
+        for id in 1..=5 {
+            channel.insert_message(message(id, id * 10, "old page"));
+        }
+        for id in 3..=8 {
+            channel.insert_message(message(id, id * 10, "new page"));
+        }
+
+        assert_eq!(channel.messages_list, (1..=8).collect::<Vec<_>>());
+        assert_eq!(channel.messages_map.len(), 8);
+        assert_eq!(text(&channel, 4), "new page");
+    }
+
+    #[test]
+    fn out_of_order_timestamps_are_sorted() {
+        let mut channel = channel();
+        channel.insert_message(message(1, 30, "newest"));
+        channel.insert_message(message(2, 10, "oldest"));
+        channel.insert_message(message(3, 20, "middle"));
+
+        assert_eq!(channel.messages_list, vec![2, 3, 1]);
+    }
+
+    #[test]
+    fn equal_timestamps_are_ordered_by_id() {
+        let mut channel = channel();
+        channel.insert_message(message(5, 10, "c"));
+        channel.insert_message(message(3, 10, "a"));
+        channel.insert_message(message(4, 10, "b"));
+        channel.insert_message(message(1, 5, "earlier"));
+        channel.insert_message(message(2, 15, "later"));
+
+        assert_eq!(channel.messages_list, vec![1, 3, 4, 5, 2]);
+    }
+
+    #[test]
+    fn messages_are_found_by_position() {
+        let mut channel = channel();
+        channel.insert_message(message(5, 10, "c"));
+        channel.insert_message(message(3, 10, "a"));
+        channel.insert_message(message(1, 5, "earlier"));
+        channel.insert_message(message(2, 15, "later"));
+
+        for (i, &id) in channel.messages_list.iter().enumerate() {
+            assert_eq!(channel.index_of(id), Some(i));
+        }
+        assert_eq!(channel.index_of(4), None);
+
+        channel.selected = Some(3);
+        assert_eq!(channel.scroll_selected(), 2);
+        channel.insert_message(message(6, 1, "older page"));
+        assert_eq!(channel.scroll_selected(), 2);
+    }
+
+    #[test]
+    fn only_new_messages_at_the_bottom_count_as_new_below() {
+        let mut channel = channel();
+        channel.insert_message(message(1, 10, "a"));
+        channel.insert_message(message(2, 20, "b"));
+        channel.selected = Some(1);
+
+        channel.insert_message(message(3, 5, "older page"));
+        channel.insert_message(message(4, 30, "arrived"));
+        channel.insert_message(message(4, 30, "replayed"));
+
+        assert_eq!(channel.new_below, 1);
+    }
+
+    #[test]
+    fn history_pages_keep_the_view_in_place() {
+        let mut state = crate::bench::channel_state(1, 2);
+        state.mode = AppMode::Scroll;
+        let channel = state.current_channel_mut().unwrap();
+        for id in 100..130 {
+            channel.insert_message(message(id, id, "loaded"));
+        }
+        channel.select(110);
+
+        let anchor = |state: &AppState| {
+            let channel = state.current_channel().unwrap();
+            (channel.selected, channel.view_bottom.load(Ordering::Relaxed), channel.view_offset.load(Ordering::Relaxed))
+        };
+        crate::bench::render(&state, 80, 12);
+        let before = anchor(&state);
+        let position = state.current_channel().unwrap().scroll_selected();
+        assert_eq!(before.0, Some(110));
+        assert_ne!(before.1, 0);
+
+        // The page goes above everything loaded, the way `fetch_page` adds it
+        let channel = state.current_channel_mut().unwrap();
+        for id in 1..50 {
+            channel.insert_message(message(id, id, "older page"));
+        }
+        crate::bench::render(&state, 80, 12);
+
+        assert_eq!(anchor(&state), before);
+        assert_eq!(state.current_channel().unwrap().scroll_selected(), position);
+        assert_eq!(state.current_channel().unwrap().new_below, 0);
+    }
+}
+
+#[derive(Default)]
+/// Represents the current state of the app.
+pub struct AppState {
+    /// The current mode the app is in.
+    mode: AppMode,
+
+    /// The mode to return to after running a command.
+    command_return: AppMode,
+
+    /// The user's configuration.
+    config: Config,
+
+    /// A status message to display in the status bar, such as an error.
+    status: Option<String>,
+
+    /// The styles used to draw the UI.
+    theme: Theme,
+
+    /// The stack of popups drawn over the UI; the last one has focus.
+    modals: Vec<Modal>,
+
+    /// The per channel notification level overrides.
+    notify_levels: HashMap<(u64, u64), NotifyLevel>,
+
+    /// The timestamp manually set do not disturb ends at, if it is on.
+    dnd_until: Option<u64>,
+
+    /// Whether notification sounds are muted with `:mute`.
+    sounds_muted: bool,
+
+    /// What's been loaded so far after logging in, shown in place of the UI until everything is ready.
+    splash: Option<Splash>,
+
+    /// The text shown with the user's status, if they set one.
+    status_text: Option<String>,
+
+    /// The status text set by the rich presence command, which is shown in place of `status_text` while there is one.
+    presence_text: Option<String>,
+
+    /// Whether do not disturb is currently on, either manually or by schedule.
+    dnd_active: bool,
+
+    /// The timestamp of the user's last key press.
+    last_input: u64,
+
+    /// When the user last sent a typing notification.
+    typing_sent: Option<Instant>,
+
+    /// The prefix being completed in the input box and the index of the current completion.
+    completion: Option<(String, usize)>,
+
+    /// The number of notices created, used to give notices unique ids.
+    notice_count: u64,
+
+    /// The index of the selected button on the selected embed in scroll mode.
+    action_select: usize,
+
+    /// The guilds whose events are subscribed to.
+    subscribed_guilds: HashSet<u64>,
+
+    /// Messages waiting to be sent later.
+    scheduled: Vec<ScheduledMessage>,
+
+    /// The number of messages scheduled, used to give them unique ids.
+    scheduled_count: u64,
+
+    /// Reminders about messages waiting to fire.
+    reminders: Vec<Reminder>,
+
+    /// Reminders that fired while the user was typing, whose popups wait until insert mode is left.
+    due_reminders: Vec<Reminder>,
+
+    /// The user's saved messages.
+    bookmarks: Vec<Bookmark>,
+
+    /// The user's saved filters, shown in the sidebar.
+    filters: Vec<SavedFilter>,
+
+    /// The filter typed in guild or channel select mode, if filtering.
+    list_filter: Option<String>,
+
+    /// The url of the homeserver, used to download media.
+    homeserver: String,
+
+    /// The session token, used to upload media.
+    token: String,
+
+    /// Uploads and downloads started this session.
+    transfers: Vec<Arc<Transfer>>,
+
+    /// The health of the connection to the homeserver.
+    health: health::Health,
+
+    /// Recently visited channels as (guild id, channel id), oldest first.
+    jump_list: Vec<(u64, u64)>,
+
+    /// The position of the current channel in the jump list.
+    jump_index: usize,
+
+    /// The count typed before a key in scroll mode, if any.
+    scroll_count: Option<usize>,
+
+    /// The reply thread being viewed in place of the current channel, if any.
+    thread: Option<Thread>,
+
+    /// What the homeserver supports.
+    capabilities: Capabilities,
+
+    /// Performance counters shown by `:stats`.
+    stats: Arc<Stats>,
+
+    /// Whether only the current channel is shown, without the sidebar, as asked for with `--channel`.
+    single_channel: bool,
+
+    /// The lock screen hiding everything, if it's up.
+    locked: Option<LockScreen>,
+
+    /// The hash of the passphrase chosen with `:lock` this session, used in place of the one in the config.
+    lock_hash: Option<String>,
+
+    /// Whether the homeserver's address and invites are hidden, for sharing the screen.
+    streamer_mode: bool,
+
+    /// Whether every channel is read only, as asked for with `--read-only`.
+    read_only: bool,
+
+    /// The name, and avatar if any, that messages typed into the box are shown with instead of the user's, picked
+    /// with `:as`.
+    send_as: Option<(String, Option<String>)>,
+
+    /// The channels made read only with `:readonly`, as (guild id, channel id).
+    read_only_channels: HashSet<(u64, u64)>,
+
+    /// Whether the `:stats` overlay is shown.
+    show_stats: bool,
+
+    /// The log of calls and events shown by `:debug`.
+    debug_log: Arc<DebugLog>,
+
+    /// The guild whose default channel opens once its channels are fetched, if any.
+    pending_default: Option<u64>,
+
+    /// The map of users.
+    users: HashMap<u64, Member>,
+
+    /// The map of guilds.
+    guilds_map: HashMap<u64, Guild>,
+
+    /// The list of guilds
+    guilds_list: Vec<u64>,
+
+    /// The currently selected guild, if any.
+    guilds_select: Option<usize>,
+
+    /// How the guild list is sorted.
+    guild_sort: GuildSort,
+
+    /// The current guild being viewed.
+    current_guild: Option<u64>,
+
+    /// The id of the user using this application.
+    current_user: u64,
+
+    /// Determines whether or not the user is currently editing a message.
+    editing: bool,
+
+    /// The input box.
+    input: TextBuffer,
+
+    /// The old value of the input box before editing.
+    old_input: TextBuffer,
+
+    /// The diff shown while editing, kept between frames so it's only computed again when the draft changes.
+    edit_diff: diff::DiffCache,
+
+    /// What each terminal attached to the daemon has of its own, such as its mode and draft.
+    views: daemon::Views,
+
+    /// The command prompt.
+    command: TextBuffer,
+
+    /// Text yanked and deleted with `"a` … `"z` and the unnamed register.
+    registers: Registers,
+
+    /// Mentions, direct messages, and invites from every guild, shown by `:inbox`.
+    inbox: Inbox,
+
+    /// What the next key names a register for, if `"` or ctrl+r was just pressed.
+    register_prompt: Option<RegisterPrompt>,
+
+    /// The register picked with `"` for the next yank, paste, or delete, if any.
+    register: Option<char>,
+
+    /// The last change made to the input box, which `.` makes again.
+    last_edit: Option<Edit>,
+
+    /// Whether typed text joins the last change, which it does until insert mode is left.
+    edit_joins: bool,
+}
+
+impl AppState {
+    fn current_guild(&self) -> Option<&Guild> {
+        self.current_guild.and_then(|v| self.guilds_map.get(&v))
+    }
+
+    /// Gets the pane that has focus.
+    fn focus(&self) -> Pane {
+        self.mode.pane().or_else(|| self.command_return.pane()).unwrap_or(Pane::Input)
+    }
+
+    /// Creates the border of a pane, highlighted if the pane has focus.
+    fn pane_block<'a>(&self, pane: Pane) -> widgets::Block<'a> {
+        let block = widgets::Block::default().borders(widgets::Borders::ALL);
+        if self.focus() == pane {
+            block.border_style(self.theme.header)
+        } else {
+            block
+        }
+    }
+
+    fn current_channel(&self) -> Option<&Channel> {
+        self.current_guild().and_then(Guild::current_channel)
+    }
+
+    /// Gets the hash of the passphrase that unlocks the lock screen: the one chosen this session, or else the config's
+    /// if it's an Argon2 hash.
+    fn lock_passphrase(&self) -> Option<String> {
+        self.lock_hash.clone().or_else(|| self.config.lock.passphrase.clone().filter(|v| lock::is_valid_hash(v)))
+    }
+
+    /// Brings up the lock screen, which asks for a passphrase to be chosen if there isn't one yet.
+    fn lock(&mut self) {
+        let hash = self.lock_passphrase();
+        if hash.is_none() && self.config.lock.passphrase.is_some() {
+            self.status = Some(String::from("the [lock] passphrase isn't an Argon2 hash, so one is chosen for this session instead"));
+        }
+        self.locked = Some(LockScreen::new(hash));
+    }
+
+    /// Gets the homeserver's address without its scheme.
+    fn homeserver_host(&self) -> &str {
+        self.homeserver.split("://").last().unwrap_or_default().trim_end_matches('/')
+    }
+
+    /// Gets the overrides for messages typed into the box, if a name was picked with `:as`.
+    fn send_as_overrides(&self) -> Option<chat::Overrides> {
+        self.send_as.as_ref().map(|(username, avatar)| chat::Overrides {
+            username: Some(username.clone()),
+            avatar: avatar.clone(),
+            reason: Some(overrides::Reason::UserDefined(Default::default())),
+        })
+    }
+
+    /// Hides the homeserver's address in text shown on screen while streamer mode is on.
+    fn redact(&self, text: &str) -> String {
+        let host = self.homeserver_host();
+        if self.streamer_mode && !host.is_empty() {
+            text.replace(host, "<homeserver>")
+        } else {
+            text.to_owned()
+        }
+    }
+
+    /// Shows in the status bar that a request to the homeserver failed.
+    fn request_failed(&mut self, what: &str, error: &BackendError) {
+        self.status = Some(format!("could not {}: {}", what, self.redact(&error.to_string())));
+    }
+
+    /// Checks whether nothing can be sent, edited, or deleted in a channel.
+    fn is_read_only(&self, guild_id: u64, channel_id: u64) -> bool {
+        self.read_only || self.read_only_channels.contains(&(guild_id, channel_id))
+    }
+
+    /// Checks whether the current channel is read only, which hides the input box.
+    fn current_read_only(&self) -> bool {
+        self.current_channel().map(|v| self.is_read_only(v.guild_id, v.id)).unwrap_or(self.read_only)
+    }
+
+    /// Checks whether an event would send, edit, or delete something in a read only channel.
+    fn is_blocked(&self, event: &ClientEvent) -> bool {
+        let target = match *event {
+            ClientEvent::Send(_) | ClientEvent::SendAction(_) | ClientEvent::Edit(..) | ClientEvent::Delete(_) | ClientEvent::Typing => {
+                self.current_channel().map(|v| (v.guild_id, v.id))
+            }
+            ClientEvent::SendTo(guild_id, channel_id, _)
+            | ClientEvent::SendAttachment(guild_id, channel_id, ..)
+            | ClientEvent::Forward(guild_id, channel_id, ..)
+            | ClientEvent::SendContent(guild_id, channel_id, ..) => Some((guild_id, channel_id)),
+            _ => None,
+        };
+        target.map(|(guild_id, channel_id)| self.is_read_only(guild_id, channel_id)).unwrap_or(false)
+    }
+
+    /// Switches to insert mode unless the current channel is read only, returning whether it did.
+    fn start_insert(&mut self) -> bool {
+        if self.current_read_only() {
+            self.status = Some(String::from("this channel is read only"));
+            false
+        } else {
+            self.mode = AppMode::TextInsert;
+            self.edit_joins = false;
+            true
+        }
+    }
+
+    /// Saves yanked or deleted text to the register picked with `"`, or the unnamed one.
+    fn yank(&mut self, text: String) {
+        let name = self.register.take();
+        if !text.is_empty() {
+            self.registers.set(name, text);
+        }
+    }
+
+    /// Pastes a register at the cursor of the command prompt in command mode, or of the input box otherwise.
+    fn paste(&mut self, name: Option<char>) {
+        let text = match self.registers.get(name) {
+            Some(text) => text.to_owned(),
+            None => {
+                self.status = Some(format!("nothing in register {}", name.unwrap_or(registers::UNNAMED)));
+                return;
+            }
+        };
+
+        if matches!(self.mode, AppMode::Command) {
+            self.command.insert(&text);
+        } else {
+            self.input.insert(&text);
+        }
+    }
+
+    /// Checks whether the sidebar is hidden, which it is in single channel mode unless the channel is gone.
+    fn sidebar_hidden(&self) -> bool {
+        self.single_channel && self.current_channel().is_some()
+    }
+
+    /// Moves the splash screen on to the given step, if it's still shown.
+    fn loading(&mut self, step: Step) {
+        if let Some(splash) = &mut self.splash {
+            splash.start(step);
+        }
+    }
+
+    /// Gets the number of messages cached in every channel and an estimate of the memory they use.
+    fn message_cache_size(&self) -> (usize, usize) {
+        self.guilds_map
+            .values()
+            .flat_map(|v| v.channels_map.values())
+            .fold((0, 0), |(count, bytes), channel| {
+                let map = channel.messages_map.capacity() * std::mem::size_of::<(u64, Message)>();
+                let list = channel.messages_list.capacity() * std::mem::size_of::<u64>();
+                let heap: usize = channel.messages_map.values().map(Message::heap_size).sum();
+                (count + channel.messages_map.len(), bytes + map + list + heap)
+            })
+    }
+
+    /// Starts tracking a new upload or download.
+    fn start_transfer(&mut self, name: &str, upload: bool) -> Arc<Transfer> {
+        let transfer = Arc::new(Transfer::new(name, upload));
+        self.transfers.push(transfer.clone());
+        transfer
+    }
+
+    /// Switches to the given channel, marking the channel being left as read.
+    /// Returns whether the channel has no messages loaded yet.
+    fn open_channel(&mut self, guild_id: u64, channel_id: u64) -> bool {
+        let guild = match self.guilds_map.get_mut(&guild_id) {
+            Some(v) => v,
+            None => return false,
+        };
+
+        if let Some(channel) = guild.current_channel_mut() {
+            channel.first_unread = None;
+        }
+        guild.channels_select = guild.channels_list.iter().position(|&v| v == channel_id);
+        guild.current_channel = Some(channel_id);
+        guild.unseen_channels.remove(&channel_id);
+        self.inbox.mark_channel_read(guild_id, channel_id);
+        let empty = match guild.channels_map.get_mut(&channel_id) {
+            Some(channel) => {
+                channel.last_visited = presence::now();
+                channel.messages_list.is_empty()
+            }
+            None => false,
+        };
+
+        self.guilds_select = self.guilds_list.iter().position(|&v| v == guild_id);
+        self.current_guild = Some(guild_id);
+        self.thread = None;
+        empty
+    }
+
+    /// Switches to the given channel and adds it to the jump list, forgetting the channels ahead of the current one.
+    /// Returns whether the channel has no messages loaded yet.
+    fn visit_channel(&mut self, guild_id: u64, channel_id: u64) -> bool {
+        self.jump_list.truncate(self.jump_index + 1);
+        self.jump_list.retain(|&v| v != (guild_id, channel_id));
+        self.jump_list.push((guild_id, channel_id));
+        if self.jump_list.len() > JUMP_LIST_SIZE {
+            self.jump_list.remove(0);
+        }
+        self.jump_index = self.jump_list.len() - 1;
+
+        self.open_channel(guild_id, channel_id)
+    }
+
+    /// Moves back or forward through the jump list, skipping channels that no longer exist.
+    /// Returns whether the channel has no messages loaded yet, or `None` if there's nowhere to go.
+    fn jump(&mut self, back: bool) -> Option<bool> {
+        loop {
+            let index = if back {
+                self.jump_index.checked_sub(1)?
+            } else {
+                Some(self.jump_index + 1).filter(|&v| v < self.jump_list.len())?
+            };
+
+            let (guild_id, channel_id) = self.jump_list[index];
+            if self.get_channel_mut(guild_id, channel_id).is_some() {
+                self.jump_index = index;
+                return Some(self.open_channel(guild_id, channel_id));
+            }
+
+            // Removing an older channel shifts the current one down
+            self.jump_list.remove(index);
+            if back {
+                self.jump_index -= 1;
+            }
+        }
+    }
+
+    /// Gets the root of the thread being viewed, if it's in the current channel.
+    fn current_thread(&self) -> Option<u64> {
+        let channel_id = self.current_channel()?.id;
+        self.thread.as_ref().filter(|v| v.channel_id == channel_id).map(|v| v.root)
+    }
+
+    /// Gets the ids of the messages shown in the message pane, oldest first.
+    fn visible_messages(&self) -> Cow<'_, [u64]> {
+        match (self.current_channel(), self.current_thread()) {
+            (Some(channel), Some(root)) => Cow::Owned(channel.thread(root)),
+            (Some(channel), None) => Cow::Borrowed(&channel.messages_list),
+            (None, _) => Cow::Borrowed(&[]),
+        }
+    }
+
+    /// Gets the position of the selected message among the visible messages, counting from the bottom.
+    fn visible_selected(&self) -> usize {
+        let channel = match self.current_channel() {
+            Some(v) => v,
+            None => return 0,
+        };
+
+        match self.current_thread() {
+            Some(root) => {
+                let selected = channel.selected_message().map(|v| v.id);
+                channel.thread(root).iter().rev().position(|&v| Some(v) == selected).unwrap_or(0)
+            }
+            None => channel.scroll_selected(),
+        }
+    }
+
+    /// Moves the selection by the given number of visible messages, up towards older messages if positive.
+    fn scroll_by(&mut self, offset: isize) {
+        let thread = self.current_thread();
+        let selected = self.visible_selected() as isize;
+        if let Some(channel) = self.current_channel_mut() {
+            match thread {
+                Some(root) => {
+                    let ids = channel.thread(root);
+                    let target = (selected + offset).clamp(0, ids.len().saturating_sub(1) as isize) as usize;
+                    if let Some(&id) = ids.iter().rev().nth(target) {
+                        channel.select(id);
+                    }
+                }
+                None => channel.scroll_by(offset),
+            }
+        }
+    }
+
+    /// Opens the thread of the selected message, or closes the open thread and restores the channel's scroll position.
+    fn toggle_thread(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            if let Some(channel) = self.current_channel_mut().filter(|v| v.id == thread.channel_id) {
+                channel.selected = thread.channel_selected;
+            }
+            return;
+        }
+
+        self.thread = self.current_channel().and_then(|channel| {
+            channel.selected_message().map(|v| Thread {
+                channel_id: channel.id,
+                root: v.id,
+                channel_selected: channel.selected,
+            })
+        });
+    }
+
+    /// Replaces the given bytes of the input box with a snippet's expansion, moving the cursor to its marker.
+    fn insert_snippet(&mut self, range: Range<usize>, template: &str) {
+        let guild = self.current_guild().map(|v| v.name.as_str()).unwrap_or("");
+        let channel = self.current_channel().map(|v| v.name.as_str()).unwrap_or("");
+        let (text, cursor) = snippets::expand(template, &SnippetContext { guild, channel });
+
+        let start = range.start;
+        self.input.replace_range(range, &text);
+        self.input.set_cursor(start + cursor);
+    }
+
+    /// Gets the channel to open when a guild is selected: the one configured for the guild, then the one last viewed,
+    /// then the first one.
+    fn default_channel(&self, guild_id: u64) -> Option<u64> {
+        let guild = self.guilds_map.get(&guild_id)?;
+        let configured = self
+            .config
+            .default_channels
+            .get(&guild_id.to_string())
+            .or_else(|| self.config.default_channels.get(&guild.name))
+            .and_then(|name| guild.channels_map.values().find(|v| v.name.eq_ignore_ascii_case(name.trim_start_matches('#'))))
+            .map(|v| v.id);
+
+        configured.or(guild.current_channel).or_else(|| guild.channels_list.first().cloned())
+    }
+
+    /// Opens the default channel of a guild, or lets the user pick one if it has none.
+    /// Returns whether the channel has no messages loaded yet.
+    fn open_default_channel(&mut self, guild_id: u64) -> bool {
+        match self.default_channel(guild_id) {
+            Some(channel_id) => {
+                self.mode = AppMode::TextNormal;
+                self.visit_channel(guild_id, channel_id)
+            }
+
+            None => {
+                self.mode = AppMode::ChannelSelect;
+                false
+            }
+        }
+    }
+
+    /// Finds a guild by name, preferring an exact match (ignoring case) over the best fuzzy match.
+    fn find_guild(&self, name: &str) -> Option<u64> {
+        let guilds = self.guilds_list.iter().filter_map(|v| self.guilds_map.get(v));
+        if let Some(guild) = guilds.clone().find(|v| v.name.eq_ignore_ascii_case(name)) {
+            return Some(guild.id);
+        }
+
+        guilds
+            .filter_map(|v| fuzzy::score(name, &v.name).map(|m| (v.id, m.score)))
+            .max_by_key(|&(_, score)| score)
+            .map(|(id, _)| id)
+    }
+
+    /// Creates the popup asking whether to leave a guild.
+    fn leave_prompt(&self, guild_id: u64) -> Option<Modal> {
+        self.guilds_map.get(&guild_id).map(|v| Modal::confirm(
+            "leave this guild?",
+            Text::from(Span::styled(v.name.clone(), self.theme.header)),
+            ConfirmAction::LeaveGuild(v.id),
+        ))
+    }
+
+    /// Creates one of the two prompts asking whether to delete the user's account, the second being the last chance.
+    fn delete_account_prompt(&self, last: bool) -> Modal {
+        let name = self.users.get(&self.current_user).map(|v| v.name.clone()).unwrap_or_default();
+        let body = if last {
+            Text::from(vec![
+                Spans::from(Span::styled("This can't be undone.", self.theme.danger)),
+                Spans::from(""),
+                Spans::from("The homeserver forgets the account for good."),
+            ])
+        } else {
+            Text::from(vec![
+                Spans::from(Span::styled(name, self.theme.header)),
+                Spans::from(""),
+                Spans::from("The account and everything the homeserver keeps"),
+                Spans::from("about it are deleted, then local data is wiped"),
+                Spans::from("and the client quits. Export your data first"),
+                Spans::from("with :account export if you want a copy."),
+            ])
+        };
+        let title = if last { "really delete your account?" } else { "delete your account?" };
+        Modal::confirm(title, body, ConfirmAction::DeleteAccount(last))
+    }
+
+    /// Gets the open popup changing an account setting, if any.
+    fn account_flow(&mut self) -> Option<&mut AuthState> {
+        self.modals.iter_mut().rev().find_map(|v| match &mut v.kind {
+            ModalKind::AccountFlow(flow) => Some(flow),
+            _ => None,
+        })
+    }
+
+    /// Closes the popup changing an account setting.
+    fn close_account_flow(&mut self) {
+        self.modals.retain(|v| !matches!(v.kind, ModalKind::AccountFlow(_)));
+    }
+
+    /// Creates a prompt asking whether to wipe the local data of a guild, or of every guild if the id is `None`.
+    fn purge_prompt(&self, guild_id: Option<u64>) -> Option<Modal> {
+        let scope = match guild_id {
+            Some(guild_id) => self.guilds_map.get(&guild_id)?.name.clone(),
+            None => String::from("every guild"),
+        };
+
+        let body = Text::from(vec![
+            Spans::from(Span::styled(scope, self.theme.header)),
+            Spans::from(""),
+            Spans::from("Loaded messages, unread markers, saved messages,"),
+            Spans::from("downloaded attachments, and the draft are removed"),
+            Spans::from("from memory and disk. The homeserver keeps everything."),
+        ]);
+        Some(Modal::confirm("wipe local data?", body, ConfirmAction::PurgeLocal(guild_id)))
+    }
+
+    /// Wipes the loaded messages, unread state, saved messages, and downloaded attachments of a guild, or of every guild
+    /// if the id is `None`, along with the draft if it's in one of them. The guilds and channels stay.
+    /// Returns whether the current channel was wiped, so its messages can be loaded again.
+    fn purge_local(&mut self, guild_id: Option<u64>) -> bool {
+        let in_scope = |id: u64| guild_id.is_none() || guild_id == Some(id);
+        let mut attachments = vec![];
+        for guild in self.guilds_map.values_mut().filter(|v| in_scope(v.id)) {
+            guild.unseen_channels.clear();
+            for channel in guild.channels_map.values_mut() {
+                for message in channel.messages_map.values() {
+                    if let MessageContent::Files(files) = &message.content {
+                        attachments.extend(files.iter().filter_map(Attachment::cache_path));
+                    }
+                }
+
+                channel.messages_map.clear();
+                channel.messages_list.clear();
+                channel.selected = None;
+                channel.view_bottom.store(0, Ordering::Relaxed);
+                channel.view_offset.store(0, Ordering::Relaxed);
+                channel.new_below = 0;
+                channel.requested_before = None;
+                channel.first_unread = None;
+                channel.history = History::Unloaded;
+                channel.readers.clear();
+            }
+        }
+
+        // Every downloaded attachment goes at once when everything is wiped, including ones no longer loaded
+        match guild_id {
+            Some(_) => {
+                for path in attachments {
+                    std::fs::remove_file(path).ok();
+                }
+            }
+            None => {
+                cache::clear().ok();
+                self.registers = Registers::default();
+            }
+        }
+
+        match guild_id {
+            Some(guild_id) => self.inbox.remove_guild(guild_id),
+            None => self.inbox.clear(),
+        }
+
+        self.bookmarks.retain(|v| !in_scope(v.guild_id));
+        bookmarks::save(self.current_user, &self.bookmarks);
+
+        let current = self.current_guild.map(in_scope).unwrap_or(false);
+        if current {
+            self.thread = None;
+            self.editing = false;
+            self.input.clear();
+            self.old_input.clear();
+            if matches!(self.mode, AppMode::TextInsert) {
+                self.mode = AppMode::TextNormal;
+            }
+        }
+        current
+    }
+
+    /// Replaces the config with one reloaded from disk, returning whether any of it needs a restart to take effect.
+    fn apply_config(&mut self, mut config: Config) -> bool {
+        config.low_data |= low_data_flag();
+        if config.guild_sort != self.config.guild_sort {
+            self.guild_sort = config.guild_sort;
+            self.sort_guilds();
+        }
+
+        self.theme = Theme::from_config(&config);
+        cache::set_limit(&config.media.cache_size);
+        multiplexer::configure(&config.multiplexer);
+        let restart = config.encrypt_data != self.config.encrypt_data || config.relay != self.config.relay || config.metrics != self.config.metrics;
+        self.config = config;
+        restart
+    }
+
+    /// Describes each user in the online popup by their name, status, and the guild shared with them.
+    fn online_items(&self, contacts: &[(u64, u64)]) -> Vec<(u64, String)> {
+        contacts
+            .iter()
+            .enumerate()
+            .map(|(i, &(user_id, guild_id))| {
+                let (name, status) = self
+                    .users
+                    .get(&user_id)
+                    .map(|v| (v.name.as_str(), v.status))
+                    .unwrap_or(("<unknown user>", UserStatus::OfflineUnspecified));
+                let guild = self.guilds_map.get(&guild_id).map(|v| v.name.as_str()).unwrap_or_default();
+                (i as u64, format!("{} ({}) in {}", name, presence::status_name(status), guild))
+            })
+            .collect()
+    }
+
+    /// Updates the statuses shown in the online popup if it's open.
+    fn refresh_online(&mut self) {
+        let contacts = self.modals.iter().find_map(|v| match &v.kind {
+            ModalKind::List { list: ListKind::Online(contacts), .. } => Some(contacts.clone()),
+            _ => None,
+        });
+
+        if let Some(contacts) = contacts {
+            let updated = self.online_items(&contacts);
+            for modal in self.modals.iter_mut() {
+                if let ModalKind::List { items, list: ListKind::Online(_), .. } = &mut modal.kind {
+                    *items = updated.clone();
+                }
+            }
+        }
+    }
+
+    /// Gets the role editor open for a guild, if any.
+    fn role_editor_mut(&mut self, guild_id: u64) -> Option<&mut RoleEditor> {
+        self.modals.iter_mut().find_map(|v| match &mut v.kind {
+            ModalKind::Roles(editor) if editor.guild_id == guild_id => Some(editor),
+            _ => None,
+        })
+    }
+
+    /// Gets every channel as (guild id, channel id), in the order of the guild and channel lists.
+    fn all_channels(&self) -> Vec<(u64, u64)> {
+        self.guilds_list
+            .iter()
+            .filter_map(|v| self.guilds_map.get(v))
+            .flat_map(|guild| guild.channels_list.iter().map(move |&v| (guild.id, v)))
+            .collect()
+    }
+
+    /// Finds a channel written as `#channel` in the current guild or `guild#channel` in any guild.
+    fn find_channel(&self, target: &str) -> Option<(u64, u64)> {
+        let (guild, channel) = target.split_once('#')?;
+        let guild = if guild.is_empty() {
+            self.current_guild()?
+        } else {
+            self.guilds_map.values().find(|v| v.name.eq_ignore_ascii_case(guild))?
+        };
+
+        guild.channels_map.values().find(|v| v.name.eq_ignore_ascii_case(channel)).map(|v| (guild.id, v.id))
+    }
+
+    /// Gets the names channels can be written as for `find_channel`: `#channel` for the current guild and
+    /// `guild#channel` for guilds whose names are a single word.
+    fn channel_targets(&self) -> Vec<String> {
+        let mut targets = vec![];
+        if let Some(guild) = self.current_guild() {
+            targets.extend(guild.channels_list.iter().filter_map(|v| guild.channels_map.get(v)).map(|v| format!("#{}", v.name)));
+        }
+
+        for guild in self.guilds_list.iter().filter_map(|v| self.guilds_map.get(v)).filter(|v| !v.name.contains(' ')) {
+            targets.extend(guild.channels_list.iter().filter_map(|v| guild.channels_map.get(v)).map(|v| format!("{}#{}", guild.name, v.name)));
+        }
+        targets
+    }
+
+    /// Gets the name of a channel prefixed with its guild's name, for lists of channels across guilds.
+    fn channel_label(&self, guild_id: u64, channel_id: u64) -> Option<String> {
+        let guild = self.guilds_map.get(&guild_id)?;
+        let channel = guild.channels_map.get(&channel_id)?;
+        Some(format!("{} #{}", guild.name, channel.name))
+    }
+
+    /// Gets the most recently active or visited channels across all guilds as (guild id, channel id), most recent first.
+    fn recent_channels(&self) -> Vec<(u64, u64)> {
+        let mut channels: Vec<_> = self
+            .guilds_map
+            .values()
+            .flat_map(|v| v.channels_map.values())
+            .filter(|v| v.last_activity > 0 || v.last_visited > 0)
+            .map(|v| (v.last_activity.max(v.last_visited), v.guild_id, v.id))
+            .collect();
+        channels.sort_by_key(|v| std::cmp::Reverse(v.0));
+        channels.into_iter().take(RECENT_CHANNELS).map(|(_, guild_id, channel_id)| (guild_id, channel_id)).collect()
+    }
+
+    /// Switches to the given channel and selects the given message if it's loaded.
+    /// Returns whether the message isn't loaded, so history has to be loaded back to it with `LoadMessage`.
+    fn jump_to_message(&mut self, guild_id: u64, channel_id: u64, message_id: u64) -> bool {
+        self.visit_channel(guild_id, channel_id);
+        let loaded = self.get_channel_mut(guild_id, channel_id).map_or(false, |channel| {
+            channel.select(message_id);
+            channel.messages_map.contains_key(&message_id)
+        });
+
+        self.mode = AppMode::Scroll;
+        !loaded
+    }
+
+    fn current_guild_mut(&mut self) -> Option<&mut Guild> {
+        self.current_guild.and_then(|v| self.guilds_map.get_mut(&v))
+    }
+
+    fn current_channel_mut(&mut self) -> Option<&mut Channel> {
+        self.current_guild_mut().and_then(Guild::current_channel_mut)
+    }
+
+    fn get_channel(&self, guild_id: u64, channel_id: u64) -> Option<&Channel> {
+        self.guilds_map.get(&guild_id).and_then(|v| v.channels_map.get(&channel_id))
+    }
+
+    fn get_channel_mut(&mut self, guild_id: u64, channel_id: u64) -> Option<&mut Channel> {
+        self.guilds_map.get_mut(&guild_id).and_then(|v| v.channels_map.get_mut(&channel_id))
+    }
+
+    /// Sorts the guild list according to the current sort mode, keeping the selection on the same guild.
+    fn sort_guilds(&mut self) {
+        let selected = self.guilds_select.and_then(|v| self.guilds_list.get(v)).cloned();
+        let guilds_map = &self.guilds_map;
+
+        match self.guild_sort {
+            GuildSort::Manual => self.guilds_list.sort_by_key(|v| guilds_map.get(v).map(|v| v.position)),
+            GuildSort::Activity => self.guilds_list.sort_by_key(|v| std::cmp::Reverse(guilds_map.get(v).map(|v| v.last_activity))),
+        }
+
+        if let Some(selected) = selected {
+            self.guilds_select = self.guilds_list.iter().position(|&v| v == selected);
+        }
+    }
+
+    /// Gets the indices of the guilds matching the list filter.
+    fn filtered_guilds(&self) -> Vec<usize> {
+        let names = self.guilds_list.iter().map(|v| self.guilds_map.get(v).map(|v| v.name.as_str()).unwrap_or(""));
+        filter_indices(self.list_filter.as_deref(), names)
+    }
+
+    /// Gets the indices of the current guild's channels matching the list filter.
+    fn filtered_channels(&self) -> Vec<usize> {
+        match self.current_guild() {
+            Some(guild) => {
+                let names = guild.channels_list.iter().map(|v| guild.channels_map.get(v).map(|v| v.name.as_str()).unwrap_or(""));
+                filter_indices(self.list_filter.as_deref(), names)
+            }
+            None => vec![],
+        }
+    }
+
+    /// Gets the notification level of a channel.
+    fn notify_level(&self, guild_id: u64, channel_id: u64) -> NotifyLevel {
+        self.notify_levels
+            .get(&(guild_id, channel_id))
+            .cloned()
+            .unwrap_or(self.config.notifications.default_level)
+    }
+
+    /// Checks whether the given text mentions the user or contains a highlight word for the given guild.
+    fn is_highlighted(&self, guild_id: u64, text: &FormattedText) -> bool {
+        let mentioned = text.format.iter().any(|v| matches!(&v.format, Some(Format::UserMention(mention)) if mention.user_id == self.current_user));
+        let guild_name = self.guilds_map.get(&guild_id).map(|v| v.name.as_str()).unwrap_or("");
+        mentioned || notifications::contains_word(&text.text, self.config.highlight.words_for(guild_id, guild_name))
+    }
+
+    /// Adds a notice to the end of a channel, shown as sent at the given unix timestamp.
+    fn push_notice(&mut self, guild_id: u64, channel_id: u64, text: String, timestamp: u64) {
+        // Notices count down from the maximum id so they don't collide with real messages
+        self.notice_count += 1;
+        let id = u64::MAX - self.notice_count;
+
+        if let Some(channel) = self.get_channel_mut(guild_id, channel_id) {
+            channel.insert_message(Message {
+                id,
+                author_id: 0,
+                override_username: None,
+                content: MessageContent::Notice(text),
+                timestamp,
+                edited_timestamp: None,
+                translation: None,
+                highlighted: false,
+                in_reply_to: None,
+                bridged: None,
+            });
+        }
+    }
+
+    /// Records activity in a channel, marking it as unseen if the user isn't looking at it.
+    fn record_activity(&mut self, guild_id: u64, channel_id: u64, timestamp: u64, author_id: u64) {
+        let viewing = self.current_guild == Some(guild_id)
+            && self.current_guild().and_then(|v| v.current_channel) == Some(channel_id);
+        let current_user = self.current_user;
+        let muted = self.notify_level(guild_id, channel_id) == NotifyLevel::Nothing;
+
+        if let Some(guild) = self.guilds_map.get_mut(&guild_id) {
+            guild.last_activity = guild.last_activity.max(timestamp);
+            if !viewing && !muted && author_id != current_user {
+                guild.unseen_channels.insert(channel_id);
+            }
+
+            if let Some(channel) = guild.channels_map.get_mut(&channel_id) {
+                channel.last_activity = channel.last_activity.max(timestamp);
+                if !viewing && author_id != current_user && channel.first_unread.is_none() {
+                    channel.first_unread = Some(timestamp);
+                }
+            }
+        }
+
+        if let GuildSort::Activity = self.guild_sort {
+            self.sort_guilds();
+        }
+    }
+}
+
+/// Checks whether low data mode was turned on for this session with `--low-data`.
+fn low_data_flag() -> bool {
+    std::env::args().skip(1).any(|v| v == "--low-data")
+}
+
+/// Gets the guild and channel given with `--channel <guild>/<channel>`, the only channel shown for the session.
+fn channel_flag() -> Result<Option<(String, String)>, String> {
+    let args: Vec<_> = std::env::args().skip(1).collect();
+    let target = match args.iter().position(|v| v == "--channel") {
+        Some(i) => args.get(i + 1).ok_or("usage: ilo-toki --channel <guild>/<channel>")?,
+        None => return Ok(None),
+    };
+
+    match target.rsplit_once('/') {
+        Some((guild, channel)) if !guild.is_empty() && !channel.is_empty() => {
+            Ok(Some((guild.to_owned(), channel.trim_start_matches('#').to_owned())))
+        }
+        _ => Err(format!("invalid channel {}, expected <guild>/<channel>", target)),
+    }
+}
+
+/// Checks whether every channel was made read only for this session with `--read-only`.
+fn read_only_flag() -> bool {
+    std::env::args().skip(1).any(|v| v == "--read-only")
+}
+
+/// Checks whether this process should run as a daemon that terminals attach to, with `--daemon`.
+fn daemon_flag() -> bool {
+    std::env::args().skip(1).any(|v| v == "--daemon")
+}
+
+/// Runs the client until the user quits.
+pub async fn run() -> ClientResult<()> {
+    // Stop everything cleanly on quit, signals, and panics
+    let shutdown = CancellationToken::new();
+    tokio::spawn(shutdown::watch_signals(shutdown.clone()));
+    shutdown::install_panic_hook();
+
+    // With a daemon already running, this terminal attaches to it instead of connecting on its own
+    let socket = daemon::socket_path();
+    if let Some(socket) = socket.as_ref().filter(|_| !daemon_flag()) {
+        match daemon::attach(socket, &shutdown).await {
+            Ok(true) => return Ok(()),
+            Ok(false) => (),
+            Err(e) => {
+                shutdown::restore_terminal();
+                eprintln!("could not attach to the daemon: {}", e);
+                return Ok(());
+            }
+        }
+    }
+
+    // Set up the state, falling back to the default config if it's invalid
+    let (mut config, config_error) = match Config::try_load() {
+        Ok(config) => (config, None),
+        Err(e) => (Config::default(), Some(format!("could not load config, using the defaults: {}", e))),
+    };
+    config.low_data |= low_data_flag();
+    cache::set_limit(&config.media.cache_size);
+    multiplexer::configure(&config.multiplexer);
+
+    let single_channel = match channel_flag() {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("{}", e);
+            return Ok(());
+        }
+    };
+
+    let theme = Theme::from_config(&config);
+
+    // Read input in the background for as long as the program runs, or until a daemon stops using this terminal
+    let local_input = shutdown.child_token();
+    let mut input_events = input::spawn(local_input.clone());
+
+    // Saved data can only be read once it's unlocked
+    match vault::unlock(config.encrypt_data, &theme, &shutdown, &mut input_events).await {
+        Ok(true) => (),
+        Ok(false) => {
+            shutdown::restore_terminal();
+            return Ok(());
+        }
+        Err(e) => {
+            shutdown::restore_terminal();
+            eprintln!("could not unlock saved data: {}", e);
+            return Ok(());
+        }
+    }
+
+    // Programs opening attachments need them unencrypted, so with encryption on they're only kept for the session
+    if config.encrypt_data {
+        if let Err(e) = cache::use_private() {
+            shutdown::restore_terminal();
+            eprintln!("could not make a private media cache: {}", e);
+            return Ok(());
+        }
+    }
+
+    let state = Arc::new(RwLock::new(AppState {
+        guild_sort: config.guild_sort,
+        notify_levels: notifications::load_levels(),
+        last_input: presence::now(),
+        status_text: presence::load_status_text(),
+        splash: Some(Splash::default()),
+        read_only: read_only_flag(),
+        status: config_error,
+        config,
+        theme,
+        ..AppState::default()
+    }));
+
+    // Create a mpsc channel
+    let (tx, mut rx) = mpsc::channel(128);
+
+    // Get auth data
+    let homeserver_default = "https://chat.harmonyapp.io:2289";
+    let auth_data = dirs::data_dir().and_then(|v| vault::read_to_string(&v.join("ilo-toki/auth")).ok());
+
+    // Create client
+    let client = if let Some(auth_data) = auth_data {
+        let mut split = auth_data.split('\n');
+        let homeserver = split.next().unwrap_or(homeserver_default);
+        let token = split.next();
+        let user_id = split.next().and_then(|v| v.parse().ok());
+        let session = match (token, user_id) {
+            (Some(token), Some(user_id)) => Some(Session::new(user_id, String::from(token))),
+            _ => None,
+        };
+        Client::new(homeserver.parse().unwrap_or_else(|_| homeserver_default.parse().unwrap()), session)
+            .await
+            .unwrap()
+    } else {
+        Client::new(homeserver_default.parse().unwrap(), None)
+            .await
+            .unwrap()
+    };
+    let input_events = if client.auth_status().is_authenticated() {
+        input_events
+    } else {
+        auth(&client, theme, &shutdown, input_events).await
+    };
+
+    if shutdown.is_cancelled() {
+        shutdown::restore_terminal();
+        return Ok(());
+    } else if let Some(auth_path) = dirs::data_dir() {
+        std::fs::create_dir(auth_path.join("ilo-toki/")).ok();
+        let auth_status = client.auth_status();
+        let auth = auth_status.session().unwrap();
+        vault::write(&auth_path.join("ilo-toki/auth"), &format!("{}\n{}\n{}\n", client.homeserver_url(), auth.session_token, auth.user_id)).unwrap();
+    }
+
+    // A daemon takes its input from the terminals attached to it and draws for them instead of this one
+    let input_events = match socket.filter(|_| daemon_flag()) {
+        Some(socket) => match daemon::listen(&socket, shutdown.clone()) {
+            Ok(input_events) => {
+                // Reading a terminal the daemon was moved to the background from would stop it
+                local_input.cancel();
+                shutdown::restore_terminal();
+                println!("listening on {}; run ilo-toki to attach and ctrl+\\ to detach", socket.display());
+                input_events
+            }
+            Err(e) => {
+                shutdown::restore_terminal();
+                eprintln!("could not start the daemon: {}", e);
+                return Ok(());
+            }
+        },
+        None => input::from_local(input_events),
+    };
+
+    // Spawn UI stuff
+    let supervisor = Supervisor::new(shutdown.clone());
+    let tui = supervisor.critical("ui", {
+        let (state, shutdown) = (state.clone(), shutdown.clone());
+        async move { tui(state, shutdown).await.map_err(|e| e.to_string()) }
+    });
+    supervisor.critical("input handler", {
+        let (state, tx, shutdown) = (state.clone(), tx.clone(), shutdown.clone());
+        async move {
+            ui_events(state, tx, input_events, shutdown).await;
+            Ok(())
+        }
+    });
+
+    // Everything past logging in goes through the backend
+    // Calls are logged for `:debug`
+    let client = Arc::new(client);
+    let (debug_log, stats) = {
+        let state = state.read().await;
+        (state.debug_log.clone(), state.stats.clone())
+    };
+    let backend: Arc<dyn ChatBackend> = Arc::new(LoggingBackend::new(client.clone(), debug_log, stats.clone()));
+
+    // Load everything before showing the UI, stopping if the user quits from the splash screen
+    let sync = async {
+        // Change our status to online
+        backend.set_status(UserStatus::Online).await.unwrap();
+
+        // Our account's user id
+        let self_id = client.auth_status().session().unwrap().user_id;
+        {
+            let mut state = state.write().await;
+            state.loading(Step::SavedData);
+            state.current_user = self_id;
+            state.homeserver = client.homeserver_url().to_string();
+            state.token = client.auth_status().session().map(|v| v.session_token.clone()).unwrap_or_default();
+            state.bookmarks = bookmarks::load(self_id);
+            schedule::load(&mut state);
+            state.filters = filters::load(self_id);
+            state.loading(Step::Capabilities);
+        }
+
+        // Find out what the homeserver supports so unsupported features can say so instead of failing
+        let capabilities = backend.capabilities().await.unwrap_or_default();
+        let status_text = {
+            let mut state = state.write().await;
+            state.capabilities = capabilities;
+            state.loading(Step::GuildList);
+            state.status_text.clone().filter(|_| state.capabilities.supports(Feature::StatusText))
+        };
+
+        // Restore the status text from last time
+        if status_text.is_some() {
+            let _ = tx.send(ClientEvent::SetStatusText(status_text)).await;
+        }
+
+        // Event filters
+        let guilds = backend.guild_list().await.unwrap();
+        let mut events = vec![
+            EventSource::Homeserver,
+            EventSource::Action,
+        ];
+        events.extend(guilds.iter().map(|&v| EventSource::Guild(v)));
+
+        {
+            let mut state = state.write().await;
+            state.subscribed_guilds.extend(guilds.iter().copied());
+            state.loading(Step::Guilds);
+        }
+
+        // The state isn't locked while fetching so the splash screen can show the progress
+        for (i, &guild_id) in guilds.iter().enumerate() {
+            if let Some(splash) = &mut state.write().await.splash {
+                splash.progress(i, guilds.len());
+            }
+            let guild = backend.get_guild(guild_id).await.unwrap();
+            if let Some(guild) = guild {
+                let mut state = state.write().await;
+                let guild = Guild {
+                    id: guild_id,
+                    channels_list: vec![],
+                    channels_select: None,
+                    channels_map: HashMap::new(),
+                    name: guild.name,
+                    current_channel: None,
+                    position: state.guilds_list.len(),
+                    last_activity: 0,
+                    unseen_channels: HashSet::new(),
+                    member_count: None,
+                    permissions: HashMap::new(),
+                };
+                state.guilds_list.push(guild_id);
+                state.guilds_map.insert(guild_id, guild);
+            }
+        }
+
+        // Open the channel given with `--channel` before anything is shown
+        if let Some((guild_name, channel_name)) = &single_channel {
+            let guild_id = state.read().await.find_guild(guild_name);
+            let channels = match guild_id {
+                Some(guild_id) => backend.get_channels(guild_id).await.unwrap(),
+                None => vec![],
+            };
+
+            let mut state = state.write().await;
+            let found = guild_id.and_then(|guild_id| {
+                let guild = state.guilds_map.get_mut(&guild_id)?;
+                guild.merge_channels(channels);
+                guild.channels_map.values().find(|v| v.name.eq_ignore_ascii_case(channel_name)).map(|v| (guild_id, v.id))
+            });
+
+            match found {
+                Some((guild_id, channel_id)) => {
+                    state.single_channel = true;
+                    state.current_guild = Some(guild_id);
+                    state.guilds_select = state.guilds_list.iter().position(|&v| v == guild_id);
+                    if state.visit_channel(guild_id, channel_id) {
+                        let _ = tx.send(ClientEvent::GetMoreMessages(None)).await;
+                    }
+                }
+
+                None => state.status = Some(format!("could not find {}/{}, so every channel is shown", guild_name, channel_name)),
+            }
+        }
+
+        state.write().await.splash = None;
+        (self_id, events)
+    };
+
+    let (self_id, events) = tokio::select! {
+        v = sync => v,
+        _ = shutdown.cancelled() => {
+            let _ = tui.await;
+            shutdown::restore_terminal();
+            std::process::exit(0);
+        }
+    };
+
+    // Spawn event loop
+    supervisor.restarting(
+        "presence task",
+        {
+            let (state, tx, shutdown) = (state.clone(), tx.clone(), shutdown.clone());
+            move |_| {
+                let task = presence::presence_task(state.clone(), tx.clone(), shutdown.clone());
+                async move {
+                    task.await;
+                    Ok(())
+                }
+            }
+        },
+        report_status(&state),
+    );
+    supervisor.restarting(
+        "channel header",
+        {
+            let (state, tx, shutdown) = (state.clone(), tx.clone(), shutdown.clone());
+            move |_| {
+                let task = header::header_task(state.clone(), tx.clone(), shutdown.clone());
+                async move {
+                    task.await;
+                    Ok(())
+                }
+            }
+        },
+        report_status(&state),
+    );
+    supervisor.restarting(
+        "config reload",
+        {
+            let (state, shutdown) = (state.clone(), shutdown.clone());
+            move |_| {
+                let task = reload::reload_task(state.clone(), shutdown.clone());
+                async move {
+                    task.await;
+                    Ok(())
+                }
+            }
+        },
+        report_status(&state),
+    );
+    supervisor.restarting(
+        "rich presence",
+        {
+            let (state, tx, shutdown) = (state.clone(), tx.clone(), shutdown.clone());
+            move |_| {
+                let task = presence::rich_presence_task(state.clone(), tx.clone(), shutdown.clone());
+                async move {
+                    task.await;
+                    Ok(())
+                }
+            }
+        },
+        report_status(&state),
+    );
+    supervisor.restarting(
+        "scheduler",
+        {
+            let (state, tx, shutdown) = (state.clone(), tx.clone(), shutdown.clone());
+            move |_| {
+                let task = schedule::schedule_task(state.clone(), tx.clone(), shutdown.clone());
+                async move {
+                    task.await;
+                    Ok(())
+                }
+            }
+        },
+        report_status(&state),
+    );
+    supervisor.restarting(
+        "connection health check",
+        {
+            let (state, backend, shutdown) = (state.clone(), backend.clone(), shutdown.clone());
+            move |_| {
+                let task = health::health_task(state.clone(), backend.clone(), self_id, shutdown.clone());
+                async move {
+                    task.await;
+                    Ok(())
+                }
+            }
+        },
+        report_status(&state),
+    );
+    supervisor.restarting(
+        "event loop lag measurement",
+        {
+            let shutdown = shutdown.clone();
+            move |_| {
+                let task = stats::lag_task(stats.clone(), shutdown.clone());
+                async move {
+                    task.await;
+                    Ok(())
+                }
+            }
+        },
+        report_status(&state),
+    );
+    supervisor.restarting(
+        "relay",
+        {
+            let (state, tx, shutdown) = (state.clone(), tx.clone(), shutdown.clone());
+            move |_| relay::relay_task(state.clone(), tx.clone(), shutdown.clone())
+        },
+        report_status(&state),
+    );
+    supervisor.restarting(
+        "metrics endpoint",
+        {
+            let (state, shutdown) = (state.clone(), shutdown.clone());
+            move |_| metrics::metrics_task(state.clone(), shutdown.clone())
+        },
+        report_status(&state),
+    );
+    supervisor.restarting(
+        "read receipts",
+        {
+            let (state, tx, shutdown) = (state.clone(), tx.clone(), shutdown.clone());
+            move |_| {
+                let task = receipts::receipts_task(state.clone(), tx.clone(), shutdown.clone());
+                async move {
+                    task.await;
+                    Ok(())
+                }
+            }
+        },
+        report_status(&state),
+    );
+    supervise_events(&supervisor, &state, &client, events, &tx, &shutdown);
+
+    // Send events
+    loop {
+        let event = tokio::select! {
+            event = rx.recv() => match event {
+                Some(event) => event,
+                None => break,
+            },
+            _ = shutdown.cancelled() => break,
+        };
+
+        // Nothing is sent to read only channels, however it was asked for
+        if state.read().await.is_blocked(&event) {
+            state.write().await.status = Some(String::from("this channel is read only"));
+            continue;
+        }
+
+        match event {
+            // Send messages
+            ClientEvent::Send(msg) => {
+                let target = state.read().await.current_channel().map(|v| (v.guild_id, v.id));
+                if let Some((guild_id, channel_id)) = target {
+                    let content = chat::Content::new(Some(Content::new_text_message(TextContent::new(Some(FormattedText::new(msg, vec![]))))));
+                    send_typed(&*backend, &state, guild_id, channel_id, content).await;
+                }
+            }
+
+            // Send actions as wholly italic text
+            ClientEvent::SendAction(msg) => {
+                let target = state.read().await.current_channel().map(|v| (v.guild_id, v.id));
+                if let Some((guild_id, channel_id)) = target {
+                    let italic = chat::Format {
+                        start: 0,
+                        length: msg.len() as _,
+                        format: Some(Format::Italic(chat::format::Italic {})),
+                    };
+                    let text = TextContent::new(Some(FormattedText::new(msg, vec![italic])));
+                    let content = chat::Content::new(Some(Content::new_text_message(text)));
+                    send_typed(&*backend, &state, guild_id, channel_id, content).await;
+                }
+            }
+
+            // Send scheduled messages
+            ClientEvent::SendTo(guild_id, channel_id, msg) => {
+                send_text(&*backend, &state, guild_id, channel_id, msg).await;
+            }
+
+            // Send uploaded files
+            ClientEvent::SendAttachment(guild_id, channel_id, attachment, size) => {
+                let file = chat::Attachment {
+                    id: attachment.id,
+                    name: attachment.name,
+                    mimetype: attachment.mimetype,
+                    size: size as _,
+                    ..Default::default()
+                };
+
+                let content = chat::Content::new(Some(Content::AttachmentMessage(chat::content::AttachmentContent { files: vec![file] })));
+                if let Err(e) = backend.send_message(guild_id, channel_id, content).await {
+                    state.write().await.request_failed("send the attachment", &e);
+                }
+            }
+
+            // Forward messages, reusing the files already on the homeserver
+            ClientEvent::Forward(guild_id, channel_id, text, attachments) => {
+                send_text(&*backend, &state, guild_id, channel_id, text).await;
+
+                if !attachments.is_empty() {
+                    let files = attachments
+                        .into_iter()
+                        .map(|v| chat::Attachment {
+                            id: v.id,
+                            name: v.name,
+                            mimetype: v.mimetype,
+                            ..Default::default()
+                        })
+                        .collect();
+                    let content = chat::Content::new(Some(Content::AttachmentMessage(chat::content::AttachmentContent { files })));
+                    if let Err(e) = backend.send_message(guild_id, channel_id, content).await {
+                        state.write().await.request_failed("forward the attachments", &e);
+                    }
+                }
+            }
+
+            // Send hand written payloads, saying why the homeserver refused them since they're easy to get wrong
+            ClientEvent::SendContent(guild_id, channel_id, content, overrides) => {
+                let result = match overrides {
+                    Some(overrides) => backend.send_message_as(guild_id, channel_id, content, overrides).await,
+                    None => backend.send_message(guild_id, channel_id, content).await,
+                };
+                if let Err(e) = result {
+                    state.write().await.status = Some(format!("could not send: {}", e));
+                }
+            }
+
+            // Quit
+            ClientEvent::Quit => break,
+
+            // Get more messages
+            ClientEvent::GetMoreMessages(message_id) => {
+                fetch_page(&state, &*backend, message_id).await;
+            }
+
+            // Get messages until a point in history
+            ClientEvent::LoadUntil(timestamp) => {
+                let mut previous = None;
+                loop {
+                    let oldest = state
+                        .read()
+                        .await
+                        .current_channel()
+                        .and_then(|v| v.messages_list.first().and_then(|id| v.messages_map.get(id)))
+                        .map(|v| (v.id, v.timestamp));
+
+                    match oldest {
+                        Some((_, oldest)) if oldest <= timestamp => break,
+                        Some((id, _)) if previous == Some(id) => break,
+                        _ => (),
+                    }
+
+                    previous = oldest.map(|v| v.0);
+                    if fetch_page(&state, &*backend, previous).await == 0 {
+                        break;
+                    }
+                }
+
+                let mut state = state.write().await;
+                if let Some(channel) = state.current_channel_mut() {
+                    let first = channel
+                        .messages_list
+                        .iter()
+                        .position(|v| channel.messages_map.get(v).map(|v| v.timestamp >= timestamp).unwrap_or(false));
+                    if let Some(i) = first {
+                        let id = channel.messages_list[i];
+                        channel.select(id);
+                    }
+                }
+                state.mode = AppMode::Scroll;
+            }
+
+            // Page back through history to a message, giving up if it can't be found that far back
+            ClientEvent::LoadMessage(message_id) => {
+                let mut previous = None;
+                for _ in 0..JUMP_PAGES {
+                    let (loaded, oldest) = {
+                        let state = state.read().await;
+                        let channel = state.current_channel();
+                        (
+                            channel.map_or(false, |v| v.messages_map.contains_key(&message_id)),
+                            channel.and_then(|v| v.messages_list.first().copied()),
+                        )
+                    };
+
+                    if loaded || (oldest.is_some() && oldest == previous) {
+                        break;
+                    }
+
+                    previous = oldest;
+                    if fetch_page(&state, &*backend, previous).await == 0 {
+                        break;
+                    }
+                }
+
+                let mut state = state.write().await;
+                let found = state.current_channel_mut().map_or(false, |channel| {
+                    channel.select(message_id);
+                    channel.messages_map.contains_key(&message_id)
+                });
+                if !found {
+                    state.status = Some(format!(
+                        "couldn't find the message in the last {} pages of history; it may have been deleted, or `:history <date>` loads further back",
+                        JUMP_PAGES
+                    ));
+                }
+                state.mode = AppMode::Scroll;
+            }
+
+            // Delete a message
+            ClientEvent::Delete(message_id) => {
+                let target = state.read().await.current_guild().and_then(|v| v.current_channel.map(|c| (v.id, c)));
+                if let Some((guild_id, channel_id)) = target {
+                    if let Err(e) = backend.delete_message(guild_id, channel_id, message_id).await {
+                        state.write().await.request_failed("delete the message", &e);
+                    }
+                }
+            }
+
+            // Edit a message
+            ClientEvent::Edit(message_id, edit) => {
+                let target = state.read().await.current_guild().and_then(|v| v.current_channel.map(|c| (v.id, c)));
+                if let Some((guild_id, channel_id)) = target {
+                    if let Err(e) = backend.edit_message(guild_id, channel_id, message_id, edit).await {
+                        state.write().await.request_failed("edit the message", &e);
+                    }
+                }
+            }
+
+            ClientEvent::GetChannels(guild_id) => {
+                let channels = backend.get_channels(guild_id).await;
+                let mut state = state.write().await;
+                let channels = match channels {
+                    Ok(channels) => channels,
+                    Err(e) => {
+                        state.request_failed("get the channels", &e);
+                        continue;
+                    }
+                };
+                if let Some(guild) = state.guilds_map.get_mut(&guild_id) {
+                    guild.merge_channels(channels);
+                }
+
+                // Open the default channel of the guild the user selected now that its channels are known
+                if state.pending_default == Some(guild_id) {
+                    state.pending_default = None;
+                    if state.current_guild == Some(guild_id) && matches!(state.mode, AppMode::ChannelSelect) && state.open_default_channel(guild_id) {
+                        let _ = tx.send(ClientEvent::GetMoreMessages(None)).await;
+                    }
+                }
+            }
+
+            // Authors that can't be fetched stay unknown rather than interrupting with an error
+            ClientEvent::GetUser(user_id) => {
+                if let Ok(Some(profile)) = backend.get_profile(user_id).await {
+                    reducer::reduce(&mut *state.write().await, reducer::Action::UserFetched(user_id, profile), reducer::Clock::now());
+                }
+            }
+
+            ClientEvent::LeaveGuild(guild_id) => {
+                match backend.leave_guild(guild_id).await {
+                    // Clean up straight away rather than waiting for the server to say the guild was removed
+                    Ok(()) => {
+                        reducer::reduce(&mut *state.write().await, reducer::Action::GuildRemoved(guild_id), reducer::Clock::now());
+                    }
+                    Err(e) => state.write().await.status = Some(format!("could not leave guild: {}", e)),
+                }
+            }
+
+            ClientEvent::PreviewInvite(invite) => {
+                let preview = backend.preview_invite(invite.clone()).await;
+                let mut state = state.write().await;
+                let lines = match preview {
+                    Ok(preview) => {
+                        let mut lines = vec![Spans::from(Span::styled(preview.name, state.theme.header))];
+                        if let Some(count) = preview.member_count {
+                            lines.push(Spans::from(format!("{} member{}", count, if count == 1 { "" } else { "s" })));
+                        }
+                        if let Some(description) = preview.description.filter(|v| !v.is_empty()) {
+                            lines.push(Spans::from(""));
+                            lines.push(Spans::from(description));
+                        }
+                        lines
+                    }
+
+                    // Still let the user join if the homeserver can't preview invites
+                    Err(e) if capabilities::is_unsupported(&e) => vec![
+                        Spans::from(Span::styled(
+                            if state.streamer_mode { String::from("<invite hidden>") } else { invite.clone() },
+                            state.theme.header,
+                        )),
+                        Spans::from("(this homeserver can't preview invites)"),
+                    ],
+
+                    Err(e) => {
+                        state.status = Some(format!("could not preview invite: {}", e));
+                        continue;
+                    }
+                };
+
+                state.modals.push(Modal::confirm("join this guild?", Text::from(lines), ConfirmAction::JoinGuild(invite)));
+            }
+
+            ClientEvent::JoinGuild(invite) => {
+                let guild_id = match backend.join_guild(invite).await {
+                    Ok(guild_id) => guild_id,
+                    Err(e) => {
+                        state.write().await.status = Some(format!("could not join guild: {}", e));
+                        continue;
+                    }
+                };
+
+                let guild = match backend.get_guild(guild_id).await {
+                    Ok(guild) => guild,
+                    Err(e) => {
+                        state.write().await.request_failed("get the joined guild", &e);
+                        None
+                    }
+                };
+                if let Some(guild) = guild {
+                    let mut state = state.write().await;
+                    let guild = Guild {
+                        id: guild_id,
+                        channels_list: vec![],
+                        channels_select: None,
+                        channels_map: HashMap::new(),
+                        name: guild.name,
+                        current_channel: None,
+                        position: state.guilds_map.values().map(|v| v.position + 1).max().unwrap_or(0),
+                        last_activity: 0,
+                        unseen_channels: HashSet::new(),
+                        member_count: None,
+                        permissions: HashMap::new(),
+                    };
+
+                    state.guilds_list.push(guild_id);
+                    state.guilds_map.insert(guild_id, guild);
+                    state.sort_guilds();
+                }
+
+                // Receive the new guild's events
+                let _ = tx.send(ClientEvent::RefreshGuild(guild_id)).await;
+            }
+
+            // Translation requests can be slow, so they're done in the background
+            ClientEvent::Translate(message_id, language) => {
+                // Get the text to translate if it hasn't been translated already
+                let (config, guild_id, channel_id, text) = {
+                    let state = state.read().await;
+                    let (guild_id, channel_id) = match (state.current_guild, state.current_guild().and_then(|v| v.current_channel)) {
+                        (Some(g), Some(c)) => (g, c),
+                        _ => continue,
+                    };
+                    let text = state.get_channel(guild_id, channel_id).and_then(|v| v.messages_map.get(&message_id)).and_then(|v| match &v.content {
+                        MessageContent::Text(_) if matches!(&v.translation, Some((lang, _)) if *lang == language) => None,
+                        MessageContent::Text(text) => Some(text.source()),
+                        MessageContent::Embed(_) | MessageContent::Files(_) | MessageContent::Notice(_) => None,
+                    });
+
+                    match text {
+                        Some(text) => (state.config.translate.clone(), guild_id, channel_id, text),
+                        None => continue,
+                    }
+                };
+
+                // Translate and cache the translation on the message it came from, even if the channel changed since
+                let state = state.clone();
+                tokio::spawn(async move {
+                    let result = translate::translate(&config, &text, &language).await;
+                    let mut state = state.write().await;
+                    match result {
+                        Ok(translation) => {
+                            if let Some(message) = state.get_channel_mut(guild_id, channel_id).and_then(|v| v.messages_map.get_mut(&message_id)) {
+                                message.translation = Some((language, translation));
+                            }
+                        }
+
+                        Err(e) => state.status = Some(format!("translation failed: {}", e)),
+                    }
+                });
+            }
+
+            // Rendering takes a while, so do it in the background
+            ClientEvent::OpenMath(sources) => {
+                let state = state.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = math::open_math(sources).await {
+                        state.write().await.status = Some(format!("could not render the math: {}", e));
+                    }
+                });
+            }
+
+            ClientEvent::SetStatus(status) => {
+                if let Err(e) = backend.set_status(status).await {
+                    state.write().await.request_failed("set your status", &e);
+                }
+            }
+
+            ClientEvent::SetStatusText(text) => {
+                let result = backend.set_status_text(text).await;
+                let mut state = state.write().await;
+                match result {
+                    Ok(true) => (),
+                    Ok(false) => state.status = Some(state.capabilities.unsupported_message(Feature::StatusText)),
+                    Err(e) => state.status = Some(state.capabilities.describe_error(Feature::StatusText, &e)),
+                }
+            }
+
+            // Typing notifications are best effort, so failing to send one isn't worth interrupting for
+            ClientEvent::Typing => {
+                let target = state.read().await.current_channel().map(|v| (v.guild_id, v.id));
+                if let Some((guild_id, channel_id)) = target {
+                    let _ = backend.typing(guild_id, channel_id).await;
+                }
+            }
+
+            ClientEvent::RefreshGuild(guild_id) => {
+                let refreshed = match (backend.get_guild(guild_id).await, backend.get_channels(guild_id).await) {
+                    (Ok(guild), Ok(channels)) => Ok(guild.map(|v| (v, channels))),
+                    (Err(e), _) | (_, Err(e)) => Err(e),
+                };
+
+                // Subscribe even if the guild couldn't be refreshed, so its events still arrive
+                let subscribe = {
+                    let mut state = state.write().await;
+                    match refreshed {
+                        Ok(Some((info, channels))) => {
+                            if let Some(guild) = state.guilds_map.get_mut(&guild_id) {
+                                guild.name = info.name;
+                                guild.merge_channels(channels);
+                            }
+                        }
+                        Ok(None) => (),
+                        Err(e) => state.request_failed("refresh the guild", &e),
+                    }
+
+                    state.subscribed_guilds.insert(guild_id)
+                };
+
+                // Resubscribe so the conversation continues
+                if subscribe {
+                    supervise_events(&supervisor, &state, &client, vec![EventSource::Guild(guild_id)], &tx, &shutdown);
+                }
+            }
+
+            ClientEvent::TriggerAction(message_id, data) => {
+                let (guild_id, channel_id) = {
+                    let mut state = state.write().await;
+                    if !state.capabilities.supports(Feature::Actions) {
+                        state.status = Some(state.capabilities.unsupported_message(Feature::Actions));
+                        continue;
+                    }
+
+                    match state.current_channel() {
+                        Some(channel) => (channel.guild_id, channel.id),
+                        None => continue,
+                    }
+                };
+
+                let result = backend.trigger_action(guild_id, channel_id, message_id, data).await;
+
+                let mut state = state.write().await;
+                state.status = Some(match result {
+                    Ok(_) => String::from("action sent"),
+                    Err(e) => state.capabilities.describe_error(Feature::Actions, &e),
+                });
+            }
+
+            ClientEvent::Report(guild_id, channel_id, message_id, reason, report) => {
+                // Don't bother asking homeservers known not to take reports
+                let reported = if state.read().await.capabilities.supports(Feature::Reports) {
+                    backend.report_message(guild_id, channel_id, message_id, reason).await
+                } else {
+                    Ok(false)
+                };
+
+                let status = match reported {
+                    Ok(true) => String::from("message reported"),
+                    Ok(false) => match clipboard::copy(&report) {
+                        Ok(()) => String::from("this homeserver doesn't take reports, so the report was copied to the clipboard"),
+                        Err(e) => format!("could not copy the report: {}", e),
+                    },
+                    Err(e) => format!("could not report the message: {}", e),
+                };
+                state.write().await.status = Some(status);
+            }
+
+            ClientEvent::GetChannelInfo(guild_id, channel_id) => {
+                let members = backend.get_members(guild_id).await.ok().map(|v| v.len());
+                let mut permissions = vec![];
+                for &(node, _) in header::NODES {
+                    if let Ok(allowed) = backend.has_permission(guild_id, Some(channel_id), node.to_owned()).await {
+                        permissions.push((node, allowed));
+                    }
+                }
+
+                let mut state = state.write().await;
+                if let Some(guild) = state.guilds_map.get_mut(&guild_id) {
+                    if members.is_some() {
+                        guild.member_count = members;
+                    }
+                    for (node, allowed) in permissions {
+                        guild.permissions.insert((channel_id, node), allowed);
+                    }
+                }
+            }
+
+            ClientEvent::Search(query) => {
+                // Homeservers known not to search aren't asked
+                let supported = state.read().await.capabilities.supports(Feature::Search);
+                let hits = if supported { backend.search_messages(query.clone()).await } else { Ok(None) };
+
+                let mut state = state.write().await;
+                state.status = None;
+                let popup = match hits {
+                    Ok(Some(hits)) => search::results_popup(&state, &query, hits, false),
+                    Ok(None) => search::results_popup(&state, &query, search::search_local(&state, &query), true),
+                    Err(e) => {
+                        state.status = Some(state.capabilities.describe_error(Feature::Search, &e));
+                        search::results_popup(&state, &query, search::search_local(&state, &query), true)
+                    }
+                };
+                state.modals.push(popup);
+            }
+
+            ClientEvent::GetReadReceipts(guild_id, channel_id, message_id, show) => {
+                let receipts = backend.read_receipts(guild_id, channel_id, message_id).await;
+                let mut state = state.write().await;
+                match receipts {
+                    Ok(Some(receipts)) => {
+                        if show {
+                            let popup = receipts::readers_popup(&state, &receipts);
+                            state.modals.push(popup);
+                        }
+                        if let Some(channel) = state.get_channel_mut(guild_id, channel_id) {
+                            channel.readers.insert(message_id, receipts);
+                        }
+                    }
+                    Ok(None) if show => state.status = Some(state.capabilities.unsupported_message(Feature::ReadReceipts)),
+                    Ok(None) => (),
+
+                    // Receipts fetched in the background fail quietly, but still show if the homeserver has none
+                    Err(e) => {
+                        let error = state.capabilities.describe_error(Feature::ReadReceipts, &e);
+                        if show {
+                            state.status = Some(error);
+                        }
+                    }
+                }
+            }
+
+            ClientEvent::SendReadReceipt(guild_id, channel_id, message_id) => {
+                if let Err(e) = backend.send_read_receipt(guild_id, channel_id, message_id).await {
+                    state.write().await.capabilities.describe_error(Feature::ReadReceipts, &e);
+                }
+            }
+
+            ClientEvent::ExportAccount => {
+                let export = backend.export_account().await;
+                let mut state = state.write().await;
+                match export {
+                    Ok(Some(location)) => {
+                        state.status = None;
+                        let body = Text::from(vec![Spans::from("Your data is being exported:"), Spans::from(location)]);
+                        state.modals.push(Modal::info("data export", body));
+                    }
+                    Ok(None) => state.status = Some(state.capabilities.unsupported_message(Feature::AccountExport)),
+                    Err(e) => state.status = Some(state.capabilities.describe_error(Feature::AccountExport, &e)),
+                }
+            }
+
+            ClientEvent::DeleteAccount => match backend.delete_account().await {
+                // The session is gone with the account, so the next start asks to log in again
+                Ok(true) => {
+                    if let Some(data_dir) = dirs::data_dir() {
+                        std::fs::remove_file(data_dir.join("ilo-toki/auth")).ok();
+                    }
+                    state.write().await.purge_local(None);
+                    break;
+                }
+                Ok(false) => {
+                    let mut state = state.write().await;
+                    state.status = Some(state.capabilities.unsupported_message(Feature::AccountDeletion));
+                }
+                Err(e) => {
+                    let mut state = state.write().await;
+                    state.status = Some(state.capabilities.describe_error(Feature::AccountDeletion, &e));
+                }
+            },
+
+            // The popup may have been closed while waiting, in which case the step is dropped
+            ClientEvent::BeginAccountFlow(setting) => {
+                let step = backend.begin_account_flow(setting).await;
+                let mut state = state.write().await;
+                match step {
+                    Ok(Some(step)) => {
+                        state.status = None;
+                        if let Some(flow) = state.account_flow() {
+                            flow.show_step(step);
+                        }
+                    }
+                    Ok(None) => {
+                        state.close_account_flow();
+                        state.status = Some(state.capabilities.unsupported_message(Feature::AccountSettings));
+                    }
+                    Err(e) => {
+                        state.close_account_flow();
+                        state.status = Some(state.capabilities.describe_error(Feature::AccountSettings, &e));
+                    }
+                }
+            }
+
+            // A failed step leaves the popup open to try again
+            ClientEvent::AccountStep(response) => {
+                let step = backend.next_account_step(response).await;
+                let mut state = state.write().await;
+                match step {
+                    Ok(Some(step)) => {
+                        state.status = None;
+                        if let Some(flow) = state.account_flow() {
+                            flow.show_step(step);
+                        }
+                    }
+                    Ok(None) => {
+                        state.close_account_flow();
+                        state.status = Some(String::from("account updated"));
+                    }
+                    Err(e) => state.status = Some(state.capabilities.describe_error(Feature::AccountSettings, &e)),
+                }
+            }
+
+            ClientEvent::ServerInfo => {
+                let info = backend.server_info().await;
+                let mut state = state.write().await;
+                let info = match info {
+                    Ok(info) => info,
+                    Err(e) => {
+                        state.status = Some(state.capabilities.describe_error(Feature::ServerInfo, &e));
+                        continue;
+                    }
+                };
+
+                let auth_methods = match info.auth_methods {
+                    Some(methods) if !methods.is_empty() => methods.join(", "),
+                    _ => String::from("(not listed)"),
+                };
+                let mut lines = vec![
+                    Spans::from(Span::styled(info.name, state.theme.header)),
+                    Spans::from(state.redact(&state.homeserver)),
+                    Spans::from(""),
+                    Spans::from(format!("version: {}", info.version)),
+                    Spans::from(format!("auth methods: {}", auth_methods)),
+                    Spans::from(format!("federation: {}", if info.federates { "yes" } else { "no" })),
+                ];
+
+                let unsupported = state.capabilities.unsupported_names();
+                if !unsupported.is_empty() {
+                    lines.push(Spans::from(format!("not supported: {}", unsupported.join(", "))));
+                }
+
+                if !info.about.is_empty() {
+                    lines.push(Spans::from(""));
+                    lines.push(Spans::from(info.about));
+                }
+
+                if !info.motd.is_empty() {
+                    lines.push(Spans::from(""));
+                    lines.push(Spans::from(format!("message of the day: {}", info.motd)));
+                }
+
+                state.modals.push(Modal::info("server info", Text::from(lines)));
+            }
+
+            ClientEvent::ShowOnline => {
+                let (guilds, current_user) = {
+                    let state = state.read().await;
+                    (state.guilds_list.clone(), state.current_user)
+                };
+
+                // Each user is listed with the smallest guild shared with them
+                let mut shared: HashMap<u64, (u64, usize)> = HashMap::new();
+                let mut failed = 0;
+                for guild_id in guilds {
+                    let members = match backend.get_members(guild_id).await {
+                        Ok(members) => members,
+                        Err(_) => {
+                            failed += 1;
+                            continue;
+                        }
+                    };
+
+                    if members.len() > SMALL_GUILD_SIZE {
+                        continue;
+                    }
+
+                    let size = members.len();
+                    for user_id in members.into_iter().filter(|&v| v != current_user) {
+                        let entry = shared.entry(user_id).or_insert((guild_id, size));
+                        if size < entry.1 {
+                            *entry = (guild_id, size);
+                        }
+                    }
+                }
+
+                // Fetch everyone's profile so their status is current; profile events keep it current from then on
+                for &user_id in shared.keys() {
+                    if let Ok(Some(profile)) = backend.get_profile(user_id).await {
+                        reducer::reduce(&mut *state.write().await, reducer::Action::UserFetched(user_id, profile), reducer::Clock::now());
+                    }
+                }
+
+                let mut state = state.write().await;
+                if shared.is_empty() {
+                    state.status = Some(match failed {
+                        0 => String::from("you don't share any small guilds with anyone"),
+                        _ => format!("could not get the members of {} guilds", failed),
+                    });
+                    continue;
+                }
+
+                // Online users first, then by name
+                let mut contacts: Vec<_> = shared.into_iter().map(|(user_id, (guild_id, _))| (user_id, guild_id)).collect();
+                contacts.sort_by_cached_key(|&(user_id, _)| match state.users.get(&user_id) {
+                    Some(user) => (user.status == UserStatus::OfflineUnspecified, user.name.to_lowercase()),
+                    None => (true, String::new()),
+                });
+
+                let items = state.online_items(&contacts);
+                state.status = None;
+                state.modals.push(Modal::list("online", Text::default(), items, ListKind::Online(contacts)));
+            }
+
+            ClientEvent::OpenRoleEditor(guild_id) => {
+                // The editor can't do anything without permission, so don't show it
+                let allowed = backend.has_permission(guild_id, None, String::from("roles.manage")).await;
+                let roles = match allowed {
+                    Ok(true) => backend.get_roles(guild_id).await,
+                    Ok(false) => {
+                        state.write().await.status = Some(String::from("you don't have permission to manage roles here"));
+                        continue;
+                    }
+                    Err(e) => Err(e),
+                };
+
+                let mut state = state.write().await;
+                let roles = match roles {
+                    Ok(roles) => roles,
+                    Err(e) => {
+                        state.status = Some(state.capabilities.describe_error(Feature::Roles, &e));
+                        continue;
+                    }
+                };
+
+                if let Some(guild) = state.guilds_map.get(&guild_id) {
+                    let channels = guild
+                        .channels_list
+                        .iter()
+                        .filter_map(|v| guild.channels_map.get(v))
+                        .map(|v| (v.id, v.name.clone()))
+                        .collect();
+                    let editor = RoleEditor::new(guild_id, roles::from_raw(roles), channels);
+                    state.modals.push(Modal::roles(editor));
+                }
+            }
+
+            ClientEvent::GetRoles(guild_id) => {
+                let roles = backend.get_roles(guild_id).await;
+                let mut state = state.write().await;
+                match roles {
+                    Ok(roles) => {
+                        if let Some(editor) = state.role_editor_mut(guild_id) {
+                            editor.set_roles(roles::from_raw(roles));
+                        }
+                    }
+                    Err(e) => state.status = Some(state.capabilities.describe_error(Feature::Roles, &e)),
+                }
+            }
+
+            // The role events keep the editor up to date, so only failures need to be shown
+            ClientEvent::EditRoles(guild_id, request) => {
+                let result = match request {
+                    RoleRequest::Create(name) => backend.add_role(guild_id, name, 0).await.map(|_| ()),
+                    RoleRequest::Rename(role_id, name) => backend.modify_role(guild_id, role_id, Some(name), None).await,
+                    RoleRequest::Recolor(role_id, color) => backend.modify_role(guild_id, role_id, None, Some(color)).await,
+                    RoleRequest::Move(role_id, next_to, after) => backend.move_role(guild_id, role_id, next_to, after).await,
+                    RoleRequest::Delete(role_id) => backend.delete_role(guild_id, role_id).await,
+                    RoleRequest::SetPermissions(role_id, channel_id, permissions) => {
+                        backend.set_permissions(guild_id, channel_id, role_id, permissions).await
+                    }
+
+                    RoleRequest::GetPermissions(role_id, channel_id) => match backend.get_permissions(guild_id, channel_id, role_id).await {
+                        Ok(permissions) => {
+                            if let Some(editor) = state.write().await.role_editor_mut(guild_id) {
+                                editor.set_permissions(role_id, channel_id, permissions);
+                            }
+                            Ok(())
+                        }
+
+                        Err(e) => Err(e),
+                    },
+                };
+
+                if let Err(e) = result {
+                    let mut state = state.write().await;
+                    state.status = Some(state.capabilities.describe_error(Feature::Roles, &e));
+                }
+            }
+        }
+    }
+
+    // Stop the other tasks and wait for the UI to stop drawing
+    shutdown.cancel();
+    let _ = tui.await;
+
+    // Save anything not saved yet
+    {
+        let state = state.read().await;
+        notifications::save_levels(&state.notify_levels);
+        bookmarks::save(state.current_user, &state.bookmarks);
+    }
+
+    // Change our account's status back to offline, without letting an unreachable homeserver hang the exit
+    let offline = backend.set_status(UserStatus::OfflineUnspecified);
+    let _ = tokio::time::timeout(Duration::from_secs(5), offline).await;
+
+    // Die! :D
+    cache::remove_private();
+    shutdown::restore_terminal();
+    if let Some(failure) = supervisor.failure() {
+        eprintln!("ilo toki stopped because the {}", failure);
+        std::process::exit(1);
+    }
+    std::process::exit(0);
+}
+
+/// Gets a page of messages from the current channel before the given message, or the latest messages if none is given.
+/// Returns how many messages were received. The selection and the bottom of the view are kept as message ids, so older
+/// messages added above them don't move what's on screen.
+async fn fetch_page(state: &Arc<RwLock<AppState>>, backend: &dyn ChatBackend, message_id: Option<u64>) -> usize {
+    // Work out what to request
+    let (guild_id, channel_id, page) = {
+        let mut state = state.write().await;
+        let page = state.config.messages.page_size.max(1);
+        let page = if state.config.low_data { page.min(LOW_DATA_MESSAGE_PAGE) } else { page };
+        if let Some(channel) = state.current_channel_mut() {
+            if message_id.is_none() {
+                channel.history = History::Loading;
+            }
+            (channel.guild_id, channel.id, page)
+        } else {
+            return 0;
+        }
+    };
+
+    // Get the messages, saying what went wrong if they can't be
+    let messages = match backend.get_messages(guild_id, channel_id, message_id, page + 1).await {
+        Ok(messages) => messages,
+        Err(e) => {
+            let mut state = state.write().await;
+            let error = state.redact(&e.to_string());
+            if let Some(channel) = state.get_channel_mut(guild_id, channel_id) {
+                match message_id {
+                    None => channel.history = History::Failed(error),
+
+                    // Let the same page be requested again
+                    Some(_) => {
+                        channel.requested_before = None;
+                        state.status = Some(format!("could not load older messages: {}", error));
+                    }
+                }
+            }
+            return 0;
+        }
+    };
+    let count = messages.len().saturating_sub(1);
+
+    // Save the messages, collecting unknown authors so each profile is only fetched once per page
+    let mut unknown_authors = BTreeSet::new();
+    {
+        let mut state = state.write().await;
+        if let Some(channel) = state.get_channel_mut(guild_id, channel_id).filter(|_| message_id.is_none()) {
+            channel.history = History::Loaded;
+        }
+        for (message_id, message) in messages.into_iter().skip(1) {
+            if let Some(author_id) = handle_message(&mut *state, message, guild_id, channel_id, message_id) {
+                unknown_authors.insert(author_id);
+            }
+        }
+    }
+
+    for author_id in unknown_authors {
+        if let Ok(Some(profile)) = backend.get_profile(author_id).await {
+            reducer::reduce(&mut *state.write().await, reducer::Action::UserFetched(author_id, profile), reducer::Clock::now());
+        }
+    }
+
+    count
+}
+
+/// Sends a text message to the given channel.
+async fn send_text(backend: &dyn ChatBackend, state: &Arc<RwLock<AppState>>, guild_id: u64, channel_id: u64, msg: String) {
+    let content = chat::Content::new(Some(Content::new_text_message(TextContent::new(Some(FormattedText::new(msg, vec![]))))));
+    if let Err(e) = backend.send_message(guild_id, channel_id, content).await {
+        state.write().await.request_failed("send the message", &e);
+    }
+}
+
+/// Sends a message typed into the box, shown with the name and avatar picked with `:as` if there is one.
+/// Homeservers only allow that for some users, so being refused is shown instead of treated as a lost connection.
+async fn send_typed(backend: &dyn ChatBackend, state: &Arc<RwLock<AppState>>, guild_id: u64, channel_id: u64, content: chat::Content) {
+    let overrides = state.read().await.send_as_overrides();
+    match overrides {
+        Some(overrides) => {
+            if let Err(e) = backend.send_message_as(guild_id, channel_id, content, overrides).await {
+                state.write().await.status = Some(format!("could not send as someone else: {}", e));
+            }
+        }
+        None => {
+            if let Err(e) = backend.send_message(guild_id, channel_id, content).await {
+                state.write().await.request_failed("send the message", &e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod request_tests {
+    use super::*;
+    use crate::backend::mock::OfflineBackend;
+
+    fn state() -> Arc<RwLock<AppState>> {
+        Arc::new(RwLock::new(bench::channel_state(1, 2)))
+    }
+
+    #[tokio::test]
+    async fn failed_sends_show_in_status() {
+        let state = state();
+        send_text(&OfflineBackend, &state, 1, 2, String::from("hi")).await;
+        assert_eq!(state.read().await.status.as_deref(), Some("could not send the message: offline"));
+
+        state.write().await.status = None;
+        let content = chat::Content::new(Some(Content::new_text_message(TextContent::new(Some(FormattedText::new(String::from("hi"), vec![]))))));
+        send_typed(&OfflineBackend, &state, 1, 2, content).await;
+        assert_eq!(state.read().await.status.as_deref(), Some("could not send the message: offline"));
+    }
+
+    #[tokio::test]
+    async fn failed_history_shows_in_channel() {
+        let state = state();
+        assert_eq!(fetch_page(&state, &OfflineBackend, None).await, 0);
+        let state = state.read().await;
+        assert!(matches!(&state.current_channel().unwrap().history, History::Failed(error) if error == "offline"));
+    }
+}
+
+enum AuthFormFieldType {
+    Text,
+    Email,
+    Number,
+    Password,
+    NewPassword,
+}
+
+enum AuthInput {
+    Initial,
+
+    Choice {
+        choices: Vec<String>,
+        current_choice: Option<usize>,
+    },
+
+    Form {
+        fields: Vec<(String, AuthFormFieldType, String, Option<String>)>,
+        selected: Option<usize>,
+        selected_second: bool,
+        editing: bool,
+    },
+
+    Waiting(String),
+}
+
+impl Default for AuthInput {
+    fn default() -> Self {
+        Self::Initial
+    }
+}
+
+#[derive(Default)]
+struct AuthState {
+    can_go_back: bool,
+    title: String,
+    input: AuthInput,
+
+    /// Why the form couldn't be submitted, shown until it's submitted or the step changes.
+    error: Option<String>,
+}
+
+/// What a key press in an auth step asks for.
+enum AuthKey {
+    /// Nothing beyond what the step handled itself.
+    None,
+
+    /// Going back to the previous step.
+    Back,
+
+    /// Giving up on the flow.
+    Quit,
+
+    /// Answering the step.
+    Submit(AuthStepResponse),
+}
+
+impl AuthState {
+    /// Shows a step sent by the server.
+    fn show_step(&mut self, step: AuthStep) {
+        let can_go_back = step.can_go_back;
+        if let Some(step) = step.step { // why are there so many nested optionals
+            self.can_go_back = can_go_back;
+            self.error = None;
+
+            match step {
+                Step::Choice(mut choice) => {
+                    for choice in choice.options.iter_mut() {
+                        *choice = choice.replace('-', " ");
+                    }
+
+                    self.title = choice.title.replace('-', " ");
+
+                    self.input = AuthInput::Choice {
+                        choices: choice.options,
+                        current_choice: None,
+                    };
+                }
+
+                Step::Form(form) => {
+                    self.title = form.title.replace('-', " ");
+                    let fields = form.fields.iter().map(|v| (v.name.replace('-', " "), match v.r#type.as_str() {
+                        "password" => AuthFormFieldType::Password,
+                        "new-password" => AuthFormFieldType::NewPassword,
+                        "email" => AuthFormFieldType::Email,
+                        "number" => AuthFormFieldType::Number,
+                        _ => AuthFormFieldType::Text,
+                    }, String::new(), if v.r#type == "new-password" {
+                        Some(String::new())
+                    } else {
+                        None
+                    })).collect();
+
+                    self.input = AuthInput::Form {
+                        fields,
+                        selected: None,
+                        selected_second: false,
+                        editing: false,
+                    };
+                }
+
+                // I don't think this is reachable
+                Step::Session(_) => (),
+
+                Step::Waiting(wait) => {
+                    self.input = AuthInput::Waiting(wait.description);
+                }
+            }
+        }
+    }
+
+    /// Gets how many lines the step takes to draw, including its border.
+    fn height(&self) -> u16 {
+        let inner = match &self.input {
+            AuthInput::Initial | AuthInput::Waiting(_) => 1,
+            AuthInput::Choice { choices, .. } => choices.len() as u16,
+            AuthInput::Form { fields, .. } => fields
+                .iter()
+                .map(|v| if let AuthFormFieldType::NewPassword = v.1 { 7 } else { 4 })
+                .sum(),
+        };
+        inner + 2
+    }
+
+    /// Checks whether a form field is being typed in, which escape stops.
+    fn is_editing(&self) -> bool {
+        matches!(self.input, AuthInput::Form { editing: true, .. })
+    }
+
+    /// Handles a key press in the current step.
+    fn handle_key(&mut self, key: KeyEvent) -> AuthKey {
+        let can_go_back = self.can_go_back;
+
+        match &mut self.input {
+            AuthInput::Initial | AuthInput::Waiting(_) => {
+                match key.code {
+                    KeyCode::Char('h') | KeyCode::Right if can_go_back => AuthKey::Back,
+                    KeyCode::Char('q') => AuthKey::Quit,
+                    _ => AuthKey::None,
+                }
+            }
+
+            AuthInput::Choice { choices, current_choice } => {
+                match key.code {
+                    KeyCode::Char('h') | KeyCode::Right if can_go_back => AuthKey::Back,
+                    KeyCode::Char('q') => AuthKey::Quit,
+
+                    KeyCode::Char('j') | KeyCode::Down | KeyCode::Tab => {
+                        if let Some(choice) = current_choice.as_mut() {
+                            if *choice + 1 < choices.len() {
+                                *choice += 1;
+                            }
+                        } else {
+                            *current_choice = Some(0);
+                        }
+                        AuthKey::None
+                    }
+
+                    KeyCode::Char('k') | KeyCode::Up | KeyCode::BackTab => {
+                        if let Some(choice) = current_choice.as_mut() {
+                            if *choice > 0 {
+                                *choice -= 1;
+                            }
+                        } else {
+                            *current_choice = Some(choices.len() - 1);
+                        }
+                        AuthKey::None
+                    }
+
+                    KeyCode::Enter => match current_choice {
+                        Some(choice) => AuthKey::Submit(AuthStepResponse::Choice(choices.get(*choice).unwrap().replace(' ', "-"))),
+                        None => AuthKey::None,
+                    },
+
+                    _ => AuthKey::None,
+                }
+            }
+
+            AuthInput::Form { fields, selected, selected_second, editing } => {
+                match key.code {
+                    KeyCode::Char('h') | KeyCode::Right if can_go_back && !*editing => return AuthKey::Back,
+                    KeyCode::Char('q') if !*editing => return AuthKey::Quit,
+
+                    KeyCode::Esc => {
+                        *editing = false;
+                    }
+
+                    KeyCode::Tab => {
+                        if let Some(selection) = selected.as_mut() {
+                            if *selection + 1 < fields.len() {
+                                *selection += 1;
+                            }
+                        } else {
+                            *selected = Some(0);
+                        }
+                    }
+
+                    KeyCode::BackTab => {
+                        if let Some(selection) = selected.as_mut() {
+                            if *selection > 0 {
+                                *selection -= 1;
+                            }
+                        } else {
+                            *selected = Some(fields.len() - 1);
+                        }
+                    }
+
+                    KeyCode::Char('i') if !*editing && selected.is_some() => {
+                        *editing = true;
+                    }
+
+                    KeyCode::Char('j') | KeyCode::Down if !*editing => {
+                        if let Some(selection) = selected.as_mut() {
+                            if *selection + 1 < fields.len() {
+                                *selection += 1;
+                            }
+                        } else {
+                            *selected = Some(0);
+                        }
+                    }
+
+                    KeyCode::Char('k') | KeyCode::Up if !*editing => {
+                        if let Some(selection) = selected.as_mut() {
+                            if *selection > 0 {
+                                *selection -= 1;
+                            }
+                        } else {
+                            *selected = Some(fields.len() - 1);
+                        }
+                    }
+
+                    KeyCode::Char(c) if *editing && input::is_text(&key) => {
+                        if let Some((_, _, input, input2)) = selected.and_then(|v| fields.get_mut(v)) {
+                            let input = if *selected_second {
+                                input2.as_mut().unwrap()
+                            } else {
+                                input
+                            };
+                            input.push(c);
+                        }
+                    }
+
+                    KeyCode::Backspace if *editing => {
+                        if let Some((_, _, input, input2)) = selected.and_then(|v| fields.get_mut(v)) {
+                            let input = if *selected_second {
+                                input2.as_mut().unwrap()
+                            } else {
+                                input
+                            };
+                            input.pop();
+                        }
+                    }
+
+                    // TODO: arrow keys and vim controls (or maybe not; after all, this is
+                    // just login stuff)
+
+                    // Check the fields before submitting, so a typo asks again instead of being sent
+                    KeyCode::Enter => {
+                        let mut result = vec![];
+                        for (name, type_, input, input2) in fields.iter() {
+                            match type_ {
+                                AuthFormFieldType::Text => {
+                                    result.push(Field::String(input.clone()));
+                                }
+
+                                AuthFormFieldType::Email => {
+                                    // TODO: verification
+                                    result.push(Field::String(input.clone()));
+                                }
+
+                                AuthFormFieldType::Number => match input.trim().parse() {
+                                    Ok(number) => result.push(Field::Number(number)),
+                                    Err(_) => {
+                                        self.error = Some(format!("{} must be a number", name));
+                                        return AuthKey::None;
+                                    }
+                                },
+
+                                AuthFormFieldType::Password => {
+                                    result.push(Field::Bytes(input.bytes().collect()));
+                                }
+
+                                AuthFormFieldType::NewPassword => {
+                                    if Some(input) != input2.as_ref() {
+                                        self.error = Some(format!("the two {} fields don't match", name));
+                                        return AuthKey::None;
+                                    }
+                                    result.push(Field::Bytes(input.bytes().collect()));
+                                }
+                            }
+                        }
+
+                        self.error = None;
+                        return AuthKey::Submit(AuthStepResponse::Form(result));
+                    }
+
+                    _ => (),
+                }
+                AuthKey::None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod auth_tests {
+    use super::*;
+
+    /// Creates a form with the given fields already typed in.
+    fn form(fields: Vec<(AuthFormFieldType, &str, Option<&str>)>) -> AuthState {
+        AuthState {
+            input: AuthInput::Form {
+                fields: fields
+                    .into_iter()
+                    .map(|(type_, input, input2)| (String::from("field"), type_, String::from(input), input2.map(String::from)))
+                    .collect(),
+                selected: Some(0),
+                selected_second: false,
+                editing: false,
+            },
+            ..AuthState::default()
+        }
+    }
+
+    fn submit(state: &mut AuthState) -> AuthKey {
+        state.handle_key(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE))
+    }
+
+    #[test]
+    fn bad_number_asks_again() {
+        let mut state = form(vec![(AuthFormFieldType::Number, "12a", None)]);
+        assert!(matches!(submit(&mut state), AuthKey::None));
+        assert_eq!(state.error.as_deref(), Some("field must be a number"));
+
+        state = form(vec![(AuthFormFieldType::Number, "12", None)]);
+        assert!(matches!(submit(&mut state), AuthKey::Submit(AuthStepResponse::Form(_))));
+        assert_eq!(state.error, None);
+    }
+
+    #[test]
+    fn mismatched_passwords_ask_again() {
+        let mut state = form(vec![(AuthFormFieldType::NewPassword, "hunter2", Some("hunter3"))]);
+        assert!(matches!(submit(&mut state), AuthKey::None));
+        assert_eq!(state.error.as_deref(), Some("the two field fields don't match"));
+
+        state = form(vec![(AuthFormFieldType::NewPassword, "hunter2", Some("hunter2"))]);
+        assert!(matches!(submit(&mut state), AuthKey::Submit(AuthStepResponse::Form(_))));
+    }
+}
+
+async fn auth(client: &Client, theme: Theme, shutdown: &CancellationToken, input_events: mpsc::Receiver<Event>) -> mpsc::Receiver<Event> {
+    client.begin_auth().await.unwrap();
+    let state = Arc::new(RwLock::new(AuthState::default()));
+
+    // The input handler gives the input back once it's done with it, for the main UI to use
+    let (tx, mut rx) = mpsc::channel(128);
+    let done = shutdown.child_token();
+    let tui = tokio::spawn(auth_tui(state.clone(), theme, shutdown.clone()));
+    let ui_events = tokio::spawn(auth_ui_events(state.clone(), tx, input_events, done.clone(), shutdown.clone()));
+
+    let mut step = client.next_auth_step(AuthStepResponse::Initial).await.unwrap_or(None).and_then(|v| v.step);
+    'a: while !shutdown.is_cancelled() {
+        if let Some(step) = step {
+            state.write().await.show_step(step);
+        }
+
+        loop {
+            let request = tokio::select! {
+                request = rx.recv() => match request {
+                    Some(v) => v,
+                    None => break 'a,
+                },
+                _ = shutdown.cancelled() => break 'a,
+            };
+            if matches!(request, AuthStepResponse::Initial) {
+                let response = client.prev_auth_step().await;
+                if let Ok(back) = response {
+                    step = back.step;
+                    break;
+                }
+            } else {
+                let response = client.next_auth_step(request).await;
+                match response {
+                    Ok(Some(forwards)) => {
+                        step = forwards.step;
+                        break;
+                    }
+
+                    Ok(None) => break 'a,
+                    Err(_) => (),
+                }
+            }
+        }
+    }
+
+    tui.abort();
+    done.cancel();
+    ui_events.await.unwrap()
+}
+
+/// Draws a step of a server-driven auth flow, used for logging in and for changing account settings.
+fn draw_auth_step<B: tui::backend::Backend>(f: &mut tui::Frame<B>, area: layout::Rect, state: &AuthState, theme: &Theme) {
+    let title = match &state.error {
+        Some(error) => Spans::from(vec![
+            Span::raw(format!("{} - ", state.title)),
+            Span::styled(error.as_str(), theme.danger),
+        ]),
+        None => Spans::from(state.title.as_str()),
+    };
+    let block = widgets::Block::default()
+        .borders(widgets::Borders::ALL)
+        .title(title);
+
+    match &state.input {
+        AuthInput::Initial => (),
+
+        AuthInput::Choice { choices, current_choice } => {
+            let list: Vec<_> = choices.iter().map(|v| widgets::ListItem::new(v.as_str())).collect();
+            let list = widgets::List::new(list)
+                .block(block)
+                .highlight_style(theme.selected);
+            let mut list_state = widgets::ListState::default();
+            list_state.select(*current_choice);
+            f.render_stateful_widget(list, area, &mut list_state);
+        }
+
+        AuthInput::Form { fields, selected, selected_second, editing: _ }=> {
+            let layout_vec: Vec<_> = fields
+                .iter()
+                .map(|v| if let AuthFormFieldType::NewPassword = v.1 {
+                    layout::Constraint::Length(7)
+                } else {
+                    layout::Constraint::Length(4)
+                })
+                .collect();
+            let fields_layout = layout::Layout::default()
+                .direction(layout::Direction::Vertical)
+                .constraints(layout_vec)
+                .split(block.inner(area));
+            f.render_widget(block, area);
+
+            for (i, ((name, type_, input, input2), rect)) in fields.iter().zip(fields_layout.into_iter()).enumerate() {
+                let partial = layout::Layout::default()
+                    .direction(layout::Direction::Vertical)
+                    .constraints([
+                        layout::Constraint::Length(1),
+                        layout::Constraint::Length(3),
+                        layout::Constraint::Length(3),
+                    ])
+                    .split(rect);
+
+                let label = widgets::Paragraph::new(Span::styled(name.as_str(), Style::default().add_modifier(Modifier::BOLD)));
+                f.render_widget(label, partial[0]);
+
+                let input_box = widgets::Block::default()
+                    .borders(widgets::Borders::ALL)
+                    .style(if matches!(*selected, Some(j) if j == i) && !selected_second {
+                        theme.selected
+                    } else {
+                        Style::default()
+                    });
+                let input_box = if let AuthFormFieldType::Password | AuthFormFieldType::NewPassword = type_ {
+                    widgets::Paragraph::new("*".repeat(input.len()))
+                } else {
+                    widgets::Paragraph::new(input.as_str())
+                }.block(input_box);
+                f.render_widget(input_box, partial[1]);
+
+                if let Some(input) = input2 {
+                    let input_box = widgets::Block::default()
+                        .borders(widgets::Borders::ALL)
+                        .style(if matches!(*selected, Some(j) if j == i) && *selected_second {
+                            theme.selected
+                        } else {
+                            Style::default()
+                        });
+                    let input_box = widgets::Paragraph::new("*".repeat(input.len()))
+                        .block(input_box);
+                    f.render_widget(input_box, partial[2]);
+                }
+            }
+        }
+
+        // TODO
+        AuthInput::Waiting(_) => {}
+    }
+}
+
+async fn auth_tui(state: Arc<RwLock<AuthState>>, theme: Theme, shutdown: CancellationToken) -> Result<(), std::io::Error> {
+    // Set up
+    let stdout = std::io::stdout();
+    let backend = CrosstermBackend::new(stdout);
+    let mut terminal = Terminal::new(backend)?;
+    crossterm::terminal::enable_raw_mode()?;
+    terminal.clear()?;
+
+    while !shutdown.is_cancelled() {
+        let state = state.read().await;
+
+        terminal.draw(|f| {
+            let size = f.size();
+            let vertical = layout::Layout::default()
+                .direction(layout::Direction::Vertical)
+                .constraints([
+                    layout::Constraint::Min(1),
+                    layout::Constraint::Length(1),
+                ]).split(size);
+
+            draw_auth_step(f, vertical[0], &state, &theme);
+
+            let status = if state.can_go_back {
+                widgets::Paragraph::new("press right arrow to go back, q to quit")
+            } else {
+                widgets::Paragraph::new("press q to quit")
+            };
+            f.render_widget(status, vertical[1]);
+        }).unwrap();
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    Ok(())
+}
+
+async fn auth_ui_events(
+    state: Arc<RwLock<AuthState>>,
+    tx: mpsc::Sender<AuthStepResponse>,
+    mut input_events: mpsc::Receiver<Event>,
+    done: CancellationToken,
+    shutdown: CancellationToken,
+) -> mpsc::Receiver<Event> {
+    loop {
+        let event = tokio::select! {
+            event = input_events.recv() => match event {
+                Some(event) => event,
+                None => break,
+            },
+            _ = done.cancelled() => break,
+        };
+
+        match event {
+            crossterm::event::Event::Key(key) => {
+                let response = state.write().await.handle_key(key);
+                match response {
+                    AuthKey::None => (),
+
+                    AuthKey::Back => {
+                        let _ = tx.send(AuthStepResponse::Initial).await;
+                    }
+
+                    AuthKey::Quit => {
+                        shutdown.cancel();
+                        break;
+                    }
+
+                    AuthKey::Submit(response) => {
+                        let _ = tx.send(response).await;
+                    }
+                }
+            }
+
+            // TODO
+            crossterm::event::Event::Mouse(_) => {
+            }
+
+            crossterm::event::Event::Resize(_, _) => (),
+        }
+    }
+
+    input_events
+}
+
+/// Handles a message, returning the author id if the author is unknown.
+fn handle_message(state: &mut AppState, message: RawMessage, guild_id: u64, channel_id: u64, message_id: u64) -> Option<u64> {
+    // Get content
+    let author_id = message.author_id;
+    let theme = state.theme;
+    let highlighted = author_id != state.current_user && match message.content.as_ref().and_then(|v| v.content.as_ref()) {
+        Some(Content::TextMessage(text)) => text.content.as_ref().map(|v| state.is_highlighted(guild_id, v)).unwrap_or(false),
+        _ => false,
+    };
+    let upgrader = match message.content.as_ref().and_then(|v| v.content.as_ref()) {
+        Some(Content::RoomUpgradedToGuild(upgrade)) => Some(
+            state
+                .users
+                .get(&upgrade.upgraded_by)
+                .map(|v| v.name.clone())
+                .unwrap_or_else(|| String::from("<unknown user>")),
+        ),
+        _ => None,
+    };
+
+    if let Some(channel) = state.get_channel_mut(guild_id, channel_id) {
+        let content = match message.content.and_then(|v| v.content) {
+            // Text message
+            Some(Content::TextMessage(text)) => text.content.map(|text| {
+                // Remember slash commands for completion
+                if let Some(command) = text.text.strip_prefix('/').and_then(|v| v.split_whitespace().next()) {
+                    channel.slash_commands.insert(format!("/{}", command));
+                }
+
+                MessageContent::Text(convert_formatted_text_to_rich_text(text, &theme))
+            }),
+
+            // Embed message
+            Some(Content::EmbedMessage(embed)) => embed.embed.map(|v| MessageContent::Embed(convert_embed(v, &theme))),
+
+            // Room upgraded to guild
+            Some(Content::RoomUpgradedToGuild(_)) => upgrader.map(|v| MessageContent::Notice(format!("{} upgraded this room to a guild", v))),
+
+            // Attachments and photos
+            Some(Content::AttachmentMessage(attachments)) => Some(MessageContent::Files(
+                attachments
+                    .files
+                    .into_iter()
+                    .map(|v| Attachment {
+                        id: v.id,
+                        name: v.name,
+                        mimetype: v.mimetype,
+                    })
+                    .collect(),
+            )),
+            Some(Content::PhotoMessage(photos)) => Some(MessageContent::Files(
+                photos
+                    .photos
+                    .into_iter()
+                    .map(|v| Attachment {
+                        id: v.hmc,
+                        name: v.name,
+                        mimetype: String::from("image"),
+                    })
+                    .collect(),
+            )),
+
+            // TODO
+            Some(Content::InviteRejected(_)) => None,
+            Some(Content::InviteAccepted(_)) => None,
+            None => None,
+        };
+
+        if let Some(content) = content {
+            let (override_username, bridged) = match message.overrides {
+                Some(overrides) => (overrides.username, overrides.reason.map(|v| matches!(v, overrides::Reason::Bridge(_)))),
+                None => (None, None),
+            };
+            let message = Message {
+                id: message_id,
+                author_id,
+                override_username,
+                content,
+                timestamp: message.created_at,
+                edited_timestamp: message.edited_at,
+                translation: None,
+                highlighted,
+                in_reply_to: message.in_reply_to,
+                bridged,
+            };
+
+            channel.typing.remove(&author_id);
+            channel.insert_message(message);
+        }
+    }
+
+    if !state.users.contains_key(&author_id) {
+        Some(author_id)
+    } else {
+        None
+    }
+}
+
+fn convert_embed(embed: chat::Embed, theme: &Theme) -> Embed {
+    let mut actions = vec![];
+    let fields = embed
+        .fields
+        .into_iter()
+        .map(|field| {
+            // Only buttons are supported for now
+            for action in field.actions {
+                if let Some(chat::action::Kind::Button(button)) = action.kind {
+                    actions.push(EmbedAction {
+                        text: button.text,
+                        data: button.data,
+                        url: button.url,
+                    });
+                }
+            }
+
+            EmbedField {
+                title: field.title,
+                subtitle: field.subtitle,
+                body: field.body.map(|v| convert_formatted_text_to_rich_text(v, theme)),
+            }
+        })
+        .collect();
+
+    Embed {
+        title: embed.title,
+        body: embed.body.map(|v| convert_formatted_text_to_rich_text(v, theme)),
+        fields,
+        actions,
+    }
+}
+
+/// Opens an attachment with its configured handler in the background, showing any errors in the status bar.
+async fn open_attachment(state: &Arc<RwLock<AppState>>, attachment: Attachment) {
+    let (homeserver, handlers, transfer) = {
+        let mut state = state.write().await;
+        let transfer = state.start_transfer(&attachment.name, false);
+        (state.homeserver.clone(), state.config.media.handlers.clone(), transfer)
+    };
+
+    let state = state.clone();
+    tokio::spawn(async move {
+        if let Err(e) = media::open(&attachment, &homeserver, &handlers, &transfer).await {
+            state.write().await.status = Some(format!("could not open {}: {}", attachment.name, e));
+        }
+    });
+}
+
+/// Opens a url or file with the system's default program.
+fn open_external(target: &str) {
+    let _ = std::process::Command::new(if cfg!(target_os = "macos") { "open" } else { "xdg-open" })
+        .arg(target)
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn();
+}
+
+/// Wraps plain text into lines of at most the given width in columns.
+fn wrap_plain(text: &str, width: usize) -> Vec<String> {
+    wrap::wrap(text, width)
+        .into_iter()
+        .map(|v| if v.hyphen { format!("{}-", &text[v.range]) } else { text[v.range].to_owned() })
+        .collect()
+}
+
+/// Splits the given bytes of rich text into spans, styling the parts that are formatted.
+fn rich_spans(text: &RichText, line: Range<usize>) -> Vec<Span<'_>> {
+    let mut spans = vec![];
+    let mut pos = line.start;
+    for (span, style, _) in text.formats.iter() {
+        let start = span.start.max(pos);
+        let end = span.end.min(line.end);
+        if start >= end {
+            continue;
+        }
+
+        if pos < start {
+            spans.push(Span::raw(&text.contents[pos..start]));
+        }
+        spans.push(Span::styled(&text.contents[start..end], *style));
+        pos = end;
+    }
+
+    if pos < line.end {
+        spans.push(Span::raw(&text.contents[pos..line.end]));
+    }
+    spans
+}
+
+/// Splits the given bytes of the input box into spans, marking what a substitution being typed would replace and
+/// showing what would replace it right after.
+fn substitution_spans<'a>(text: &'a str, line: Range<usize>, matches: &[(Range<usize>, String)], theme: &Theme) -> Vec<Span<'a>> {
+    let mut spans = vec![];
+    let mut pos = line.start;
+    for (range, replacement) in matches {
+        let start = range.start.clamp(pos, line.end);
+        let end = range.end.clamp(pos, line.end);
+
+        // The replacement goes where the match ends, on the next line if a wrapped line ends there
+        let ends_here = (line.start..line.end).contains(&range.end)
+            || (range.end == line.end && !text[line.end..].starts_with(|c: char| c != '\n'));
+        if start >= end && !ends_here {
+            continue;
+        }
+
+        if pos < start {
+            spans.push(Span::raw(&text[pos..start]));
+        }
+        if start < end {
+            spans.push(Span::styled(&text[start..end], theme.diff_removed));
+        }
+        if ends_here {
+            spans.push(Span::styled(replacement.clone(), theme.diff_added));
+        }
+        pos = end;
+    }
+
+    if pos < line.end {
+        spans.push(Span::raw(&text[pos..line.end]));
+    }
+    spans
+}
+
+fn convert_formatted_text_to_rich_text(mut text: FormattedText, theme: &Theme) -> RichText {
+    let mut rich = RichText {
+        contents: text.text,
+        formats: vec![],
+    };
+
+    text.format.sort_by(|a, b| a.length.cmp(&b.length));
+    for format in text.format {
+        let (start, end) = (format.start as usize, (format.start + format.length) as usize);
+
+        if let Some(format) = format.format {
+            let (style, meta) = match format {
+                Format::Bold(_) => {
+                    (Style::default().add_modifier(Modifier::BOLD), FormatMetadata::Bold)
+                }
+
+                Format::Italic(_) => {
+                    (Style::default().add_modifier(Modifier::ITALIC), FormatMetadata::Italic)
+                }
+
+                Format::Underline(_) => {
+                    (Style::default().add_modifier(Modifier::UNDERLINED), FormatMetadata::Underline)
+                }
+
+                Format::Monospace(_) => {
+                    (theme.monospace, FormatMetadata::Monospace)
+                }
+
+                Format::Superscript(_) => {
+                    (Style::default(), FormatMetadata::Superscript)
+                }
+
+                Format::Subscript(_) => {
+                    (Style::default(), FormatMetadata::Subscript)
+                }
+
+                Format::CodeBlock(_) => todo!(),
+
+                Format::UserMention(_) => {
+                    (Style::default().add_modifier(Modifier::BOLD), FormatMetadata::UserMention)
+                }
+
+                Format::RoleMention(_) => todo!(),
+
+                Format::ChannelMention(_) => todo!(),
+
+                Format::GuildMention(_) => todo!(),
+
+                Format::Emoji(_) => todo!(),
+
+                Format::Color(colour) => {
+                    match colour.kind() {
+                        color::Kind::DimUnspecified => todo!(),
+                        color::Kind::Bright => todo!(),
+                        color::Kind::Negative => todo!(),
+                        color::Kind::Positive => todo!(),
+                        color::Kind::Info => todo!(),
+                        color::Kind::Warning => todo!(),
+                    }
+                }
+
+                Format::Localization(_) => todo!(),
+            };
+
+            rich.formats.push((start..end, style, meta));
+        }
+    }
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        let mut merged = vec![];
+        for (i, (span1, style1, meta1)) in rich.formats.iter().enumerate() {
+            let mut merged_bool = false;
+            for (span2, style2, meta2) in rich.formats.iter().skip(i + 1) {
+                if span1.contains(&span2.start) || span1.contains(&(span2.end - 1)) {
+                    changed = true;
+                    merged_bool = true;
+                    let span_merged = span1.start.max(span2.start)..span1.end.min(span2.end);
+
+                    let meta3 = if meta1 == meta2 {
+                        meta1.clone()
+                    } else {
+                        FormatMetadata::Compose(match (meta1.clone(), meta2.clone()) {
+                            (FormatMetadata::Compose(mut v1), FormatMetadata::Compose(v2)) => {
+                                v1.extend(v2);
+                                v1
+                            }
+
+                            (FormatMetadata::Compose(mut v), m) | (m, FormatMetadata::Compose(mut v)) => {
+                                v.push(m);
+                                v
+                            }
+
+                            (a, b) => vec![a, b],
+                        })
+                    };
+                    merged.push((span_merged, style1.patch(*style2), meta3));
+
+                    // TODO: aaaaaaaaaaaaaaaaaaaaaaa
+                    /*
+                    let (span1, span2) = {
+                        ()
+                    };
+                    */
+                }
+            }
+
+            if !merged_bool {
+                merged.push((span1.clone(), *style1, meta1.clone()));
+            }
+        }
+
+        rich.formats = merged;
+    }
+
+    rich.formats.sort_by(|a, b| a.0.start.cmp(&b.0.start));
+    math::render_math(&mut rich, theme.math);
+
+    rich
+}
+
+/// Shows a desktop notification for a newly received message if the channel's notification level allows it.
+fn notify_message(state: &AppState, guild_id: u64, channel_id: u64, message_id: u64) {
+    let guild = match state.guilds_map.get(&guild_id) {
+        Some(v) => v,
+        None => return,
+    };
+    let level = state.notify_level(guild_id, channel_id);
+    if state.dnd_active {
+        return;
+    }
+
+    if let Some(channel) = guild.channels_map.get(&channel_id) {
+        if let Some(message) = channel.messages_map.get(&message_id) {
+            let notify = match level {
+                NotifyLevel::All => message.author_id != state.current_user,
+                NotifyLevel::Mentions => message.highlighted,
+                NotifyLevel::Nothing => false,
+            };
+
+            if notify {
+                let author = message
+                    .override_username
+                    .as_deref()
+                    .or_else(|| state.users.get(&message.author_id).map(|v| v.name.as_str()))
+                    .unwrap_or("<unknown user>");
+
+                if let MessageContent::Text(text) = &message.content {
+                    let title = format!("{} in {} #{}", author, guild.name, channel.name);
+                    let body = if state.streamer_mode || state.locked.is_some() {
+                        String::from("(hidden)")
+                    } else {
+                        text.contents.clone()
+                    };
+                    notifications::notify(&state.config.notifications, title, body);
+                }
+
+                // Mentions also make a sound, for when the terminal is in the background
+                let config = &state.config.notifications;
+                if message.highlighted && !state.sounds_muted {
+                    if let Some(sound) = config.sound_for(guild_id, &guild.name, &channel.name) {
+                        notifications::play_sound(config, sound);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn handle_user(state: &mut AppState, user_id: u64, user: Profile) {
+    state.users.insert(user_id, Member {
+        name: user.user_name,
+        is_bot: user.is_bot,
+        avatar: user.user_avatar,
+        status: UserStatus::from_i32(user.user_status).unwrap_or(UserStatus::OfflineUnspecified),
+    });
+}
+
+/// Event loop to process incoming events.
+async fn receive_events(
+    state: Arc<RwLock<AppState>>,
+    client: Arc<Client>,
+    events: Vec<EventSource>,
+    tx: mpsc::Sender<ClientEvent>,
+    shutdown: CancellationToken,
+) -> Result<(), String> {
+    let health_state = state.clone();
+    let stopped = shutdown.clone();
+    let stream = client.event_loop(events, {
+        move |_client, event| {
+            // This has to be done for ownership reasons
+            let state2 = state.clone();
+            let tx = tx.clone();
+
+            async move {
+                // Stop if not running
+                if stopped.is_cancelled() {
+                    Ok(true)
+                } else {
+                    // Log the event before handling it, including events that are ignored
+                    let name = debug::variant_name(&event);
+                    let action = reducer::Action::from_event(event);
+                    let details = action.as_ref().map(|v| v.describe()).unwrap_or_else(|| String::from("(ignored)"));
+                    state2.read().await.debug_log.push(format!("← {} {}", name, details));
+                    if let Some(action) = action {
+                        let effects = reducer::reduce(&mut *state2.write().await, action, reducer::Clock::now());
+                        reducer::perform(&state2, &tx, effects).await;
+                    }
+                    Ok(false)
+                }
+            }
+        }
+    });
+
+    let result = tokio::select! {
+        result = stream => result,
+        _ = shutdown.cancelled() => return Ok(()),
+    };
+
+    // The stream ending while still running means the connection broke
+    if !shutdown.is_cancelled() {
+        health_state.write().await.health.stalled_streams += 1;
+    }
+    result.map_err(|e| e.to_string())
+}
+
+/// Receives events from the given sources under the supervisor, reconnecting whenever the stream breaks.
+fn supervise_events(
+    supervisor: &Supervisor,
+    state: &Arc<RwLock<AppState>>,
+    client: &Arc<Client>,
+    events: Vec<EventSource>,
+    tx: &mpsc::Sender<ClientEvent>,
+    shutdown: &CancellationToken,
+) {
+    let (state, client, tx, shutdown) = (state.clone(), client.clone(), tx.clone(), shutdown.clone());
+    let status = report_status(&state);
+    supervisor.restarting(
+        "event stream",
+        move |restart| {
+            let (state, client, events, tx, shutdown) = (state.clone(), client.clone(), events.clone(), tx.clone(), shutdown.clone());
+            async move {
+                if restart {
+                    let mut state = state.write().await;
+                    state.health.stalled_streams = state.health.stalled_streams.saturating_sub(1);
+                    state.stats.record_reconnect();
+                }
+
+                receive_events(state, client, events, tx, shutdown).await
+            }
+        },
+        status,
+    );
+}
+
+/// Creates a callback that shows a supervisor's warning in the status bar.
+fn report_status(state: &Arc<RwLock<AppState>>) -> impl Fn(String) + Send + 'static {
+    let state = state.clone();
+    move |message| {
+        let state = state.clone();
+        tokio::spawn(async move {
+            state.write().await.status = Some(format!("warning: {}", message));
+        });
+    }
+}
+
+/// Handles rendering the terminal UI.
+async fn tui(state: Arc<RwLock<AppState>>, shutdown: CancellationToken) -> Result<(), std::io::Error> {
+    // A daemon draws for the attached terminals, which can't show thumbnails drawn on this machine's display
+    if daemon::is_daemon() {
+        return render_frontends(state, shutdown).await;
+    }
+
+    // Set up
+    let stdout = std::io::stdout();
+    let backend = CrosstermBackend::new(stdout);
+    let mut terminal = Terminal::new(backend)?;
+    crossterm::terminal::enable_raw_mode()?;
+    terminal.clear()?;
+
+    // Images are drawn on top of the terminal if possible
+    let thumbnails = media::Thumbnails::new();
+    render(&mut terminal, thumbnails, state, shutdown).await?;
+
+    // Reset terminal
+    terminal.clear()?;
+    crossterm::terminal::disable_raw_mode()?;
+    terminal.set_cursor(0, 0)?;
+
+    Ok(())
+}
+
+/// Draws the UI for each terminal attached to the daemon, with its own view and at its own size, until the program
+/// stops.
+async fn render_frontends(state: Arc<RwLock<AppState>>, shutdown: CancellationToken) -> Result<(), std::io::Error> {
+    let mut terminals = HashMap::new();
+    while !shutdown.is_cancelled() {
+        let attached = daemon::attached();
+        terminals.retain(|id, _| attached.contains(id));
+
+        {
+            // Each view is put back before the lock is let go, so input handling only ever sees its own
+            let mut state = state.write().await;
+            let active = state.views.active();
+            for &id in attached.iter() {
+                let terminal = match terminals.entry(id) {
+                    Entry::Occupied(entry) => entry.into_mut(),
+                    Entry::Vacant(entry) => entry.insert(Terminal::new(daemon::FrontendBackend::new(id))?),
+                };
+
+                // A terminal that just attached, was resized, or fell behind needs the whole screen
+                if daemon::take_redraw(id) {
+                    terminal.clear()?;
+                }
+
+                daemon::switch(&mut state, id);
+                let start = Instant::now();
+                terminal.draw(|f| {
+                    draw(f, &state);
+                })?;
+                state.stats.record_frame(start.elapsed());
+            }
+            daemon::switch(&mut state, active);
+        }
+
+        // Good night! :3
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    Ok(())
+}
+
+/// Draws the UI until the program stops.
+async fn render<B: tui::backend::Backend>(
+    terminal: &mut Terminal<B>,
+    mut thumbnails: Option<media::Thumbnails>,
+    state: Arc<RwLock<AppState>>,
+    shutdown: CancellationToken,
+) -> Result<(), std::io::Error> {
+    while !shutdown.is_cancelled() {
+        let state = state.read().await;
+        let mut placements = vec![];
+        let start = Instant::now();
+        terminal.draw(|f| placements = draw(f, &state))?;
+        state.stats.record_frame(start.elapsed());
+
+        // Thumbnails turned off for the multiplexer are hidden, including ones already shown
+        if let Some(thumbnails) = thumbnails.as_mut() {
+            thumbnails.show(if multiplexer::thumbnails() { &placements[..] } else { &[] });
+        }
+
+        // Good night! :3
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    Ok(())
+}
+
+/// Draws the guild and channel lists in the sidebar.
+fn draw_sidebar<B: tui::backend::Backend>(f: &mut tui::Frame<B>, state: &AppState, area: layout::Rect) {
+    // Saved filters take the bottom of the sidebar, up to a third of it, when there are any
+    let filters_height = if state.filters.is_empty() { 0 } else { (state.filters.len() as u16 + 2).min(area.height / 3) };
+    let sidebar = layout::Layout::default()
+        .direction(layout::Direction::Vertical)
+        .constraints([
+            layout::Constraint::Percentage(50),
+            layout::Constraint::Percentage(50),
+        ])
+        .split(layout::Rect {
+            height: area.height - filters_height,
+            ..area
+        });
+
+    // Guild list
+    let filter_title = state.list_filter.as_ref().map(|v| format!("/{}", v)).unwrap_or_default();
+    let visible_guilds = state.filtered_guilds();
+    let guilds_list: Vec<_> = visible_guilds
+        .iter()
+        .filter_map(|&i| state.guilds_list.get(i))
+        .filter_map(|v| state.guilds_map.get(v))
+        .map(|v| {
+            let item = widgets::ListItem::new(Text::from(highlight_filter(&v.name, state.list_filter.as_deref(), state.theme.highlight)));
+            if v.unseen_channels.is_empty() {
+                item
+            } else {
+                item.style(state.theme.unseen)
+            }
+        })
+        .collect();
+    let guilds = state.pane_block(Pane::Guilds);
+    let guilds = if matches!(state.mode, AppMode::GuildSelect) {
+        guilds.title(filter_title.as_str())
+    } else {
+        guilds
+    };
+    let guilds = widgets::List::new(guilds_list)
+        .block(guilds)
+        .highlight_style(if matches!(state.modals.last(), Some(Modal { kind: ModalKind::Confirm(ConfirmAction::LeaveGuild(_)), .. })) {
+            state.theme.danger
+        } else {
+            state.theme.selected
+        });
+    let mut list_state = widgets::ListState::default();
+    list_state.select(state.guilds_select.and_then(|v| visible_guilds.iter().position(|&i| i == v)));
+    f.render_stateful_widget(guilds, sidebar[0], &mut list_state);
+
+    // Channel list
+    let visible_channels = state.filtered_channels();
+    let channels_list: Vec<_> = visible_channels
+        .iter()
+        .filter_map(|&i| state.current_guild().and_then(|v| v.channels_list.get(i)))
+        .filter_map(|v| {
+            if let Some(guild) = state.current_guild() {
+                guild.channels_map.get(v)
+            } else {
+                None
+            }
+        })
+        .map(|v| {
+            let item = widgets::ListItem::new(Text::from(highlight_filter(&v.name, state.list_filter.as_deref(), state.theme.highlight)));
+            if state.current_guild().map(|g| g.unseen_channels.contains(&v.id)).unwrap_or(false) {
+                item.style(state.theme.unseen)
+            } else {
+                item
+            }
+        })
+        .collect();
+    let channels = state.pane_block(Pane::Channels);
+    let channels = if matches!(state.mode, AppMode::ChannelSelect) {
+        channels.title(filter_title.as_str())
+    } else {
+        channels
+    };
+    let channels = widgets::List::new(channels_list)
+        .block(channels)
+        .highlight_style(state.theme.selected);
+    let mut list_state = widgets::ListState::default();
+    list_state.select(state.current_guild().and_then(|v| v.channels_select).and_then(|v| visible_channels.iter().position(|&i| i == v)));
+    f.render_stateful_widget(channels, sidebar[1], &mut list_state);
+
+    // Saved filters, with how many matching messages arrived since each was opened
+    if filters_height > 0 {
+        let filters_list: Vec<_> = state
+            .filters
+            .iter()
+            .map(|v| {
+                if v.unseen == 0 {
+                    widgets::ListItem::new(format!("~{}", v.name))
+                } else {
+                    widgets::ListItem::new(format!("~{} ({})", v.name, v.unseen)).style(Style::default().add_modifier(Modifier::BOLD))
+                }
+            })
+            .collect();
+        let area = layout::Rect {
+            y: area.y + area.height - filters_height,
+            height: filters_height,
+            ..area
+        };
+        let filters = widgets::List::new(filters_list).block(widgets::Block::default().borders(widgets::Borders::ALL).title("filters"));
+        f.render_widget(filters, area);
+    }
+}
+
+/// Draws the app, returning where images should be placed on top of it.
+fn draw<B: tui::backend::Backend>(f: &mut tui::Frame<B>, state: &AppState) -> Vec<media::Placement> {
+    let size = f.size();
+
+    // Nothing else can be shown until everything is loaded
+    if let Some(splash) = &state.splash {
+        splash.render(f, size, &state.theme);
+        return vec![];
+    }
+
+    // Or while the screen is locked
+    if let Some(lock) = &state.locked {
+        lock.render(f, size, &state.theme);
+        return vec![];
+    }
+
+    // Create layout
+    let horizontal = layout::Layout::default()
+        .direction(layout::Direction::Horizontal)
+        .constraints([
+            layout::Constraint::Length(if state.sidebar_hidden() { 0 } else { 20 }),
+            layout::Constraint::Percentage(90),
+        ])
+        .split(size);
+
+    // Preview a substitution while it's typed
+    let substitution = match state.mode {
+        AppMode::Command => substitute::parse(&state.command).and_then(Result::ok),
+        _ => None,
+    };
+    let substitution_matches = substitution.map(|v| v.matches(&state.input)).unwrap_or_default();
+
+    // Generate input text
+    let input_width = (horizontal[1].width as usize).saturating_sub(2).max(1);
+    let input_text = Text::from(
+        state
+            .input
+            .lines(input_width)
+            .into_iter()
+            .map(|v| {
+                let mut spans = substitution_spans(&state.input, v.range, &substitution_matches, &state.theme);
+                if v.hyphen {
+                    spans.push(Span::raw("-"));
+                }
+                Spans::from(spans)
+            })
+            .collect::<Vec<_>>(),
+    );
+
+    // Long drafts scroll inside the input box, which is capped so it can't squeeze out the messages
+    let input_lines = MAX_INPUT_LINES.min(horizontal[1].height / 3).max(1);
+    let (cursor_column, cursor_line) = state.input.cursor_position(input_width);
+    let input_scroll = (cursor_line as u16 + 1).saturating_sub(input_lines);
+
+    // Generate edit preview
+    let edit_preview = if state.editing {
+        state.current_channel().and_then(Channel::selected_message).map(|v| {
+            let old = if let MessageContent::Text(text) = &v.content {
+                text.source()
+            } else {
+                String::new()
+            };
+
+            let spans: Vec<_> = state
+                .edit_diff
+                .diff_words(&old, &state.input)
+                .into_iter()
+                .map(|v| match v {
+                    diff::Change::Same(v) => Span::raw(v),
+                    diff::Change::Removed(v) => Span::styled(v, state.theme.diff_removed),
+                    diff::Change::Added(v) => Span::styled(v, state.theme.diff_added),
+                })
+                .collect();
+            let width = (horizontal[1].width as usize).saturating_sub(2).max(1);
+            let height = spans.iter().map(|v| v.width()).sum::<usize>() / width + 1;
+            (Spans::from(spans), height.min(6) as u16 + 2)
+        })
+    } else {
+        None
+    };
+
+    // More layout stuff, leaving out the input box if nothing can be sent
+    let read_only = state.current_read_only();
+    let content = layout::Layout::default()
+        .direction(layout::Direction::Vertical)
+        .constraints([
+            layout::Constraint::Min(3),
+            layout::Constraint::Length(edit_preview.as_ref().map(|v| v.1).unwrap_or(0)),
+            layout::Constraint::Length(if read_only { 0 } else { (input_text.height() as u16).min(input_lines) + 2 }),
+            layout::Constraint::Length(1),
+        ])
+        .split(horizontal[1]);
+
+    // The sidebar is left out while only one channel is shown
+    if !state.sidebar_hidden() {
+        draw_sidebar(f, state, horizontal[0]);
+    }
+
+    // Messages
+    let messages = state.pane_block(Pane::Messages);
+    let messages = if state.current_thread().is_some() {
+        messages.title(Span::styled("thread", state.theme.header))
+    } else {
+        messages
+    };
+    let selected = if matches!(state.mode, AppMode::Scroll) || state.editing {
+        Some(state.visible_selected())
+    } else {
+        None
+    };
+
+    // Format current list of messages, leaving room for the gutter
+    let header = state.theme.header;
+    let numbers = state.config.messages.relative_numbers;
+    let time_column = state.config.messages.timestamps == Timestamps::Column;
+    let gutter = (if numbers { 4 } else { 0 }) + (if time_column { TIME_COLUMN_WIDTH } else { 0 });
+
+    // The header takes the top line of the messages pane
+    let (header_area, messages_rect) = if state.config.messages.header && state.current_channel().is_some() && content[0].height > 3 {
+        let split = layout::Layout::default()
+            .direction(layout::Direction::Vertical)
+            .constraints([layout::Constraint::Length(1), layout::Constraint::Min(3)])
+            .split(content[0]);
+        (Some(split[0]), split[1])
+    } else {
+        (None, content[0])
+    };
+    if let Some(area) = header_area {
+        f.render_widget(widgets::Paragraph::new(header::line(state)), area);
+    }
+
+    // Wide terminals get a narrower reading column in the middle
+    let column = match state.config.messages.max_width {
+        Some(max) if max > 0 && messages_rect.width > max + gutter + 2 => {
+            let width = max + gutter + 2;
+            layout::Rect {
+                x: messages_rect.x + (messages_rect.width - width) / 2,
+                width,
+                ..messages_rect
+            }
+        }
+        _ => messages_rect,
+    };
+    let messages_list: Vec<_> = state
+        .visible_messages()
+        .iter()
+        .rev()
+        .filter_map(|v| {
+            let mut inner = messages.inner(column);
+            inner.width = inner.width.saturating_sub(gutter);
+            let mut result = vec![];
+
+            if let Some(channel) = state.current_channel() {
+                if let Some(v) = channel.messages_map.get(v) {
+                    // Notices are a single line
+                    if let MessageContent::Notice(notice) = &v.content {
+                        result.push(Spans::from(Span::styled(format!("* {}", notice), state.theme.notice)));
+                        return Some((v.id, result, false, v.timestamp));
+                    }
+
+                    // Metadata
+                    let (author, is_bot) = state
+                        .users
+                        .get(&v.author_id)
+                        .map(|v| (v.name.as_str(), v.is_bot))
+                        .unwrap_or(("<unknown user>", true));
+
+                    // Actions are written as what the author did, like on IRC
+                    if let Some((action, named)) = v.action() {
+                        let line = if named {
+                            action.to_owned()
+                        } else {
+                            format!("* {} {}", v.override_username.as_deref().unwrap_or(author), action)
+                        };
+                        let style = Style::default().add_modifier(Modifier::ITALIC);
+                        for line in wrap_plain(&line, inner.width as usize) {
+                            result.push(Spans::from(Span::styled(line, style)));
+                        }
+                        return Some((v.id, result, v.highlighted, v.timestamp));
+                    }
+
+                    let mut metadata = vec![];
+                    match &v.override_username {
+                        // Bridge bots send everyone's messages under their names, so the bot isn't the real author
+                        Some(override_username) if v.bridged.unwrap_or(is_bot) => {
+                            metadata.push(Span::styled(override_username.as_str(), header));
+                            metadata.push(Span::styled(" [bridge]", state.theme.translation));
+                        }
+
+                        Some(override_username) => {
+                            metadata.push(Span::styled(override_username.as_str(), header));
+                            metadata.push(Span::styled(" [OVR]", header));
+                        }
+
+                        None => {
+                            metadata.push(Span::styled(author, header));
+                            if is_bot {
+                                metadata.push(Span::styled(" [BOT]", header));
+                            }
+                        }
+                    }
+                    if !time_column {
+                        let time: DateTime<Local> =
+                            DateTime::from(UNIX_EPOCH + Duration::from_secs(v.timestamp));
+                        let format = time.format(" - %H:%M (%x)").to_string();
+                        metadata.push(Span::styled(format, header));
+                    }
+
+                    if v.edited_timestamp.is_some() {
+                        metadata.push(Span::styled(" (edited)", header));
+                    }
+
+                    // The user's messages are marked as delivered, or read once someone else has read them
+                    if v.author_id == state.current_user && state.capabilities.supports(Feature::ReadReceipts) {
+                        match channel.readers.get(&v.id).map(|receipts| receipts::read_by(state, receipts)).unwrap_or(0) {
+                            0 => metadata.push(Span::styled(" ✓", state.theme.translation)),
+                            count => metadata.push(Span::styled(format!(" ✓✓ {}", count), state.theme.translation)),
+                        }
+                    }
+                    result.push(Spans::from(metadata));
+
+                    // Content
+                    match &v.content {
+                        // Text wraps between words, keeping its formatting
+                        MessageContent::Text(text) => {
+                            for line in wrap::wrap(&text.contents, inner.width as usize) {
+                                let mut spans = rich_spans(text, line.range);
+                                if line.hyphen {
+                                    spans.push(Span::raw("-"));
+                                }
+                                result.push(Spans::from(spans));
+                            }
+                        }
+
+                        // Embeds are drawn with a bar on the left
+                        MessageContent::Embed(embed) => {
+                            let bar = || Span::styled("┃ ", header);
+                            let width = (inner.width as usize).saturating_sub(2);
+                            result.push(Spans::from(vec![bar(), Span::styled(embed.title.as_str(), header)]));
+                            if let Some(body) = &embed.body {
+                                for line in wrap_plain(&body.contents, width) {
+                                    result.push(Spans::from(vec![bar(), Span::raw(line)]));
+                                }
+                            }
+
+                            for field in embed.fields.iter() {
+                                let mut title = vec![bar(), Span::styled(field.title.as_str(), header)];
+                                if let Some(subtitle) = &field.subtitle {
+                                    title.push(Span::raw(format!(" - {}", subtitle)));
+                                }
+                                result.push(Spans::from(title));
+
+                                if let Some(body) = &field.body {
+                                    for line in wrap_plain(&body.contents, width) {
+                                        result.push(Spans::from(vec![bar(), Span::raw(line)]));
+                                    }
+                                }
+                            }
+
+                            // Buttons, with the selected one reversed in scroll mode
+                            if !embed.actions.is_empty() {
+                                let selected = if matches!(state.mode, AppMode::Scroll) && channel.selected_message().map(|m| m.id) == Some(v.id) {
+                                    Some(state.action_select.min(embed.actions.len() - 1))
+                                } else {
+                                    None
+                                };
+
+                                let mut buttons = vec![bar()];
+                                for (i, action) in embed.actions.iter().enumerate() {
+                                    let style = if selected == Some(i) {
+                                        Style::default().add_modifier(Modifier::REVERSED | Modifier::BOLD)
+                                    } else {
+                                        header
+                                    };
+                                    buttons.push(Span::styled(format!("[ {} ]", action.text), style));
+                                    buttons.push(Span::raw(" "));
+                                }
+                                result.push(Spans::from(buttons));
+                            }
+                        }
+
+                        // One line per file, with the selected one reversed in scroll mode
+                        MessageContent::Files(attachments) => {
+                            let selected = if matches!(state.mode, AppMode::Scroll) && channel.selected_message().map(|m| m.id) == Some(v.id) {
+                                Some(state.action_select.min(attachments.len().saturating_sub(1)))
+                            } else {
+                                None
+                            };
+
+                            for (i, attachment) in attachments.iter().enumerate() {
+                                let style = if selected == Some(i) {
+                                    Style::default().add_modifier(Modifier::REVERSED | Modifier::BOLD)
+                                } else {
+                                    header
+                                };
+
+                                result.push(Spans::from(vec![
+                                    Span::raw("📎 "),
+                                    Span::styled(attachment.name.as_str(), style),
+                                    Span::raw(format!(" ({})", attachment.mimetype)),
+                                ]));
+                            }
+                        }
+
+                        MessageContent::Notice(_) => (),
+                    }
+
+                    // Translation
+                    if let Some((language, translation)) = &v.translation {
+                        let style = state.theme.translation;
+                        result.push(Spans::from(Span::styled(format!("translated to {}:", language), style)));
+
+                        for line in wrap_plain(translation, inner.width as usize) {
+                            result.push(Spans::from(Span::styled(line, style)));
+                        }
+                    }
+
+                    Some((v.id, result, v.highlighted, v.timestamp))
+                } else {
+                    None
+                }
+            } else {
+                None
+            }
+        })
+        .enumerate()
+        .map(|(i, (id, mut v, highlighted, timestamp))| {
+            // Times go in their own column, next to the first line of each message
+            if time_column {
+                let time: DateTime<Local> = DateTime::from(UNIX_EPOCH + Duration::from_secs(timestamp));
+                for (j, line) in v.iter_mut().enumerate() {
+                    let time = if j == 0 { time.format("%-H:%M").to_string() } else { String::new() };
+                    line.0.insert(0, Span::styled(format!("{:>5} ", time), header));
+                }
+            }
+
+            // Number each message by its distance from the selected one
+            if numbers {
+                let selected = selected.unwrap_or(0) as isize;
+                for (j, line) in v.iter_mut().enumerate() {
+                    let number = if j == 0 { format!("{:>3} ", (i as isize - selected).abs()) } else { String::from("    ") };
+                    line.0.insert(0, Span::styled(number, state.theme.translation));
+                }
+            }
+
+            (id, v, highlighted)
+        })
+        .collect();
+
+    // While a message is selected, the message at the bottom of the pane and how far it's scrolled stay put as messages
+    // arrive, only moving to keep the selected message on screen
+    let height = messages.inner(column).height as usize;
+    let mut bottom = 0;
+    let mut offset = 0;
+    if let Some(channel) = state.current_channel() {
+        match selected.filter(|&v| v < messages_list.len()) {
+            Some(selected) => {
+                let view_bottom = channel.view_bottom.load(Ordering::Relaxed);
+                if channel.selected.is_some() {
+                    bottom = messages_list.iter().position(|v| v.0 == view_bottom).unwrap_or(0).min(selected);
+                }
+
+                // The offset only holds for the message it was scrolled in
+                let heights: Vec<_> = messages_list.iter().map(|v| v.1.len()).collect();
+                let mut kept = Some(channel.view_offset.load(Ordering::Relaxed)).filter(|_| messages_list[bottom].0 == view_bottom);
+                while bottom < selected
+                    && heights[bottom..=selected].iter().sum::<usize>() - kept.unwrap_or(0).min(heights[bottom].saturating_sub(1)) > height
+                {
+                    bottom += 1;
+                    kept = None;
+                }
+
+                // A message taller than the pane is shown a pane at a time, starting from its top
+                offset = if bottom == selected {
+                    let hidden = heights[bottom].saturating_sub(height);
+                    kept.map_or(hidden, |v| v.min(hidden))
+                } else {
+                    kept.unwrap_or(0).min(heights[bottom].saturating_sub(1))
+                };
+                channel.view_bottom.store(messages_list[bottom].0, Ordering::Relaxed);
+                channel.view_offset.store(offset, Ordering::Relaxed);
+            }
+            None => {
+                channel.view_bottom.store(0, Ordering::Relaxed);
+                channel.view_offset.store(0, Ordering::Relaxed);
+            }
+        }
+    }
+
+    // Render messages, cutting the bottom one down to the lines scrolled into view
+    let messages_area = messages.inner(column);
+    let messages_list: Vec<_> = messages_list
+        .into_iter()
+        .skip(bottom)
+        .enumerate()
+        .map(|(i, (_, mut lines, highlighted))| {
+            if i == 0 {
+                lines.truncate(lines.len().saturating_sub(offset));
+                let above = lines.len().saturating_sub(height);
+                lines.drain(..above);
+            }
+
+            let item = widgets::ListItem::new(Text::from(lines));
+            if highlighted {
+                item.style(state.theme.highlight)
+            } else {
+                item
+            }
+        })
+        .collect();
+    let messages = widgets::List::new(messages_list)
+        .block(messages)
+        .start_corner(layout::Corner::BottomLeft)
+        .highlight_style(if matches!(state.modals.last(), Some(Modal { kind: ModalKind::Confirm(ConfirmAction::DeleteMessage(_)), .. })) {
+            state.theme.danger
+        } else if state.editing {
+            state.theme.editing
+        } else {
+            state.theme.selected
+        });
+    let mut list_state = widgets::ListState::default();
+    list_state.select(selected.map(|v| v.saturating_sub(bottom)));
+    f.render_stateful_widget(messages, column, &mut list_state);
+
+    // Say why a channel has no messages
+    if let Some(channel) = state.current_channel().filter(|v| v.messages_list.is_empty()) {
+        let (text, style) = match &channel.history {
+            History::Unloaded | History::Loading => {
+                let millis = std::time::SystemTime::now().duration_since(UNIX_EPOCH).map(|v| v.as_millis()).unwrap_or(0);
+                let frame = (millis / SPINNER_FRAME_MILLIS) as usize % SPINNER.len();
+                (format!("{} loading messages…", SPINNER[frame]), state.theme.translation)
+            }
+            History::Loaded => (String::from("no messages yet — say hi!"), state.theme.translation),
+            History::Failed(e) => (format!("could not load messages: {}\npress r to try again", e), state.theme.danger),
+        };
+
+        let height = messages_area.height.min(3);
+        let area = layout::Rect {
+            y: messages_area.y + (messages_area.height - height) / 2,
+            height,
+            ..messages_area
+        };
+        let paragraph = widgets::Paragraph::new(text)
+            .style(style)
+            .alignment(layout::Alignment::Center)
+            .wrap(widgets::Wrap { trim: true });
+        f.render_widget(paragraph, area);
+    }
+
+    // Messages that arrived below while scrolled up are counted in the bottom corner
+    if let Some(channel) = state.current_channel().filter(|v| v.selected.is_some() && v.new_below > 0) {
+        let plural = if channel.new_below == 1 { "" } else { "s" };
+        let text = format!(" {} new message{} ↓ (G) ", channel.new_below, plural);
+        let width = (text.chars().count() as u16).min(column.width.saturating_sub(2));
+        if column.height > 0 {
+            let area = layout::Rect {
+                x: column.x + column.width.saturating_sub(width + 1),
+                y: column.y + column.height - 1,
+                width,
+                height: 1,
+            };
+            f.render_widget(widgets::Paragraph::new(Span::styled(text, state.theme.selected)), area);
+        }
+    }
+
+    // Edit preview
+    if let Some((preview, _)) = edit_preview {
+        let block = widgets::Block::default()
+            .borders(widgets::Borders::ALL)
+            .title("edit preview");
+        let preview = widgets::Paragraph::new(preview)
+            .block(block)
+            .wrap(widgets::Wrap { trim: false });
+        f.render_widget(preview, content[1]);
+    }
+
+    // Input
+    let broadcasting_typing = !state.editing
+        && !state.input.is_empty()
+        && state.typing_sent.map(|v| v.elapsed() < TYPING_DURATION).unwrap_or(false);
+    let input = state.pane_block(Pane::Input);
+    let input = if broadcasting_typing {
+        input.title(Span::styled("typing…", state.theme.typing))
+    } else {
+        input
+    };
+
+    if !read_only {
+        let input = widgets::Paragraph::new(input_text).block(input).scroll((input_scroll, 0));
+        f.render_widget(input, content[2]);
+    }
+
+    // Status bar (mode and who is typing)
+    let status = {
+        // Get who is typing
+        let now = Instant::now();
+        let typing: Vec<_> = state
+            .current_channel()
+            .map(|v| v.typing.iter().filter(|(_, &expiry)| expiry > now).map(|(id, _)| *id).collect())
+            .unwrap_or_default();
+        let typing: Vec<_> = typing
+            .iter()
+            .map(|v| state.users.get(v).map(|v| v.name.as_str()).unwrap_or("<unknown user>"))
+            .collect();
+        let typing = match typing.len() {
+            0 => None,
+            1 => Some(format!("{} is typing…", typing[0])),
+            2 | 3 => Some(format!("{} are typing…", typing.join(", "))),
+            _ => Some(String::from("several people are typing…")),
+        };
+
+        let with_status = |mode: &str| {
+            let mut mode = if state.dnd_active {
+                format!("{} [dnd]", mode)
+            } else {
+                mode.to_owned()
+            };
+
+            if state.config.low_data {
+                mode.push_str(" [low data]");
+            }
+
+            if state.sounds_muted {
+                mode.push_str(" [muted]");
+            }
+
+            if read_only {
+                mode.push_str(" [read only]");
+            }
+
+            if state.streamer_mode {
+                mode.push_str(" [streamer]");
+            }
+
+            if let Some((name, _)) = &state.send_as {
+                mode.push_str(&format!(" [as {}]", name));
+            }
+
+            if let Some(typing) = &typing {
+                mode.push_str(" | ");
+                mode.push_str(typing);
+            }
+
+            // Errors can include the homeserver's address
+            let mode = match &state.status {
+                Some(status) => format!("{} - {}", mode, state.redact(status)),
+                None => mode,
+            };
+
+            Spans::from(vec![state.health.indicator(&state.theme), Span::raw(" "), Span::raw(mode)])
+        };
+
+        match state.mode {
+            AppMode::TextNormal => widgets::Paragraph::new(with_status("normal")),
+            AppMode::TextInsert => widgets::Paragraph::new(with_status("insert")),
+            AppMode::Scroll => widgets::Paragraph::new(with_status("scroll")),
+
+            // Invites being typed are hidden character for character so the cursor stays in place
+            AppMode::Command => match state.command.strip_prefix("join ").filter(|_| state.streamer_mode) {
+                Some(invite) => widgets::Paragraph::new(format!(":join {}", "•".repeat(invite.chars().count()))),
+                None => widgets::Paragraph::new(Spans::from(vec![
+                    Span::raw(":"),
+                    Span::raw(state.command.as_str()),
+                ])),
+            },
+
+            AppMode::GuildSelect => widgets::Paragraph::new("select a guild"),
+
+            AppMode::ChannelSelect => widgets::Paragraph::new("select a channel"),
+        }
+    };
+    f.render_widget(status, content[3]);
+
+    // Popups
+    let placements = modal::render_stack(&state.modals, f, size, &state.theme);
+
+    // Stats go over everything so they can be watched while doing anything
+    if state.show_stats {
+        let (count, bytes) = state.message_cache_size();
+        let lines: Vec<_> = state.stats.describe(count, bytes).into_iter().map(Spans::from).collect();
+        let width = (lines.iter().map(Spans::width).max().unwrap_or(0) as u16 + 2).min(size.width);
+        let height = (lines.len() as u16 + 2).min(size.height);
+        let rect = layout::Rect::new(size.width - width, 0, width, height);
+        let block = widgets::Block::default()
+            .borders(widgets::Borders::ALL)
+            .border_style(state.theme.header)
+            .title("stats");
+        f.render_widget(widgets::Clear, rect);
+        f.render_widget(widgets::Paragraph::new(lines).block(block), rect);
+    }
+
+    // The homeserver, invites, and ids are blotted out of whatever pane or popup they ended up in while sharing the screen
+    if state.streamer_mode {
+        let invites = state.inbox.newest_first().filter_map(|v| match &v.kind {
+            InboxKind::Invite(invite) => Some(invite.as_str()),
+            _ => None,
+        });
+        let secrets = std::iter::once(state.homeserver_host()).chain(invites).collect();
+        f.render_widget(redact::Redact::new(secrets), size);
+    }
+
+    // Cursor stuff is dependent on mode, but popups and the hidden input box hide it
+    if !state.modals.is_empty() || (read_only && matches!(state.mode, AppMode::TextNormal | AppMode::TextInsert)) {
+        return placements;
+    }
+
+    match state.mode {
+        // Normal mode -> draw cursor as a block in input
+        AppMode::TextNormal => {
+            use crossterm::cursor::{CursorShape, SetCursorShape};
+            if multiplexer::cursor_shape() {
+                execute!(daemon::stdout(), SetCursorShape(CursorShape::Block)).unwrap();
+            }
+            f.set_cursor(
+                content[2].x + cursor_column as u16 + 1,
+                content[2].y + cursor_line as u16 + 1 - input_scroll,
+            );
+        }
+
+        // Insert mode -> draw cursor as a line in input
+        AppMode::TextInsert => {
+            use crossterm::cursor::{CursorShape, SetCursorShape};
+            if multiplexer::cursor_shape() {
+                execute!(daemon::stdout(), SetCursorShape(CursorShape::Line)).unwrap();
+            }
+            f.set_cursor(
+                content[2].x + cursor_column as u16 + 1,
+                content[2].y + cursor_line as u16 + 1 - input_scroll,
+            );
+        }
+
+        // Command mode -> draw cursor as a line in prompt
+        AppMode::Command => {
+            use crossterm::cursor::{CursorShape, SetCursorShape};
+            if multiplexer::cursor_shape() {
+                execute!(daemon::stdout(), SetCursorShape(CursorShape::Line)).unwrap();
+            }
+            f.set_cursor(
+                content[3].x + state.command.column() as u16 + 1,
+                content[3].y + 1,
+            );
+        }
+
+        // Everything else -> don't draw cursor
+        _ => (),
+    }
+
+    placements
+}
+
+/// Handles UI events such as key presses and mouse events.
+async fn ui_events(
+    state: Arc<RwLock<AppState>>,
+    tx: mpsc::Sender<ClientEvent>,
+    mut input_events: mpsc::Receiver<(input::Source, Event)>,
+    shutdown: CancellationToken,
+) {
+    // The events that ended a paste, handled in order before reading more
+    let mut pending = VecDeque::new();
+
+    // Event loop
+    loop {
+        let (source, event) = match pending.pop_front() {
+            Some(event) => event,
+            None => match input_events.recv().await {
+                Some(event) => event,
+                None => break,
+            },
+        };
+
+        // Each terminal attached to the daemon has its own mode, draft, and place
+        daemon::switch(&mut *state.write().await, source);
+
+        // Get mode
+        let mode = state.read().await.mode;
+        match event {
+            // Key events
+            crossterm::event::Event::Key(key) => {
+                // The only thing to do while loading is quit
+                if state.read().await.splash.is_some() {
+                    let quit = matches!(key.code, KeyCode::Esc | KeyCode::Char('q'))
+                        || (key.code == KeyCode::Char('c') && key.modifiers == KeyModifiers::CONTROL);
+                    if quit {
+                        shutdown.cancel();
+                    }
+                    continue;
+                }
+
+                // The lock screen takes every key until it's unlocked
+                {
+                    let mut state = state.write().await;
+                    if let Some(lock) = &mut state.locked {
+                        match lock.handle_key(key) {
+                            LockEvent::None => (),
+                            LockEvent::Unlocked => {
+                                state.locked = None;
+                                state.last_input = presence::now();
+                            }
+                            LockEvent::Chose(hash) => state.lock_hash = Some(hash),
+                        }
+                        continue;
+                    }
+                }
+
+                // Clear status message and record activity
+                {
+                    let mut state = state.write().await;
+                    state.status = None;
+                    state.last_input = presence::now();
+
+                    if key.code != KeyCode::Tab {
+                        state.completion = None;
+                    }
+                }
+
+                // Popups take focus from everything else
+                let modal_event = state.write().await.modals.last_mut().map(|v| v.handle_key(key));
+                if let Some(modal_event) = modal_event {
+                    match modal_event {
+                        ModalEvent::None => (),
+
+                        ModalEvent::Close => {
+                            state.write().await.modals.pop();
+                        }
+
+                        ModalEvent::Accept => {
+                            let popup = state.write().await.modals.pop();
+                            match popup.map(|v| v.kind) {
+                                Some(ModalKind::Confirm(action)) => match action {
+                                    ConfirmAction::DeleteMessage(message_id) => {
+                                        let _ = tx.send(ClientEvent::Delete(message_id)).await;
+                                    }
+
+                                    ConfirmAction::LeaveGuild(guild_id) => {
+                                        let _ = tx.send(ClientEvent::LeaveGuild(guild_id)).await;
+                                    }
+
+                                    ConfirmAction::JoinGuild(invite) => {
+                                        let _ = tx.send(ClientEvent::JoinGuild(invite)).await;
+                                    }
+
+                                    ConfirmAction::JumpToMessage(guild_id, channel_id, message_id) => {
+                                        let missing = state.write().await.jump_to_message(guild_id, channel_id, message_id);
+                                        if missing {
+                                            let _ = tx.send(ClientEvent::LoadMessage(message_id)).await;
+                                        }
+                                    }
+
+                                    ConfirmAction::Broadcast(targets, text) => {
+                                        let count = targets.len();
+                                        for (guild_id, channel_id) in targets {
+                                            let _ = tx.send(ClientEvent::SendTo(guild_id, channel_id, text.clone())).await;
+                                        }
+                                        state.write().await.status = Some(format!("sent to {} channels", count));
+                                    }
+
+                                    ConfirmAction::DeleteRole(guild_id, role_id) => {
+                                        let _ = tx.send(ClientEvent::EditRoles(guild_id, RoleRequest::Delete(role_id))).await;
+                                    }
+
+                                    ConfirmAction::PurgeLocal(guild_id) => {
+                                        let reload = {
+                                            let mut state = state.write().await;
+                                            state.status = Some(String::from("local data wiped"));
+                                            state.purge_local(guild_id)
+                                        };
+                                        if reload {
+                                            let _ = tx.send(ClientEvent::GetMoreMessages(None)).await;
+                                        }
+                                    }
+
+                                    // Accepting the first prompt brings up the second
+                                    ConfirmAction::DeleteAccount(false) => {
+                                        let mut state = state.write().await;
+                                        let popup = state.delete_account_prompt(true);
+                                        state.modals.push(popup);
+                                    }
+                                    ConfirmAction::DeleteAccount(true) => {
+                                        state.write().await.status = Some(String::from("deleting your account..."));
+                                        let _ = tx.send(ClientEvent::DeleteAccount).await;
+                                    }
+                                },
+
+                                // Encode, upload, and send the voice message in the background
+                                Some(ModalKind::Recording(recording)) => {
+                                    let (homeserver, token, target) = {
+                                        let state = state.read().await;
+                                        (state.homeserver.clone(), state.token.clone(), state.current_channel().map(|v| (v.guild_id, v.id)))
+                                    };
+
+                                    if let Some((guild_id, channel_id)) = target {
+                                        let name = "voice-message.ogg";
+                                        let transfer = state.write().await.start_transfer(name, true);
+                                        let state = state.clone();
+                                        let tx = tx.clone();
+                                        tokio::spawn(async move {
+                                            let result = async {
+                                                let bytes = tokio::task::spawn_blocking(move || recording.finish()).await.map_err(|e| e.to_string())??;
+                                                let size = bytes.len();
+                                                let mimetype = "audio/ogg";
+                                                let id = media::upload(&homeserver, &token, name, mimetype, bytes, transfer)
+                                                    .await
+                                                    .map_err(|e| e.to_string())?;
+                                                let attachment = Attachment {
+                                                    id,
+                                                    name: name.to_owned(),
+                                                    mimetype: mimetype.to_owned(),
+                                                };
+                                                let _ = tx.send(ClientEvent::SendAttachment(guild_id, channel_id, attachment, size)).await;
+                                                Ok::<_, String>(())
+                                            }
+                                            .await;
+
+                                            if let Err(e) = result {
+                                                state.write().await.status = Some(format!("could not send voice message: {}", e));
+                                            }
+                                        });
+                                    }
+                                }
+
+                                _ => (),
+                            }
+                        }
+
+                        ModalEvent::OpenAttachment(attachment) => {
+                            open_attachment(&state, attachment).await;
+                        }
+
+                        ModalEvent::Roles(request) => {
+                            let mut state = state.write().await;
+                            let popup = match state.modals.last().map(|v| &v.kind) {
+                                // Ask before deleting a role
+                                Some(ModalKind::Roles(editor)) => match request {
+                                    RoleRequest::Delete(role_id) => {
+                                        let name = editor.role(role_id).map(|v| v.name.clone()).unwrap_or_default();
+                                        let body = Text::from(format!("{} will be taken away from everyone who has it.", name));
+                                        Some(Modal::confirm("delete this role?", body, ConfirmAction::DeleteRole(editor.guild_id, role_id)))
+                                    }
+
+                                    request => {
+                                        let _ = tx.send(ClientEvent::EditRoles(editor.guild_id, request)).await;
+                                        None
+                                    }
+                                },
+
+                                _ => None,
+                            };
+
+                            if let Some(popup) = popup {
+                                state.modals.push(popup);
+                            }
+                        }
+
+                        ModalEvent::AccountStep(response) => {
+                            let _ = tx.send(ClientEvent::AccountStep(response)).await;
+                        }
+
+                        // Save the setting, then apply it the same way as editing the file by hand
+                        ModalEvent::Setting(key, value) => {
+                            let mut state = state.write().await;
+                            state.status = Some(match settings::save(key, &value).and_then(|_| Config::try_load()) {
+                                Ok(config) if state.apply_config(config) => format!("saved {}; {} settings apply after a restart", key, STARTUP_SETTINGS),
+                                Ok(_) => format!("saved {}", key),
+                                Err(e) => format!("could not save {}: {}", key, e),
+                            });
+                        }
+
+                        ModalEvent::Save(attachment) => {
+                            let (homeserver, transfer) = {
+                                let mut state = state.write().await;
+                                (state.homeserver.clone(), state.start_transfer(&attachment.name, false))
+                            };
+                            let state = state.clone();
+                            tokio::spawn(async move {
+                                let status = match media::save(&attachment, &homeserver, &transfer).await {
+                                    Ok(path) => format!("saved to {}", path.display()),
+                                    Err(e) => format!("could not download {}: {}", attachment.name, e),
+                                };
+                                state.write().await.status = Some(status);
+                            });
+                        }
+
+                        ModalEvent::Open(id) => {
+                            let mut state = state.write().await;
+                            let list = match state.modals.last().map(|v| &v.kind) {
+                                Some(ModalKind::List { list, .. }) => Some(list.clone()),
+                                _ => None,
+                            };
+
+                            match list {
+                                Some(ListKind::Scheduled) => (),
+
+                                // Switch to the channel
+                                Some(ListKind::Channels(channels)) => {
+                                    if let Some(&(guild_id, channel_id)) = channels.get(id as usize) {
+                                        state.modals.pop();
+                                        if state.visit_channel(guild_id, channel_id) {
+                                            let _ = tx.send(ClientEvent::GetMoreMessages(None)).await;
+                                        }
+                                        state.mode = AppMode::TextNormal;
+                                    }
+                                }
+
+                                // Switch to the guild shared with the user
+                                Some(ListKind::Online(contacts)) => {
+                                    if let Some(&(_, guild_id)) = contacts.get(id as usize) {
+                                        state.modals.pop();
+                                        state.list_filter = None;
+                                        state.current_guild = Some(guild_id);
+                                        state.guilds_select = state.guilds_list.iter().position(|&v| v == guild_id);
+
+                                        if state.current_guild().map(|v| v.channels_list.is_empty()).unwrap_or(false) {
+                                            // Pick the channel once they're fetched
+                                            state.pending_default = Some(guild_id);
+                                            state.mode = AppMode::ChannelSelect;
+                                        } else if state.open_default_channel(guild_id) {
+                                            let _ = tx.send(ClientEvent::GetMoreMessages(None)).await;
+                                        }
+                                        let _ = tx.send(ClientEvent::GetChannels(guild_id)).await;
+                                    }
+                                }
+
+                                // Insert the snippet at the cursor
+                                Some(ListKind::Snippets(names)) => {
+                                    if let Some(template) = names.get(id as usize).and_then(|v| state.config.snippets.get(v)).cloned() {
+                                        state.modals.pop();
+                                        if state.start_insert() {
+                                            let pos = state.input.cursor();
+                                            state.insert_snippet(pos..pos, &template);
+                                        }
+                                    }
+                                }
+
+                                // Send the forwarded message to the channel
+                                Some(ListKind::Forward { channels, text, files }) => {
+                                    if let Some(&(guild_id, channel_id)) = channels.get(id as usize) {
+                                        state.modals.pop();
+                                        let label = state.channel_label(guild_id, channel_id).unwrap_or_default();
+                                        let _ = tx.send(ClientEvent::Forward(guild_id, channel_id, text, files)).await;
+                                        state.status = Some(format!("forwarded to {}", label));
+                                    }
+                                }
+
+                                // Jump to the message, or preview the guild the invite is for
+                                Some(ListKind::Inbox) => {
+                                    if let Some(entry) = state.inbox.get(id).cloned() {
+                                        state.modals.pop();
+                                        state.inbox.mark_read(id);
+                                        match entry.kind {
+                                            InboxKind::Invite(invite) => {
+                                                let _ = tx.send(ClientEvent::PreviewInvite(invite)).await;
+                                            }
+                                            InboxKind::Mention | InboxKind::DirectMessage => {
+                                                if state.jump_to_message(entry.guild_id, entry.channel_id, entry.message_id) {
+                                                    let _ = tx.send(ClientEvent::LoadMessage(entry.message_id)).await;
+                                                }
+                                            }
+                                        }
+                                    }
+                                }
+
+                                // Jump to the result, loading history back to it if it isn't loaded
+                                Some(ListKind::Search(hits)) | Some(ListKind::Filter(_, hits)) => {
+                                    if let Some(hit) = hits.get(id as usize) {
+                                        state.modals.pop();
+                                        let loaded = state
+                                            .get_channel(hit.guild_id, hit.channel_id)
+                                            .map(|v| v.messages_map.contains_key(&hit.message_id))
+                                            .unwrap_or(false);
+                                        if loaded {
+                                            state.jump_to_message(hit.guild_id, hit.channel_id, hit.message_id);
+                                        } else {
+                                            state.visit_channel(hit.guild_id, hit.channel_id);
+                                            state.mode = AppMode::Scroll;
+                                            let _ = tx.send(ClientEvent::LoadUntil(hit.timestamp)).await;
+                                        }
+                                    }
+                                }
+
+                                // Show the messages matching the filter
+                                Some(ListKind::Filters(names)) => {
+                                    if let Some(i) = names.get(id as usize).and_then(|name| state.filters.iter().position(|v| v.name == *name)) {
+                                        state.modals.pop();
+                                        state.filters[i].unseen = 0;
+                                        let popup = filters::view(&state, &state.filters[i]);
+                                        state.modals.push(popup);
+                                    }
+                                }
+
+                                // Jump to the saved message
+                                Some(ListKind::Bookmarks) => {
+                                    let target = state.bookmarks.iter().find(|v| v.message_id == id).map(|v| (v.guild_id, v.channel_id));
+                                    if let Some((guild_id, channel_id)) = target {
+                                        state.modals.pop();
+                                        if state.jump_to_message(guild_id, channel_id, id) {
+                                            let _ = tx.send(ClientEvent::LoadMessage(id)).await;
+                                        }
+                                    }
+                                }
+
+                                None => (),
+                            }
+                        }
+
+                        ModalEvent::Remove(id) => {
+                            let mut state = state.write().await;
+                            let list = match state.modals.last().map(|v| &v.kind) {
+                                Some(ModalKind::List { list, .. }) => Some(list.clone()),
+                                _ => None,
+                            };
+
+                            match list {
+                                Some(ListKind::Scheduled) => {
+                                    state.scheduled.retain(|v| v.id != id);
+                                    schedule::save(&state);
+                                }
+
+                                // Removing a channel or snippet only hides it from the popup
+                                Some(ListKind::Channels(_))
+                                | Some(ListKind::Online(_))
+                                | Some(ListKind::Forward { .. })
+                                | Some(ListKind::Snippets(_))
+                                | Some(ListKind::Search(_))
+                                | Some(ListKind::Filter(..)) => (),
+
+                                Some(ListKind::Filters(names)) => {
+                                    if let Some(name) = names.get(id as usize) {
+                                        state.filters.retain(|v| v.name != *name);
+                                        filters::save(state.current_user, &state.filters);
+                                    }
+                                }
+
+                                Some(ListKind::Bookmarks) => {
+                                    state.bookmarks.retain(|v| v.message_id != id);
+                                    bookmarks::save(state.current_user, &state.bookmarks);
+                                }
+
+                                Some(ListKind::Inbox) => state.inbox.remove(id),
+
+                                None => (),
+                            }
+                        }
+                    }
+
+                    continue;
+                }
+
+                // After `"` or ctrl+r, the next key names a register
+                {
+                    let mut state = state.write().await;
+                    if let Some(prompt) = state.register_prompt.take() {
+                        if let KeyCode::Char(name) = key.code {
+                            match prompt {
+                                // Run the last command again by sending it through the prompt
+                                RegisterPrompt::Execute if name == registers::COMMAND => match state.registers.get(Some(name)).map(str::to_owned) {
+                                    Some(command) => {
+                                        state.command = TextBuffer::new(&command);
+                                        state.command_return = state.mode;
+                                        state.mode = AppMode::Command;
+                                        pending.push_back((source, Event::Key(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE))));
+                                    }
+                                    None => state.status = Some(String::from("no command to repeat")),
+                                },
+                                RegisterPrompt::Execute => state.status = Some(String::from("only @: is supported")),
+
+                                RegisterPrompt::Select if registers::is_register(name) => state.register = Some(name),
+
+                                // Pasting into the input box can be repeated, unlike pasting into the prompt
+                                RegisterPrompt::Insert if registers::is_register(name) || name == registers::COMMAND => {
+                                    if matches!(state.mode, AppMode::Command) {
+                                        state.paste(Some(name));
+                                    } else {
+                                        edit::apply(&mut state, Edit::Paste { register: Some(name), after: false });
+                                    }
+                                }
+
+                                _ => state.status = Some(format!("no register {}", name)),
+                            }
+                        }
+                        continue;
+                    }
+                }
+
+                // Keys go to the focused pane, except in command mode, which takes them whatever has focus
+                let focus = state.read().await.focus();
+                match (focus, mode) {
+                    // Normal mode
+                    (Pane::Input, AppMode::TextNormal) => {
+                        match key.code {
+                            // Exit editing if editing
+                            KeyCode::Esc if state.read().await.editing => {
+                                let mut state = state.write().await;
+                                state.mode = AppMode::Scroll;
+                                state.editing = false;
+                                state.input = std::mem::take(&mut state.old_input);
+                            }
+
+                            // Jump back to the previous channel
+                            KeyCode::Char('o') if key.modifiers == KeyModifiers::CONTROL => {
+                                jump_channel(&state, &tx, true).await;
+                            }
+
+                            // Jump forward to the next channel (terminals send ctrl+i as tab)
+                            KeyCode::Char('i') if key.modifiers == KeyModifiers::CONTROL => {
+                                jump_channel(&state, &tx, false).await;
+                            }
+                            KeyCode::Tab => {
+                                jump_channel(&state, &tx, false).await;
+                            }
+
+                            // Show recent channels
+                            KeyCode::Char('t') if key.modifiers == KeyModifiers::CONTROL => {
+                                show_recent_channels(&mut *state.write().await);
+                            }
+
+                            // Enter insert mode
+                            KeyCode::Char('i') => {
+                                state.write().await.start_insert();
+                            }
+
+                            // Enter scroll mode
+                            KeyCode::Char('s') => {
+                                state.write().await.mode = AppMode::Scroll;
+                            }
+
+                            // Try loading the channel's messages again
+                            KeyCode::Char('r') => {
+                                retry_history(&state, &tx).await;
+                            }
+
+                            // Enter guild select mode
+                            KeyCode::Char('g') => {
+                                let mut state = state.write().await;
+                                if state.sidebar_hidden() {
+                                    state.status = Some(String::from("the sidebar is hidden while only one channel is shown"));
+                                } else {
+                                    state.mode = AppMode::GuildSelect;
+                                }
+                            }
+
+                            // Enter channel select mode
+                            KeyCode::Char('c') => {
+                                let mut state = state.write().await;
+                                if state.sidebar_hidden() {
+                                    state.status = Some(String::from("the sidebar is hidden while only one channel is shown"));
+                                } else {
+                                    state.mode = AppMode::ChannelSelect;
+                                }
+                            }
+
+                            // TODO: up/down
+
+                            // Move left
+                            KeyCode::Char('h') | KeyCode::Left => {
+                                state.write().await.input.move_left();
+                            }
+
+                            // Move right
+                            KeyCode::Char('l') | KeyCode::Right => {
+                                state.write().await.input.move_right();
+                            }
+
+                            // Pick a register for the next paste or delete
+                            KeyCode::Char('"') => {
+                                state.write().await.register_prompt = Some(RegisterPrompt::Select);
+                            }
+
+                            // Paste after the cursor
+                            KeyCode::Char('p') => {
+                                let mut state = state.write().await;
+                                let register = state.register.take();
+                                edit::apply(&mut state, Edit::Paste { register, after: true });
+                            }
+
+                            // Paste before the cursor
+                            KeyCode::Char('P') => {
+                                let mut state = state.write().await;
+                                let register = state.register.take();
+                                edit::apply(&mut state, Edit::Paste { register, after: false });
+                            }
+
+                            // Delete the character under the cursor
+                            KeyCode::Char('x') => {
+                                edit::apply(&mut *state.write().await, Edit::Delete);
+                            }
+
+                            // Delete to the end of the input
+                            KeyCode::Char('D') => {
+                                edit::apply(&mut *state.write().await, Edit::DeleteToEnd);
+                            }
+
+                            // Make the last change to the input again
+                            KeyCode::Char('.') => {
+                                edit::repeat(&mut *state.write().await);
+                            }
+
+                            // Run the last command again with `@:`
+                            KeyCode::Char('@') => {
+                                state.write().await.register_prompt = Some(RegisterPrompt::Execute);
+                            }
+
+                            // Enter command prompt
+                            KeyCode::Char(':') => {
+                                let mut state = state.write().await;
+                                state.mode = AppMode::Command;
+                                state.command_return = AppMode::TextNormal;
+                                state.command.clear();
+                            }
+
+                            // Send message
+                            KeyCode::Enter => {
+                                send_message(&state, &tx).await;
+                            }
+
+                            // Don't do anything on invalid input
+                            _ => (),
+                        }
+                    }
+
+                    // Insert mode
+                    (Pane::Input, AppMode::TextInsert) => {
+                        match key.code {
+                            // Exit insert mode into normal mode
+                            KeyCode::Esc => {
+                                let mut state = state.write().await;
+                                state.mode = AppMode::TextNormal;
+                                state.edit_joins = false;
+                            }
+
+                            // TODO: up/down
+
+                            // Move left, which starts a new change like in vim
+                            KeyCode::Left => {
+                                let mut state = state.write().await;
+                                state.input.move_left();
+                                state.edit_joins = false;
+                            }
+
+                            // Move right
+                            KeyCode::Right => {
+                                let mut state = state.write().await;
+                                state.input.move_right();
+                                state.edit_joins = false;
+                            }
+
+                            // Backspace
+                            KeyCode::Backspace => {
+                                edit::apply(&mut *state.write().await, Edit::Backspace);
+                            }
+
+                            // Paste a register
+                            KeyCode::Char('r') if key.modifiers == KeyModifiers::CONTROL => {
+                                state.write().await.register_prompt = Some(RegisterPrompt::Insert);
+                            }
+
+                            // Delete the word before the cursor
+                            KeyCode::Char('w') if key.modifiers == KeyModifiers::CONTROL => {
+                                edit::apply(&mut *state.write().await, Edit::DeleteWord);
+                            }
+
+                            // Delete everything before the cursor
+                            KeyCode::Char('u') if key.modifiers == KeyModifiers::CONTROL => {
+                                edit::apply(&mut *state.write().await, Edit::DeleteToStart);
+                            }
+
+                            // Insert character
+                            KeyCode::Char(c) if input::is_text(&key) => {
+                                // Pasted text is inserted all at once, keeping its line breaks
+                                let (text, next) = input::take_burst(c, source, &mut input_events, true);
+                                pending.extend(next);
+
+                                let mut state = state.write().await;
+                                edit::apply(&mut state, Edit::Insert(text));
+
+                                // Tell others we're typing, but not too often
+                                if !state.editing && state.typing_sent.map(|v| v.elapsed() >= TYPING_DURATION).unwrap_or(true) {
+                                    state.typing_sent = Some(Instant::now());
+                                    let _ = tx.send(ClientEvent::Typing).await;
+                                }
+                            }
+
+                            // Send message
+                            KeyCode::Enter => {
+                                send_message(&state, &tx).await;
+                            }
+
+                            // Complete slash commands
+                            KeyCode::Tab => {
+                                // Work out what to complete, letting go of the state while the candidates are searched
+                                let (input, word_start, word_end, prefix, index, matcher) = {
+                                    let mut state = state.write().await;
+                                    let word_end = state.input.cursor();
+                                    let word_start = state.input[..word_end].rfind(' ').map(|v| v + 1).unwrap_or(0);
+
+                                    // Expand the word before the cursor if it's a snippet
+                                    if state.completion.is_none() {
+                                        if let Some(template) = state.config.snippets.get(&state.input[word_start..word_end]).cloned() {
+                                            state.insert_snippet(word_start..word_end, &template);
+                                            continue;
+                                        }
+                                    }
+
+                                    let (prefix, index) = match state.completion.take() {
+                                        Some((prefix, index)) => (prefix, index + 1),
+                                        None => (state.input[word_start..word_end].to_owned(), 0),
+                                    };
+
+                                    // Slash commands at the start of the message and mentions anywhere
+                                    let matcher = if prefix.starts_with('/') && word_start == 0 {
+                                        let commands: Vec<_> = state.current_channel().map(|v| v.slash_commands.iter().cloned().collect()).unwrap_or_default();
+                                        Matcher::default().source(commands)
+                                    } else if prefix.starts_with('@') {
+                                        let members: Vec<_> = state.users.values().map(|v| format!("@{}", v.name)).collect();
+                                        Matcher::default().source(members)
+                                    } else {
+                                        continue;
+                                    };
+
+                                    (state.input.to_string(), word_start, word_end, prefix, index, matcher)
+                                };
+
+                                let candidates = matcher.search(&prefix).await;
+
+                                // Typing while the candidates were searched makes them stale
+                                let mut state = state.write().await;
+                                if !candidates.is_empty() && state.input == input.as_str() {
+                                    let (candidate, _) = &candidates[index % candidates.len()];
+                                    state.input.replace_range(word_start..word_end, candidate);
+                                    state.completion = Some((prefix, index));
+                                }
+                            }
+
+                            // Nothing else is valid
+                            _ => (),
+                        }
+                    }
+
+                    // Command mode
+                    (_, AppMode::Command) => {
+                        match key.code {
+                            // Exit command mode into the previous mode
+                            KeyCode::Esc => {
+                                let mut state = state.write().await;
+                                state.mode = state.command_return;
+                            }
+
+                            // Process command
+                            KeyCode::Enter => {
+                                let mut state = state.write().await;
+                                state.mode = state.command_return;
+
+                                // Remember the command for `@:`
+                                if !state.command.is_empty() {
+                                    let command = state.command.as_str().to_owned();
+                                    state.registers.set_command(command);
+                                }
+
+                                // TODO: better command system
+                                if state.command == "q" || state.command == "quit" {
+                                    shutdown.cancel();
+                                    let _ = tx.send(ClientEvent::Quit).await;
+                                } else if let Some(invite) =  state.command.strip_prefix("join ") {
+                                    let _ = tx.send(ClientEvent::PreviewInvite(invite.to_owned())).await;
+                                } else if state.command == "leave" || state.command.starts_with("leave ") {
+                                    // Leave the named guild, or the current one if no name is given
+                                    let name = state.command["leave".len()..].trim().to_owned();
+                                    let guild_id = if name.is_empty() { state.current_guild } else { state.find_guild(&name) };
+                                    match guild_id.and_then(|v| state.leave_prompt(v)) {
+                                        Some(popup) => state.modals.push(popup),
+                                        None if name.is_empty() => state.status = Some(String::from("no guild selected")),
+                                        None => state.status = Some(format!("no guild named {}", name)),
+                                    }
+                                } else if state.command == "purge-local" || state.command.starts_with("purge-local ") {
+                                    // Wipe local data for the named guild, the current one if no name is given, or everything
+                                    let name = state.command["purge-local".len()..].trim().to_owned();
+                                    let scope = match name.as_str() {
+                                        "" => state.current_guild.map(Some),
+                                        "all" => Some(None),
+                                        name => state.find_guild(name).map(Some),
+                                    };
+
+                                    match scope.and_then(|v| state.purge_prompt(v)) {
+                                        Some(popup) => state.modals.push(popup),
+                                        None if name.is_empty() => state.status = Some(String::from("no guild selected")),
+                                        None => state.status = Some(format!("no guild named {}", name)),
+                                    }
+                                } else if state.command == "cache" || state.command.starts_with("cache ") {
+                                    // Show how much the media cache holds, or empty it
+                                    match state.command["cache".len()..].trim() {
+                                        "" | "stats" => {
+                                            let stats = cache::stats();
+                                            let limit = if stats.limit == u64::MAX { String::from("no limit") } else { format!("limit {}", transfers::format_bytes(stats.limit)) };
+                                            state.status = Some(format!("media cache: {} files, {} ({})", stats.files, transfers::format_bytes(stats.bytes), limit));
+                                        }
+                                        "clear" => {
+                                            state.status = Some(match cache::clear() {
+                                                Ok(removed) => format!("media cache cleared ({} files)", removed),
+                                                Err(e) => format!("could not clear the media cache: {}", e),
+                                            });
+                                        }
+                                        arg => state.status = Some(format!("unknown cache command {}; use stats or clear", arg)),
+                                    }
+                                } else if state.command == "copy-link" || state.command == "copy-link avatar" {
+                                    // Copy a url for the selected attachment, or the author's avatar, for use outside the client
+                                    let avatar = state.command.ends_with(" avatar");
+                                    let reference = state.current_channel().and_then(Channel::selected_message).and_then(|message| {
+                                        if avatar {
+                                            state.users.get(&message.author_id).and_then(|v| v.avatar.clone())
+                                        } else if let MessageContent::Files(attachments) = &message.content {
+                                            attachments.get(state.action_select.min(attachments.len().saturating_sub(1))).map(|v| v.id.clone())
+                                        } else {
+                                            None
+                                        }
+                                    });
+
+                                    state.status = Some(match reference {
+                                        Some(reference) => match clipboard::copy(&media::resolve_url(&reference, &state.homeserver)) {
+                                            Ok(()) => String::from("link copied"),
+                                            Err(e) => format!("could not copy the link: {}", e),
+                                        },
+                                        None if avatar => String::from("the author has no avatar"),
+                                        None => String::from("no attachment selected"),
+                                    });
+                                } else if state.command == "open-math" {
+                                    // Render the math in the selected message as an image
+                                    if let Some(MessageContent::Text(text)) = state.current_channel().and_then(Channel::selected_message).map(|v| &v.content) {
+                                        let sources: Vec<_> = text.formats.iter().filter_map(|(_, _, meta)| match meta {
+                                            FormatMetadata::Math(source) => Some(source.clone()),
+                                            _ => None,
+                                        }).collect();
+
+                                        if !sources.is_empty() {
+                                            let _ = tx.send(ClientEvent::OpenMath(sources)).await;
+                                        }
+                                    }
+                                } else if let Some(key) = state.command.strip_prefix("translate-key ") {
+                                    // Save the translation api key with the rest of the saved data, encrypted if that's on
+                                    let key = key.trim();
+                                    let result = translate::save_key(Some(key).filter(|v| *v != "off"));
+                                    state.status = Some(match result {
+                                        Ok(()) if key == "off" => String::from("translation api key forgotten"),
+                                        Ok(()) => String::from("translation api key saved"),
+                                        Err(e) => format!("could not save the api key: {}", e),
+                                    });
+                                } else if state.command == "translate" || state.command.starts_with("translate ") {
+                                    // Translate the selected message
+                                    let language = state.command["translate".len()..].trim();
+                                    let language = if language.is_empty() {
+                                        state.config.translate.language.clone()
+                                    } else {
+                                        language.to_owned()
+                                    };
+
+                                    if let Some(message) = state.current_channel().and_then(Channel::selected_message) {
+                                        let _ = tx.send(ClientEvent::Translate(message.id, language)).await;
+                                    }
+                                } else if let Some(sort) = state.command.strip_prefix("sort ") {
+                                    // Change how the guild list is sorted
+                                    let sort = match sort.trim() {
+                                        "manual" => Some(GuildSort::Manual),
+                                        "activity" => Some(GuildSort::Activity),
+                                        _ => None,
+                                    };
+
+                                    match sort {
+                                        Some(sort) => {
+                                            state.guild_sort = sort;
+                                            state.sort_guilds();
+                                        }
+                                        None => state.status = Some(String::from("unknown sort; use `manual` or `activity`")),
+                                    }
+                                } else if state.command == "dnd" || state.command.starts_with("dnd ") {
+                                    // Turn on do not disturb, optionally for a certain amount of time
+                                    let arg = state.command["dnd".len()..].trim().to_owned();
+                                    if arg.is_empty() {
+                                        state.dnd_until = Some(u64::MAX);
+                                    } else if arg == "off" {
+                                        state.dnd_until = None;
+                                    } else if let Some(until) = duration::parse_duration(&arg).and_then(|v| presence::now().checked_add(v.as_secs())) {
+                                        state.dnd_until = Some(until);
+                                    } else {
+                                        state.status = Some(format!("invalid duration {} (at most 100 years)", arg));
+                                    }
+                                } else if state.command == "mute" {
+                                    // Toggle notification sounds
+                                    state.sounds_muted = !state.sounds_muted;
+                                    state.status = Some(String::from(if state.sounds_muted { "sounds muted" } else { "sounds unmuted" }));
+                                } else if state.command == "status" || state.command.starts_with("status ") {
+                                    // Show, set, or clear the status text, which is kept across restarts
+                                    let arg = state.command["status".len()..].trim().to_owned();
+                                    if arg.is_empty() {
+                                        state.status = Some(match (&state.presence_text, &state.status_text) {
+                                            (Some(text), _) => format!("status text: {} (from rich presence)", text),
+                                            (None, Some(text)) => format!("status text: {}", text),
+                                            (None, None) => String::from("no status text set"),
+                                        });
+                                    } else {
+                                        let text = Some(arg).filter(|v| v != "off");
+                                        presence::save_status_text(text.as_deref());
+                                        state.status_text = text.clone();
+                                        if state.presence_text.is_some() {
+                                            state.status = Some(String::from("status text saved for when rich presence has nothing to show"));
+                                        } else if state.capabilities.supports(Feature::StatusText) {
+                                            let _ = tx.send(ClientEvent::SetStatusText(text)).await;
+                                        } else {
+                                            state.status = Some(format!("{}; saved for later", state.capabilities.unsupported_message(Feature::StatusText)));
+                                        }
+                                    }
+                                } else if state.command.starts_with("schedule ") {
+                                    // Send a message in the current channel later
+                                    let args = state.command["schedule ".len()..].to_owned();
+                                    let (time, text) = args.trim_start().split_once(' ').unwrap_or((&args, ""));
+                                    let text = text.trim().to_owned();
+                                    let send_at = schedule::parse_time(time);
+                                    let target = state.current_channel().map(|v| (v.guild_id, v.id));
+
+                                    match (send_at, target) {
+                                        (None, _) => state.status = Some(format!("invalid time {}", time)),
+                                        (_, None) => state.status = Some(String::from("no channel selected")),
+                                        (Some(_), Some(_)) if text.is_empty() => state.status = Some(String::from("nothing to send")),
+                                        (Some(send_at), Some((guild_id, channel_id))) => {
+                                            state.scheduled_count += 1;
+                                            let id = state.scheduled_count;
+                                            state.scheduled.push(ScheduledMessage {
+                                                id,
+                                                guild_id,
+                                                channel_id,
+                                                send_at,
+                                                text,
+                                            });
+                                            schedule::save(&state);
+
+                                            let time: DateTime<Local> = DateTime::from(UNIX_EPOCH + Duration::from_secs(send_at));
+                                            state.status = Some(time.format("scheduled for %H:%M (%x)").to_string());
+                                        }
+                                    }
+                                } else if state.command.starts_with("remind ") {
+                                    // Remind about the selected message later
+                                    let time = state.command["remind ".len()..].trim().to_owned();
+                                    let reminder = state.current_channel().and_then(Channel::selected_message).map(|v| (v.id, message_preview(&state, v)));
+
+                                    match (schedule::parse_time(&time), reminder) {
+                                        (None, _) => state.status = Some(format!("invalid duration {}", time)),
+                                        (_, None) => state.status = Some(String::from("no message selected")),
+                                        (Some(remind_at), Some((message_id, preview))) => {
+                                            let (guild_id, channel_id) = state.current_channel().map(|v| (v.guild_id, v.id)).unwrap_or_default();
+                                            let summary = preview
+                                                .lines
+                                                .iter()
+                                                .map(|line| line.0.iter().map(|v| v.content.as_ref()).collect::<String>())
+                                                .collect::<Vec<_>>()
+                                                .join("\n");
+                                            state.reminders.push(Reminder {
+                                                guild_id,
+                                                channel_id,
+                                                message_id,
+                                                remind_at,
+                                                preview,
+                                                summary,
+                                            });
+                                            schedule::save(&state);
+
+                                            let time: DateTime<Local> = DateTime::from(UNIX_EPOCH + Duration::from_secs(remind_at));
+                                            state.status = Some(time.format("reminding at %H:%M (%x)").to_string());
+                                        }
+                                    }
+                                } else if state.command.starts_with("history ") {
+                                    // Load history back to a date and select the first message from then
+                                    let date = state.command["history ".len()..].trim().to_owned();
+                                    match schedule::parse_past(&date) {
+                                        Some(_) if state.current_channel().is_none() => state.status = Some(String::from("no channel selected")),
+                                        Some(timestamp) => {
+                                            let _ = tx.send(ClientEvent::LoadUntil(timestamp)).await;
+                                        }
+                                        None => state.status = Some(format!("invalid date {}", date)),
+                                    }
+                                } else if let Some(args) = state.command.strip_prefix("broadcast ").map(str::to_owned) {
+                                    // Send a message to several channels once the targets are confirmed
+                                    let mut targets = vec![];
+                                    let mut rest = args.trim_start();
+                                    let mut unknown = None;
+                                    while let Some(word) = rest.split(' ').next().filter(|v| v.contains('#')) {
+                                        match state.find_channel(word) {
+                                            Some(target) if targets.contains(&target) => (),
+                                            Some(target) => targets.push(target),
+                                            None => {
+                                                unknown = Some(word.to_owned());
+                                                break;
+                                            }
+                                        }
+                                        rest = rest[word.len()..].trim_start();
+                                    }
+
+                                    if let Some(unknown) = unknown {
+                                        state.status = Some(format!("unknown channel {}", unknown));
+                                    } else if targets.is_empty() || rest.is_empty() {
+                                        state.status = Some(String::from("usage: broadcast <#channel | guild#channel>... <message>"));
+                                    } else {
+                                        let mut body: Vec<_> = targets
+                                            .iter()
+                                            .filter_map(|&(guild_id, channel_id)| state.channel_label(guild_id, channel_id))
+                                            .map(|v| Spans::from(Span::styled(v, state.theme.header)))
+                                            .collect();
+                                        body.push(Spans::from(""));
+                                        body.push(Spans::from(rest.to_owned()));
+
+                                        let title = format!("send to {} channels?", targets.len());
+                                        let popup = Modal::confirm(title, Text::from(body), ConfirmAction::Broadcast(targets, rest.to_owned()));
+                                        state.modals.push(popup);
+                                    }
+                                } else if state.command == "forward" {
+                                    // Pick a channel to send a copy of the selected message to
+                                    let forward = state.current_channel().and_then(|channel| {
+                                        let message = channel.selected_message()?;
+                                        let header = format!("forwarded from {} in #{}:", message_author(&state, message), channel.name);
+                                        match &message.content {
+                                            MessageContent::Text(text) => Some((format!("{}\n{}", header, text.contents), vec![])),
+                                            MessageContent::Embed(embed) => {
+                                                let mut text = format!("{}\n{}", header, embed.title);
+                                                if let Some(body) = &embed.body {
+                                                    text = format!("{}\n{}", text, body.contents);
+                                                }
+                                                for field in embed.fields.iter() {
+                                                    text = format!("{}\n{}", text, field.title);
+                                                    if let Some(body) = &field.body {
+                                                        text = format!("{}: {}", text, body.contents);
+                                                    }
+                                                }
+                                                Some((text, vec![]))
+                                            }
+                                            MessageContent::Files(files) => Some((header, files.clone())),
+                                            MessageContent::Notice(_) => None,
+                                        }
+                                    });
+
+                                    match forward {
+                                        Some((text, files)) => {
+                                            let channels = state.all_channels();
+                                            let items = channels
+                                                .iter()
+                                                .enumerate()
+                                                .filter_map(|(i, &(guild_id, channel_id))| state.channel_label(guild_id, channel_id).map(|v| (i as u64, v)))
+                                                .collect();
+                                            let popup = Modal::list("forward to", Text::default(), items, ListKind::Forward { channels, text, files });
+                                            state.modals.push(popup);
+                                        }
+                                        None => state.status = Some(String::from("no message to forward")),
+                                    }
+                                } else if state.command == "report" || state.command.starts_with("report ") {
+                                    // Report the selected message with an optional reason
+                                    let reason = state.command["report".len()..].trim().to_owned();
+                                    let report = state.current_channel().and_then(|channel| {
+                                        let message = channel.selected_message().filter(|v| !matches!(v.content, MessageContent::Notice(_)))?;
+                                        let time: DateTime<Local> = DateTime::from(UNIX_EPOCH + Duration::from_secs(message.timestamp));
+                                        let report = format!(
+                                            "Report of a message on {}\nLink: {}\nAuthor: {} ({})\nSent: {}\nReason: {}\nContent:\n{}",
+                                            state.homeserver,
+                                            message_link(&state, channel.guild_id, channel.id, message.id),
+                                            message_author(&state, message),
+                                            message.author_id,
+                                            time.format("%Y-%m-%d %H:%M:%S %z"),
+                                            if reason.is_empty() { "(none given)" } else { &reason },
+                                            message_snippet(message, usize::MAX),
+                                        );
+                                        Some((channel.guild_id, channel.id, message.id, report))
+                                    });
+
+                                    match report {
+                                        Some((guild_id, channel_id, message_id, report)) => {
+                                            let _ = tx.send(ClientEvent::Report(guild_id, channel_id, message_id, reason, report)).await;
+                                        }
+                                        None => state.status = Some(String::from("no message to report")),
+                                    }
+                                } else if state.command == "readers" {
+                                    // List who has read the selected message
+                                    let selected = state
+                                        .current_channel()
+                                        .and_then(|channel| channel.selected_message().map(|v| (channel.guild_id, channel.id, v.id)));
+                                    if !state.capabilities.supports(Feature::ReadReceipts) {
+                                        state.status = Some(state.capabilities.unsupported_message(Feature::ReadReceipts));
+                                    } else if let Some((guild_id, channel_id, message_id)) = selected {
+                                        let _ = tx.send(ClientEvent::GetReadReceipts(guild_id, channel_id, message_id, true)).await;
+                                    } else {
+                                        state.status = Some(String::from("no message selected"));
+                                    }
+                                } else if state.command == "snippet" || state.command.starts_with("snippet ") {
+                                    // Insert the named snippet, or pick one if no name is given
+                                    let name = state.command["snippet".len()..].trim().to_owned();
+                                    if name.is_empty() {
+                                        let mut names: Vec<_> = state.config.snippets.keys().cloned().collect();
+                                        names.sort();
+                                        let items = names
+                                            .iter()
+                                            .enumerate()
+                                            .map(|(i, v)| (i as u64, format!("{} - {}", v, state.config.snippets[v].replace('\n', " "))))
+                                            .collect();
+                                        state.modals.push(Modal::list("snippets", Text::default(), items, ListKind::Snippets(names)));
+                                    } else if let Some(template) = state.config.snippets.get(&name).cloned() {
+                                        if state.start_insert() {
+                                            let pos = state.input.cursor();
+                                            state.insert_snippet(pos..pos, &template);
+                                        }
+                                    } else {
+                                        state.status = Some(format!("unknown snippet {}", name));
+                                    }
+                                } else if let Some(substitution) = substitute::parse(&state.command) {
+                                    // Replace text in the draft, or the message being edited
+                                    match substitution {
+                                        Ok(substitution) => {
+                                            let (text, count) = substitution.apply(&state.input);
+                                            if count == 0 {
+                                                state.status = Some(String::from("pattern not found"));
+                                            } else {
+                                                state.input.set(&text);
+                                                state.status = Some(format!("{} substitution{}", count, if count == 1 { "" } else { "s" }));
+                                            }
+                                        }
+                                        Err(error) => state.status = Some(error),
+                                    }
+                                } else if state.command == "header" {
+                                    // Toggle the line about the channel above the messages
+                                    state.config.messages.header = !state.config.messages.header;
+                                } else if state.command == "numbers" {
+                                    // Toggle the relative number gutter
+                                    state.config.messages.relative_numbers = !state.config.messages.relative_numbers;
+                                } else if state.command == "max-width" || state.command.starts_with("max-width ") {
+                                    // Limit how wide message text gets, for the session
+                                    let arg = state.command["max-width".len()..].trim();
+                                    if arg.is_empty() {
+                                        state.status = Some(match state.config.messages.max_width {
+                                            Some(max) if max > 0 => format!("messages are at most {} columns wide", max),
+                                            _ => String::from("messages use the whole width"),
+                                        });
+                                    } else if arg == "off" {
+                                        state.config.messages.max_width = None;
+                                    } else if let Ok(max) = arg.parse::<u16>() {
+                                        state.config.messages.max_width = Some(max);
+                                    } else {
+                                        state.status = Some(String::from("usage: :max-width [columns|off]"));
+                                    }
+                                } else if state.command == "readonly" {
+                                    // Toggle whether anything can be sent to the current channel
+                                    let target = state.current_channel().map(|v| (v.guild_id, v.id));
+                                    if state.read_only {
+                                        state.status = Some(String::from("every channel is read only this session (--read-only)"));
+                                    } else if let Some(target) = target {
+                                        if state.read_only_channels.remove(&target) {
+                                            state.status = Some(String::from("channel is no longer read only"));
+                                        } else {
+                                            state.read_only_channels.insert(target);
+                                            state.status = Some(String::from("channel is read only"));
+                                        }
+                                    }
+                                } else if let Some(offset) = state.command.strip_prefix("goto-offset ") {
+                                    // Move the selection by an offset, negative going up to older messages like the gutter shows
+                                    match offset.trim().parse::<isize>() {
+                                        Ok(offset) => {
+                                            state.mode = AppMode::Scroll;
+                                            state.action_select = 0;
+                                            state.scroll_by(-offset);
+                                        }
+                                        Err(_) => state.status = Some(format!("invalid offset {}", offset.trim())),
+                                    }
+                                } else if state.command == "voice-message" {
+                                    // Record a voice message from the microphone
+                                    match voice::Recording::start() {
+                                        Ok(recording) => state.modals.push(Modal::recording(recording)),
+                                        Err(e) => state.status = Some(format!("could not record: {}", e)),
+                                    }
+                                } else if state.command == "account" || state.command.starts_with("account ") {
+                                    // Export, delete, or change the account on the homeserver, if it can
+                                    let setting = match state.command["account".len()..].trim() {
+                                        "password" => Some((AccountSetting::Password, "change password")),
+                                        "email" => Some((AccountSetting::Email, "change email")),
+                                        "2fa on" => Some((AccountSetting::EnableTotp, "enable two-factor authentication")),
+                                        "2fa off" => Some((AccountSetting::DisableTotp, "disable two-factor authentication")),
+                                        _ => None,
+                                    };
+                                    match (state.command["account".len()..].trim(), setting) {
+                                        (_, Some(_)) if !state.capabilities.supports(Feature::AccountSettings) => {
+                                            state.status = Some(state.capabilities.unsupported_message(Feature::AccountSettings));
+                                        }
+                                        (_, Some((setting, title))) => {
+                                            state.modals.push(Modal::account_flow(title));
+                                            let _ = tx.send(ClientEvent::BeginAccountFlow(setting)).await;
+                                        }
+                                        ("export", _) if !state.capabilities.supports(Feature::AccountExport) => {
+                                            state.status = Some(state.capabilities.unsupported_message(Feature::AccountExport));
+                                        }
+                                        ("export", _) => {
+                                            state.status = Some(String::from("requesting a data export..."));
+                                            let _ = tx.send(ClientEvent::ExportAccount).await;
+                                        }
+                                        ("delete", _) if !state.capabilities.supports(Feature::AccountDeletion) => {
+                                            state.status = Some(state.capabilities.unsupported_message(Feature::AccountDeletion));
+                                        }
+                                        ("delete", _) => {
+                                            let popup = state.delete_account_prompt(false);
+                                            state.modals.push(popup);
+                                        }
+                                        _ => state.status = Some(String::from("usage: account <export|delete|password|email|2fa on|2fa off>")),
+                                    }
+                                } else if let Some(json) = state.command.strip_prefix("raw-send ") {
+                                    // Send a message built from JSON, for prototyping what a bot would send
+                                    let target = state.current_channel().map(|v| (v.guild_id, v.id));
+                                    match (target, payload::parse(json)) {
+                                        (None, _) => state.status = Some(String::from("no channel to send to")),
+                                        (Some(_), Err(e)) => state.status = Some(e),
+                                        (Some((guild_id, channel_id)), Ok((content, overrides))) => {
+                                            let _ = tx.send(ClientEvent::SendContent(guild_id, channel_id, content, overrides)).await;
+                                        }
+                                    }
+                                } else if state.command == "serverinfo" {
+                                    // Show the homeserver's version and what it supports
+                                    if state.capabilities.supports(Feature::ServerInfo) {
+                                        let _ = tx.send(ClientEvent::ServerInfo).await;
+                                    } else {
+                                        state.status = Some(state.capabilities.unsupported_message(Feature::ServerInfo));
+                                    }
+                                } else if state.command == "online" {
+                                    // List the users in small guilds with the user, once their statuses are fetched
+                                    state.status = Some(String::from("checking who's online..."));
+                                    let _ = tx.send(ClientEvent::ShowOnline).await;
+                                } else if state.command == "settings" {
+                                    // Edit the config without opening the file
+                                    let editor = settings::SettingsEditor::new(&state.config);
+                                    state.modals.push(Modal::settings(editor));
+                                } else if state.command == "stats" {
+                                    // Toggle the performance overlay, starting the slowest frame and lag over
+                                    state.show_stats = !state.show_stats;
+                                    if state.show_stats {
+                                        state.stats.reset();
+                                    }
+                                } else if state.command == "chanstats" {
+                                    // Chart the activity in the loaded history of the current channel
+                                    match state.current_channel() {
+                                        Some(channel) => {
+                                            let stats = chanstats::ChannelStats::compute(&state, channel);
+                                            let title = format!("#{} stats", channel.name);
+                                            state.modals.push(Modal::channel_stats(title, stats));
+                                        }
+                                        None => state.status = Some(String::from("no channel selected")),
+                                    }
+                                } else if state.command == "debug" {
+                                    // Show the log of calls and events, for diagnosing problems with the homeserver
+                                    if state.streamer_mode {
+                                        state.status = Some(String::from("the debug log shows ids, so it's hidden in streamer mode"));
+                                    } else {
+                                        let log = state.debug_log.clone();
+                                        state.modals.push(Modal::debug(log));
+                                    }
+                                } else if state.command == "lock" {
+                                    // Hide everything until the passphrase is typed
+                                    state.lock();
+                                } else if state.command == "as" || state.command.starts_with("as ") {
+                                    // Show messages typed into the box with another name, and avatar if the last word is
+                                    // a link to one, where the homeserver allows it
+                                    let args = state.command["as".len()..].trim().to_owned();
+                                    if args.is_empty() {
+                                        state.status = Some(match &state.send_as {
+                                            Some((name, _)) => format!("sending as {}", name),
+                                            None => String::from("sending as yourself"),
+                                        });
+                                    } else if args == "off" {
+                                        state.send_as = None;
+                                        state.status = Some(String::from("sending as yourself"));
+                                    } else {
+                                        let (name, avatar) = match args.rsplit_once(' ') {
+                                            Some((name, avatar)) if ["http://", "https://", "hmc://"].iter().any(|v| avatar.starts_with(v)) => {
+                                                (name.trim().to_owned(), Some(avatar.to_owned()))
+                                            }
+                                            _ => (args, None),
+                                        };
+                                        state.status = Some(format!("sending as {}; :as off to stop", name));
+                                        state.send_as = Some((name, avatar));
+                                    }
+                                } else if state.command == "streamer-mode" {
+                                    // Hide the homeserver, invites, and notification previews while sharing the screen
+                                    state.streamer_mode = !state.streamer_mode;
+                                    state.status = Some(String::from(if state.streamer_mode { "streamer mode on" } else { "streamer mode off" }));
+                                } else if state.command == "roleedit" {
+                                    // Manage the roles of the current guild
+                                    match state.current_guild {
+                                        _ if !state.capabilities.supports(Feature::Roles) => {
+                                            state.status = Some(state.capabilities.unsupported_message(Feature::Roles));
+                                        }
+                                        Some(guild_id) => {
+                                            let _ = tx.send(ClientEvent::OpenRoleEditor(guild_id)).await;
+                                        }
+                                        None => state.status = Some(String::from("no guild selected")),
+                                    }
+                                } else if state.command == "transfers" {
+                                    // Show uploads and downloads, newest first
+                                    let transfers = state.transfers.iter().rev().cloned().collect();
+                                    state.modals.push(Modal::transfers(transfers));
+                                } else if state.command == "gallery" {
+                                    // Browse the attachments in the current channel
+                                    let items: Vec<_> = state
+                                        .current_channel()
+                                        .map(|channel| {
+                                            channel
+                                                .messages_list
+                                                .iter()
+                                                .filter_map(|v| channel.messages_map.get(v))
+                                                .filter_map(|v| match &v.content {
+                                                    MessageContent::Files(attachments) => Some(attachments.iter().cloned()),
+                                                    _ => None,
+                                                })
+                                                .flatten()
+                                                .collect()
+                                        })
+                                        .unwrap_or_default();
+
+                                    // Download the newest images in the background for thumbnails, unless saving data
+                                    let low_data = state.config.low_data;
+                                    let images: Vec<_> = items
+                                        .iter()
+                                        .rev()
+                                        .filter(|v| !low_data && v.is_image() && v.cache_path().map(|v| !v.exists()).unwrap_or(false))
+                                        .take(GALLERY_PREFETCH)
+                                        .cloned()
+                                        .map(|v| {
+                                            let transfer = state.start_transfer(&v.name, false);
+                                            (v, transfer)
+                                        })
+                                        .collect();
+                                    let homeserver = state.homeserver.clone();
+                                    tokio::spawn(async move {
+                                        for (image, transfer) in images {
+                                            let _ = media::fetch(&image, &homeserver, &transfer).await;
+                                        }
+                                    });
+
+                                    state.modals.push(Modal::gallery("gallery", items));
+                                } else if state.command == "filter" || state.command.starts_with("filter ") {
+                                    // Save, remove, show, or list saved filters
+                                    let args = state.command["filter".len()..].trim().to_owned();
+                                    let (action, rest) = args.split_once(' ').unwrap_or((args.as_str(), ""));
+                                    match action {
+                                        "add" => match rest.trim().split_once(' ') {
+                                            Some((name, criteria)) => match SavedFilter::parse(name, criteria) {
+                                                Ok(filter) => {
+                                                    state.status = Some(format!("saved filter ~{}: {}", filter.name, filter.criteria()));
+                                                    state.filters.retain(|v| v.name != name);
+                                                    state.filters.push(filter);
+                                                    filters::save(state.current_user, &state.filters);
+                                                }
+                                                Err(e) => state.status = Some(e),
+                                            },
+                                            None => state.status = Some(String::from("usage: filter add <name> [from:<user>] [in:<guild>] [text]")),
+                                        },
+                                        "remove" => {
+                                            let name = rest.trim();
+                                            if name.is_empty() {
+                                                state.status = Some(String::from("usage: filter remove <name>"));
+                                            } else if state.filters.iter().any(|v| v.name == name) {
+                                                state.filters.retain(|v| v.name != name);
+                                                filters::save(state.current_user, &state.filters);
+                                                state.status = Some(format!("removed filter ~{}", name));
+                                            } else {
+                                                state.status = Some(format!("no filter named {}", name));
+                                            }
+                                        }
+                                        "" => {
+                                            let items = state
+                                                .filters
+                                                .iter()
+                                                .enumerate()
+                                                .map(|(i, v)| (i as u64, format!("~{} - {}", v.name, v.criteria())))
+                                                .collect();
+                                            let names = state.filters.iter().map(|v| v.name.clone()).collect();
+                                            state.modals.push(Modal::list("saved filters", Text::default(), items, ListKind::Filters(names)));
+                                        }
+                                        name => match state.filters.iter().position(|v| v.name == name) {
+                                            Some(i) => {
+                                                state.filters[i].unseen = 0;
+                                                let popup = filters::view(&state, &state.filters[i]);
+                                                state.modals.push(popup);
+                                            }
+                                            None => state.status = Some(format!("no filter named {}", name)),
+                                        },
+                                    }
+                                } else if state.command == "inbox" || state.command.starts_with("inbox ") {
+                                    // Mark everything as seen, clear the inbox, or list it newest first
+                                    match state.command["inbox".len()..].trim() {
+                                        "read" => {
+                                            state.inbox.mark_all_read();
+                                            state.status = Some(String::from("inbox marked as read"));
+                                        }
+                                        "clear" => {
+                                            state.inbox.clear();
+                                            state.status = Some(String::from("inbox cleared"));
+                                        }
+                                        "" => {
+                                            let items = state
+                                                .inbox
+                                                .newest_first()
+                                                .map(|v| {
+                                                    let time: DateTime<Local> = DateTime::from(UNIX_EPOCH + Duration::from_secs(v.timestamp));
+                                                    let marker = if v.read { ' ' } else { '•' };
+                                                    let source = match v.kind {
+                                                        InboxKind::Invite(_) => String::from("invite"),
+                                                        InboxKind::Mention | InboxKind::DirectMessage => {
+                                                            state.channel_label(v.guild_id, v.channel_id).unwrap_or_default()
+                                                        }
+                                                    };
+                                                    (v.id, format!("{} {} {} - {}: {}", marker, time.format("%m-%d %H:%M"), source, v.author, v.snippet))
+                                                })
+                                                .collect();
+
+                                            let title = format!("inbox ({} unread)", state.inbox.unread());
+                                            state.modals.push(Modal::list(title, Text::default(), items, ListKind::Inbox));
+                                        }
+                                        _ => state.status = Some(String::from("usage: inbox [read|clear]")),
+                                    }
+                                } else if state.command == "search" || state.command.starts_with("search ") {
+                                    // Search every guild, on the server if it can
+                                    let query = state.command["search".len()..].trim().to_owned();
+                                    if query.is_empty() {
+                                        state.status = Some(String::from("usage: search <text>"));
+                                    } else {
+                                        state.status = Some(format!("searching for {}...", query));
+                                        let _ = tx.send(ClientEvent::Search(query)).await;
+                                    }
+                                } else if state.command == "saved" {
+                                    // List saved messages across all guilds
+                                    let items = state
+                                        .bookmarks
+                                        .iter()
+                                        .map(|v| {
+                                            let guild = state.guilds_map.get(&v.guild_id).map(|v| v.name.as_str()).unwrap_or("<unknown guild>");
+                                            let channel = state
+                                                .get_channel(v.guild_id, v.channel_id)
+                                                .map(|v| v.name.as_str())
+                                                .unwrap_or("<unknown channel>");
+                                            (v.message_id, format!("{} #{} - {}: {}", guild, channel, v.author, v.snippet))
+                                        })
+                                        .collect();
+
+                                    let popup = Modal::list("saved messages", Text::default(), items, ListKind::Bookmarks);
+                                    state.modals.push(popup);
+                                } else if state.command == "scheduled" {
+                                    // List pending scheduled messages
+                                    let mut scheduled: Vec<_> = state.scheduled.iter().collect();
+                                    scheduled.sort_by_key(|v| v.send_at);
+                                    let items = scheduled
+                                        .into_iter()
+                                        .map(|v| {
+                                            let time: DateTime<Local> = DateTime::from(UNIX_EPOCH + Duration::from_secs(v.send_at));
+                                            let channel = state
+                                                .get_channel(v.guild_id, v.channel_id)
+                                                .map(|v| v.name.as_str())
+                                                .unwrap_or("<unknown channel>");
+                                            (v.id, format!("{} #{}: {}", time.format("%H:%M (%x)"), channel, v.text))
+                                        })
+                                        .collect();
+
+                                    let popup = Modal::list("scheduled messages", Text::default(), items, ListKind::Scheduled);
+                                    state.modals.push(popup);
+                                } else if state.command == "notifylevel" || state.command.starts_with("notifylevel ") {
+                                    // Change the notification level of the current channel
+                                    let level = state.command["notifylevel".len()..].trim().to_owned();
+                                    if let Some((guild_id, channel_id)) = state.current_channel().map(|v| (v.guild_id, v.id)) {
+                                        if level.is_empty() {
+                                            let level = state.notify_level(guild_id, channel_id);
+                                            state.status = Some(format!("notification level: {:?}", level).to_lowercase());
+                                        } else if level == "default" {
+                                            state.notify_levels.remove(&(guild_id, channel_id));
+                                            notifications::save_levels(&state.notify_levels);
+                                        } else if let Ok(level) = level.parse() {
+                                            state.notify_levels.insert((guild_id, channel_id), level);
+                                            notifications::save_levels(&state.notify_levels);
+                                        } else {
+                                            state.status = Some(String::from("expected one of all, mentions, nothing, or default"));
+                                        }
+                                    }
+                                }
+                            }
+
+                            // Complete the command name
+                            KeyCode::Tab => {
+                                // Work out what to complete, letting go of the state while the candidates are searched
+                                let (command, start, prefix, index, matcher) = {
+                                    let mut state = state.write().await;
+                                    let (start, matcher) = if !state.command.contains(' ') {
+                                        let commands: Vec<_> = COMMANDS.iter().map(|v| v.to_string()).collect();
+                                        (0, Matcher::default().source(commands))
+                                    } else if let Some(command) = ["leave ", "purge-local "].into_iter().find(|v| state.command.starts_with(v)) {
+                                        // Complete the guild name, which can contain spaces
+                                        let mut guilds: Vec<_> = state.guilds_list.iter().filter_map(|v| state.guilds_map.get(v)).map(|v| v.name.clone()).collect();
+                                        if command == "purge-local " {
+                                            guilds.push(String::from("all"));
+                                        }
+                                        (command.len(), Matcher::default().source(guilds))
+                                    } else if state.command.starts_with("broadcast ") {
+                                        // Complete the channel being typed
+                                        let word_start = state.command.rfind(' ').map(|v| v + 1).unwrap_or(0);
+                                        (word_start, Matcher::default().source(state.channel_targets()))
+                                    } else {
+                                        continue;
+                                    };
+
+                                    let (prefix, index) = match state.completion.take() {
+                                        Some((prefix, index)) => (prefix, index + 1),
+                                        None => (state.command[start..].to_owned(), 0),
+                                    };
+                                    (state.command.to_string(), start, prefix, index, matcher)
+                                };
+
+                                let candidates = matcher.search(&prefix).await;
+
+                                // Typing while the candidates were searched makes them stale
+                                let mut state = state.write().await;
+                                if !candidates.is_empty() && state.command == command.as_str() {
+                                    let (candidate, _) = &candidates[index % candidates.len()];
+                                    let end = state.command.len();
+                                    state.command.replace_range(start..end, candidate);
+                                    state.completion = Some((prefix, index));
+                                }
+                            }
+
+                            // TODO: up/down to scroll through history
+
+                            // Move left
+                            KeyCode::Left => {
+                                state.write().await.command.move_left();
+                            }
+
+                            // Move right
+                            KeyCode::Right => {
+                                state.write().await.command.move_right();
+                            }
+
+                            // Backspace
+                            KeyCode::Backspace => {
+                                // Backspacing an empty prompt leaves it
+                                let mut state = state.write().await;
+                                if !state.command.backspace() && state.command.is_empty() {
+                                    state.mode = state.command_return;
+                                }
+                            }
+
+                            // Paste a register
+                            KeyCode::Char('r') if key.modifiers == KeyModifiers::CONTROL => {
+                                state.write().await.register_prompt = Some(RegisterPrompt::Insert);
+                            }
+
+                            // Delete the word before the cursor
+                            KeyCode::Char('w') if key.modifiers == KeyModifiers::CONTROL => {
+                                let mut state = state.write().await;
+                                let deleted = state.command.delete_word();
+                                state.yank(deleted);
+                            }
+
+                            // Delete everything before the cursor
+                            KeyCode::Char('u') if key.modifiers == KeyModifiers::CONTROL => {
+                                let mut state = state.write().await;
+                                let deleted = state.command.delete_to_start();
+                                state.yank(deleted);
+                            }
+
+                            // Insert character
+                            KeyCode::Char(c) if input::is_text(&key) => {
+                                // Pasted text is inserted all at once
+                                let (text, next) = input::take_burst(c, source, &mut input_events, false);
+                                pending.extend(next);
+
+                                let mut state = state.write().await;
+                                state.command.insert(&text);
+                            }
+
+                            // Invalid does nothing
+                            _ => (),
+                        }
+                    }
+
+                    // Scroll mode
+                    (Pane::Messages, _) => {
+                        // Digits build up a count for the next key
+                        let count = {
+                            let mut state = state.write().await;
+                            if let KeyCode::Char(c @ '0'..='9') = key.code {
+                                if c != '0' || state.scroll_count.is_some() {
+                                    let digit = c as usize - '0' as usize;
+                                    state.scroll_count = Some(state.scroll_count.unwrap_or(0).saturating_mul(10).saturating_add(digit));
+                                    continue;
+                                }
+                            }
+
+                            // A count before anything but moving acts on the message that many above the selection
+                            let count = state.scroll_count.take();
+                            let moves = matches!(key.code, KeyCode::Up | KeyCode::Down | KeyCode::Char('k') | KeyCode::Char('j') | KeyCode::Esc);
+                            if let Some(count) = count.filter(|_| !moves) {
+                                state.action_select = 0;
+                                state.scroll_by(count as isize);
+                            }
+                            count.unwrap_or(1)
+                        };
+
+                        match key.code {
+                            // Escape closes the thread being viewed
+                            KeyCode::Esc if state.read().await.thread.is_some() => {
+                                state.write().await.toggle_thread();
+                            }
+
+                            // Escape exits to normal mode
+                            KeyCode::Esc => {
+                                state.write().await.mode = AppMode::TextNormal;
+                            }
+
+                            // Enter command prompt for the selected message
+                            KeyCode::Char(':') => {
+                                let mut state = state.write().await;
+                                state.mode = AppMode::Command;
+                                state.command_return = AppMode::Scroll;
+                                state.command.clear();
+                            }
+
+                            // Jump back to the previous channel
+                            KeyCode::Char('o') if key.modifiers == KeyModifiers::CONTROL => {
+                                jump_channel(&state, &tx, true).await;
+                            }
+
+                            // Jump forward to the next channel (terminals send ctrl+i as tab)
+                            KeyCode::Char('i') if key.modifiers == KeyModifiers::CONTROL => {
+                                jump_channel(&state, &tx, false).await;
+                            }
+                            KeyCode::Tab => {
+                                jump_channel(&state, &tx, false).await;
+                            }
+
+                            // Show recent channels
+                            KeyCode::Char('t') if key.modifiers == KeyModifiers::CONTROL => {
+                                show_recent_channels(&mut *state.write().await);
+                            }
+
+                            // Scroll the view up or down by lines without moving the selection, for messages taller than
+                            // the pane
+                            KeyCode::Char('y') if key.modifiers == KeyModifiers::CONTROL => {
+                                if let Some(channel) = state.write().await.current_channel_mut() {
+                                    *channel.view_offset.get_mut() += count;
+                                }
+                            }
+                            KeyCode::Char('e') if key.modifiers == KeyModifiers::CONTROL => {
+                                if let Some(channel) = state.write().await.current_channel_mut() {
+                                    let offset = channel.view_offset.get_mut();
+                                    *offset = offset.saturating_sub(count);
+                                }
+                            }
+
+                            // Scroll up
+                            KeyCode::Up | KeyCode::Char('k') => {
+                                let mut state = state.write().await;
+                                state.action_select = 0;
+                                let prefetch = state.config.messages.prefetch;
+                                state.scroll_by(count as isize);
+                                if let Some(channel) = state.current_channel_mut() {
+                                    // Fetch the previous page before the top is reached
+                                    if let Some(anchor) = channel.prefetch_anchor(prefetch) {
+                                        let _ = tx.send(ClientEvent::GetMoreMessages(Some(anchor))).await;
+                                    }
+                                }
+                            }
+
+                            // Scroll down
+                            KeyCode::Down | KeyCode::Char('j') => {
+                                let mut state = state.write().await;
+                                state.action_select = 0;
+                                state.scroll_by(-(count as isize));
+                            }
+
+                            // Go to top
+                            KeyCode::Char('g') => {
+                                let mut state = state.write().await;
+                                let len = state.visible_messages().len() as isize;
+                                state.scroll_by(len);
+                            }
+
+                            // Go to bottom
+                            KeyCode::Char('G') => {
+                                let mut state = state.write().await;
+                                let len = state.visible_messages().len() as isize;
+                                state.scroll_by(-len);
+
+                                // Any new messages have now been seen
+                                if let Some(channel) = state.current_channel_mut() {
+                                    channel.first_unread = None;
+                                }
+                            }
+
+                            // Open or close the reply thread of the selected message
+                            KeyCode::Char('t') => {
+                                let mut state = state.write().await;
+                                state.action_select = 0;
+                                state.toggle_thread();
+                            }
+
+                            // Pick a register for the next yank
+                            KeyCode::Char('"') => {
+                                state.write().await.register_prompt = Some(RegisterPrompt::Select);
+                            }
+
+                            // Run the last command again with `@:`
+                            KeyCode::Char('@') => {
+                                state.write().await.register_prompt = Some(RegisterPrompt::Execute);
+                            }
+
+                            // Yank the selected message's text
+                            KeyCode::Char('y') => {
+                                let mut state = state.write().await;
+                                let text = state.current_channel().and_then(Channel::selected_message).map(|v| match &v.content {
+                                    MessageContent::Text(text) => text.source(),
+                                    _ => message_snippet(v, usize::MAX),
+                                });
+
+                                if let Some(text) = text {
+                                    state.yank(text);
+                                    state.status = Some(String::from("message yanked"));
+                                }
+                            }
+
+                            // Save the selected message
+                            KeyCode::Char('m') => {
+                                let mut state = state.write().await;
+                                let bookmark = state.current_channel().and_then(|channel| {
+                                    channel.selected_message().map(|v| Bookmark {
+                                        guild_id: channel.guild_id,
+                                        channel_id: channel.id,
+                                        message_id: v.id,
+                                        author: message_author(&state, v),
+                                        snippet: message_snippet(v, 80),
+                                        timestamp: v.timestamp,
+                                    })
+                                });
+
+                                if let Some(bookmark) = bookmark {
+                                    if state.bookmarks.iter().any(|v| v.message_id == bookmark.message_id) {
+                                        state.status = Some(String::from("message already saved"));
+                                    } else {
+                                        state.bookmarks.push(bookmark);
+                                        bookmarks::save(state.current_user, &state.bookmarks);
+                                        state.status = Some(String::from("message saved"));
+                                    }
+                                }
+                            }
+
+                            // Jump to the first unread message
+                            KeyCode::Char('u') => {
+                                let mut state = state.write().await;
+                                match state.current_channel_mut().and_then(|v| v.first_unread.take()) {
+                                    Some(timestamp) => {
+                                        let _ = tx.send(ClientEvent::LoadUntil(timestamp)).await;
+                                    }
+                                    None => state.status = Some(String::from("no unread messages")),
+                                }
+                            }
+
+                            // Try loading the channel's messages again
+                            KeyCode::Char('r') => {
+                                retry_history(&state, &tx).await;
+                            }
+
+                            // Remind about the selected message later
+                            KeyCode::Char('R') => {
+                                let mut state = state.write().await;
+                                state.mode = AppMode::Command;
+                                state.command_return = AppMode::Scroll;
+                                state.command.set("remind ");
+                            }
+
+                            // Select previous embed button
+                            KeyCode::Left | KeyCode::Char('h') => {
+                                let mut state = state.write().await;
+                                state.action_select = state.action_select.saturating_sub(1);
+                            }
+
+                            // Select next embed button
+                            KeyCode::Right | KeyCode::Char('l') => {
+                                let mut state = state.write().await;
+                                let count = match state.current_channel().and_then(Channel::selected_message).map(|v| &v.content) {
+                                    Some(MessageContent::Embed(embed)) => embed.actions.len(),
+                                    Some(MessageContent::Files(attachments)) => attachments.len(),
+                                    _ => 0,
+                                };
+                                if state.action_select + 1 < count {
+                                    state.action_select += 1;
+                                }
+                            }
+
+                            // Open selected attachment
+                            KeyCode::Enter if matches!(state.read().await.current_channel().and_then(Channel::selected_message).map(|v| &v.content), Some(MessageContent::Files(_))) => {
+                                let attachment = {
+                                    let state = state.read().await;
+                                    match state.current_channel().and_then(Channel::selected_message).map(|v| &v.content) {
+                                        Some(MessageContent::Files(attachments)) => attachments.get(state.action_select.min(attachments.len().saturating_sub(1))).cloned(),
+                                        _ => None,
+                                    }
+                                };
+
+                                if let Some(attachment) = attachment {
+                                    open_attachment(&state, attachment).await;
+                                }
+                            }
+
+                            // Press selected embed button
+                            KeyCode::Enter => {
+                                let state = state.read().await;
+                                let action = state.current_channel().and_then(Channel::selected_message).and_then(|v| match &v.content {
+                                    MessageContent::Embed(embed) => embed
+                                        .actions
+                                        .get(state.action_select.min(embed.actions.len().saturating_sub(1)))
+                                        .map(|a| (v.id, a)),
+                                    _ => None,
+                                });
+
+                                match action {
+                                    Some((_, EmbedAction { url: Some(url), .. })) => open_external(url),
+                                    Some((id, action)) => {
+                                        let _ = tx.send(ClientEvent::TriggerAction(id, action.data.clone())).await;
+                                    }
+                                    None => (),
+                                }
+                            }
+
+                            // Delete message without prompt
+                            KeyCode::Char('d') if key.modifiers == KeyModifiers::CONTROL => {
+                                delete_message(&state, &tx).await;
+                            }
+
+                            // Delete message with prompt
+                            KeyCode::Char('d') => {
+                                let mut state = state.write().await;
+                                let popup = state
+                                    .current_channel()
+                                    .and_then(Channel::selected_message)
+                                    .filter(|v| v.author_id == state.current_user)
+                                    .map(|v| Modal::confirm("delete this message?", message_preview(&state, v), ConfirmAction::DeleteMessage(v.id)));
+
+                                if let Some(popup) = popup {
+                                    state.modals.push(popup);
+                                }
+                            }
+
+                            // Edit message
+                            KeyCode::Char('e') => {
+                                let mut state = state.write().await;
+                                let current_user = state.current_user;
+
+                                // Get contents
+                                if let Some(channel) = state.current_channel_mut() {
+                                    let temp = if let Some(message) = channel.selected_message() {
+                                        if message.author_id == current_user {
+                                            if let MessageContent::Text(text) = &message.content {
+                                                text.source()
+                                            } else {
+                                                continue;
+                                            }
+                                        } else {
+                                            continue;
+                                        }
+                                    } else {
+                                        continue;
+                                    };
+
+                                    // Switch mode
+                                    if !state.start_insert() {
+                                        continue;
+                                    }
+                                    state.editing = true;
+
+                                    // Do some moving
+                                    state.old_input = std::mem::replace(&mut state.input, TextBuffer::new(&temp));
+                                }
+                            }
+
+                            // TODO: more controls
+
+                            // Nothing
+                            _ => ()
+                        }
+                    }
+
+                    // Guild select mode
+                    (Pane::Guilds, _) => {
+                        // Typing filters the list
+                        {
+                            let mut state = state.write().await;
+                            if handle_filter_key(&mut state.list_filter, key.code, &['j', 'k', 'l']) {
+                                let visible = state.filtered_guilds();
+                                if !matches!(state.guilds_select, Some(v) if visible.contains(&v)) {
+                                    state.guilds_select = visible.first().cloned();
+                                }
+                                continue;
+                            }
+                        }
+
+                        match key.code {
+                            // Exit guild select mode
+                            KeyCode::Esc => {
+                                state.write().await.mode = AppMode::TextNormal;
+                            }
+
+                            // Move down
+                            KeyCode::Char('j') | KeyCode::Down => {
+                                let mut state = state.write().await;
+                                let visible = state.filtered_guilds();
+                                state.guilds_select = move_selection(state.guilds_select, &visible, true);
+                            }
+
+                            // Move up
+                            KeyCode::Char('k') | KeyCode::Up => {
+                                let mut state = state.write().await;
+                                let visible = state.filtered_guilds();
+                                state.guilds_select = move_selection(state.guilds_select, &visible, false);
+                            }
+
+                            // Open the guild's default channel
+                            KeyCode::Enter => {
+                                let mut state = state.write().await;
+                                state.list_filter = None;
+                                state.current_guild = state.guilds_select.and_then(|v| state.guilds_list.get(v)).cloned();
+
+                                if let Some(guild) = state.current_guild() {
+                                    let guild_id = guild.id;
+                                    if guild.channels_list.is_empty() {
+                                        // Pick the channel once they're fetched
+                                        state.pending_default = Some(guild_id);
+                                        state.mode = AppMode::ChannelSelect;
+                                    } else if state.open_default_channel(guild_id) {
+                                        let _ = tx.send(ClientEvent::GetMoreMessages(None)).await;
+                                    }
+
+                                    // Refresh the channels in case some were created or deleted
+                                    let _ = tx.send(ClientEvent::GetChannels(guild_id)).await;
+                                }
+                            }
+
+                            // Pick a channel in the guild
+                            KeyCode::Tab | KeyCode::Right => {
+                                let mut state = state.write().await;
+                                state.list_filter = None;
+                                state.current_guild = state.guilds_select.and_then(|v| state.guilds_list.get(v)).cloned();
+
+                                if let Some(guild) = state.current_guild() {
+                                    let _ = tx.send(ClientEvent::GetChannels(guild.id)).await;
+                                    state.pending_default = None;
+                                    state.mode = AppMode::ChannelSelect;
+                                }
+                            }
+
+                            // Leave guild with prompt
+                            KeyCode::Char('l') => {
+                                let mut state = state.write().await;
+                                let popup = state
+                                    .guilds_select
+                                    .and_then(|v| state.guilds_list.get(v))
+                                    .and_then(|&v| state.leave_prompt(v));
+
+                                if let Some(popup) = popup {
+                                    state.modals.push(popup);
+                                }
+                            }
+
+                            _ => (),
+                        }
+                    }
+
+                    // Channel select mode
+                    (Pane::Channels, _) => {
+                        // Typing filters the list
+                        {
+                            let mut state = state.write().await;
+                            if handle_filter_key(&mut state.list_filter, key.code, &['j', 'k']) {
+                                let visible = state.filtered_channels();
+                                if let Some(guild) = state.current_guild_mut() {
+                                    if !matches!(guild.channels_select, Some(v) if visible.contains(&v)) {
+                                        guild.channels_select = visible.first().cloned();
+                                    }
+                                }
+                                continue;
+                            }
+                        }
+
+                        match key.code {
+                            KeyCode::Esc => {
+                                state.write().await.mode = AppMode::TextNormal;
+                            }
+
+                            // Move down
+                            KeyCode::Char('j') | KeyCode::Down => {
+                                let mut state = state.write().await;
+                                let visible = state.filtered_channels();
+                                if let Some(guild) = state.current_guild_mut() {
+                                    guild.channels_select = move_selection(guild.channels_select, &visible, true);
+                                }
+                            }
+
+                            // Move up
+                            KeyCode::Char('k') | KeyCode::Up => {
+                                let mut state = state.write().await;
+                                let visible = state.filtered_channels();
+                                if let Some(guild) = state.current_guild_mut() {
+                                    guild.channels_select = move_selection(guild.channels_select, &visible, false);
+                                }
+                            }
+
+                            // Select channel
+                            KeyCode::Enter => {
+                                let mut state = state.write().await;
+                                state.list_filter = None;
+                                let target = state
+                                    .current_guild()
+                                    .and_then(|guild| guild.channels_select.and_then(|v| guild.channels_list.get(v)).map(|&v| (guild.id, v)));
+
+                                if let Some((guild_id, channel_id)) = target {
+                                    if state.visit_channel(guild_id, channel_id) {
+                                        let _ = tx.send(ClientEvent::GetMoreMessages(None)).await;
+                                    }
+
+                                    state.mode = AppMode::TextNormal;
+                                }
+                            }
+
+                            _ => (),
+                        }
+                    }
+
+                    // The input box only has focus in normal and insert mode
+                    (Pane::Input, _) => (),
+                }
+            }
+
+            // Mouse events
+            crossterm::event::Event::Mouse(_) => {
+                // TODO: mouse events
+            }
+
+            // Ignore this
+            crossterm::event::Event::Resize(_, _) => (),
+        }
+    }
+}
+
+/// Moves back or forward through the jump list, loading messages if the channel has none yet.
+async fn jump_channel(state: &Arc<RwLock<AppState>>, tx: &mpsc::Sender<ClientEvent>, back: bool) {
+    let mut state = state.write().await;
+    match state.jump(back) {
+        Some(true) => {
+            let _ = tx.send(ClientEvent::GetMoreMessages(None)).await;
+        }
+
+        Some(false) => (),
+
+        None if back => state.status = Some(String::from("no older channel in the jump list")),
+        None => state.status = Some(String::from("no newer channel in the jump list")),
+    }
+}
+
+/// Shows a popup of the most recently active or visited channels with their unread counts.
+fn show_recent_channels(state: &mut AppState) {
+    let channels = state.recent_channels();
+    let items = channels
+        .iter()
+        .enumerate()
+        .filter_map(|(i, &(guild_id, channel_id))| {
+            let label = state.channel_label(guild_id, channel_id)?;
+            let item = match state.get_channel(guild_id, channel_id).map(|v| v.unread_count(state.current_user)) {
+                Some(0) | None => label,
+                Some(unread) => format!("{} ({} unread)", label, unread),
+            };
+            Some((i as u64, item))
+        })
+        .collect();
+
+    let popup = Modal::list("recent channels", Text::default(), items, ListKind::Channels(channels));
+    state.modals.push(popup);
+}
+
+/// Fetches the current channel's latest messages again if they couldn't be loaded.
+async fn retry_history(state: &Arc<RwLock<AppState>>, tx: &mpsc::Sender<ClientEvent>) {
+    let failed = matches!(state.read().await.current_channel().map(|v| &v.history), Some(History::Failed(_)));
+    if failed {
+        let _ = tx.send(ClientEvent::GetMoreMessages(None)).await;
+    }
+}
+
+async fn send_message(state: &Arc<RwLock<AppState>>, tx: &mpsc::Sender<ClientEvent>) {
+    let mut state = state.write().await;
+    if state.editing {
+        state.editing = false;
+        let message = state.input.take();
+
+        if let Some(channel) = state.current_channel() {
+            if let Some(message_id) = channel.selected_message().map(|v| v.id) {
+                if !message.is_empty() {
+                    let _ = tx.send(ClientEvent::Edit(message_id, message)).await;
+                }
+            }
+        }
+
+        state.mode = AppMode::Scroll;
+        state.editing = false;
+        state.input = std::mem::take(&mut state.old_input);
+    } else {
+        let message = state.input.take();
+        state.typing_sent = None;
+
+        if let Some(message) = input_command(&mut state, tx, &message).await.filter(|v| !v.is_empty()) {
+            let _ = tx.send(ClientEvent::Send(message)).await;
+        }
+    }
+}
+
+/// Runs an IRC style command typed into the message box, returning the message to send instead, if any.
+/// Anything that isn't one of these commands (such as a bot's slash command) is sent as is, and starting a message with
+/// `//` sends it with a single `/` instead of running it.
+async fn input_command(state: &mut AppState, tx: &mpsc::Sender<ClientEvent>, message: &str) -> Option<String> {
+    let (command, args) = match message.strip_prefix('/') {
+        Some(rest) if rest.starts_with('/') => return Some(rest.to_owned()),
+        Some(rest) => match rest.split_once(' ') {
+            Some((command, args)) => (command, args.trim()),
+            None => (rest, ""),
+        },
+        None => return Some(message.to_owned()),
+    };
+
+    // The user's last message in the current channel, for editing and deleting
+    let last_own = state.current_channel().and_then(|channel| {
+        channel
+            .messages_list
+            .iter()
+            .rev()
+            .filter_map(|v| channel.messages_map.get(v))
+            .find(|v| v.author_id == state.current_user && !matches!(v.content, MessageContent::Notice(_)))
+    });
+
+    match command {
+        "me" if !args.is_empty() => {
+            let _ = tx.send(ClientEvent::SendAction(args.to_owned())).await;
+            None
+        }
+
+        "shrug" if args.is_empty() => Some(String::from(SHRUG)),
+        "shrug" => Some(format!("{} {}", args, SHRUG)),
+
+        "spoiler" if !args.is_empty() => Some(format!("||{}||", args)),
+
+        "edit" if !args.is_empty() => {
+            match last_own.map(|v| v.id) {
+                Some(message_id) => {
+                    let _ = tx.send(ClientEvent::Edit(message_id, args.to_owned())).await;
+                }
+                None => state.status = Some(String::from("no message of yours to edit")),
+            }
+            None
+        }
+
+        "delete" => {
+            match last_own.map(|v| Modal::confirm("delete this message?", message_preview(state, v), ConfirmAction::DeleteMessage(v.id))) {
+                Some(popup) => state.modals.push(popup),
+                None => state.status = Some(String::from("no message of yours to delete")),
+            }
+            None
+        }
+
+        "join" if !args.is_empty() => {
+            let _ = tx.send(ClientEvent::PreviewInvite(args.to_owned())).await;
+            None
+        }
+
+        _ => Some(message.to_owned()),
+    }
+}
+
+/// Creates a preview of a message for popups, showing the author, time, and the start of the content.
+fn message_preview(state: &AppState, message: &Message) -> Text<'static> {
+    let author = message_author(state, message);
+    let time: DateTime<Local> = DateTime::from(UNIX_EPOCH + Duration::from_secs(message.timestamp));
+    let snippet = message_snippet(message, 200);
+
+    Text::from(vec![
+        Spans::from(vec![
+            Span::styled(author, state.theme.header),
+            Span::styled(time.format(" - %H:%M (%x)").to_string(), state.theme.header),
+        ]),
+        Spans::from(snippet),
+    ])
+}
+
+/// Gets the indices of the names that fuzzy match the filter.
+fn filter_indices<'a>(filter: Option<&str>, names: impl Iterator<Item = &'a str>) -> Vec<usize> {
+    names
+        .enumerate()
+        .filter(|(_, name)| filter.map(|v| fuzzy::matches(v, name)).unwrap_or(true))
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// Highlights the characters of a list item that match the filter.
+fn highlight_filter<'a>(name: &'a str, filter: Option<&str>, style: Style) -> Spans<'a> {
+    match filter.and_then(|v| fuzzy::score(v, name)) {
+        Some(matched) => Spans::from(fuzzy::highlight(name, &matched.indices, style)),
+        None => Spans::from(name),
+    }
+}
+
+/// Moves a list selection to the next or previous visible index.
+fn move_selection(selected: Option<usize>, visible: &[usize], down: bool) -> Option<usize> {
+    match selected.and_then(|v| visible.iter().position(|&i| i == v)) {
+        Some(p) if down => visible.get(p + 1).or_else(|| visible.get(p)).cloned(),
+        Some(p) => visible.get(p.saturating_sub(1)).cloned(),
+        None if down => visible.first().cloned(),
+        None => visible.last().cloned(),
+    }
+}
+
+/// Handles typing into the guild or channel list filter, returning whether the key was used.
+/// Characters that aren't bound to anything start filtering, as does `/`.
+fn handle_filter_key(filter: &mut Option<String>, code: KeyCode, bindings: &[char]) -> bool {
+    match code {
+        KeyCode::Esc if filter.is_some() => *filter = None,
+
+        KeyCode::Backspace => match filter {
+            Some(filter) => {
+                filter.pop();
+            }
+            None => return false,
+        },
+
+        KeyCode::Char(c) => match filter {
+            Some(filter) => filter.push(c),
+            None if c == '/' => *filter = Some(String::new()),
+            None if !bindings.contains(&c) => *filter = Some(c.to_string()),
+            None => return false,
+        },
+
+        _ => return false,
+    }
+
+    true
+}
+
+/// Gets a link identifying a message on the homeserver, for referring to it outside the client.
+fn message_link(state: &AppState, guild_id: u64, channel_id: u64, message_id: u64) -> String {
+    format!("harmony://{}/{}/{}/{}", state.homeserver_host(), guild_id, channel_id, message_id)
+}
+
+/// Gets the name shown for the author of a message.
+fn message_author(state: &AppState, message: &Message) -> String {
+    message
+        .override_username
+        .clone()
+        .or_else(|| state.users.get(&message.author_id).map(|v| v.name.clone()))
+        .unwrap_or_else(|| String::from("<unknown user>"))
+}
+
+/// Gets the start of a message's contents as plain text, shortened to the given number of characters.
+fn message_snippet(message: &Message, length: usize) -> String {
+    let files;
+    let contents = match &message.content {
+        MessageContent::Text(text) => text.contents.as_str(),
+        MessageContent::Embed(embed) => embed.title.as_str(),
+        MessageContent::Notice(notice) => notice.as_str(),
+        MessageContent::Files(attachments) => {
+            files = attachments.iter().map(|v| v.name.as_str()).collect::<Vec<_>>().join(", ");
+            files.as_str()
+        }
+    };
+
+    let mut snippet: String = contents.chars().take(length).collect();
+    if snippet.len() < contents.len() {
+        snippet.push('…');
+    }
+    snippet
+}
+
+async fn delete_message(state: &Arc<RwLock<AppState>>, tx: &mpsc::Sender<ClientEvent>) {
+    let state = state.read().await;
+    if let Some(channel) = state.current_channel() {
+        if let Some(message) = channel.selected_message() {
+            if message.author_id == state.current_user {
+                let _ = tx.send(ClientEvent::Delete(message.id)).await;
+            }
+        }
+    }
+}
+
@@ -0,0 +1,188 @@
+use argon2::{
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use chacha20poly1305::aead::OsRng;
+use crossterm::event::{KeyCode, KeyEvent};
+use tui::{
+    backend::Backend,
+    layout::{Alignment, Rect},
+    text::{Span, Spans, Text},
+    widgets::{Block, Borders, Paragraph},
+    Frame,
+};
+
+use crate::{modal::centered_rect, theme::Theme};
+
+/// Hashes a passphrase the way it's written in the config file: with Argon2id and a random salt, like the vault,
+/// as a PHC string starting with `$argon2id$`.
+pub fn hash(passphrase: &str) -> Option<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default().hash_password(passphrase.as_bytes(), &salt).ok().map(|v| v.to_string())
+}
+
+/// Checks whether a passphrase hash can be used, which only Argon2 ones in PHC strings can.
+pub fn is_valid_hash(hash: &str) -> bool {
+    PasswordHash::new(hash).map_or(false, |v| v.algorithm.as_str().starts_with("argon2"))
+}
+
+/// Checks whether a passphrase is the one a hash was made from.
+fn verify(passphrase: &str, hash: &str) -> bool {
+    PasswordHash::new(hash).map_or(false, |hash| Argon2::default().verify_password(passphrase.as_bytes(), &hash).is_ok())
+}
+
+/// The result of passing a key press to the lock screen.
+pub enum LockEvent {
+    /// The lock screen stays up.
+    None,
+
+    /// The right passphrase was typed.
+    Unlocked,
+
+    /// A passphrase was chosen for the session and typed again to confirm it, given as its hash.
+    Chose(String),
+}
+
+/// A screen hiding everything until the passphrase is typed. Nothing leaves the computer, so the connection stays up.
+pub struct LockScreen {
+    /// The hash of the passphrase that unlocks it, or `None` while one is being chosen.
+    hash: Option<String>,
+
+    /// What's been typed so far.
+    input: String,
+
+    /// The passphrase being chosen, once it's been typed the first time and is waiting to be confirmed.
+    chosen: Option<String>,
+
+    /// What went wrong with the last passphrase typed, if anything.
+    error: Option<&'static str>,
+}
+
+impl LockScreen {
+    /// Creates a lock screen unlocked by the passphrase with the given hash, or asking for one if there isn't one.
+    pub fn new(hash: Option<String>) -> LockScreen {
+        LockScreen {
+            hash,
+            input: String::new(),
+            chosen: None,
+            error: None,
+        }
+    }
+
+    /// Handles a key press.
+    pub fn handle_key(&mut self, key: KeyEvent) -> LockEvent {
+        match key.code {
+            KeyCode::Char(c) => self.input.push(c),
+
+            KeyCode::Backspace => {
+                self.input.pop();
+            }
+
+            KeyCode::Esc => self.input.clear(),
+
+            KeyCode::Enter if !self.input.is_empty() => {
+                let typed = std::mem::take(&mut self.input);
+                match (&self.hash, self.chosen.take()) {
+                    (Some(hash), _) if verify(&typed, hash) => return LockEvent::Unlocked,
+                    (Some(_), _) => self.error = Some("wrong passphrase"),
+
+                    // A new passphrase is typed twice so a typo can't lock the user out
+                    (None, None) => {
+                        self.chosen = Some(typed);
+                        self.error = None;
+                    }
+
+                    // Stay locked with the new passphrase
+                    (None, Some(chosen)) if chosen == typed => match hash(&typed) {
+                        Some(hash) => {
+                            self.hash = Some(hash.clone());
+                            self.error = None;
+                            return LockEvent::Chose(hash);
+                        }
+                        None => self.error = Some("could not hash the passphrase"),
+                    },
+                    (None, Some(_)) => self.error = Some("the passphrases didn't match"),
+                }
+            }
+
+            _ => (),
+        }
+
+        LockEvent::None
+    }
+
+    /// Renders the lock screen in the middle of the given area, leaving the rest blank.
+    pub fn render<B: Backend>(&self, f: &mut Frame<B>, area: Rect, theme: &Theme) {
+        let prompt = match (&self.hash, &self.chosen) {
+            (Some(_), _) => "passphrase: ",
+            (None, None) => "choose a passphrase: ",
+            (None, Some(_)) => "type it again: ",
+        };
+        let mut lines = vec![
+            Spans::from(Span::styled("locked", theme.header)),
+            Spans::from(""),
+            Spans::from(format!("{}{}", prompt, "•".repeat(self.input.chars().count()))),
+        ];
+
+        if let Some(error) = self.error {
+            lines.push(Spans::from(""));
+            lines.push(Spans::from(Span::styled(error, theme.danger)));
+        }
+
+        let rect = centered_rect(44.min(area.width), lines.len() as u16 + 2, area);
+        let paragraph = Paragraph::new(Text::from(lines))
+            .block(Block::default().borders(Borders::ALL))
+            .alignment(Alignment::Center);
+        f.render_widget(paragraph, rect);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::KeyModifiers;
+
+    /// Types text into the lock screen and presses enter.
+    fn enter(lock: &mut LockScreen, text: &str) -> LockEvent {
+        for c in text.chars() {
+            lock.handle_key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+        }
+        lock.handle_key(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE))
+    }
+
+    #[test]
+    fn hashes_are_salted_argon2() {
+        let first = hash("toki").unwrap();
+        let second = hash("toki").unwrap();
+        assert!(first.starts_with("$argon2id$"));
+        assert_ne!(first, second);
+        assert!(is_valid_hash(&first));
+        assert!(verify("toki", &first) && verify("toki", &second));
+        assert!(!verify("pona", &first));
+    }
+
+    #[test]
+    fn old_sha256_hashes_are_refused() {
+        let sha256 = "8e3f9e8b1a1c8c0c7e1bd1b0b4e3e3cfd1e8b2a1c4c5e5f6a7b8c9d0e1f2a3b4";
+        assert!(!is_valid_hash(sha256));
+        assert!(!verify("toki", sha256));
+    }
+
+    #[test]
+    fn new_passphrase_is_confirmed() {
+        let mut lock = LockScreen::new(None);
+        assert!(matches!(enter(&mut lock, "toki"), LockEvent::None));
+        assert!(matches!(enter(&mut lock, "tkoi"), LockEvent::None));
+        assert_eq!(lock.error, Some("the passphrases didn't match"));
+
+        assert!(matches!(enter(&mut lock, "toki"), LockEvent::None));
+        let hash = match enter(&mut lock, "toki") {
+            LockEvent::Chose(hash) => hash,
+            _ => panic!("the passphrase wasn't chosen"),
+        };
+        assert!(verify("toki", &hash));
+
+        assert!(matches!(enter(&mut lock, "pona"), LockEvent::None));
+        assert!(matches!(enter(&mut lock, "toki"), LockEvent::Unlocked));
+    }
+}
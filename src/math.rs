@@ -0,0 +1,610 @@
+use std::{io, ops::Range, path::Path, process::Stdio, time::Duration};
+
+use tokio::process::Command;
+use tui::style::Style;
+
+use crate::{FormatMetadata, RichText};
+
+/// How deeply arguments can nest before they're left as source.
+const MAX_DEPTH: usize = 32;
+
+/// How long the rendered image is kept around after the viewer is started, for it to load the image.
+const VIEWER_GRACE: Duration = Duration::from_secs(60);
+
+/// Finds all math regions (`$...$` and `$$...$$`) in a string.
+/// Returns the range of the whole region (including delimiters) and the range of the source inside.
+fn find_math(text: &str) -> Vec<(Range<usize>, Range<usize>)> {
+    let bytes = text.as_bytes();
+    let mut result = vec![];
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            // Escaped dollar signs are not math
+            b'\\' => i += 2,
+
+            // Display math
+            b'$' if bytes.get(i + 1) == Some(&b'$') => {
+                if let Some(end) = text[i + 2..].find("$$") {
+                    let end = i + 2 + end;
+                    if end > i + 2 {
+                        result.push((i..end + 2, i + 2..end));
+                    }
+                    i = end + 2;
+                } else {
+                    break;
+                }
+            }
+
+            // Inline math
+            // Follows pandoc's rules so that things like "$5 and $10" aren't treated as math
+            b'$' => {
+                let mut j = i + 1;
+                let mut found = None;
+                if !matches!(bytes.get(j), None | Some(b' ' | b'\t' | b'\n')) {
+                    while j < bytes.len() {
+                        match bytes[j] {
+                            b'\\' => j += 2,
+                            b'$' => {
+                                if !matches!(bytes[j - 1], b' ' | b'\t' | b'\n') && !matches!(bytes.get(j + 1), Some(b'0'..=b'9')) {
+                                    found = Some(j);
+                                }
+                                break;
+                            }
+                            _ => j += 1,
+                        }
+                    }
+                }
+
+                if let Some(end) = found {
+                    result.push((i..end + 1, i + 1..end));
+                    i = end + 1;
+                } else {
+                    i += 1;
+                }
+            }
+
+            _ => i += 1,
+        }
+    }
+
+    result
+}
+
+/// Converts a LaTeX command (without the backslash) into its unicode equivalent.
+fn command_to_unicode(command: &str) -> Option<&'static str> {
+    Some(match command {
+        // Greek letters
+        "alpha" => "α",
+        "beta" => "β",
+        "gamma" => "γ",
+        "delta" => "δ",
+        "epsilon" => "ϵ",
+        "varepsilon" => "ε",
+        "zeta" => "ζ",
+        "eta" => "η",
+        "theta" => "θ",
+        "vartheta" => "ϑ",
+        "iota" => "ι",
+        "kappa" => "κ",
+        "lambda" => "λ",
+        "mu" => "μ",
+        "nu" => "ν",
+        "xi" => "ξ",
+        "pi" => "π",
+        "rho" => "ρ",
+        "sigma" => "σ",
+        "tau" => "τ",
+        "upsilon" => "υ",
+        "phi" => "ϕ",
+        "varphi" => "φ",
+        "chi" => "χ",
+        "psi" => "ψ",
+        "omega" => "ω",
+        "Gamma" => "Γ",
+        "Delta" => "Δ",
+        "Theta" => "Θ",
+        "Lambda" => "Λ",
+        "Xi" => "Ξ",
+        "Pi" => "Π",
+        "Sigma" => "Σ",
+        "Upsilon" => "Υ",
+        "Phi" => "Φ",
+        "Psi" => "Ψ",
+        "Omega" => "Ω",
+
+        // Big operators
+        "sum" => "∑",
+        "prod" => "∏",
+        "int" => "∫",
+        "iint" => "∬",
+        "oint" => "∮",
+        "bigcup" => "⋃",
+        "bigcap" => "⋂",
+
+        // Relations
+        "leq" | "le" => "≤",
+        "geq" | "ge" => "≥",
+        "neq" | "ne" => "≠",
+        "approx" => "≈",
+        "equiv" => "≡",
+        "sim" => "∼",
+        "simeq" => "≃",
+        "cong" => "≅",
+        "propto" => "∝",
+        "in" => "∈",
+        "notin" => "∉",
+        "ni" => "∋",
+        "subset" => "⊂",
+        "subseteq" => "⊆",
+        "supset" => "⊃",
+        "supseteq" => "⊇",
+        "mid" => "∣",
+        "perp" => "⊥",
+        "parallel" => "∥",
+
+        // Binary operators
+        "pm" => "±",
+        "mp" => "∓",
+        "times" => "×",
+        "div" => "÷",
+        "cdot" => "⋅",
+        "circ" => "∘",
+        "ast" => "∗",
+        "oplus" => "⊕",
+        "otimes" => "⊗",
+        "cup" => "∪",
+        "cap" => "∩",
+        "setminus" => "∖",
+        "wedge" | "land" => "∧",
+        "vee" | "lor" => "∨",
+        "neg" | "lnot" => "¬",
+
+        // Arrows
+        "to" | "rightarrow" => "→",
+        "leftarrow" | "gets" => "←",
+        "leftrightarrow" => "↔",
+        "Rightarrow" | "implies" => "⇒",
+        "Leftarrow" => "⇐",
+        "Leftrightarrow" | "iff" => "⇔",
+        "mapsto" => "↦",
+        "uparrow" => "↑",
+        "downarrow" => "↓",
+
+        // Miscellaneous symbols
+        "infty" => "∞",
+        "partial" => "∂",
+        "nabla" => "∇",
+        "forall" => "∀",
+        "exists" => "∃",
+        "nexists" => "∄",
+        "emptyset" | "varnothing" => "∅",
+        "aleph" => "ℵ",
+        "hbar" => "ℏ",
+        "ell" => "ℓ",
+        "Re" => "ℜ",
+        "Im" => "ℑ",
+        "angle" => "∠",
+        "degree" => "°",
+        "prime" => "′",
+        "ldots" | "dots" => "…",
+        "cdots" => "⋯",
+        "vdots" => "⋮",
+        "ddots" => "⋱",
+        "langle" => "⟨",
+        "rangle" => "⟩",
+        "lfloor" => "⌊",
+        "rfloor" => "⌋",
+        "lceil" => "⌈",
+        "rceil" => "⌉",
+        "sqrt" => "√",
+
+        // Blackboard bold shorthands
+        "N" => "ℕ",
+        "Z" => "ℤ",
+        "Q" => "ℚ",
+        "R" => "ℝ",
+        "C" => "ℂ",
+
+        // Spacing
+        "," | ":" | ";" | " " | "quad" => " ",
+        "qquad" => "  ",
+        "!" => "",
+
+        // Escaped symbols
+        "{" => "{",
+        "}" => "}",
+        "$" => "$",
+        "%" => "%",
+        "&" => "&",
+        "#" => "#",
+        "_" => "_",
+        "\\" => " ",
+
+        _ => return None,
+    })
+}
+
+/// Converts a character to superscript, if a superscript version exists.
+fn superscript(c: char) -> Option<char> {
+    Some(match c {
+        '0' => '⁰',
+        '1' => '¹',
+        '2' => '²',
+        '3' => '³',
+        '4' => '⁴',
+        '5' => '⁵',
+        '6' => '⁶',
+        '7' => '⁷',
+        '8' => '⁸',
+        '9' => '⁹',
+        '+' => '⁺',
+        '-' => '⁻',
+        '=' => '⁼',
+        '(' => '⁽',
+        ')' => '⁾',
+        'a' => 'ᵃ',
+        'b' => 'ᵇ',
+        'c' => 'ᶜ',
+        'd' => 'ᵈ',
+        'e' => 'ᵉ',
+        'f' => 'ᶠ',
+        'g' => 'ᵍ',
+        'h' => 'ʰ',
+        'i' => 'ⁱ',
+        'j' => 'ʲ',
+        'k' => 'ᵏ',
+        'l' => 'ˡ',
+        'm' => 'ᵐ',
+        'n' => 'ⁿ',
+        'o' => 'ᵒ',
+        'p' => 'ᵖ',
+        'r' => 'ʳ',
+        's' => 'ˢ',
+        't' => 'ᵗ',
+        'u' => 'ᵘ',
+        'v' => 'ᵛ',
+        'w' => 'ʷ',
+        'x' => 'ˣ',
+        'y' => 'ʸ',
+        'z' => 'ᶻ',
+        '′' => '′',
+        ' ' => ' ',
+        _ => return None,
+    })
+}
+
+/// Converts a character to subscript, if a subscript version exists.
+fn subscript(c: char) -> Option<char> {
+    Some(match c {
+        '0' => '₀',
+        '1' => '₁',
+        '2' => '₂',
+        '3' => '₃',
+        '4' => '₄',
+        '5' => '₅',
+        '6' => '₆',
+        '7' => '₇',
+        '8' => '₈',
+        '9' => '₉',
+        '+' => '₊',
+        '-' => '₋',
+        '=' => '₌',
+        '(' => '₍',
+        ')' => '₎',
+        'a' => 'ₐ',
+        'e' => 'ₑ',
+        'h' => 'ₕ',
+        'i' => 'ᵢ',
+        'j' => 'ⱼ',
+        'k' => 'ₖ',
+        'l' => 'ₗ',
+        'm' => 'ₘ',
+        'n' => 'ₙ',
+        'o' => 'ₒ',
+        'p' => 'ₚ',
+        'r' => 'ᵣ',
+        's' => 'ₛ',
+        't' => 'ₜ',
+        'u' => 'ᵤ',
+        'v' => 'ᵥ',
+        'x' => 'ₓ',
+        ' ' => ' ',
+        _ => return None,
+    })
+}
+
+/// Reads a single argument (either a braced group or a single character/command) starting at `i`.
+/// Returns the argument's source and the index after it.
+fn read_argument(chars: &[char], mut i: usize) -> (String, usize) {
+    while i < chars.len() && chars[i] == ' ' {
+        i += 1;
+    }
+
+    match chars.get(i) {
+        Some('{') => {
+            let mut depth = 0;
+            let mut j = i;
+            while j < chars.len() {
+                match chars[j] {
+                    '{' => depth += 1,
+                    '}' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            break;
+                        }
+                    }
+                    _ => (),
+                }
+                j += 1;
+            }
+
+            (chars[i + 1..j.min(chars.len())].iter().collect(), j + 1)
+        }
+
+        Some('\\') => {
+            let mut j = i + 1;
+            if j < chars.len() && chars[j].is_ascii_alphabetic() {
+                while j < chars.len() && chars[j].is_ascii_alphabetic() {
+                    j += 1;
+                }
+            } else {
+                j += 1;
+            }
+
+            (chars[i..j.min(chars.len())].iter().collect(), j)
+        }
+
+        Some(&c) => (c.to_string(), i + 1),
+        None => (String::new(), i),
+    }
+}
+
+/// Wraps a rendered argument in parentheses if it is longer than a single character.
+fn group(rendered: String) -> String {
+    if rendered.chars().count() > 1 {
+        format!("({})", rendered)
+    } else {
+        rendered
+    }
+}
+
+/// Converts LaTeX math source into a unicode approximation.
+pub fn latex_to_unicode(source: &str) -> String {
+    render(source, 0)
+}
+
+/// Converts LaTeX math source nested `depth` arguments deep into a unicode approximation.
+/// Arguments nested deeper than `MAX_DEPTH` are left as source, so messages can't overflow the stack.
+fn render(source: &str, depth: usize) -> String {
+    if depth > MAX_DEPTH {
+        return source.to_owned();
+    }
+
+    let chars: Vec<_> = source.chars().collect();
+    let mut result = String::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => {
+                let (command, j) = read_argument(&chars, i);
+                let name = &command[1..];
+                i = j;
+
+                match name {
+                    "frac" | "dfrac" | "tfrac" => {
+                        let (num, j) = read_argument(&chars, i);
+                        let (den, j) = read_argument(&chars, j);
+                        i = j;
+                        result.push_str(&group(render(&num, depth + 1)));
+                        result.push('/');
+                        result.push_str(&group(render(&den, depth + 1)));
+                    }
+
+                    "sqrt" => {
+                        let (arg, j) = read_argument(&chars, i);
+                        i = j;
+                        result.push('√');
+                        result.push_str(&group(render(&arg, depth + 1)));
+                    }
+
+                    "text" | "mathrm" | "mathbf" | "mathit" | "mathsf" | "mathtt" | "operatorname" => {
+                        let (arg, j) = read_argument(&chars, i);
+                        i = j;
+                        result.push_str(&render(&arg, depth + 1));
+                    }
+
+                    "mathbb" => {
+                        let (arg, j) = read_argument(&chars, i);
+                        i = j;
+                        match command_to_unicode(&arg) {
+                            Some(v) => result.push_str(v),
+                            None => result.push_str(&arg),
+                        }
+                    }
+
+                    // Delimiter sizing is meaningless in a terminal
+                    "left" | "right" | "big" | "Big" | "bigg" | "Bigg" => (),
+
+                    _ => {
+                        if let Some(v) = command_to_unicode(name) {
+                            result.push_str(v);
+                        } else {
+                            // Functions like \sin and unknown commands are written out as is
+                            result.push_str(name);
+                        }
+                    }
+                }
+            }
+
+            c @ ('^' | '_') => {
+                let (arg, j) = read_argument(&chars, i + 1);
+                i = j;
+                let rendered = render(&arg, depth + 1);
+                let convert = if c == '^' { superscript } else { subscript };
+                let converted: Option<String> = rendered.chars().map(convert).collect();
+
+                match converted {
+                    Some(v) => result.push_str(&v),
+                    None => {
+                        result.push(c);
+                        result.push_str(&group(rendered));
+                    }
+                }
+            }
+
+            '{' | '}' => i += 1,
+
+            c => {
+                result.push(c);
+                i += 1;
+            }
+        }
+    }
+
+    result
+}
+
+/// Maps a byte offset in the original text to the offset in the rendered text.
+fn map_offset(pos: usize, regions: &[(Range<usize>, Range<usize>)]) -> usize {
+    let mut delta = 0isize;
+    for (original, rendered) in regions {
+        if pos <= original.start {
+            break;
+        } else if pos < original.end {
+            return rendered.start;
+        }
+
+        delta += rendered.len() as isize - original.len() as isize;
+    }
+
+    (pos as isize + delta) as usize
+}
+
+/// Finds all math in a rich text and replaces it with a unicode rendering, keeping the original
+/// source in the format metadata.
+pub fn render_math(rich: &mut RichText, style: Style) {
+    let math = find_math(&rich.contents);
+    if math.is_empty() {
+        return;
+    }
+
+    // Replace contents
+    let mut contents = String::new();
+    let mut regions = vec![];
+    let mut sources = vec![];
+    let mut last = 0;
+    for (whole, inner) in math {
+        contents.push_str(&rich.contents[last..whole.start]);
+        let start = contents.len();
+        contents.push_str(&latex_to_unicode(&rich.contents[inner]));
+        sources.push(rich.contents[whole.clone()].to_owned());
+        last = whole.end;
+        regions.push((whole, start..contents.len()));
+    }
+    contents.push_str(&rich.contents[last..]);
+
+    // Move old formats around the math
+    let mut formats = vec![];
+    for (span, style, meta) in rich.formats.drain(..) {
+        let mut start = span.start;
+        for (original, _) in regions.iter() {
+            if original.end <= start || span.end <= original.start {
+                continue;
+            }
+
+            if start < original.start {
+                formats.push((map_offset(start, &regions)..map_offset(original.start, &regions), style, meta.clone()));
+            }
+            start = original.end;
+        }
+
+        if start < span.end {
+            formats.push((map_offset(start, &regions)..map_offset(span.end, &regions), style, meta));
+        }
+    }
+
+    // Add math formats
+    for ((_, rendered), source) in regions.into_iter().zip(sources) {
+        if !rendered.is_empty() {
+            formats.push((rendered, style, FormatMetadata::Math(source)));
+        }
+    }
+
+    formats.sort_by(|a, b| a.0.start.cmp(&b.0.start));
+    rich.contents = contents;
+    rich.formats = formats;
+}
+
+/// Runs a program in the given directory, failing if it can't be started or exits unsuccessfully.
+async fn run(program: &str, args: &[&str], dir: &Path) -> io::Result<()> {
+    let status = Command::new(program)
+        .args(args)
+        .current_dir(dir)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .await
+        .map_err(|e| io::Error::new(e.kind(), format!("could not run {}: {}", program, e)))?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::new(io::ErrorKind::Other, format!("{} failed ({})", program, status)))
+    }
+}
+
+/// Renders the given math sources to an image with LaTeX and opens it with the system viewer.
+/// The files are written to a private temporary directory, which is removed once the viewer has had time to load them.
+pub async fn open_math(sources: Vec<String>) -> io::Result<()> {
+    let dir = tempfile::TempDir::new()?;
+
+    let document = format!(
+        "\\documentclass[preview,border=4pt]{{standalone}}\n\\usepackage{{amsmath,amssymb}}\n\\begin{{document}}\n{}\n\\end{{document}}\n",
+        sources.join("\n\n")
+    );
+    tokio::fs::write(dir.path().join("math.tex"), document).await?;
+
+    // Messages are untrusted, so they must not be able to run commands through \write18
+    run("latex", &["-no-shell-escape", "-interaction=nonstopmode", "-halt-on-error", "math.tex"], dir.path()).await?;
+    run("dvipng", &["-D", "200", "-o", "math.png", "math.dvi"], dir.path()).await?;
+    let opener = if cfg!(target_os = "macos") { "open" } else { "xdg-open" };
+    run(opener, &["math.png"], dir.path()).await?;
+
+    tokio::time::sleep(VIEWER_GRACE).await;
+    drop(dir);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_inline_and_display_math() {
+        assert_eq!(find_math("a $x$ b $$y$$"), vec![(2..5, 3..4), (8..13, 10..11)]);
+        assert!(find_math("$5 and $10").is_empty());
+        assert!(find_math(r"\$x$").is_empty());
+        assert!(find_math("$$ unclosed").is_empty());
+    }
+
+    #[test]
+    fn converts_commands_and_scripts() {
+        assert_eq!(latex_to_unicode(r"\frac{1}{2}"), "1/2");
+        assert_eq!(latex_to_unicode(r"\frac{a+b}{2}"), "(a+b)/2");
+        assert_eq!(latex_to_unicode(r"\sqrt{x}"), "√x");
+        assert_eq!(latex_to_unicode("x^2 + x_i"), "x² + xᵢ");
+        assert_eq!(latex_to_unicode(r"\alpha+\pi"), "α+π");
+        assert_eq!(latex_to_unicode(r"\sin x"), "sin x");
+    }
+
+    #[test]
+    fn deep_nesting_is_left_as_source() {
+        let source = format!("{}x{}", r"\sqrt{".repeat(10_000), "}".repeat(10_000));
+        let rendered = latex_to_unicode(&source);
+        assert!(rendered.starts_with('√'));
+        assert!(rendered.contains(r"\sqrt{x}"));
+    }
+}
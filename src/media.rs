@@ -0,0 +1,353 @@
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::Arc,
+};
+
+use futures_util::StreamExt;
+use serde::Deserialize;
+use tokio::io::AsyncWriteExt;
+use tui::layout::Rect;
+use ueberzug::{Scalers, UeConf, Ueberzug};
+
+use crate::{cache, transfers::Transfer};
+
+/// How many bytes of an upload are sent at a time.
+const UPLOAD_CHUNK: usize = 16 * 1024;
+
+/// A file or photo attached to a message.
+#[derive(Clone)]
+pub struct Attachment {
+    /// The media id of the file, or its url if it's hosted elsewhere.
+    pub id: String,
+
+    /// The name of the file.
+    pub name: String,
+
+    /// The mime type of the file.
+    pub mimetype: String,
+}
+
+impl Attachment {
+    /// Checks whether the attachment is an image.
+    pub fn is_image(&self) -> bool {
+        self.mimetype.starts_with("image")
+    }
+
+    /// Gets the url to download the attachment from.
+    pub fn url(&self, homeserver: &str) -> String {
+        resolve_url(&self.id, homeserver)
+    }
+
+    /// Gets the path the attachment is cached at once downloaded.
+    /// The file's name is kept at the end so programs opening it can tell its type from the extension.
+    pub fn cache_path(&self) -> Option<PathBuf> {
+        let id: String = self.id.chars().map(|c| if c.is_alphanumeric() { c } else { '_' }).collect();
+        let name = Path::new(&self.name)
+            .file_name()
+            .map(|v| v.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        cache::dir().map(|v| v.join(format!("{}-{}", id, name)))
+    }
+}
+
+/// Resolves a reference to media, such as an attachment, photo, or avatar, into a url it can be downloaded from.
+/// References are HMCs (`hmc://server/id`, or a bare id on the current homeserver), Matrix `mxc://` urls, or urls
+/// that can already be fetched.
+pub fn resolve_url(reference: &str, homeserver: &str) -> String {
+    if reference.starts_with("http://") || reference.starts_with("https://") {
+        reference.to_owned()
+    } else if let Some(media) = reference.strip_prefix("mxc://") {
+        // Files bridged from Matrix are downloaded from the Matrix server hosting them
+        let server = media.split('/').next().unwrap_or_default();
+        format!("https://{}/_matrix/media/r0/download/{}", server, media)
+    } else if let Some(media) = reference.strip_prefix("hmc://") {
+        // Media on another homeserver is downloaded from that homeserver
+        let (server, id) = media.split_once('/').unwrap_or((media, ""));
+        format!("https://{}/_harmony/media/download/{}", server, id)
+    } else {
+        format!("{}/_harmony/media/download/{}", homeserver.trim_end_matches('/'), reference)
+    }
+}
+
+/// The response from the media api after uploading a file.
+#[derive(Deserialize)]
+struct UploadResponse {
+    id: String,
+}
+
+/// Converts a http error into an io error.
+fn to_io(e: reqwest::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, e)
+}
+
+/// Downloads a url to the given path, reporting progress to the transfer.
+/// The file is written next to the path and only moved there once it's complete.
+pub async fn download(url: &str, path: &Path, transfer: &Transfer) -> io::Result<()> {
+    let partial = path.with_extension("part");
+    let result = async {
+        let mut response = crate::net::client()
+            .get(url)
+            .send()
+            .await
+            .and_then(|v| v.error_for_status())
+            .map_err(to_io)?;
+        if let Some(length) = response.content_length() {
+            transfer.set_total(length);
+        }
+
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        let mut file = tokio::fs::File::create(&partial).await?;
+        while let Some(chunk) = response.chunk().await.map_err(to_io)? {
+            transfer.checkpoint().await?;
+            file.write_all(&chunk).await?;
+            transfer.progress(chunk.len());
+        }
+        file.flush().await?;
+        tokio::fs::rename(&partial, path).await
+    }
+    .await;
+
+    if result.is_err() {
+        let _ = tokio::fs::remove_file(&partial).await;
+    }
+    transfer.finish(&result);
+    result
+}
+
+/// Uploads a file to the homeserver's media api, returning its media id.
+pub async fn upload(
+    homeserver: &str,
+    token: &str,
+    name: &str,
+    mimetype: &str,
+    bytes: Vec<u8>,
+    transfer: Arc<Transfer>,
+) -> io::Result<String> {
+    let length = bytes.len() as u64;
+    transfer.set_total(length);
+
+    let progress = transfer.clone();
+    let chunks: Vec<Vec<u8>> = bytes.chunks(UPLOAD_CHUNK).map(|v| v.to_vec()).collect();
+    let stream = futures_util::stream::iter(chunks).then(move |chunk| {
+        let transfer = progress.clone();
+        async move {
+            transfer.checkpoint().await?;
+            transfer.progress(chunk.len());
+            Ok::<_, io::Error>(chunk)
+        }
+    });
+
+    let result = async {
+        let part = reqwest::multipart::Part::stream_with_length(reqwest::Body::wrap_stream(stream), length)
+            .file_name(name.to_owned())
+            .mime_str(mimetype)
+            .map_err(to_io)?;
+        let response: UploadResponse = crate::net::client()
+            .post(format!("{}/_harmony/media/upload", homeserver.trim_end_matches('/')))
+            .header("Authorization", token)
+            .multipart(reqwest::multipart::Form::new().part("file", part))
+            .send()
+            .await
+            .and_then(|v| v.error_for_status())
+            .map_err(to_io)?
+            .json()
+            .await
+            .map_err(to_io)?;
+        Ok(response.id)
+    }
+    .await;
+
+    transfer.finish(&result);
+    result
+}
+
+/// Downloads an attachment into the cache unless it's already there, returning where it is.
+pub async fn fetch(attachment: &Attachment, homeserver: &str, transfer: &Transfer) -> io::Result<PathBuf> {
+    let path = attachment
+        .cache_path()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no cache directory"))?;
+
+    if path.exists() {
+        cache::touch(&path);
+        transfer.finish(&Ok(()));
+    } else {
+        download(&attachment.url(homeserver), &path, transfer).await?;
+        cache::evict(&path);
+    }
+    Ok(path)
+}
+
+/// Gets the name of the `n`th copy of a file, such as `name (1).ext`, so saving never replaces an existing file.
+fn numbered(name: &str, n: usize) -> String {
+    if n == 0 {
+        return name.to_owned();
+    }
+
+    match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => format!("{} ({}).{}", stem, n, extension),
+        _ => format!("{} ({})", name, n),
+    }
+}
+
+/// Downloads an attachment into the downloads directory, returning where it was saved.
+/// Files already there are never replaced; the copy is numbered instead.
+pub async fn save(attachment: &Attachment, homeserver: &str, transfer: &Transfer) -> io::Result<PathBuf> {
+    let name = Path::new(&attachment.name)
+        .file_name()
+        .map(|v| v.to_string_lossy().into_owned())
+        .unwrap_or_else(|| String::from("download"));
+
+    // Hidden files could be config the user's shell or other programs pick up
+    if name.starts_with('.') {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("refusing to save a file named {}", name)));
+    }
+
+    let dir = dirs::download_dir().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no downloads directory"))?;
+    let cached = fetch(attachment, homeserver, transfer).await?;
+    let mut source = tokio::fs::File::open(&cached).await?;
+
+    let mut n = 0;
+    loop {
+        let path = dir.join(numbered(&name, n));
+        match tokio::fs::OpenOptions::new().write(true).create_new(true).open(&path).await {
+            Ok(mut file) => {
+                tokio::io::copy(&mut source, &mut file).await?;
+                file.flush().await?;
+                return Ok(path);
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => n += 1,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Finds the command configured for a mime type, preferring exact matches over `type/*` over `*`.
+pub fn handler_for<'a>(handlers: &'a HashMap<String, String>, mimetype: &str) -> Option<&'a str> {
+    let category = mimetype.split('/').next().unwrap_or(mimetype);
+    handlers
+        .get(mimetype)
+        .or_else(|| handlers.get(&format!("{}/*", category)))
+        .or_else(|| handlers.get("*"))
+        .map(|v| v.as_str())
+}
+
+/// Downloads an attachment into the cache and opens it with the handler configured for its mime type,
+/// or the system's default program if there is none.
+/// `{}` in the handler is replaced with the path of the file, which is otherwise added at the end.
+pub async fn open(
+    attachment: &Attachment,
+    homeserver: &str,
+    handlers: &HashMap<String, String>,
+    transfer: &Transfer,
+) -> io::Result<()> {
+    let path = fetch(attachment, homeserver, transfer).await?;
+    let path = path.to_string_lossy().into_owned();
+    let handler = match handler_for(handlers, &attachment.mimetype) {
+        Some(handler) => handler,
+        None => {
+            crate::open_external(&path);
+            return Ok(());
+        }
+    };
+
+    let mut parts = handler.split_whitespace();
+    let program = parts
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "empty handler"))?;
+    let mut args: Vec<_> = parts.map(|v| v.replace("{}", &path)).collect();
+    if !handler.contains("{}") {
+        args.push(path);
+    }
+
+    tokio::process::Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    Ok(())
+}
+
+/// An image to draw over a part of the terminal.
+pub struct Placement {
+    /// The path of the image.
+    pub path: PathBuf,
+
+    /// Where to draw the image.
+    pub area: Rect,
+}
+
+/// Draws images in the terminal using ueberzug.
+pub struct Thumbnails {
+    /// The ueberzug process.
+    ueberzug: Ueberzug,
+
+    /// The images currently drawn.
+    shown: Vec<(String, Rect)>,
+}
+
+impl Thumbnails {
+    /// Starts drawing thumbnails if the terminal supports it (an X display and ueberzug are available).
+    pub fn new() -> Option<Thumbnails> {
+        std::env::var_os("DISPLAY")?;
+        let paths = std::env::var_os("PATH")?;
+        if !std::env::split_paths(&paths).any(|v| v.join("ueberzug").is_file()) {
+            return None;
+        }
+
+        Some(Thumbnails {
+            ueberzug: Ueberzug::new(),
+            shown: vec![],
+        })
+    }
+
+    /// Draws the given images, clearing ones that are no longer shown.
+    pub fn show(&mut self, placements: &[Placement]) {
+        let wanted: Vec<_> = placements
+            .iter()
+            .map(|v| (v.path.to_string_lossy().into_owned(), v.area))
+            .collect();
+
+        for (identifier, area) in self.shown.iter() {
+            if !wanted.iter().any(|(i, a)| i == identifier && a == area) {
+                self.ueberzug.clear(identifier);
+            }
+        }
+
+        for (identifier, area) in wanted.iter() {
+            if !self.shown.iter().any(|(i, a)| i == identifier && a == area) {
+                self.ueberzug.draw(&UeConf {
+                    identifier,
+                    path: identifier,
+                    x: area.x,
+                    y: area.y,
+                    width: Some(area.width),
+                    height: Some(area.height),
+                    scaler: Some(Scalers::FitContain),
+                    ..Default::default()
+                });
+            }
+        }
+
+        self.shown = wanted;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copies_are_numbered_before_the_extension() {
+        assert_eq!(numbered("cat.png", 0), "cat.png");
+        assert_eq!(numbered("cat.png", 1), "cat (1).png");
+        assert_eq!(numbered("cat.tar.gz", 2), "cat.tar (2).gz");
+        assert_eq!(numbered("README", 3), "README (3)");
+    }
+}
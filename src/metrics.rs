@@ -0,0 +1,75 @@
+use std::sync::Arc;
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::RwLock,
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{daemon, relay, stats::Stats, AppState};
+
+/// The most of a request that's read, which is plenty for the request line of a scrape.
+const MAX_REQUEST: usize = 4096;
+
+/// Background task that serves the stats in the Prometheus text format at `/metrics`, when running as a daemon and
+/// an address is configured.
+pub async fn metrics_task(state: Arc<RwLock<AppState>>, shutdown: CancellationToken) -> Result<(), String> {
+    let (config, stats) = {
+        let state = state.read().await;
+        (state.config.metrics.clone(), state.stats.clone())
+    };
+
+    let listen = match config.listen.filter(|_| daemon::is_daemon()) {
+        Some(listen) => listen,
+        None => {
+            shutdown.cancelled().await;
+            return Ok(());
+        }
+    };
+
+    // The endpoint has no authentication, so it stays on this machine unless asked otherwise
+    if !config.allow_remote && !relay::is_loopback(&listen).await? {
+        state.write().await.status = Some(format!(
+            "metrics are only served on loopback addresses unless allow_remote is set, not {}",
+            listen
+        ));
+        shutdown.cancelled().await;
+        return Ok(());
+    }
+
+    let listener = TcpListener::bind(&listen).await.map_err(|e| format!("could not listen on {}: {}", listen, e))?;
+    loop {
+        let (stream, _) = tokio::select! {
+            accepted = listener.accept() => accepted.map_err(|e| e.to_string())?,
+            _ = shutdown.cancelled() => return Ok(()),
+        };
+
+        tokio::spawn(serve(stream, stats.clone()));
+    }
+}
+
+/// Answers one HTTP request, closing the connection afterwards.
+async fn serve(mut stream: TcpStream, stats: Arc<Stats>) {
+    let mut request = vec![0; MAX_REQUEST];
+    let read = match stream.read(&mut request).await {
+        Ok(read) => read,
+        Err(_) => return,
+    };
+
+    let request = String::from_utf8_lossy(&request[..read]);
+    let mut words = request.split_whitespace();
+    let response = match (words.next(), words.next()) {
+        (Some("GET"), Some("/metrics")) => {
+            let body = stats.prometheus();
+            format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+        }
+        _ => String::from("HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"),
+    };
+
+    let _ = stream.write_all(response.as_bytes()).await;
+}
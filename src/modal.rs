@@ -0,0 +1,705 @@
+use std::sync::Arc;
+
+use crossterm::event::{KeyCode, KeyEvent};
+use harmony_rust_sdk::client::api::auth::AuthStepResponse;
+use tui::{
+    backend::Backend,
+    layout::{Alignment, Rect},
+    text::{Span, Spans, Text},
+    widgets::{Block, Borders, Clear, Paragraph, Wrap},
+    Frame,
+};
+
+use crate::{
+    backend::SearchHit,
+    chanstats::ChannelStats,
+    debug::DebugLog,
+    media::{Attachment, Placement},
+    roles::{RoleEditor, RoleRequest},
+    settings::{SettingsEditor, Value},
+    theme::Theme,
+    transfers::Transfer,
+    voice::Recording,
+    AuthInput, AuthKey, AuthState,
+};
+
+/// The number of columns in the gallery grid.
+const GALLERY_COLUMNS: usize = 4;
+
+/// The height of a cell in the gallery grid.
+const GALLERY_CELL_HEIGHT: u16 = 7;
+
+/// Gets a rectangle of the given size centered in the given area, shrinking it if it doesn't fit.
+pub fn centered_rect(width: u16, height: u16, area: Rect) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    }
+}
+
+/// An action performed when a confirmation popup is accepted.
+pub enum ConfirmAction {
+    /// Deletes the message with the given id in the current channel.
+    DeleteMessage(u64),
+
+    /// Leaves the guild with the given id.
+    LeaveGuild(u64),
+
+    /// Joins the guild the given invite is for.
+    JoinGuild(String),
+
+    /// Switches to the given guild and channel and selects the given message.
+    JumpToMessage(u64, u64, u64),
+
+    /// Sends the given text to each of the given channels as (guild id, channel id).
+    Broadcast(Vec<(u64, u64)>, String),
+
+    /// Deletes the role with the given id from the guild with the given id.
+    DeleteRole(u64, u64),
+
+    /// Wipes what's kept locally about the guild with the given id, or about every guild if it's `None`.
+    PurgeLocal(Option<u64>),
+
+    /// Deletes the user's account, once the second of two prompts is accepted. The flag is set on the second one.
+    DeleteAccount(bool),
+}
+
+/// What a list popup contains, which determines what happens to opened and removed items.
+#[derive(Clone)]
+pub enum ListKind {
+    /// Scheduled messages, keyed by their id. Removing one cancels it.
+    Scheduled,
+
+    /// Saved messages, keyed by message id. Opening one jumps to it.
+    Bookmarks,
+
+    /// Inbox entries, keyed by their id. Opening one jumps to its message or previews its invite.
+    Inbox,
+
+    /// Search results, keyed by their index. Opening one jumps to the message, loading history back to it if needed.
+    Search(Vec<SearchHit>),
+
+    /// The messages matching the saved filter with the given name, keyed by their index, which are shown again as
+    /// matching messages arrive. Opening one jumps to it like a search result.
+    Filter(String, Vec<SearchHit>),
+
+    /// Names of saved filters, keyed by their index. Opening one shows its messages and removing one deletes it.
+    Filters(Vec<String>),
+
+    /// Channels as (guild id, channel id), keyed by their index. Opening one switches to it.
+    Channels(Vec<(u64, u64)>),
+
+    /// Snippet names, keyed by their index. Opening one inserts it into the input box.
+    Snippets(Vec<String>),
+
+    /// Users the user shares a small guild with, as (user id, guild id), keyed by their index.
+    /// Opening one switches to the guild.
+    Online(Vec<(u64, u64)>),
+
+    /// Channels to forward a message to, keyed by their index. Opening one sends the message there.
+    Forward {
+        /// The channels as (guild id, channel id).
+        channels: Vec<(u64, u64)>,
+
+        /// The text of the forwarded message.
+        text: String,
+
+        /// The files of the forwarded message.
+        files: Vec<Attachment>,
+    },
+}
+
+/// The kind of a popup, which determines how it reacts to keys.
+pub enum ModalKind {
+    /// Shows some text until it's closed.
+    Info,
+
+    /// Asks the user to confirm an action with yes or no.
+    Confirm(ConfirmAction),
+
+    /// A list of items that can be opened or removed.
+    List {
+        /// The id and text of each item.
+        items: Vec<(u64, String)>,
+
+        /// The index of the selected item.
+        selected: usize,
+
+        /// What the list contains.
+        list: ListKind,
+    },
+
+    /// A voice message being recorded, with a level meter.
+    Recording(Recording),
+
+    /// A grid of attachments that can be opened or saved.
+    Gallery {
+        /// The attachments.
+        items: Vec<Attachment>,
+
+        /// The index of the selected attachment.
+        selected: usize,
+    },
+
+    /// Uploads and downloads with their progress, which can be paused or cancelled.
+    Transfers {
+        /// The transfers, newest first.
+        transfers: Vec<Arc<Transfer>>,
+
+        /// The index of the selected transfer.
+        selected: usize,
+    },
+
+    /// The roles of a guild and their permissions, which can be edited.
+    Roles(RoleEditor),
+
+    /// The newest entries of the debug log, updated as they come in.
+    Debug(Arc<DebugLog>),
+
+    /// The settings, which can be changed and saved to the config file.
+    Settings(SettingsEditor),
+
+    /// Activity statistics for the loaded history of a channel, drawn as charts.
+    ChannelStats(ChannelStats),
+
+    /// The steps the homeserver asks for to change an account setting, drawn like the login screen.
+    AccountFlow(AuthState),
+}
+
+/// The result of passing a key press to a popup.
+pub enum ModalEvent {
+    /// The popup handled the key and stays open.
+    None,
+
+    /// The popup should be closed without doing anything.
+    Close,
+
+    /// The popup was accepted and should be closed, performing its action.
+    Accept,
+
+    /// The item with the given id was opened from a list popup.
+    Open(u64),
+
+    /// The item with the given id was removed from a list popup, which stays open.
+    Remove(u64),
+
+    /// The given attachment of a gallery should be saved to the downloads directory.
+    Save(Attachment),
+
+    /// The given attachment of a gallery should be opened externally.
+    OpenAttachment(Attachment),
+
+    /// The role editor asked for a change to the guild's roles.
+    Roles(RoleRequest),
+
+    /// The settings editor changed the setting with the given key.
+    Setting(&'static str, Value),
+
+    /// The current step of changing an account setting was answered, or going back a step was asked for.
+    AccountStep(AuthStepResponse),
+}
+
+/// A popup drawn over the rest of the UI.
+pub struct Modal {
+    /// The title of the popup.
+    pub title: String,
+
+    /// The contents of the popup.
+    pub body: Text<'static>,
+
+    /// What kind of popup this is.
+    pub kind: ModalKind,
+}
+
+impl Modal {
+    /// Creates a new popup that only shows text.
+    pub fn info(title: impl Into<String>, body: Text<'static>) -> Modal {
+        Modal {
+            title: title.into(),
+            body,
+            kind: ModalKind::Info,
+        }
+    }
+
+    /// Creates a new confirmation popup.
+    pub fn confirm(title: impl Into<String>, body: Text<'static>, action: ConfirmAction) -> Modal {
+        Modal {
+            title: title.into(),
+            body,
+            kind: ModalKind::Confirm(action),
+        }
+    }
+
+    /// Creates a new list popup.
+    pub fn list(title: impl Into<String>, body: Text<'static>, items: Vec<(u64, String)>, list: ListKind) -> Modal {
+        Modal {
+            title: title.into(),
+            body,
+            kind: ModalKind::List {
+                items,
+                selected: 0,
+                list,
+            },
+        }
+    }
+
+    /// Creates a new popup for recording a voice message.
+    pub fn recording(recording: Recording) -> Modal {
+        Modal {
+            title: String::from("voice message"),
+            body: Text::default(),
+            kind: ModalKind::Recording(recording),
+        }
+    }
+
+    /// Creates a new gallery popup.
+    pub fn gallery(title: impl Into<String>, items: Vec<Attachment>) -> Modal {
+        Modal {
+            title: title.into(),
+            body: Text::default(),
+            kind: ModalKind::Gallery { items, selected: 0 },
+        }
+    }
+
+    /// Creates a new popup showing the given transfers.
+    pub fn transfers(transfers: Vec<Arc<Transfer>>) -> Modal {
+        Modal {
+            title: String::from("transfers"),
+            body: Text::default(),
+            kind: ModalKind::Transfers { transfers, selected: 0 },
+        }
+    }
+
+    /// Creates a new popup for editing the roles of a guild.
+    pub fn roles(editor: RoleEditor) -> Modal {
+        Modal {
+            title: String::from("roles"),
+            body: Text::default(),
+            kind: ModalKind::Roles(editor),
+        }
+    }
+
+    /// Creates a new popup for changing the settings.
+    pub fn settings(editor: SettingsEditor) -> Modal {
+        Modal {
+            title: String::from("settings"),
+            body: Text::default(),
+            kind: ModalKind::Settings(editor),
+        }
+    }
+
+    /// Creates a new popup showing the debug log.
+    pub fn debug(log: Arc<DebugLog>) -> Modal {
+        Modal {
+            title: String::from("debug"),
+            body: Text::default(),
+            kind: ModalKind::Debug(log),
+        }
+    }
+
+    /// Creates a new popup showing the activity statistics of a channel.
+    pub fn channel_stats(title: impl Into<String>, stats: ChannelStats) -> Modal {
+        Modal {
+            title: title.into(),
+            body: Text::default(),
+            kind: ModalKind::ChannelStats(stats),
+        }
+    }
+
+    /// Creates a new popup for changing an account setting, waiting for the homeserver's first step.
+    pub fn account_flow(title: impl Into<String>) -> Modal {
+        Modal {
+            title: title.into(),
+            body: Text::default(),
+            kind: ModalKind::AccountFlow(AuthState::default()),
+        }
+    }
+
+    /// Handles a key press while this popup has focus.
+    pub fn handle_key(&mut self, key: KeyEvent) -> ModalEvent {
+        // The role editor uses escape to back out of its prompt and permissions, and the settings editor and account
+        // forms to stop typing
+        let handles_escape = match &self.kind {
+            ModalKind::Roles(_) => true,
+            ModalKind::Settings(editor) => editor.is_typing(),
+            ModalKind::AccountFlow(flow) => flow.is_editing(),
+            _ => false,
+        };
+        if key.code == KeyCode::Esc && !handles_escape {
+            return ModalEvent::Close;
+        }
+
+        match &mut self.kind {
+            ModalKind::Info => match key.code {
+                KeyCode::Char('q') | KeyCode::Enter => ModalEvent::Close,
+                _ => ModalEvent::None,
+            },
+
+            ModalKind::Confirm(_) => match key.code {
+                KeyCode::Char('y') | KeyCode::Char('Y') => ModalEvent::Accept,
+                KeyCode::Char('n') | KeyCode::Char('N') => ModalEvent::Close,
+                _ => ModalEvent::None,
+            },
+
+            ModalKind::Recording(_) => match key.code {
+                KeyCode::Enter => ModalEvent::Accept,
+                _ => ModalEvent::None,
+            },
+
+            ModalKind::AccountFlow(flow) => match flow.handle_key(key) {
+                AuthKey::None => ModalEvent::None,
+                AuthKey::Back => ModalEvent::AccountStep(AuthStepResponse::Initial),
+                AuthKey::Quit => ModalEvent::Close,
+                AuthKey::Submit(response) => ModalEvent::AccountStep(response),
+            },
+
+            ModalKind::ChannelStats(_) => match key.code {
+                KeyCode::Char('q') | KeyCode::Enter => ModalEvent::Close,
+                _ => ModalEvent::None,
+            },
+
+            ModalKind::Roles(editor) => editor.handle_key(key),
+
+            ModalKind::Settings(editor) => editor.handle_key(key),
+
+            ModalKind::Debug(log) => match key.code {
+                KeyCode::Char('q') => ModalEvent::Close,
+                KeyCode::Char('c') => {
+                    log.clear();
+                    ModalEvent::None
+                }
+                _ => ModalEvent::None,
+            },
+
+            ModalKind::List { items, selected, .. } => match key.code {
+                KeyCode::Char('q') => ModalEvent::Close,
+
+                KeyCode::Char('j') | KeyCode::Down => {
+                    if *selected + 1 < items.len() {
+                        *selected += 1;
+                    }
+                    ModalEvent::None
+                }
+
+                KeyCode::Char('k') | KeyCode::Up => {
+                    *selected = selected.saturating_sub(1);
+                    ModalEvent::None
+                }
+
+                KeyCode::Enter => match items.get(*selected) {
+                    Some((id, _)) => ModalEvent::Open(*id),
+                    None => ModalEvent::None,
+                },
+
+                // Number keys open the item with that number
+                KeyCode::Char(c @ '1'..='9') => match items.get(c as usize - '1' as usize) {
+                    Some((id, _)) => ModalEvent::Open(*id),
+                    None => ModalEvent::None,
+                },
+
+                KeyCode::Char('d') | KeyCode::Delete if *selected < items.len() => {
+                    let (id, _) = items.remove(*selected);
+                    *selected = (*selected).min(items.len().saturating_sub(1));
+                    ModalEvent::Remove(id)
+                }
+
+                _ => ModalEvent::None,
+            },
+
+            ModalKind::Transfers { transfers, selected } => {
+                match key.code {
+                    KeyCode::Char('q') => return ModalEvent::Close,
+
+                    KeyCode::Char('j') | KeyCode::Down => {
+                        if *selected + 1 < transfers.len() {
+                            *selected += 1;
+                        }
+                    }
+
+                    KeyCode::Char('k') | KeyCode::Up => *selected = selected.saturating_sub(1),
+
+                    KeyCode::Char('p') | KeyCode::Char(' ') => {
+                        if let Some(transfer) = transfers.get(*selected).filter(|v| v.is_running()) {
+                            transfer.toggle_pause();
+                        }
+                    }
+
+                    KeyCode::Char('d') | KeyCode::Delete => {
+                        if let Some(transfer) = transfers.get(*selected) {
+                            transfer.cancel();
+                        }
+                    }
+
+                    _ => (),
+                }
+                ModalEvent::None
+            }
+
+            ModalKind::Gallery { items, selected } => {
+                let moved = match key.code {
+                    KeyCode::Char('q') => return ModalEvent::Close,
+                    KeyCode::Char('h') | KeyCode::Left => selected.checked_sub(1),
+                    KeyCode::Char('l') | KeyCode::Right => Some(*selected + 1),
+                    KeyCode::Char('k') | KeyCode::Up => selected.checked_sub(GALLERY_COLUMNS),
+                    KeyCode::Char('j') | KeyCode::Down => Some(*selected + GALLERY_COLUMNS),
+
+                    KeyCode::Enter => {
+                        return items.get(*selected).cloned().map(ModalEvent::OpenAttachment).unwrap_or(ModalEvent::None);
+                    }
+
+                    KeyCode::Char('D') => {
+                        return items.get(*selected).cloned().map(ModalEvent::Save).unwrap_or(ModalEvent::None);
+                    }
+
+                    _ => None,
+                };
+
+                if let Some(moved) = moved.filter(|&v| v < items.len()) {
+                    *selected = moved;
+                }
+                ModalEvent::None
+            }
+        }
+    }
+
+    /// Renders the popup centered in the given area, returning the images to draw over it.
+    fn render<B: Backend>(&self, f: &mut Frame<B>, area: Rect, theme: &Theme, focused: bool) -> Vec<Placement> {
+        if let ModalKind::Gallery { items, selected } = &self.kind {
+            return self.render_gallery(f, area, theme, items, *selected);
+        }
+        if let ModalKind::Debug(log) = &self.kind {
+            return self.render_debug(f, area, theme, log);
+        }
+        if let ModalKind::ChannelStats(stats) = &self.kind {
+            return self.render_channel_stats(f, area, theme, stats);
+        }
+        if let ModalKind::AccountFlow(flow) = &self.kind {
+            return self.render_account_flow(f, area, theme, flow);
+        }
+
+        let width = 60.min(area.width.saturating_sub(4));
+        let inner_width = width.saturating_sub(2).max(1) as usize;
+        let mut text = self.body.clone();
+
+        match &self.kind {
+            ModalKind::Info => {
+                text.lines.push(Spans::from(""));
+                text.lines.push(Spans::from("esc: close"));
+            }
+
+            ModalKind::Confirm(_) => {
+                text.lines.push(Spans::from(""));
+                text.lines.push(Spans::from(vec![
+                    Span::styled(" y: yes ", theme.danger),
+                    Span::raw("   "),
+                    Span::styled(" n: no ", theme.selected),
+                ]));
+            }
+
+            ModalKind::Gallery { .. } | ModalKind::Debug(_) | ModalKind::ChannelStats(_) | ModalKind::AccountFlow(_) => (),
+
+            ModalKind::Roles(editor) => editor.render(&mut text, theme),
+
+            ModalKind::Settings(editor) => editor.render(&mut text, theme),
+
+            ModalKind::Recording(recording) => {
+                let width = 30;
+                let filled = (recording.level() * width as f32) as usize;
+                text.lines.push(Spans::from("recording…"));
+                text.lines.push(Spans::from(vec![
+                    Span::styled("█".repeat(filled), theme.highlight),
+                    Span::raw("░".repeat(width - filled)),
+                ]));
+                text.lines.push(Spans::from(""));
+                text.lines.push(Spans::from(vec![
+                    Span::styled(" enter: send ", theme.selected),
+                    Span::raw("   "),
+                    Span::styled(" esc: cancel ", theme.danger),
+                ]));
+            }
+
+            ModalKind::Transfers { transfers, selected } => {
+                if transfers.is_empty() {
+                    text.lines.push(Spans::from("(no transfers)"));
+                }
+
+                for (i, transfer) in transfers.iter().enumerate() {
+                    if i == *selected {
+                        text.lines.push(Spans::from(Span::styled(transfer.describe(), theme.selected)));
+                    } else {
+                        text.lines.push(Spans::from(transfer.describe()));
+                    }
+                }
+
+                text.lines.push(Spans::from(""));
+                text.lines.push(Spans::from("j/k: move   p: pause/resume   d: cancel   esc: close"));
+            }
+
+            ModalKind::List { items, selected, .. } => {
+                if items.is_empty() {
+                    text.lines.push(Spans::from("(empty)"));
+                }
+
+                for (i, (_, item)) in items.iter().enumerate() {
+                    let item = if i < 9 { format!("{}. {}", i + 1, item) } else { item.clone() };
+                    if i == *selected {
+                        text.lines.push(Spans::from(Span::styled(item, theme.selected)));
+                    } else {
+                        text.lines.push(Spans::from(item));
+                    }
+                }
+
+                text.lines.push(Spans::from(""));
+                text.lines.push(Spans::from("j/k: move   enter/1-9: open   d: remove   esc: close"));
+            }
+        }
+
+        let height: usize = text.lines.iter().map(|v| v.width() / inner_width + 1).sum();
+        let rect = centered_rect(width, height as u16 + 2, area);
+
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_style(if focused { theme.header } else { Default::default() })
+            .title(self.title.as_str());
+        let paragraph = Paragraph::new(text)
+            .block(block)
+            .alignment(Alignment::Center)
+            .wrap(Wrap { trim: false });
+
+        f.render_widget(Clear, rect);
+        f.render_widget(paragraph, rect);
+        vec![]
+    }
+
+    /// Renders the debug log filling most of the screen, with the newest entries at the bottom.
+    fn render_debug<B: Backend>(&self, f: &mut Frame<B>, area: Rect, theme: &Theme, log: &DebugLog) -> Vec<Placement> {
+        let rect = centered_rect(area.width.saturating_sub(4).min(140), area.height.saturating_sub(2), area);
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_style(theme.header)
+            .title(format!("{} - c: clear   esc: close", self.title));
+        let inner = block.inner(rect);
+
+        let entries = log.last(inner.height as usize);
+        let text = if entries.is_empty() {
+            Text::from("(nothing logged yet)")
+        } else {
+            Text::from(entries.into_iter().map(Spans::from).collect::<Vec<_>>())
+        };
+
+        f.render_widget(Clear, rect);
+        f.render_widget(Paragraph::new(text).block(block), rect);
+        vec![]
+    }
+
+    /// Renders the charts of a channel's statistics in a box sized to fit them.
+    fn render_channel_stats<B: Backend>(&self, f: &mut Frame<B>, area: Rect, theme: &Theme, stats: &ChannelStats) -> Vec<Placement> {
+        let rect = centered_rect(area.width.saturating_sub(4).min(80), area.height.saturating_sub(2).min(30), area);
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_style(theme.header)
+            .title(format!("{} - esc: close", self.title));
+        let inner = block.inner(rect);
+
+        f.render_widget(Clear, rect);
+        f.render_widget(block, rect);
+        stats.render(f, inner, theme);
+        vec![]
+    }
+
+    /// Renders the current step of changing an account setting with the login screen's renderer, in a box sized to fit it.
+    fn render_account_flow<B: Backend>(&self, f: &mut Frame<B>, area: Rect, theme: &Theme, flow: &AuthState) -> Vec<Placement> {
+        let rect = centered_rect(60.min(area.width.saturating_sub(4)), flow.height().min(area.height.saturating_sub(2)), area);
+        f.render_widget(Clear, rect);
+
+        // Steps without a form or choices aren't drawn by the login screen, so say what's being waited on instead
+        match &flow.input {
+            AuthInput::Initial | AuthInput::Waiting(_) => {
+                let text = match &flow.input {
+                    AuthInput::Waiting(description) => description.clone(),
+                    _ => String::from("waiting for the homeserver..."),
+                };
+                let block = Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(theme.header)
+                    .title(format!("{} - q: cancel", self.title));
+                f.render_widget(Paragraph::new(text).block(block), rect);
+            }
+
+            _ => crate::draw_auth_step(f, rect, flow, theme),
+        }
+        vec![]
+    }
+
+    /// Renders a gallery as a grid of cells, scrolled to show the selected attachment.
+    fn render_gallery<B: Backend>(&self, f: &mut Frame<B>, area: Rect, theme: &Theme, items: &[Attachment], selected: usize) -> Vec<Placement> {
+        let rect = centered_rect(area.width.saturating_sub(4).min(100), area.height.saturating_sub(2), area);
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_style(theme.header)
+            .title(format!("{} - h/j/k/l: move   enter: open   D: download   esc: close", self.title));
+        let inner = block.inner(rect);
+        f.render_widget(Clear, rect);
+        f.render_widget(block, rect);
+
+        if items.is_empty() {
+            f.render_widget(Paragraph::new("(no attachments)").alignment(Alignment::Center), inner);
+            return vec![];
+        }
+
+        let cell_width = inner.width / GALLERY_COLUMNS as u16;
+        let visible_rows = (inner.height / GALLERY_CELL_HEIGHT).max(1) as usize;
+        let first_row = (selected / GALLERY_COLUMNS).saturating_sub(visible_rows - 1);
+
+        let mut placements = vec![];
+        for (i, item) in items.iter().enumerate().skip(first_row * GALLERY_COLUMNS).take(visible_rows * GALLERY_COLUMNS) {
+            let row = (i / GALLERY_COLUMNS - first_row) as u16;
+            let column = (i % GALLERY_COLUMNS) as u16;
+            let cell = Rect {
+                x: inner.x + column * cell_width,
+                y: inner.y + row * GALLERY_CELL_HEIGHT,
+                width: cell_width,
+                height: GALLERY_CELL_HEIGHT.min(inner.height),
+            };
+
+            let block = Block::default()
+                .borders(Borders::ALL)
+                .border_style(if i == selected { theme.selected } else { Default::default() })
+                .title(item.name.as_str());
+            let cell_inner = block.inner(cell);
+            f.render_widget(block, cell);
+
+            // Images get thumbnails once they're downloaded, everything else just shows its type
+            match item.cache_path().filter(|v| item.is_image() && v.exists()) {
+                Some(path) => placements.push(Placement { path, area: cell_inner }),
+                None => f.render_widget(
+                    Paragraph::new(item.mimetype.as_str()).alignment(Alignment::Center).wrap(Wrap { trim: true }),
+                    cell_inner,
+                ),
+            }
+        }
+
+        placements
+    }
+}
+
+/// Renders a stack of popups from bottom to top, with the top one having focus.
+/// Each popup is offset slightly so the ones underneath remain visible.
+/// Returns the images to draw over the top popup.
+pub fn render_stack<B: Backend>(modals: &[Modal], f: &mut Frame<B>, area: Rect, theme: &Theme) -> Vec<Placement> {
+    let mut placements = vec![];
+    for (i, modal) in modals.iter().enumerate() {
+        let offset = (i as u16).min(area.height / 4);
+        let area = Rect {
+            y: area.y + offset,
+            height: area.height - offset,
+            ..area
+        };
+        placements = modal.render(f, area, theme, i + 1 == modals.len());
+    }
+    placements
+}
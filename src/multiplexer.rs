@@ -0,0 +1,98 @@
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+use crate::config::{MultiplexerConfig, MultiplexerKind};
+
+/// A terminal multiplexer sitting between ilo toki and the terminal.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Multiplexer {
+    /// tmux, which only passes sequences it doesn't understand through when `allow-passthrough` is on.
+    Tmux,
+
+    /// GNU screen, which passes through DCS strings but doesn't forward cursor shapes.
+    Screen,
+}
+
+/// The multiplexer in use: 0 for none, 1 for tmux, and 2 for screen.
+static KIND: AtomicU8 = AtomicU8::new(0);
+
+/// Whether the cursor changes shape between modes.
+static CURSOR_SHAPE: AtomicBool = AtomicBool::new(true);
+
+/// Whether copying goes to the system clipboard.
+static CLIPBOARD: AtomicBool = AtomicBool::new(true);
+
+/// Whether thumbnails are drawn with ueberzug.
+static THUMBNAILS: AtomicBool = AtomicBool::new(true);
+
+/// Works out which multiplexer ilo toki is running in from the environment variables they set.
+pub fn detect() -> Option<Multiplexer> {
+    if std::env::var_os("TMUX").is_some() {
+        Some(Multiplexer::Tmux)
+    } else if std::env::var_os("STY").is_some()
+        || std::env::var("TERM").map(|v| v.starts_with("screen")).unwrap_or(false)
+    {
+        Some(Multiplexer::Screen)
+    } else {
+        None
+    }
+}
+
+/// Sets which multiplexer escape sequences are adjusted for and which features are on, from the `[multiplexer]`
+/// settings. Features that aren't set are on unless the multiplexer is known to break them.
+pub fn configure(config: &MultiplexerConfig) {
+    let kind = match config.kind {
+        MultiplexerKind::Auto => detect(),
+        MultiplexerKind::Tmux => Some(Multiplexer::Tmux),
+        MultiplexerKind::Screen => Some(Multiplexer::Screen),
+        MultiplexerKind::None => None,
+    };
+
+    KIND.store(
+        match kind {
+            None => 0,
+            Some(Multiplexer::Tmux) => 1,
+            Some(Multiplexer::Screen) => 2,
+        },
+        Ordering::Relaxed,
+    );
+
+    // screen swallows cursor shapes and draws over ueberzug's windows when switching windows
+    let screen = kind == Some(Multiplexer::Screen);
+    CURSOR_SHAPE.store(config.cursor_shape.unwrap_or(!screen), Ordering::Relaxed);
+    CLIPBOARD.store(config.clipboard.unwrap_or(true), Ordering::Relaxed);
+    THUMBNAILS.store(config.thumbnails.unwrap_or(!screen), Ordering::Relaxed);
+}
+
+/// Gets the multiplexer escape sequences are adjusted for.
+pub fn current() -> Option<Multiplexer> {
+    match KIND.load(Ordering::Relaxed) {
+        1 => Some(Multiplexer::Tmux),
+        2 => Some(Multiplexer::Screen),
+        _ => None,
+    }
+}
+
+/// Wraps an escape sequence so the multiplexer passes it on to the terminal instead of handling it itself.
+/// tmux needs every escape inside doubled; screen passes the contents of any DCS string through as is.
+pub fn passthrough(sequence: &str) -> String {
+    match current() {
+        Some(Multiplexer::Tmux) => format!("\x1bPtmux;{}\x1b\\", sequence.replace('\x1b', "\x1b\x1b")),
+        Some(Multiplexer::Screen) => format!("\x1bP{}\x1b\\", sequence),
+        None => sequence.to_owned(),
+    }
+}
+
+/// Checks whether the cursor should change shape between modes.
+pub fn cursor_shape() -> bool {
+    CURSOR_SHAPE.load(Ordering::Relaxed)
+}
+
+/// Checks whether copying should go to the system clipboard.
+pub fn clipboard() -> bool {
+    CLIPBOARD.load(Ordering::Relaxed)
+}
+
+/// Checks whether thumbnails should be drawn.
+pub fn thumbnails() -> bool {
+    THUMBNAILS.load(Ordering::Relaxed)
+}
@@ -0,0 +1,9 @@
+use std::sync::OnceLock;
+
+/// The http client shared by everything that talks to the network.
+static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+
+/// Gets the shared http client.
+pub fn client() -> reqwest::Client {
+    CLIENT.get_or_init(reqwest::Client::new).clone()
+}
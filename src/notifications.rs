@@ -0,0 +1,108 @@
+use std::{
+    collections::HashMap,
+    io::Write,
+    process::Stdio,
+};
+
+use notify_rust::Notification;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{NotificationConfig, NotifyLevel},
+    daemon, vault,
+};
+
+/// A per channel notification level override as stored on disk.
+#[derive(Deserialize, Serialize)]
+struct LevelOverride {
+    guild_id: u64,
+    channel_id: u64,
+    level: NotifyLevel,
+}
+
+/// Loads the per channel notification level overrides from the data directory.
+pub fn load_levels() -> HashMap<(u64, u64), NotifyLevel> {
+    dirs::data_dir()
+        .and_then(|v| vault::read_to_string(&v.join("ilo-toki/notify_levels.json")).ok())
+        .and_then(|v| serde_json::from_str::<Vec<LevelOverride>>(&v).ok())
+        .unwrap_or_default()
+        .into_iter()
+        .map(|v| ((v.guild_id, v.channel_id), v.level))
+        .collect()
+}
+
+/// Saves the per channel notification level overrides to the data directory.
+pub fn save_levels(levels: &HashMap<(u64, u64), NotifyLevel>) {
+    if let Some(data_dir) = dirs::data_dir() {
+        let levels: Vec<_> = levels
+            .iter()
+            .map(|(&(guild_id, channel_id), &level)| LevelOverride { guild_id, channel_id, level })
+            .collect();
+
+        if let Ok(json) = serde_json::to_string(&levels) {
+            std::fs::create_dir(data_dir.join("ilo-toki/")).ok();
+            vault::write(&data_dir.join("ilo-toki/notify_levels.json"), &json).ok();
+        }
+    }
+}
+
+/// Shows a desktop notification if notifications are enabled.
+pub fn notify(config: &NotificationConfig, title: String, body: String) {
+    if !config.enabled {
+        return;
+    }
+
+    // Notification servers can be slow to respond, so don't block the caller
+    tokio::task::spawn_blocking(move || {
+        let _ = Notification::new()
+            .appname("ilo toki")
+            .summary(&title)
+            .body(&body)
+            .show();
+    });
+}
+
+/// Plays a notification sound, either a file through the sound command or the terminal bell.
+/// Failures are ignored, since a missing sound shouldn't get in the way of chatting.
+pub fn play_sound(config: &NotificationConfig, sound: &str) {
+    if sound == "bell" {
+        let mut stdout = daemon::stdout();
+        let _ = stdout.write_all(b"\x07").and_then(|_| stdout.flush());
+        return;
+    }
+
+    let command = config
+        .sound_command
+        .as_deref()
+        .unwrap_or(if cfg!(target_os = "macos") { "afplay" } else { "paplay" });
+    let mut parts = command.split_whitespace();
+    let program = match parts.next() {
+        Some(program) => program,
+        None => return,
+    };
+    let mut args: Vec<_> = parts.map(|v| v.replace("{}", sound)).collect();
+    if !command.contains("{}") {
+        args.push(sound.to_owned());
+    }
+
+    let _ = tokio::process::Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+}
+
+/// Checks whether the given text contains any of the given words, ignoring case.
+/// Words only match on word boundaries, so "art" doesn't match "start".
+pub fn contains_word(text: &str, words: &[String]) -> bool {
+    let text = text.to_lowercase();
+    words.iter().filter(|v| !v.is_empty()).any(|word| {
+        let word = word.to_lowercase();
+        text.match_indices(&word).any(|(i, _)| {
+            let before = text[..i].chars().next_back();
+            let after = text[i + word.len()..].chars().next();
+            !before.map(char::is_alphanumeric).unwrap_or(false) && !after.map(char::is_alphanumeric).unwrap_or(false)
+        })
+    })
+}
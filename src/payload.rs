@@ -0,0 +1,193 @@
+use harmony_rust_sdk::api::chat::{
+    self,
+    content::{AttachmentContent, Content, EmbedContent, TextContent},
+    overrides::Reason,
+    FormattedText,
+};
+use serde::Deserialize;
+
+/// A message written out as JSON for `:raw-send`, for prototyping what a bot would send.
+/// Exactly one of the text, embed, or attachments is given.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Payload {
+    /// The text of a text message.
+    text: Option<String>,
+
+    /// The embed of an embed message.
+    embed: Option<PayloadEmbed>,
+
+    /// The files already uploaded to the homeserver of an attachment message.
+    attachments: Option<Vec<PayloadAttachment>>,
+
+    /// The name and avatar to show instead of the user's, and why.
+    overrides: Option<PayloadOverrides>,
+}
+
+/// An embed written out as JSON.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PayloadEmbed {
+    /// The title of the embed.
+    #[serde(default)]
+    title: String,
+
+    /// The body of the embed.
+    body: Option<String>,
+
+    /// The fields of the embed.
+    #[serde(default)]
+    fields: Vec<PayloadField>,
+}
+
+/// A field of an embed written out as JSON.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PayloadField {
+    /// The title of the field.
+    #[serde(default)]
+    title: String,
+
+    /// The subtitle of the field.
+    subtitle: Option<String>,
+
+    /// The body of the field.
+    body: Option<String>,
+
+    /// The buttons of the field.
+    #[serde(default)]
+    buttons: Vec<PayloadButton>,
+}
+
+/// A button of an embed field written out as JSON.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PayloadButton {
+    /// The text on the button.
+    text: String,
+
+    /// The data sent back to the bot when the button is pressed.
+    #[serde(default)]
+    data: String,
+
+    /// If present, the button opens this url instead of notifying the bot.
+    url: Option<String>,
+}
+
+/// A reference to a file already uploaded to the homeserver, written out as JSON.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PayloadAttachment {
+    /// The id the homeserver gave the file.
+    id: String,
+
+    /// The name of the file.
+    name: String,
+
+    /// The mimetype of the file.
+    #[serde(default = "default_mimetype")]
+    mimetype: String,
+
+    /// The size of the file in bytes, if known.
+    #[serde(default)]
+    size: u32,
+}
+
+/// Message overrides written out as JSON.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PayloadOverrides {
+    /// The name shown instead of the user's.
+    username: Option<String>,
+
+    /// The avatar shown instead of the user's, as a url or file id.
+    avatar: Option<String>,
+
+    /// Why the message is shown as someone else: `user`, `webhook`, `plurality`, `system`, or `bridge`.
+    reason: Option<String>,
+}
+
+/// Gets the mimetype of attachments that don't give one.
+fn default_mimetype() -> String {
+    String::from("application/octet-stream")
+}
+
+/// Parses the reason given for overrides.
+pub fn parse_reason(reason: &str) -> Result<Reason, String> {
+    Ok(match reason {
+        "user" => Reason::UserDefined(Default::default()),
+        "webhook" => Reason::WebhookUsername(Default::default()),
+        "plurality" => Reason::SystemPlurality(Default::default()),
+        "system" => Reason::SystemMessage(Default::default()),
+        "bridge" => Reason::Bridge(Default::default()),
+        _ => return Err(format!("unknown override reason {} (expected user, webhook, plurality, system, or bridge)", reason)),
+    })
+}
+
+/// Builds the content and overrides of a message from its JSON payload.
+pub fn parse(json: &str) -> Result<(chat::Content, Option<chat::Overrides>), String> {
+    let payload: Payload = serde_json::from_str(json).map_err(|e| format!("invalid payload: {}", e))?;
+
+    let content = match (payload.text, payload.embed, payload.attachments) {
+        (Some(text), None, None) => Content::new_text_message(TextContent::new(Some(FormattedText::new(text, vec![])))),
+
+        (None, Some(embed), None) => {
+            let fields = embed
+                .fields
+                .into_iter()
+                .map(|field| chat::EmbedField {
+                    title: field.title,
+                    subtitle: field.subtitle,
+                    body: field.body.map(|v| FormattedText::new(v, vec![])),
+                    actions: field
+                        .buttons
+                        .into_iter()
+                        .map(|button| chat::Action {
+                            kind: Some(chat::action::Kind::Button(chat::action::Button {
+                                text: button.text,
+                                data: button.data.into_bytes(),
+                                url: button.url,
+                            })),
+                            ..Default::default()
+                        })
+                        .collect(),
+                    ..Default::default()
+                })
+                .collect();
+            let embed = chat::Embed {
+                title: embed.title,
+                body: embed.body.map(|v| FormattedText::new(v, vec![])),
+                fields,
+                ..Default::default()
+            };
+            Content::EmbedMessage(EmbedContent { embed: Some(embed) })
+        }
+
+        (None, None, Some(attachments)) => {
+            let files = attachments
+                .into_iter()
+                .map(|v| chat::Attachment {
+                    id: v.id,
+                    name: v.name,
+                    mimetype: v.mimetype,
+                    size: v.size as _,
+                    ..Default::default()
+                })
+                .collect();
+            Content::AttachmentMessage(AttachmentContent { files })
+        }
+
+        _ => return Err(String::from("a payload needs exactly one of text, embed, or attachments")),
+    };
+
+    let overrides = match payload.overrides {
+        Some(overrides) => Some(chat::Overrides {
+            username: overrides.username,
+            avatar: overrides.avatar,
+            reason: Some(parse_reason(overrides.reason.as_deref().unwrap_or("user"))?),
+        }),
+        None => None,
+    };
+
+    Ok((chat::Content::new(Some(content)), overrides))
+}
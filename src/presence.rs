@@ -0,0 +1,196 @@
+use std::{
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use chrono::{Local, NaiveTime};
+use harmony_rust_sdk::client::api::profile::UserStatus;
+use tokio::{
+    sync::{mpsc, RwLock},
+    time::Duration,
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{capabilities::Feature, config::DndConfig, duration, vault, AppState, ClientEvent};
+
+/// How often the rich presence command is run if the configured interval is invalid.
+const RICH_PRESENCE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The shortest interval the rich presence command is run at, so a zero interval can't run it in a busy loop.
+const MIN_RICH_PRESENCE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The longest status text the rich presence command can set, in characters.
+const RICH_PRESENCE_LENGTH: usize = 128;
+
+/// Parses a schedule of the form `23:00-08:00`.
+fn parse_schedule(schedule: &str) -> Option<(NaiveTime, NaiveTime)> {
+    let (start, end) = schedule.split_once('-')?;
+    let start = NaiveTime::parse_from_str(start.trim(), "%H:%M").ok()?;
+    let end = NaiveTime::parse_from_str(end.trim(), "%H:%M").ok()?;
+    Some((start, end))
+}
+
+/// Checks whether the current local time is in the do not disturb schedule.
+fn in_schedule(config: &DndConfig) -> bool {
+    let (start, end) = match config.schedule.as_deref().and_then(parse_schedule) {
+        Some(v) => v,
+        None => return false,
+    };
+
+    let now = Local::now().time();
+    if start <= end {
+        start <= now && now < end
+    } else {
+        // The schedule wraps around midnight
+        now >= start || now < end
+    }
+}
+
+/// Gets the current unix timestamp in seconds.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|v| v.as_secs())
+        .unwrap_or(0)
+}
+
+/// Gets the name of a status as it's shown to the user.
+pub fn status_name(status: UserStatus) -> &'static str {
+    match status {
+        UserStatus::Online => "online",
+        UserStatus::Streaming => "streaming",
+        UserStatus::DoNotDisturb => "do not disturb",
+        UserStatus::Idle => "idle",
+        UserStatus::OfflineUnspecified => "offline",
+    }
+}
+
+/// Loads the user's status text from the data directory, if they set one.
+pub fn load_status_text() -> Option<String> {
+    dirs::data_dir()
+        .and_then(|v| vault::read_to_string(&v.join("ilo-toki/status_text")).ok())
+        .map(|v| v.trim().to_owned())
+        .filter(|v| !v.is_empty())
+}
+
+/// Saves the user's status text to the data directory so it's kept across restarts, or removes it.
+pub fn save_status_text(text: Option<&str>) {
+    if let Some(data_dir) = dirs::data_dir() {
+        let path = data_dir.join("ilo-toki/status_text");
+        match text {
+            Some(text) => {
+                std::fs::create_dir(data_dir.join("ilo-toki/")).ok();
+                vault::write(&path, text).ok();
+            }
+            None => {
+                std::fs::remove_file(path).ok();
+            }
+        }
+    }
+}
+
+/// Background task that keeps the user's status in sync with do not disturb and input activity.
+pub async fn presence_task(state: Arc<RwLock<AppState>>, tx: mpsc::Sender<ClientEvent>, shutdown: CancellationToken) {
+    let mut current = UserStatus::Online;
+
+    while !shutdown.is_cancelled() {
+        let status = {
+            let mut state = state.write().await;
+
+            // Expire manual do not disturb
+            if matches!(state.dnd_until, Some(until) if until <= now()) {
+                state.dnd_until = None;
+            }
+
+            state.dnd_active = state.dnd_until.is_some() || in_schedule(&state.config.dnd);
+            let idle = duration::parse_duration(&state.config.idle.timeout)
+                .map(|v| now().saturating_sub(state.last_input) >= v.as_secs())
+                .unwrap_or(false);
+
+            // Lock the screen after long enough without input, if there's a passphrase to unlock it with
+            let lock = duration::parse_duration(&state.config.lock.timeout)
+                .map(|v| now().saturating_sub(state.last_input) >= v.as_secs())
+                .unwrap_or(false);
+            let has_passphrase = state.lock_passphrase().is_some();
+            if lock && has_passphrase && state.locked.is_none() && state.splash.is_none() {
+                state.lock();
+            }
+
+            if state.dnd_active {
+                UserStatus::DoNotDisturb
+            } else if idle {
+                UserStatus::Idle
+            } else {
+                UserStatus::Online
+            }
+        };
+
+        if status != current {
+            current = status;
+            let _ = tx.send(ClientEvent::SetStatus(status)).await;
+        }
+
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+}
+
+/// Runs the rich presence command, returning the first line it prints, if any.
+async fn run_presence_command(command: &str, timeout: Duration) -> Option<String> {
+    use tokio::process::Command;
+
+    let (shell, flag) = if cfg!(windows) { ("cmd", "/C") } else { ("sh", "-c") };
+    let output = Command::new(shell)
+        .args([flag, command])
+        .stdin(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .kill_on_drop(true)
+        .output();
+    let output = tokio::time::timeout(timeout, output).await.ok()?.ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::trim)
+        .find(|v| !v.is_empty())
+        .map(|v| v.chars().take(RICH_PRESENCE_LENGTH).collect())
+}
+
+/// Background task that sets the user's status text from the output of the rich presence command, such as the
+/// song that's playing. When the command has nothing to say, the status text set with `:status` is used instead.
+pub async fn rich_presence_task(state: Arc<RwLock<AppState>>, tx: mpsc::Sender<ClientEvent>, shutdown: CancellationToken) {
+    while !shutdown.is_cancelled() {
+        let (command, interval) = {
+            let state = state.read().await;
+            let config = &state.config.rich_presence;
+            let interval = duration::parse_duration(&config.interval)
+                .unwrap_or(RICH_PRESENCE_INTERVAL)
+                .max(MIN_RICH_PRESENCE_INTERVAL);
+            (config.command.clone(), interval)
+        };
+
+        // Without a command there's nothing to do, but keep checking in case the config changes
+        let text = match command {
+            Some(command) => run_presence_command(&command, interval).await,
+            None => None,
+        };
+
+        let update = {
+            let mut state = state.write().await;
+            if text != state.presence_text {
+                state.presence_text = text.clone();
+                let text = text.or_else(|| state.status_text.clone());
+                Some(text).filter(|_| state.capabilities.supports(Feature::StatusText))
+            } else {
+                None
+            }
+        };
+
+        if let Some(text) = update {
+            let _ = tx.send(ClientEvent::SetStatusText(text)).await;
+        }
+
+        tokio::time::sleep(interval).await;
+    }
+}
@@ -0,0 +1,98 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant, UNIX_EPOCH},
+};
+
+use chrono::{DateTime, Local};
+use tokio::sync::{mpsc, RwLock};
+use tokio_util::sync::CancellationToken;
+use tui::text::{Spans, Text};
+
+use crate::{backend::ReadReceipt, capabilities::Feature, modal::Modal, AppState, ClientEvent};
+
+/// How often the current channel is checked for messages to send or fetch receipts for.
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long the receipts of the user's newest message are kept before they're fetched again, to see who else has
+/// read it since.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Background task that tells the homeserver what the user has read and fetches who read the user's newest message,
+/// for the channel being viewed. Channels are opened and messages arrive from many places, so this watches for them
+/// instead of each place asking.
+pub async fn receipts_task(state: Arc<RwLock<AppState>>, tx: mpsc::Sender<ClientEvent>, shutdown: CancellationToken) {
+    let mut sent = None;
+    let mut fetched: Option<((u64, u64, u64), Instant)> = None;
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(CHECK_INTERVAL) => (),
+            _ = shutdown.cancelled() => break,
+        }
+
+        let (newest, own) = {
+            let state = state.read().await;
+            if !state.capabilities.supports(Feature::ReadReceipts) {
+                continue;
+            }
+
+            let channel = match state.current_channel() {
+                Some(channel) => channel,
+                None => continue,
+            };
+
+            // Nothing is read while the screen is locked, and nothing is said at all if the user turned receipts off
+            let newest = channel
+                .messages_list
+                .last()
+                .filter(|_| state.config.messages.read_receipts && state.locked.is_none())
+                .map(|&id| (channel.guild_id, channel.id, id));
+            let own = channel
+                .messages_list
+                .iter()
+                .rev()
+                .find(|&&id| channel.messages_map.get(&id).map(|v| v.author_id == state.current_user).unwrap_or(false))
+                .map(|&id| (channel.guild_id, channel.id, id));
+            (newest, own)
+        };
+
+        if newest.is_some() && newest != sent {
+            sent = newest;
+            if let Some((guild_id, channel_id, message_id)) = newest {
+                let _ = tx.send(ClientEvent::SendReadReceipt(guild_id, channel_id, message_id)).await;
+            }
+        }
+
+        let stale = match fetched {
+            Some((message, at)) => Some(message) != own || at.elapsed() >= REFRESH_INTERVAL,
+            None => true,
+        };
+        if let Some((guild_id, channel_id, message_id)) = own.filter(|_| stale) {
+            fetched = Some(((guild_id, channel_id, message_id), Instant::now()));
+            let _ = tx.send(ClientEvent::GetReadReceipts(guild_id, channel_id, message_id, false)).await;
+        }
+    }
+}
+
+/// Counts the users other than the user themselves who have read a message, if its receipts have been fetched.
+pub fn read_by(state: &AppState, receipts: &[ReadReceipt]) -> usize {
+    receipts.iter().filter(|v| v.user_id != state.current_user).count()
+}
+
+/// Creates the popup listing who has read a message and when.
+pub fn readers_popup(state: &AppState, receipts: &[ReadReceipt]) -> Modal {
+    let mut lines = vec![];
+    for receipt in receipts.iter().filter(|v| v.user_id != state.current_user) {
+        let name = state
+            .users
+            .get(&receipt.user_id)
+            .map(|v| v.name.clone())
+            .unwrap_or_else(|| String::from("<unknown user>"));
+        let time: DateTime<Local> = DateTime::from(UNIX_EPOCH + Duration::from_secs(receipt.timestamp));
+        lines.push(Spans::from(format!("{} - {}", name, time.format("%H:%M (%x)"))));
+    }
+    if lines.is_empty() {
+        lines.push(Spans::from("nobody has read this yet"));
+    }
+
+    Modal::info("read by", Text::from(lines))
+}
@@ -0,0 +1,102 @@
+use std::{ops::Range, sync::OnceLock};
+
+use regex::Regex;
+use tui::{buffer::Buffer, layout::Rect, widgets::Widget};
+
+/// What's drawn over each hidden cell.
+const HIDDEN: &str = "•";
+
+/// Matches links to things on a homeserver, the invite in a join command, and ids, which are long numbers.
+/// Only the invite is hidden in a join command, so it's still clear what the text was.
+fn pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| Regex::new(r"(?i)\b(?:hmc|harmony)://\S+|[:/]join\s+(\S+)|\b\d{12,}\b").unwrap())
+}
+
+/// Finds the parts of a line of text to hide in streamer mode: any of the secrets, such as the homeserver's address,
+/// and anything else that identifies the homeserver, an invite, or a user.
+pub fn hidden(text: &str, secrets: &[&str]) -> Vec<Range<usize>> {
+    let mut ranges: Vec<_> = secrets
+        .iter()
+        .filter(|v| !v.is_empty())
+        .flat_map(|secret| text.match_indices(secret).map(|(i, v)| i..i + v.len()))
+        .collect();
+    ranges.extend(
+        pattern()
+            .captures_iter(text)
+            .filter_map(|v| v.get(1).or_else(|| v.get(0)))
+            .map(|v| v.range()),
+    );
+    ranges
+}
+
+/// Blots out what [`hidden`] finds in everything drawn so far, so it's drawn last to cover every pane and popup.
+/// Text wrapped onto another line is matched a line at a time.
+pub struct Redact<'a> {
+    /// Text hidden wherever it appears.
+    secrets: Vec<&'a str>,
+}
+
+impl<'a> Redact<'a> {
+    /// Creates the widget, hiding the given secrets along with links, invites, and ids.
+    pub fn new(secrets: Vec<&'a str>) -> Redact<'a> {
+        Redact { secrets }
+    }
+}
+
+impl Widget for Redact<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        for y in area.top()..area.bottom() {
+            // The row as text, with where each cell starts in it
+            let mut row = String::new();
+            let mut starts = vec![];
+            for x in area.left()..area.right() {
+                starts.push(row.len());
+                row.push_str(&buf.get(x, y).symbol);
+            }
+
+            for range in hidden(&row, &self.secrets) {
+                for (x, start) in (area.left()..area.right()).zip(starts.iter()) {
+                    if range.contains(start) {
+                        buf.get_mut(x, y).set_symbol(HIDDEN);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tui::style::Style;
+
+    /// Gets the parts of the text that would be hidden.
+    fn hidden_text<'a>(text: &'a str, secrets: &[&str]) -> Vec<&'a str> {
+        hidden(text, secrets).into_iter().map(|v| &text[v]).collect()
+    }
+
+    #[test]
+    fn hides_secrets_links_invites_and_ids() {
+        assert_eq!(hidden_text("connected to chat.example.org", &["chat.example.org"]), vec!["chat.example.org"]);
+        assert_eq!(hidden_text("see hmc://chat.example.org/1234 here", &[]), vec!["hmc://chat.example.org/1234"]);
+        assert_eq!(hidden_text("type /join toki-pona to come in", &[]), vec!["toki-pona"]);
+        assert_eq!(hidden_text("user 4815162342108 joined", &[]), vec!["4815162342108"]);
+    }
+
+    #[test]
+    fn leaves_ordinary_text() {
+        assert!(hidden("we're meeting at 18:30 on 2021-12-25, join us", &[""]).is_empty());
+    }
+
+    #[test]
+    fn covers_drawn_cells() {
+        let area = Rect::new(0, 0, 20, 1);
+        let mut buffer = Buffer::empty(area);
+        buffer.set_string(0, 0, "on secret.host now", Style::default());
+        Redact::new(vec!["secret.host"]).render(area, &mut buffer);
+
+        let row: String = (0..20).map(|x| buffer.get(x, 0).symbol.as_str()).collect();
+        assert_eq!(row, format!("on {} now  ", HIDDEN.repeat(11)));
+    }
+}
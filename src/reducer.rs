@@ -0,0 +1,960 @@
+use std::time::Instant;
+
+use harmony_rust_sdk::api::{
+    chat::{self, content::Content, permission::Mode, FormattedText, Message as RawMessage},
+    profile::{self, Profile, UserStatus},
+};
+use tokio::sync::{mpsc, RwLock};
+
+use crate::{
+    convert_formatted_text_to_rich_text, handle_message, handle_user, filters, header, inbox, modal::ModalKind, notify_message, presence, relay, AppMode, AppState,
+    ClientEvent, MessageContent, TYPING_DURATION,
+};
+
+/// When an action is applied, passed in so reducing never reads the clock itself.
+#[derive(Clone, Copy)]
+pub struct Clock {
+    /// The monotonic time, for things that expire such as typing notifications.
+    pub instant: Instant,
+
+    /// The unix timestamp, for things shown with a time such as notices.
+    pub timestamp: u64,
+}
+
+impl Clock {
+    /// Gets the current time.
+    pub fn now() -> Clock {
+        Clock {
+            instant: Instant::now(),
+            timestamp: presence::now(),
+        }
+    }
+}
+
+/// Something an action causes outside the app state, carried out by [`perform`] once the state is updated.
+pub enum Effect {
+    /// Sends a client event, such as fetching something the action referred to.
+    Send(ClientEvent),
+
+    /// Shows a desktop notification and plays a sound for a new message, if the channel's settings ask for them.
+    Notify {
+        guild_id: u64,
+        channel_id: u64,
+        message_id: u64,
+    },
+
+    /// Passes a new message on to relay clients.
+    Relay {
+        guild_id: u64,
+        channel_id: u64,
+        message_id: u64,
+    },
+}
+
+/// A change to the app state caused by something the server sent.
+pub enum Action {
+    /// The user was removed from a guild.
+    GuildRemoved(u64),
+
+    /// A guild was renamed.
+    GuildRenamed {
+        guild_id: u64,
+        name: String,
+    },
+
+    /// Someone joined a guild, or left it if `joined` is false.
+    MemberChanged {
+        guild_id: u64,
+        joined: bool,
+    },
+
+    /// A channel was created or renamed.
+    ChannelUpdated {
+        guild_id: u64,
+        channel_id: u64,
+        name: String,
+    },
+
+    /// A channel was deleted.
+    ChannelDeleted {
+        guild_id: u64,
+        channel_id: u64,
+    },
+
+    /// A role in a guild was created, edited, moved, or deleted.
+    RolesChanged(u64),
+
+    /// The permissions of a role were replaced, in a channel or the whole guild.
+    RolePermissionsChanged {
+        guild_id: u64,
+        channel_id: Option<u64>,
+        role_id: u64,
+        permissions: Vec<(String, bool)>,
+    },
+
+    /// Whether the user has a permission node changed.
+    PermissionChanged {
+        guild_id: u64,
+        channel_id: Option<u64>,
+        node: String,
+        allowed: bool,
+    },
+
+    /// Someone used an action on a message.
+    ActionPerformed {
+        guild_id: u64,
+        channel_id: u64,
+        message_id: u64,
+        user_id: u64,
+    },
+
+    /// A message was sent or replayed.
+    MessageReceived {
+        guild_id: u64,
+        channel_id: u64,
+        message_id: u64,
+        message: RawMessage,
+    },
+
+    /// A message's text was edited.
+    MessageEdited {
+        guild_id: u64,
+        channel_id: u64,
+        message_id: u64,
+        content: FormattedText,
+        edited_at: u64,
+    },
+
+    /// A message was deleted.
+    MessageDeleted {
+        guild_id: u64,
+        channel_id: u64,
+        message_id: u64,
+    },
+
+    /// Someone started typing.
+    Typing {
+        guild_id: u64,
+        channel_id: u64,
+        user_id: u64,
+    },
+
+    /// Someone changed their profile.
+    ProfileUpdated {
+        user_id: u64,
+        username: Option<String>,
+        is_bot: Option<bool>,
+        avatar: Option<String>,
+        status: Option<UserStatus>,
+    },
+
+    /// A user's profile was fetched.
+    UserFetched(u64, Profile),
+
+    /// Someone invited the user to a guild.
+    InviteReceived {
+        invite_id: String,
+        server_id: Option<String>,
+        inviter_id: u64,
+    },
+}
+
+impl Action {
+    /// Describes the action by the ids it affects, without any contents, for the debug log.
+    pub fn describe(&self) -> String {
+        match self {
+            Action::GuildRemoved(guild_id)
+            | Action::RolesChanged(guild_id)
+            | Action::GuildRenamed { guild_id, .. }
+            | Action::MemberChanged { guild_id, .. } => format!("guild={}", guild_id),
+            Action::ChannelUpdated { guild_id, channel_id, .. } | Action::ChannelDeleted { guild_id, channel_id } => {
+                format!("guild={} channel={}", guild_id, channel_id)
+            }
+            Action::RolePermissionsChanged {
+                guild_id,
+                channel_id,
+                role_id,
+                permissions,
+            } => format!("guild={} channel={:?} role={} nodes={}", guild_id, channel_id, role_id, permissions.len()),
+            Action::PermissionChanged {
+                guild_id,
+                channel_id,
+                node,
+                allowed,
+            } => format!("guild={} channel={:?} node={} allowed={}", guild_id, channel_id, node, allowed),
+            Action::ActionPerformed {
+                guild_id,
+                channel_id,
+                message_id,
+                user_id,
+            } => format!("guild={} channel={} message={} user={}", guild_id, channel_id, message_id, user_id),
+            Action::MessageReceived {
+                guild_id,
+                channel_id,
+                message_id,
+                ..
+            }
+            | Action::MessageEdited {
+                guild_id,
+                channel_id,
+                message_id,
+                ..
+            }
+            | Action::MessageDeleted {
+                guild_id,
+                channel_id,
+                message_id,
+            } => format!("guild={} channel={} message={}", guild_id, channel_id, message_id),
+            Action::Typing {
+                guild_id,
+                channel_id,
+                user_id,
+            } => format!("guild={} channel={} user={}", guild_id, channel_id, user_id),
+            Action::ProfileUpdated { user_id, .. } | Action::UserFetched(user_id, _) => format!("user={}", user_id),
+            Action::InviteReceived { inviter_id, .. } => format!("inviter={}", inviter_id),
+        }
+    }
+
+    /// Converts an event from the event stream into an action, if it changes anything.
+    pub fn from_event(event: chat::Event) -> Option<Action> {
+        match event {
+            chat::Event::Chat(event) => match event {
+                chat::stream_event::Event::GuildRemovedFromList(guild) => Some(Action::GuildRemoved(guild.guild_id)),
+
+                chat::stream_event::Event::EditedGuild(guild) => guild.new_name.map(|v| Action::GuildRenamed {
+                    guild_id: guild.guild_id,
+                    name: v,
+                }),
+
+                chat::stream_event::Event::JoinedMember(member) => Some(Action::MemberChanged {
+                    guild_id: member.guild_id,
+                    joined: true,
+                }),
+
+                chat::stream_event::Event::LeftMember(member) => Some(Action::MemberChanged {
+                    guild_id: member.guild_id,
+                    joined: false,
+                }),
+
+                chat::stream_event::Event::CreatedChannel(channel) => Some(Action::ChannelUpdated {
+                    guild_id: channel.guild_id,
+                    channel_id: channel.channel_id,
+                    name: channel.name,
+                }),
+
+                chat::stream_event::Event::EditedChannel(channel) => channel.new_name.map(|v| Action::ChannelUpdated {
+                    guild_id: channel.guild_id,
+                    channel_id: channel.channel_id,
+                    name: v,
+                }),
+
+                chat::stream_event::Event::DeletedChannel(channel) => Some(Action::ChannelDeleted {
+                    guild_id: channel.guild_id,
+                    channel_id: channel.channel_id,
+                }),
+
+                chat::stream_event::Event::RoleCreated(role) => Some(Action::RolesChanged(role.guild_id)),
+                chat::stream_event::Event::RoleUpdated(role) => Some(Action::RolesChanged(role.guild_id)),
+                chat::stream_event::Event::RoleMoved(role) => Some(Action::RolesChanged(role.guild_id)),
+                chat::stream_event::Event::RoleDeleted(role) => Some(Action::RolesChanged(role.guild_id)),
+
+                chat::stream_event::Event::RolePermsUpdated(perms) => Some(Action::RolePermissionsChanged {
+                    guild_id: perms.guild_id,
+                    channel_id: perms.channel_id,
+                    role_id: perms.role_id,
+                    permissions: perms.new_perms.into_iter().map(|v| (v.matches, v.mode != Mode::Deny as i32)).collect(),
+                }),
+
+                chat::stream_event::Event::PermissionUpdated(permission) => Some(Action::PermissionChanged {
+                    guild_id: permission.guild_id,
+                    channel_id: permission.channel_id,
+                    node: permission.query,
+                    allowed: permission.ok,
+                }),
+
+                chat::stream_event::Event::ActionPerformed(action) => Some(Action::ActionPerformed {
+                    guild_id: action.guild_id,
+                    channel_id: action.channel_id,
+                    message_id: action.message_id,
+                    user_id: action.user_id,
+                }),
+
+                chat::stream_event::Event::SentMessage(message) => message.message.map(|v| Action::MessageReceived {
+                    guild_id: message.guild_id,
+                    channel_id: message.channel_id,
+                    message_id: message.message_id,
+                    message: v,
+                }),
+
+                chat::stream_event::Event::EditedMessage(message) => message.new_content.map(|v| Action::MessageEdited {
+                    guild_id: message.guild_id,
+                    channel_id: message.channel_id,
+                    message_id: message.message_id,
+                    content: v,
+                    edited_at: message.edited_at,
+                }),
+
+                chat::stream_event::Event::DeletedMessage(message) => Some(Action::MessageDeleted {
+                    guild_id: message.guild_id,
+                    channel_id: message.channel_id,
+                    message_id: message.message_id,
+                }),
+
+                chat::stream_event::Event::Typing(typing) => Some(Action::Typing {
+                    guild_id: typing.guild_id,
+                    channel_id: typing.channel_id,
+                    user_id: typing.user_id,
+                }),
+
+                chat::stream_event::Event::InviteReceived(invite) => Some(Action::InviteReceived {
+                    invite_id: invite.invite_id,
+                    server_id: invite.server_id,
+                    inviter_id: invite.inviter_id,
+                }),
+
+                // TODO: the rest of the chat events
+                _ => None,
+            },
+
+            chat::Event::Profile(profile::stream_event::Event::ProfileUpdated(profile)) => Some(Action::ProfileUpdated {
+                user_id: profile.user_id,
+                username: profile.new_username,
+                is_bot: profile.new_is_bot,
+                avatar: profile.new_avatar,
+                status: profile.new_status.and_then(UserStatus::from_i32),
+            }),
+
+            // TODO
+            chat::Event::Emote(_) => None,
+        }
+    }
+}
+
+/// Applies an action to the state, returning the effects it has outside the state.
+/// This is the only place server events change the state. It doesn't notify, relay, send, or read the clock itself, so
+/// the same actions at the same times always produce the same state and effects.
+pub fn reduce(state: &mut AppState, action: Action, clock: Clock) -> Vec<Effect> {
+    let mut effects = vec![];
+
+    match action {
+        // Forget the guild along with its channels and messages, and deselect it
+        Action::GuildRemoved(guild_id) => {
+            state.guilds_map.remove(&guild_id);
+            state.subscribed_guilds.remove(&guild_id);
+            if state.current_guild == Some(guild_id) {
+                state.current_guild = None;
+                state.thread = None;
+                if let AppMode::ChannelSelect = state.mode {
+                    state.mode = AppMode::GuildSelect;
+                }
+            }
+
+            if state.pending_default == Some(guild_id) {
+                state.pending_default = None;
+            }
+
+            if let Some(i) = state.guilds_list.iter().position(|&v| v == guild_id) {
+                state.guilds_list.remove(i);
+                state.guilds_select = match state.guilds_select {
+                    Some(v) if v == i => None,
+                    Some(v) if v > i => Some(v - 1),
+                    v => v,
+                };
+            }
+
+            // Channels in the guild can't be jumped back to
+            let current = state.jump_list.get(state.jump_index).copied();
+            state.jump_list.retain(|&(v, _)| v != guild_id);
+            state.jump_index = current
+                .and_then(|current| state.jump_list.iter().position(|&v| v == current))
+                .unwrap_or_else(|| state.jump_list.len().saturating_sub(1));
+
+            state.modals.retain(|v| !matches!(&v.kind, ModalKind::Roles(editor) if editor.guild_id == guild_id));
+        }
+
+        Action::GuildRenamed { guild_id, name } => {
+            if let Some(guild) = state.guilds_map.get_mut(&guild_id) {
+                guild.name = name;
+            }
+        }
+
+        // Keep the member count in the header current once it's known
+        Action::MemberChanged { guild_id, joined } => {
+            if let Some(count) = state.guilds_map.get_mut(&guild_id).and_then(|v| v.member_count.as_mut()) {
+                *count = if joined { *count + 1 } else { count.saturating_sub(1) };
+            }
+        }
+
+        // Add the channel, or rename it if it's already known
+        Action::ChannelUpdated {
+            guild_id,
+            channel_id,
+            name,
+        } => {
+            if let Some(guild) = state.guilds_map.get_mut(&guild_id) {
+                guild.add_channel(channel_id, name);
+            }
+        }
+
+        // Forget the channel and deselect it
+        Action::ChannelDeleted { guild_id, channel_id } => {
+            if let Some(guild) = state.guilds_map.get_mut(&guild_id) {
+                guild.remove_channel(channel_id);
+            }
+        }
+
+        // Refresh the roles if they're being edited
+        Action::RolesChanged(guild_id) => {
+            if state.role_editor_mut(guild_id).is_some() {
+                effects.push(Effect::Send(ClientEvent::GetRoles(guild_id)));
+            }
+        }
+
+        Action::RolePermissionsChanged {
+            guild_id,
+            channel_id,
+            role_id,
+            permissions,
+        } => {
+            if let Some(editor) = state.role_editor_mut(guild_id) {
+                editor.set_permissions(role_id, channel_id, permissions);
+            }
+        }
+
+        // Update the header, and close the role editor if the user can't manage roles anymore
+        Action::PermissionChanged {
+            guild_id,
+            channel_id,
+            node,
+            allowed,
+        } => {
+            if let (Some(channel_id), Some(&(node, _))) = (channel_id, header::NODES.iter().find(|v| v.0 == node)) {
+                if let Some(guild) = state.guilds_map.get_mut(&guild_id) {
+                    guild.permissions.insert((channel_id, node), allowed);
+                }
+            }
+
+            if node == "roles.manage" && channel_id.is_none() && !allowed && state.role_editor_mut(guild_id).is_some() {
+                state.modals.retain(|v| !matches!(&v.kind, ModalKind::Roles(editor) if editor.guild_id == guild_id));
+                state.status = Some(String::from("you can no longer manage roles here"));
+            }
+        }
+
+        // Show who used an action as a notice
+        Action::ActionPerformed {
+            guild_id,
+            channel_id,
+            message_id,
+            user_id,
+        } => {
+            let user = state
+                .users
+                .get(&user_id)
+                .map(|v| v.name.clone())
+                .unwrap_or_else(|| String::from("<unknown user>"));
+            let target = state
+                .guilds_map
+                .get(&guild_id)
+                .and_then(|v| v.channels_map.get(&channel_id))
+                .and_then(|v| v.messages_map.get(&message_id))
+                .and_then(|v| match &v.content {
+                    MessageContent::Text(text) => Some(text.contents.chars().take(40).collect::<String>()),
+                    MessageContent::Embed(embed) => Some(embed.title.chars().take(40).collect::<String>()),
+                    MessageContent::Files(_) | MessageContent::Notice(_) => None,
+                });
+
+            let notice = match target {
+                Some(target) => format!("{} used an action on \"{}\"", user, target),
+                None => format!("{} used an action", user),
+            };
+            state.push_notice(guild_id, channel_id, notice, clock.timestamp);
+        }
+
+        // Add the message, notifying about it unless it was replayed after reconnecting
+        Action::MessageReceived {
+            guild_id,
+            channel_id,
+            message_id,
+            message,
+        } => {
+            let replayed = state
+                .get_channel_mut(guild_id, channel_id)
+                .map(|v| v.messages_map.contains_key(&message_id))
+                .unwrap_or(false);
+            if !replayed {
+                state.record_activity(guild_id, channel_id, message.created_at, message.author_id);
+            }
+
+            let upgraded = matches!(message.content.as_ref().and_then(|v| v.content.as_ref()), Some(Content::RoomUpgradedToGuild(_)));
+            let unknown_author = handle_message(state, message, guild_id, channel_id, message_id);
+
+            if !replayed {
+                effects.push(Effect::Notify {
+                    guild_id,
+                    channel_id,
+                    message_id,
+                });
+                effects.push(Effect::Relay {
+                    guild_id,
+                    channel_id,
+                    message_id,
+                });
+                inbox::message_received(state, guild_id, channel_id, message_id);
+                filters::message_received(state, guild_id, channel_id, message_id);
+                state.stats.record_received();
+            }
+
+            // The room became a guild, so its info and channels may have changed
+            if upgraded {
+                effects.push(Effect::Send(ClientEvent::RefreshGuild(guild_id)));
+            }
+
+            if let Some(author_id) = unknown_author {
+                effects.push(Effect::Send(ClientEvent::GetUser(author_id)));
+            }
+        }
+
+        // Replace the text of the message
+        Action::MessageEdited {
+            guild_id,
+            channel_id,
+            message_id,
+            content,
+            edited_at,
+        } => {
+            let theme = state.theme;
+            let current_user = state.current_user;
+            let highlighted = state.is_highlighted(guild_id, &content);
+
+            if let Some(message) = state
+                .get_channel_mut(guild_id, channel_id)
+                .and_then(|v| v.messages_map.get_mut(&message_id))
+            {
+                // TODO: more patterns
+                if let MessageContent::Text(_) = message.content {
+                    message.content = MessageContent::Text(convert_formatted_text_to_rich_text(content, &theme));
+                    message.edited_timestamp = Some(edited_at);
+                    message.translation = None;
+                    message.highlighted = highlighted && message.author_id != current_user;
+                }
+            }
+        }
+
+        // Remove the message, moving the selection to the message after it if it was selected
+        Action::MessageDeleted {
+            guild_id,
+            channel_id,
+            message_id,
+        } => {
+            if let Some(channel) = state.get_channel_mut(guild_id, channel_id) {
+                channel.messages_map.remove(&message_id);
+                if let Some(i) = channel.messages_list.iter().position(|&v| v == message_id) {
+                    channel.messages_list.remove(i);
+                    if channel.selected == Some(message_id) {
+                        channel.selected = None;
+                        if let Some(&next) = channel.messages_list.get(i) {
+                            channel.select(next);
+                        }
+                    }
+                }
+            }
+        }
+
+        // Show that the user is typing until the notification expires
+        Action::Typing {
+            guild_id,
+            channel_id,
+            user_id,
+        } => {
+            if user_id != state.current_user {
+                if let Some(channel) = state.get_channel_mut(guild_id, channel_id) {
+                    channel.typing.retain(|_, expiry| *expiry > clock.instant);
+                    channel.typing.insert(user_id, clock.instant + TYPING_DURATION);
+                }
+            }
+        }
+
+        // Update the known user, and the online list if it's showing them
+        Action::ProfileUpdated {
+            user_id,
+            username,
+            is_bot,
+            avatar,
+            status,
+        } => {
+            if let Some(user) = state.users.get_mut(&user_id) {
+                if let Some(username) = username {
+                    user.name = username;
+                }
+
+                if let Some(is_bot) = is_bot {
+                    user.is_bot = is_bot;
+                }
+
+                if let Some(avatar) = avatar {
+                    user.avatar = Some(avatar).filter(|v| !v.is_empty());
+                }
+
+                if let Some(status) = status {
+                    user.status = status;
+                }
+            }
+
+            state.refresh_online();
+        }
+
+        // Remember the user
+        Action::UserFetched(user_id, profile) => {
+            handle_user(state, user_id, profile);
+            state.refresh_online();
+        }
+
+        // Keep the invite in the inbox until it's looked at, fetching who sent it if they're unknown
+        Action::InviteReceived {
+            invite_id,
+            server_id,
+            inviter_id,
+        } => {
+            if !state.users.contains_key(&inviter_id) {
+                effects.push(Effect::Send(ClientEvent::GetUser(inviter_id)));
+            }
+            inbox::invite_received(state, invite_id, server_id, inviter_id, clock.timestamp);
+            state.status = Some(String::from("new invite in :inbox"));
+        }
+    }
+
+    effects
+}
+
+/// Carries out the effects of applying an action, after the state has been updated.
+pub async fn perform(state: &RwLock<AppState>, tx: &mpsc::Sender<ClientEvent>, effects: Vec<Effect>) {
+    for effect in effects {
+        match effect {
+            Effect::Send(event) => {
+                let _ = tx.send(event).await;
+            }
+
+            Effect::Notify {
+                guild_id,
+                channel_id,
+                message_id,
+            } => notify_message(&*state.read().await, guild_id, channel_id, message_id),
+
+            Effect::Relay {
+                guild_id,
+                channel_id,
+                message_id,
+            } => relay::message_received(&*state.read().await, guild_id, channel_id, message_id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::Write;
+
+    use harmony_rust_sdk::api::chat::{self, content::TextContent};
+
+    use super::*;
+    use crate::{bench, message_snippet};
+
+    const GUILD: u64 = 1;
+    const CHANNEL: u64 = 2;
+    const ME: u64 = 100;
+
+    /// The unix timestamp the actions in the tests happen at.
+    const NOW: u64 = 1000;
+
+    fn state() -> AppState {
+        let mut state = bench::channel_state(GUILD, CHANNEL);
+        state.current_user = ME;
+        state
+    }
+
+    fn text(text: &str) -> FormattedText {
+        FormattedText::new(String::from(text), vec![])
+    }
+
+    fn received(channel_id: u64, message_id: u64, author_id: u64, created_at: u64, contents: &str) -> Action {
+        Action::MessageReceived {
+            guild_id: GUILD,
+            channel_id,
+            message_id,
+            message: RawMessage {
+                author_id,
+                created_at,
+                content: Some(chat::Content::new(Some(Content::new_text_message(TextContent::new(Some(text(contents))))))),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Applies the actions in order at the same time, collecting the effects they cause.
+    fn run(state: &mut AppState, actions: Vec<Action>) -> Vec<Effect> {
+        let clock = Clock {
+            instant: Instant::now(),
+            timestamp: NOW,
+        };
+        actions.into_iter().flat_map(|action| reduce(state, action, clock)).collect()
+    }
+
+    /// Describes everything the reducer can change, in a stable order.
+    fn snapshot(state: &AppState, effects: &[Effect]) -> String {
+        let mut out = String::new();
+        for guild_id in &state.guilds_list {
+            let guild = &state.guilds_map[guild_id];
+            let mut unseen: Vec<_> = guild.unseen_channels.iter().collect();
+            unseen.sort();
+            writeln!(out, "guild {} {:?} members={:?} unseen={:?}", guild.id, guild.name, guild.member_count, unseen).unwrap();
+
+            for channel_id in &guild.channels_list {
+                let channel = &guild.channels_map[channel_id];
+                writeln!(
+                    out,
+                    "  channel {} {:?} activity={} unread={:?} typing={}",
+                    channel.id,
+                    channel.name,
+                    channel.last_activity,
+                    channel.first_unread,
+                    channel.typing.len()
+                )
+                .unwrap();
+
+                for message_id in &channel.messages_list {
+                    let message = &channel.messages_map[message_id];
+                    let edited = if message.edited_timestamp.is_some() { " (edited)" } else { "" };
+                    writeln!(
+                        out,
+                        "    {} @{} by {}{}: {}",
+                        message.id,
+                        message.timestamp,
+                        message.author_id,
+                        edited,
+                        message_snippet(message, usize::MAX)
+                    )
+                    .unwrap();
+                }
+            }
+        }
+
+        writeln!(out, "current guild={:?} select={:?}", state.current_guild, state.guilds_select).unwrap();
+        for effect in effects {
+            let effect = match effect {
+                Effect::Send(ClientEvent::GetUser(user_id)) => format!("get user {}", user_id),
+                Effect::Send(ClientEvent::RefreshGuild(guild_id)) => format!("refresh guild {}", guild_id),
+                Effect::Send(ClientEvent::GetRoles(guild_id)) => format!("get roles {}", guild_id),
+                Effect::Send(_) => String::from("other"),
+                Effect::Notify { channel_id, message_id, .. } => format!("notify {}/{}", channel_id, message_id),
+                Effect::Relay { channel_id, message_id, .. } => format!("relay {}/{}", channel_id, message_id),
+            };
+            writeln!(out, "effect {}", effect).unwrap();
+        }
+        writeln!(out, "status {:?}", state.status).unwrap();
+        out
+    }
+
+    #[test]
+    fn message_lifecycle() {
+        let mut state = state();
+        let effects = run(&mut state, vec![
+            received(CHANNEL, 11, 5, 20, "second"),
+            received(CHANNEL, 10, 5, 10, "first"),
+            received(CHANNEL, 11, 5, 20, "second"),
+            Action::MessageEdited {
+                guild_id: GUILD,
+                channel_id: CHANNEL,
+                message_id: 10,
+                content: text("first, edited"),
+                edited_at: 30,
+            },
+            Action::MessageDeleted {
+                guild_id: GUILD,
+                channel_id: CHANNEL,
+                message_id: 11,
+            },
+            received(CHANNEL, 12, ME, 40, "mine"),
+        ]);
+
+        assert_eq!(
+            snapshot(&state, &effects),
+            "\
+guild 1 \"bench\" members=None unseen=[]
+  channel 2 \"bench\" activity=40 unread=None typing=0
+    10 @10 by 5 (edited): first, edited
+    12 @40 by 100: mine
+current guild=Some(1) select=Some(0)
+effect notify 2/11
+effect relay 2/11
+effect get user 5
+effect notify 2/10
+effect relay 2/10
+effect get user 5
+effect get user 5
+effect notify 2/12
+effect relay 2/12
+effect get user 100
+status None
+"
+        );
+    }
+
+    #[test]
+    fn replayed_messages_are_not_activity() {
+        let mut state = state();
+        state.guilds_map.get_mut(&GUILD).unwrap().current_channel = None;
+        run(&mut state, vec![received(CHANNEL, 10, 5, 10, "first")]);
+        state.guilds_map.get_mut(&GUILD).unwrap().unseen_channels.clear();
+        let effects = run(&mut state, vec![received(CHANNEL, 10, 5, 10, "first")]);
+
+        assert_eq!(
+            snapshot(&state, &effects),
+            "\
+guild 1 \"bench\" members=None unseen=[]
+  channel 2 \"bench\" activity=10 unread=Some(10) typing=0
+    10 @10 by 5: first
+current guild=Some(1) select=Some(0)
+effect get user 5
+status None
+"
+        );
+    }
+
+    #[test]
+    fn channels_members_and_typing() {
+        let mut state = state();
+        state.guilds_map.get_mut(&GUILD).unwrap().member_count = Some(3);
+        let effects = run(&mut state, vec![
+            Action::ChannelUpdated {
+                guild_id: GUILD,
+                channel_id: 3,
+                name: String::from("general"),
+            },
+            Action::ChannelUpdated {
+                guild_id: GUILD,
+                channel_id: CHANNEL,
+                name: String::from("renamed"),
+            },
+            received(3, 20, 5, 50, "hello"),
+            Action::MemberChanged {
+                guild_id: GUILD,
+                joined: true,
+            },
+            Action::MemberChanged {
+                guild_id: GUILD,
+                joined: false,
+            },
+            Action::MemberChanged {
+                guild_id: GUILD,
+                joined: false,
+            },
+            Action::Typing {
+                guild_id: GUILD,
+                channel_id: 3,
+                user_id: 5,
+            },
+            Action::Typing {
+                guild_id: GUILD,
+                channel_id: 3,
+                user_id: ME,
+            },
+            Action::GuildRenamed {
+                guild_id: GUILD,
+                name: String::from("renamed guild"),
+            },
+        ]);
+
+        assert_eq!(
+            snapshot(&state, &effects),
+            "\
+guild 1 \"renamed guild\" members=Some(2) unseen=[3]
+  channel 2 \"renamed\" activity=0 unread=None typing=0
+  channel 3 \"general\" activity=50 unread=Some(50) typing=1
+    20 @50 by 5: hello
+current guild=Some(1) select=Some(0)
+effect notify 3/20
+effect relay 3/20
+effect get user 5
+status None
+"
+        );
+
+        let effects = run(&mut state, vec![
+            Action::ChannelDeleted {
+                guild_id: GUILD,
+                channel_id: CHANNEL,
+            },
+            Action::ChannelDeleted {
+                guild_id: GUILD,
+                channel_id: 3,
+            },
+        ]);
+        let guild = &state.guilds_map[&GUILD];
+        assert_eq!((guild.current_channel, guild.channels_select), (None, None));
+        assert_eq!(
+            snapshot(&state, &effects),
+            "\
+guild 1 \"renamed guild\" members=Some(2) unseen=[]
+current guild=Some(1) select=Some(0)
+status None
+"
+        );
+    }
+
+    #[test]
+    fn notices_use_the_clock() {
+        let mut state = state();
+        let effects = run(&mut state, vec![
+            received(CHANNEL, 10, ME, 10, "a button"),
+            Action::ActionPerformed {
+                guild_id: GUILD,
+                channel_id: CHANNEL,
+                message_id: 10,
+                user_id: 5,
+            },
+        ]);
+        let notice = u64::MAX - 1;
+
+        assert_eq!(
+            snapshot(&state, &effects),
+            format!(
+                "\
+guild 1 \"bench\" members=None unseen=[]
+  channel 2 \"bench\" activity=10 unread=None typing=0
+    10 @10 by 100: a button
+    {} @1000 by 0: <unknown user> used an action on \"a button\"
+current guild=Some(1) select=Some(0)
+effect notify 2/10
+effect relay 2/10
+effect get user 100
+status None
+",
+                notice
+            )
+        );
+    }
+
+    #[test]
+    fn guild_removed() {
+        let mut state = state();
+        state.jump_list = vec![(GUILD, CHANNEL)];
+        let effects = run(&mut state, vec![
+            Action::GuildRemoved(GUILD),
+            Action::InviteReceived {
+                invite_id: String::from("abc"),
+                server_id: None,
+                inviter_id: 9,
+            },
+        ]);
+
+        assert_eq!((state.jump_list.len(), state.jump_index), (0, 0));
+        assert_eq!(state.inbox.newest_first().map(|v| v.timestamp).collect::<Vec<_>>(), vec![NOW]);
+        assert_eq!(
+            snapshot(&state, &effects),
+            "\
+current guild=None select=None
+effect get user 9
+status Some(\"new invite in :inbox\")
+"
+        );
+    }
+}
@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+
+use crate::clipboard;
+
+/// The register every yank and delete goes to, and that pasting uses when no other is named.
+pub const UNNAMED: char = '"';
+
+/// The register that copies to the system clipboard. The terminal can't be asked for the clipboard, so pasting from
+/// it gives back what was last copied from here.
+pub const CLIPBOARD: char = '+';
+
+/// The read-only register holding the last command run, which `@:` runs again.
+pub const COMMAND: char = ':';
+
+/// What the next key names a register for, after `"`, `@`, or ctrl+r.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RegisterPrompt {
+    /// Use the register for the next yank, paste, or delete.
+    Select,
+
+    /// Paste the register at the cursor straight away.
+    Insert,
+
+    /// Run the register as a command, which only the last command in `:` can be.
+    Execute,
+}
+
+/// Text yanked from messages or deleted from the input box and command prompt, like vim's registers.
+#[derive(Default)]
+pub struct Registers {
+    /// The text in each register, keyed by its lowercase name.
+    text: HashMap<char, String>,
+}
+
+/// Checks whether a character names a register: a letter (uppercase adds to the register), `"`, or `+`.
+pub fn is_register(name: char) -> bool {
+    name.is_ascii_alphabetic() || name == UNNAMED || name == CLIPBOARD
+}
+
+impl Registers {
+    /// Saves text to the named register, or only the unnamed one if none is named.
+    /// The unnamed register always ends up with the whole register's text, as in vim.
+    pub fn set(&mut self, name: Option<char>, text: String) {
+        let text = match name {
+            // Uppercase names add to the end of the register
+            Some(name) if name.is_ascii_uppercase() => {
+                let register = self.text.entry(name.to_ascii_lowercase()).or_default();
+                register.push_str(&text);
+                register.clone()
+            }
+
+            // The clipboard register is kept too, for pasting back
+            Some(CLIPBOARD) => {
+                let _ = clipboard::copy(&text);
+                self.text.insert(CLIPBOARD, text.clone());
+                text
+            }
+
+            Some(name) if name != UNNAMED => {
+                self.text.insert(name, text.clone());
+                text
+            }
+
+            _ => text,
+        };
+
+        self.text.insert(UNNAMED, text);
+    }
+
+    /// Saves a command that was run to the `:` register.
+    pub fn set_command(&mut self, command: String) {
+        self.text.insert(COMMAND, command);
+    }
+
+    /// Gets the text in the named register, or the unnamed one if none is named.
+    pub fn get(&self, name: Option<char>) -> Option<&str> {
+        let name = name.map(|v| v.to_ascii_lowercase()).unwrap_or(UNNAMED);
+        self.text.get(&name).map(String::as_str)
+    }
+}
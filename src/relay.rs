@@ -0,0 +1,506 @@
+use std::{
+    sync::{Arc, OnceLock},
+    time::{Duration, Instant},
+};
+
+use futures_util::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::{broadcast, mpsc, Mutex, RwLock},
+};
+use tokio_tungstenite::tungstenite::{
+    handshake::server::{ErrorResponse, Request as Handshake, Response as HandshakeResponse},
+    http::StatusCode,
+    Message as WsMessage,
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{message_author, message_snippet, AppState, ClientEvent, Message};
+
+/// How many pushed events are kept for a frontend that's slow to read them before it misses some.
+const EVENT_BUFFER: usize = 256;
+
+/// The most messages sent for one `messages` request.
+const MAX_MESSAGES: usize = 200;
+
+/// How many wrong passwords can be sent, over every connection, before the relay stops checking them for a while.
+const MAX_AUTH_FAILURES: u32 = 5;
+
+/// How long the first wrong password is answered after, doubling with each one after it.
+const AUTH_BACKOFF: Duration = Duration::from_millis(500);
+
+/// How long passwords aren't checked for after too many wrong ones.
+const AUTH_LOCKOUT: Duration = Duration::from_secs(60);
+
+/// How long a connection has to finish the websocket handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a connection has to authenticate before it's closed.
+const AUTH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Events pushed to every authenticated frontend as JSON, once the relay is running.
+static EVENTS: OnceLock<broadcast::Sender<String>> = OnceLock::new();
+
+/// Wrong passwords sent to the relay. Shared by every connection, so reconnecting or guessing over several connections
+/// at once doesn't get around the backoff.
+#[derive(Default)]
+struct Guesses {
+    /// How many wrong passwords were sent since the last right one.
+    failures: u32,
+
+    /// When the last wrong password was sent.
+    last_failure: Option<Instant>,
+}
+
+impl Guesses {
+    /// Whether too many wrong passwords were sent recently for any more to be checked.
+    fn locked_out(&self) -> bool {
+        self.failures >= MAX_AUTH_FAILURES && self.last_failure.map_or(false, |v| v.elapsed() < AUTH_LOCKOUT)
+    }
+
+    /// How long to wait before answering the next wrong password.
+    fn backoff(&self) -> Duration {
+        AUTH_BACKOFF * 2u32.pow(self.failures.min(MAX_AUTH_FAILURES))
+    }
+}
+
+/// A guild, channel, message, or user id. Ids are sent as strings, since they don't fit in a JavaScript number, but
+/// numbers are accepted too.
+#[derive(Clone, Copy, Deserialize, Serialize)]
+#[serde(try_from = "RawId", into = "String")]
+struct Id(u64);
+
+/// An id as it arrives, before it's parsed.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawId {
+    /// An id sent as a number.
+    Number(u64),
+
+    /// An id sent as a string.
+    Text(String),
+}
+
+impl TryFrom<RawId> for Id {
+    type Error = std::num::ParseIntError;
+
+    fn try_from(raw: RawId) -> Result<Id, Self::Error> {
+        match raw {
+            RawId::Number(id) => Ok(Id(id)),
+            RawId::Text(id) => id.parse().map(Id),
+        }
+    }
+}
+
+impl From<Id> for String {
+    fn from(id: Id) -> String {
+        id.0.to_string()
+    }
+}
+
+/// A request from a frontend. Everything but `auth` is refused until the frontend has authenticated.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+enum Request {
+    /// Authenticates with the relay password.
+    Auth { password: String },
+
+    /// Lists the guilds the user is in.
+    Guilds,
+
+    /// Lists the channels of a guild.
+    Channels { guild_id: Id },
+
+    /// Gets the loaded messages of a channel, newest last, optionally only those before a message.
+    Messages {
+        guild_id: Id,
+        channel_id: Id,
+        before: Option<Id>,
+        limit: Option<usize>,
+    },
+
+    /// Sends a text message to a channel.
+    Send { guild_id: Id, channel_id: Id, text: String },
+}
+
+/// A guild as the relay describes it.
+#[derive(Serialize)]
+struct GuildInfo {
+    /// The id of the guild.
+    id: Id,
+
+    /// The name of the guild.
+    name: String,
+
+    /// How many of its channels have activity the user hasn't seen.
+    unseen_channels: usize,
+}
+
+/// A channel as the relay describes it.
+#[derive(Serialize)]
+struct ChannelInfo {
+    /// The id of the channel.
+    id: Id,
+
+    /// The name of the channel.
+    name: String,
+
+    /// Whether nothing can be sent to it this session.
+    read_only: bool,
+}
+
+/// A message as the relay describes it, with its contents as plain text.
+#[derive(Serialize)]
+struct MessageInfo {
+    /// The id of the message.
+    id: Id,
+
+    /// The user id of the author.
+    author_id: Id,
+
+    /// The name shown for the author.
+    author: String,
+
+    /// The contents of the message as plain text.
+    text: String,
+
+    /// The unix timestamp the message was sent at.
+    timestamp: u64,
+
+    /// The unix timestamp the message was last edited at, if it was.
+    edited_timestamp: Option<u64>,
+}
+
+/// A reply or pushed event sent to a frontend.
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+enum Response {
+    /// The password was right.
+    Authenticated,
+
+    /// A request failed.
+    Error { message: String },
+
+    /// The guilds the user is in, in the order shown in the sidebar.
+    Guilds { guilds: Vec<GuildInfo> },
+
+    /// The channels of a guild.
+    Channels { guild_id: Id, channels: Vec<ChannelInfo> },
+
+    /// Messages of a channel, oldest first.
+    Messages {
+        guild_id: Id,
+        channel_id: Id,
+        messages: Vec<MessageInfo>,
+    },
+
+    /// A message was handed to the client to send.
+    Sent,
+
+    /// A new message arrived, pushed without being asked for.
+    Message {
+        guild_id: Id,
+        channel_id: Id,
+        message: MessageInfo,
+    },
+}
+
+impl Response {
+    /// Creates an error reply.
+    fn error(message: impl Into<String>) -> Response {
+        Response::Error { message: message.into() }
+    }
+
+    /// Serializes the reply as it's sent.
+    fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+/// Describes a message for frontends.
+fn message_info(state: &AppState, message: &Message) -> MessageInfo {
+    MessageInfo {
+        id: Id(message.id),
+        author_id: Id(message.author_id),
+        author: message_author(state, message),
+        text: message_snippet(message, usize::MAX),
+        timestamp: message.timestamp,
+        edited_timestamp: message.edited_timestamp,
+    }
+}
+
+/// Pushes a message that just arrived to the frontends, if the relay is running.
+pub fn message_received(state: &AppState, guild_id: u64, channel_id: u64, message_id: u64) {
+    let (events, message) = match (EVENTS.get(), state.get_channel(guild_id, channel_id)) {
+        (Some(events), Some(channel)) => match channel.messages_map.get(&message_id) {
+            Some(message) => (events, message),
+            None => return,
+        },
+        _ => return,
+    };
+
+    let event = Response::Message {
+        guild_id: Id(guild_id),
+        channel_id: Id(channel_id),
+        message: message_info(state, message),
+    };
+    let _ = events.send(event.to_json());
+}
+
+/// Checks a password against the configured one, comparing hashes so the time taken doesn't depend on how much of it
+/// was right.
+fn password_matches(given: &str, expected: &str) -> bool {
+    Sha256::digest(given.as_bytes()) == Sha256::digest(expected.as_bytes())
+}
+
+/// Checks whether every address the given listen address resolves to is a loopback one.
+pub async fn is_loopback(listen: &str) -> Result<bool, String> {
+    let mut addresses = tokio::net::lookup_host(listen).await.map_err(|e| format!("could not listen on {}: {}", listen, e))?;
+    Ok(addresses.all(|v| v.ip().is_loopback()))
+}
+
+/// Background task that serves the relay API on the configured address, if there is one.
+pub async fn relay_task(state: Arc<RwLock<AppState>>, tx: mpsc::Sender<ClientEvent>, shutdown: CancellationToken) -> Result<(), String> {
+    let config = {
+        let mut state = state.write().await;
+        let config = &state.config.relay;
+        match (config.listen.clone(), config.password.clone()) {
+            (Some(listen), Some(password)) if !password.is_empty() => Some((listen, password, config.allow_remote)),
+            (Some(_), _) => {
+                state.status = Some(String::from("the relay needs a password to start"));
+                None
+            }
+            (None, _) => None,
+        }
+    };
+
+    // Without an address and password there's nothing to serve
+    let (listen, password, allow_remote) = match config {
+        Some(config) => config,
+        None => {
+            shutdown.cancelled().await;
+            return Ok(());
+        }
+    };
+
+    // Anyone who can reach the relay can guess the password, so it stays on this machine unless asked otherwise
+    if !allow_remote && !is_loopback(&listen).await? {
+        state.write().await.status = Some(format!(
+            "the relay only listens on loopback addresses unless allow_remote is set, not {}",
+            listen
+        ));
+        shutdown.cancelled().await;
+        return Ok(());
+    }
+
+    let listener = TcpListener::bind(&listen).await.map_err(|e| format!("could not listen on {}: {}", listen, e))?;
+    let events = EVENTS.get_or_init(|| broadcast::channel(EVENT_BUFFER).0);
+    let password = Arc::new(password);
+    let guesses = Arc::new(Mutex::new(Guesses::default()));
+    loop {
+        let (stream, _) = tokio::select! {
+            accepted = listener.accept() => accepted.map_err(|e| e.to_string())?,
+            _ = shutdown.cancelled() => return Ok(()),
+        };
+
+        let (state, tx, password, guesses, shutdown) = (state.clone(), tx.clone(), password.clone(), guesses.clone(), shutdown.clone());
+        let events = events.subscribe();
+        tokio::spawn(async move {
+            serve(stream, state, tx, events, &password, &guesses, shutdown).await;
+        });
+    }
+}
+
+/// Serves one frontend until it disconnects or the client stops.
+async fn serve(
+    stream: TcpStream,
+    state: Arc<RwLock<AppState>>,
+    tx: mpsc::Sender<ClientEvent>,
+    mut events: broadcast::Receiver<String>,
+    password: &str,
+    guesses: &Mutex<Guesses>,
+    shutdown: CancellationToken,
+) {
+    let mut socket = match tokio::time::timeout(HANDSHAKE_TIMEOUT, tokio_tungstenite::accept_hdr_async(stream, refuse_browsers)).await {
+        Ok(Ok(socket)) => socket,
+        Ok(Err(_)) | Err(_) => return,
+    };
+
+    // Connections that never authenticate are closed instead of being held open
+    let auth_deadline = tokio::time::sleep(AUTH_TIMEOUT);
+    tokio::pin!(auth_deadline);
+
+    let mut authenticated = false;
+    loop {
+        let reply = tokio::select! {
+            message = socket.next() => match message {
+                Some(Ok(WsMessage::Text(text))) => handle(&text, &state, &tx, password, guesses, &mut authenticated).await,
+                Some(Ok(WsMessage::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => continue,
+            },
+
+            _ = &mut auth_deadline, if !authenticated => {
+                let _ = socket.close(None).await;
+                break;
+            }
+
+            // Only authenticated frontends are told about new messages
+            event = events.recv() => match event {
+                Ok(event) if authenticated => event,
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+
+            _ = shutdown.cancelled() => break,
+        };
+
+        if socket.send(WsMessage::Text(reply)).await.is_err() {
+            break;
+        }
+
+        // Close unauthenticated connections while locked out, rather than letting them wait to guess again
+        if !authenticated && guesses.lock().await.locked_out() {
+            let _ = socket.close(None).await;
+            break;
+        }
+    }
+}
+
+/// Refuses websocket handshakes from web pages. Browsers send an `Origin` with every websocket handshake, and would
+/// otherwise let any site the user visits talk to a relay on localhost; frontends that aren't browsers don't send one.
+fn refuse_browsers(request: &Handshake, response: HandshakeResponse) -> Result<HandshakeResponse, ErrorResponse> {
+    if request.headers().contains_key("origin") {
+        let mut refusal = ErrorResponse::new(Some(String::from("the relay can't be used from a browser")));
+        *refusal.status_mut() = StatusCode::FORBIDDEN;
+        Err(refusal)
+    } else {
+        Ok(response)
+    }
+}
+
+/// Handles a request from a frontend, returning the reply.
+async fn handle(
+    text: &str,
+    state: &Arc<RwLock<AppState>>,
+    tx: &mpsc::Sender<ClientEvent>,
+    password: &str,
+    guesses: &Mutex<Guesses>,
+    authenticated: &mut bool,
+) -> String {
+    let request = match serde_json::from_str(text) {
+        Ok(request) => request,
+        Err(e) => return Response::error(format!("invalid request: {}", e)).to_json(),
+    };
+
+    let response = match request {
+        // Check the password, answering wrong ones more slowly each time. The lock is held while waiting, so guesses
+        // from every connection are answered one at a time
+        Request::Auth { password: given } => {
+            let mut guesses = guesses.lock().await;
+            if guesses.locked_out() {
+                Response::error("too many wrong passwords; try again later")
+            } else if password_matches(&given, password) {
+                *guesses = Guesses::default();
+                *authenticated = true;
+                Response::Authenticated
+            } else {
+                tokio::time::sleep(guesses.backoff()).await;
+                guesses.failures += 1;
+                guesses.last_failure = Some(Instant::now());
+                Response::error("wrong password")
+            }
+        }
+
+        _ if !*authenticated => Response::error("not authenticated"),
+
+        // List guilds in sidebar order
+        Request::Guilds => {
+            let state = state.read().await;
+            let guilds = state
+                .guilds_list
+                .iter()
+                .filter_map(|id| state.guilds_map.get(id))
+                .map(|guild| GuildInfo {
+                    id: Id(guild.id),
+                    name: guild.name.clone(),
+                    unseen_channels: guild.unseen_channels.len(),
+                })
+                .collect();
+            Response::Guilds { guilds }
+        }
+
+        // List a guild's channels
+        Request::Channels { guild_id } => {
+            let state = state.read().await;
+            match state.guilds_map.get(&guild_id.0) {
+                Some(guild) => {
+                    let channels = guild
+                        .channels_list
+                        .iter()
+                        .filter_map(|id| guild.channels_map.get(id))
+                        .map(|channel| ChannelInfo {
+                            id: Id(channel.id),
+                            name: channel.name.clone(),
+                            read_only: state.is_read_only(guild.id, channel.id),
+                        })
+                        .collect();
+                    Response::Channels { guild_id, channels }
+                }
+                None => Response::error("no such guild"),
+            }
+        }
+
+        // Get loaded messages, newest last
+        Request::Messages {
+            guild_id,
+            channel_id,
+            before,
+            limit,
+        } => {
+            let state = state.read().await;
+            match state.get_channel(guild_id.0, channel_id.0) {
+                Some(channel) => {
+                    let end = before
+                        .and_then(|before| channel.messages_list.iter().position(|&v| v == before.0))
+                        .unwrap_or(channel.messages_list.len());
+                    let start = end.saturating_sub(limit.unwrap_or(MAX_MESSAGES).min(MAX_MESSAGES));
+                    let messages = channel.messages_list[start..end]
+                        .iter()
+                        .filter_map(|id| channel.messages_map.get(id))
+                        .map(|message| message_info(&state, message))
+                        .collect();
+                    Response::Messages {
+                        guild_id,
+                        channel_id,
+                        messages,
+                    }
+                }
+                None => Response::error("no such channel"),
+            }
+        }
+
+        // Send through the same path as the input box, so read only channels stay read only
+        Request::Send {
+            guild_id,
+            channel_id,
+            text,
+        } => {
+            let state = state.read().await;
+            if state.get_channel(guild_id.0, channel_id.0).is_none() {
+                Response::error("no such channel")
+            } else if state.is_read_only(guild_id.0, channel_id.0) {
+                Response::error("this channel is read only")
+            } else if text.trim().is_empty() {
+                Response::error("nothing to send")
+            } else {
+                drop(state);
+                let _ = tx.send(ClientEvent::SendTo(guild_id.0, channel_id.0, text)).await;
+                Response::Sent
+            }
+        }
+    };
+
+    response.to_json()
+}
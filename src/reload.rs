@@ -0,0 +1,61 @@
+use std::sync::Arc;
+
+use notify::{EventKind, RecursiveMode, Watcher};
+use tokio::{
+    sync::{mpsc, RwLock},
+    time::Duration,
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{config::Config, AppState, STARTUP_SETTINGS};
+
+/// How long to wait after the config file changes before reading it, since editors often save in several writes.
+const SETTLE_TIME: Duration = Duration::from_millis(200);
+
+/// Background task that applies changes to the config file as soon as it's saved, showing whether it worked.
+pub async fn reload_task(state: Arc<RwLock<AppState>>, shutdown: CancellationToken) {
+    let (path, dir) = match Config::path() {
+        Some(path) => match path.parent() {
+            Some(dir) => (path.clone(), dir.to_owned()),
+            None => return shutdown.cancelled().await,
+        },
+        None => return shutdown.cancelled().await,
+    };
+
+    let (tx, mut rx) = mpsc::channel(16);
+    let watched = path.clone();
+    let watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        if let Ok(event) = event {
+            if !matches!(event.kind, EventKind::Access(_)) && event.paths.iter().any(|v| *v == watched) {
+                let _ = tx.try_send(());
+            }
+        }
+    });
+
+    // The directory is watched rather than the file, since editors often save by replacing the file
+    let _watcher = match watcher.and_then(|mut v| v.watch(&dir, RecursiveMode::NonRecursive).map(|_| v)) {
+        Ok(watcher) => watcher,
+
+        // Without a config directory there's nothing to watch, so changes need a restart
+        Err(_) => return shutdown.cancelled().await,
+    };
+
+    loop {
+        tokio::select! {
+            changed = rx.recv() => if changed.is_none() { break },
+            _ = shutdown.cancelled() => break,
+        }
+
+        // Wait for the editor to finish, reading the file once for all the writes
+        tokio::time::sleep(SETTLE_TIME).await;
+        while rx.try_recv().is_ok() {}
+
+        let config = Config::try_load();
+        let mut state = state.write().await;
+        state.status = Some(match config {
+            Ok(config) if state.apply_config(config) => format!("config reloaded; {} settings apply after a restart", STARTUP_SETTINGS),
+            Ok(_) => String::from("config reloaded"),
+            Err(e) => format!("could not reload config: {}", e),
+        });
+    }
+}
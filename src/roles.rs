@@ -0,0 +1,427 @@
+use crossterm::event::{KeyCode, KeyEvent};
+use tui::{
+    style::{Color, Style},
+    text::{Span, Spans, Text},
+};
+
+use harmony_rust_sdk::api::chat;
+
+use crate::{modal::ModalEvent, theme::Theme};
+
+/// Permission nodes always shown in the editor, in addition to any the role already has.
+pub const PERMISSION_NODES: &[&str] = &[
+    "messages.send",
+    "messages.view",
+    "messages.pins.add",
+    "messages.pins.remove",
+    "actions.trigger",
+    "invites.view",
+    "invites.manage.create",
+    "invites.manage.delete",
+    "channels.manage.create",
+    "channels.manage.change-information",
+    "channels.manage.move",
+    "channels.manage.delete",
+    "roles.get",
+    "roles.manage",
+    "roles.user.get",
+    "roles.user.manage",
+    "permissions.query",
+    "permissions.manage.get",
+    "permissions.manage.set",
+    "guild.manage.change-information",
+    "guild.manage.delete",
+    "user.manage.kick",
+    "user.manage.ban",
+    "user.manage.unban",
+];
+
+/// A role in a guild.
+#[derive(Clone)]
+pub struct Role {
+    /// The id of the role.
+    pub id: u64,
+
+    /// The name of the role.
+    pub name: String,
+
+    /// The colour of the role as 0xRRGGBB.
+    pub color: i32,
+}
+
+/// Converts roles fetched from the server as (id, role).
+pub fn from_raw(roles: Vec<(u64, chat::Role)>) -> Vec<Role> {
+    roles.into_iter().map(|(id, role)| Role { id, name: role.name, color: role.color }).collect()
+}
+
+/// Whether a role is given a permission node.
+#[derive(Copy, Clone, PartialEq)]
+pub enum PermissionState {
+    /// The node isn't set for the role, so it's inherited.
+    Unset,
+
+    /// The node is allowed.
+    Allow,
+
+    /// The node is denied.
+    Deny,
+}
+
+impl PermissionState {
+    /// Gets the state toggling moves on to.
+    fn next(self) -> PermissionState {
+        match self {
+            PermissionState::Unset => PermissionState::Allow,
+            PermissionState::Allow => PermissionState::Deny,
+            PermissionState::Deny => PermissionState::Unset,
+        }
+    }
+}
+
+/// A change to a guild's roles requested from the role editor.
+pub enum RoleRequest {
+    /// Creates a role with the given name.
+    Create(String),
+
+    /// Renames a role.
+    Rename(u64, String),
+
+    /// Changes the colour of a role.
+    Recolor(u64, i32),
+
+    /// Moves a role next to another one, after it if the flag is set and before it otherwise.
+    Move(u64, u64, bool),
+
+    /// Deletes a role.
+    Delete(u64),
+
+    /// Gets the permissions of a role, in a channel or the whole guild.
+    GetPermissions(u64, Option<u64>),
+
+    /// Replaces the permissions of a role, in a channel or the whole guild, as (node, allowed).
+    SetPermissions(u64, Option<u64>, Vec<(String, bool)>),
+}
+
+/// What the text typed into the role editor is for.
+enum Prompt {
+    /// The name of a new role.
+    Create,
+
+    /// The new name of a role.
+    Rename(u64),
+
+    /// The new colour of a role.
+    Recolor(u64),
+}
+
+/// A pane for managing the roles of a guild and their permissions.
+pub struct RoleEditor {
+    /// The id of the guild being edited.
+    pub guild_id: u64,
+
+    /// The roles of the guild, highest first.
+    roles: Vec<Role>,
+
+    /// The index of the selected role.
+    selected: usize,
+
+    /// What permissions can be set for, as a channel id (or `None` for the whole guild) and a name.
+    scopes: Vec<(Option<u64>, String)>,
+
+    /// The index of the scope permissions are shown for.
+    scope: usize,
+
+    /// The permissions of the selected role in the selected scope while they're being edited, or `None` when the
+    /// roles are being edited instead.
+    permissions: Option<Vec<(String, PermissionState)>>,
+
+    /// The index of the selected permission node.
+    node_selected: usize,
+
+    /// What's being typed and what it's for, if anything.
+    prompt: Option<(Prompt, String)>,
+}
+
+impl RoleEditor {
+    /// Creates a new role editor for a guild with the given channels as (id, name).
+    pub fn new(guild_id: u64, roles: Vec<Role>, channels: Vec<(u64, String)>) -> RoleEditor {
+        let mut scopes = vec![(None, String::from("the whole guild"))];
+        scopes.extend(channels.into_iter().map(|(id, name)| (Some(id), format!("#{}", name))));
+
+        RoleEditor {
+            guild_id,
+            roles,
+            selected: 0,
+            scopes,
+            scope: 0,
+            permissions: None,
+            node_selected: 0,
+            prompt: None,
+        }
+    }
+
+    /// Replaces the roles, keeping the same role selected if it still exists.
+    pub fn set_roles(&mut self, roles: Vec<Role>) {
+        let selected = self.selected_role().map(|v| v.id);
+        self.roles = roles;
+
+        match selected.and_then(|id| self.roles.iter().position(|v| v.id == id)) {
+            Some(i) => self.selected = i,
+
+            // The role being edited was deleted
+            None => {
+                self.selected = self.selected.min(self.roles.len().saturating_sub(1));
+                self.permissions = None;
+            }
+        }
+    }
+
+    /// Shows the permissions of a role if they're the ones being edited.
+    pub fn set_permissions(&mut self, role_id: u64, channel_id: Option<u64>, permissions: Vec<(String, bool)>) {
+        if self.permissions.is_none() || self.selected_role().map(|v| v.id) != Some(role_id) || self.channel_id() != channel_id {
+            return;
+        }
+
+        let mut nodes: Vec<_> = PERMISSION_NODES.iter().map(|&v| (String::from(v), PermissionState::Unset)).collect();
+        for (node, allowed) in permissions {
+            let state = if allowed { PermissionState::Allow } else { PermissionState::Deny };
+            match nodes.iter_mut().find(|(v, _)| *v == node) {
+                Some((_, existing)) => *existing = state,
+                None => nodes.push((node, state)),
+            }
+        }
+
+        self.node_selected = self.node_selected.min(nodes.len().saturating_sub(1));
+        self.permissions = Some(nodes);
+    }
+
+    /// Gets a role by its id.
+    pub fn role(&self, role_id: u64) -> Option<&Role> {
+        self.roles.iter().find(|v| v.id == role_id)
+    }
+
+    /// Gets the selected role.
+    fn selected_role(&self) -> Option<&Role> {
+        self.roles.get(self.selected)
+    }
+
+    /// Gets the channel permissions are being edited in, or `None` for the whole guild.
+    fn channel_id(&self) -> Option<u64> {
+        self.scopes.get(self.scope).and_then(|v| v.0)
+    }
+
+    /// Starts editing the permissions of the selected role in the selected scope.
+    fn load_permissions(&mut self) -> ModalEvent {
+        match self.selected_role() {
+            Some(role) => {
+                let request = RoleRequest::GetPermissions(role.id, self.channel_id());
+                self.permissions = Some(vec![]);
+                ModalEvent::Roles(request)
+            }
+
+            None => ModalEvent::None,
+        }
+    }
+
+    /// Handles a key press.
+    pub fn handle_key(&mut self, key: KeyEvent) -> ModalEvent {
+        // Typing into the prompt
+        if let Some((prompt, text)) = &mut self.prompt {
+            match key.code {
+                KeyCode::Esc => self.prompt = None,
+
+                KeyCode::Backspace => {
+                    text.pop();
+                }
+
+                KeyCode::Char(c) => text.push(c),
+
+                KeyCode::Enter => {
+                    let text = text.trim().to_owned();
+                    let request = match *prompt {
+                        _ if text.is_empty() => None,
+                        Prompt::Create => Some(RoleRequest::Create(text)),
+                        Prompt::Rename(role_id) => Some(RoleRequest::Rename(role_id, text)),
+                        Prompt::Recolor(role_id) => i32::from_str_radix(text.trim_start_matches('#'), 16)
+                            .ok()
+                            .filter(|&v| (0..=0xffffff).contains(&v))
+                            .map(|color| RoleRequest::Recolor(role_id, color)),
+                    };
+
+                    // Keep the prompt open if the colour is invalid
+                    if let Some(request) = request {
+                        self.prompt = None;
+                        return ModalEvent::Roles(request);
+                    } else if text.is_empty() {
+                        self.prompt = None;
+                    }
+                }
+
+                _ => (),
+            }
+
+            return ModalEvent::None;
+        }
+
+        // Editing the permissions of the selected role
+        if let Some(permissions) = &mut self.permissions {
+            match key.code {
+                KeyCode::Esc | KeyCode::Char('q') => self.permissions = None,
+
+                KeyCode::Char('j') | KeyCode::Down => {
+                    if self.node_selected + 1 < permissions.len() {
+                        self.node_selected += 1;
+                    }
+                }
+
+                KeyCode::Char('k') | KeyCode::Up => self.node_selected = self.node_selected.saturating_sub(1),
+
+                // Cycle through the channels
+                KeyCode::Tab => {
+                    self.scope = (self.scope + 1) % self.scopes.len();
+                    return self.load_permissions();
+                }
+
+                KeyCode::BackTab => {
+                    self.scope = (self.scope + self.scopes.len() - 1) % self.scopes.len();
+                    return self.load_permissions();
+                }
+
+                // Cycle between unset, allowed, and denied, saving right away
+                KeyCode::Char(' ') | KeyCode::Enter => {
+                    if let (Some((_, state)), Some(role_id)) = (permissions.get_mut(self.node_selected), self.roles.get(self.selected).map(|v| v.id)) {
+                        *state = state.next();
+                        let permissions = permissions
+                            .iter()
+                            .filter(|(_, v)| *v != PermissionState::Unset)
+                            .map(|(node, v)| (node.clone(), *v == PermissionState::Allow))
+                            .collect();
+                        return ModalEvent::Roles(RoleRequest::SetPermissions(role_id, self.channel_id(), permissions));
+                    }
+                }
+
+                _ => (),
+            }
+
+            return ModalEvent::None;
+        }
+
+        // Editing the roles
+        match key.code {
+            KeyCode::Esc | KeyCode::Char('q') => return ModalEvent::Close,
+
+            KeyCode::Char('j') | KeyCode::Down => {
+                if self.selected + 1 < self.roles.len() {
+                    self.selected += 1;
+                }
+            }
+
+            KeyCode::Char('k') | KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+
+            // Move the role below the next one or above the previous one
+            KeyCode::Char('J') => {
+                if let (Some(role), Some(next)) = (self.roles.get(self.selected), self.roles.get(self.selected + 1)) {
+                    return ModalEvent::Roles(RoleRequest::Move(role.id, next.id, true));
+                }
+            }
+
+            KeyCode::Char('K') => {
+                if let (Some(role), Some(previous)) = (self.roles.get(self.selected), self.selected.checked_sub(1).and_then(|v| self.roles.get(v))) {
+                    return ModalEvent::Roles(RoleRequest::Move(role.id, previous.id, false));
+                }
+            }
+
+            KeyCode::Char('n') => self.prompt = Some((Prompt::Create, String::new())),
+
+            KeyCode::Char('r') => {
+                if let Some(role) = self.selected_role() {
+                    self.prompt = Some((Prompt::Rename(role.id), role.name.clone()));
+                }
+            }
+
+            KeyCode::Char('c') => {
+                if let Some(role) = self.selected_role() {
+                    self.prompt = Some((Prompt::Recolor(role.id), format!("#{:06x}", role.color)));
+                }
+            }
+
+            KeyCode::Char('d') | KeyCode::Delete => {
+                if let Some(role) = self.selected_role() {
+                    return ModalEvent::Roles(RoleRequest::Delete(role.id));
+                }
+            }
+
+            KeyCode::Char('p') | KeyCode::Enter => return self.load_permissions(),
+
+            _ => (),
+        }
+
+        ModalEvent::None
+    }
+
+    /// Adds the contents of the editor to the text of its popup.
+    pub fn render(&self, text: &mut Text<'static>, theme: &Theme) {
+        match &self.permissions {
+            Some(permissions) => {
+                let role = self.selected_role().map(|v| v.name.as_str()).unwrap_or_default();
+                let scope = self.scopes.get(self.scope).map(|v| v.1.as_str()).unwrap_or_default();
+                text.lines.push(Spans::from(format!("permissions of {} in {}", role, scope)));
+                text.lines.push(Spans::from(""));
+
+                if permissions.is_empty() {
+                    text.lines.push(Spans::from("loading…"));
+                }
+
+                for (i, (node, state)) in permissions.iter().enumerate() {
+                    let mark = match state {
+                        PermissionState::Unset => "[ ]",
+                        PermissionState::Allow => "[+]",
+                        PermissionState::Deny => "[-]",
+                    };
+                    let line = format!("{} {}", mark, node);
+                    if i == self.node_selected {
+                        text.lines.push(Spans::from(Span::styled(line, theme.selected)));
+                    } else {
+                        text.lines.push(Spans::from(line));
+                    }
+                }
+
+                text.lines.push(Spans::from(""));
+                text.lines.push(Spans::from("j/k: move   space: allow/deny/unset   tab: channel   esc: back"));
+            }
+
+            None => {
+                if self.roles.is_empty() {
+                    text.lines.push(Spans::from("(no roles)"));
+                }
+
+                for (i, role) in self.roles.iter().enumerate() {
+                    let swatch = Span::styled(
+                        "■ ",
+                        Style::default().fg(Color::Rgb((role.color >> 16) as u8, (role.color >> 8) as u8, role.color as u8)),
+                    );
+                    let name = if i == self.selected {
+                        Span::styled(role.name.clone(), theme.selected)
+                    } else {
+                        Span::raw(role.name.clone())
+                    };
+                    text.lines.push(Spans::from(vec![swatch, name]));
+                }
+
+                text.lines.push(Spans::from(""));
+                text.lines.push(Spans::from("j/k: move   J/K: reorder   n: new   r: rename   c: colour   d: delete"));
+                text.lines.push(Spans::from("enter: permissions   esc: close"));
+            }
+        }
+
+        if let Some((prompt, input)) = &self.prompt {
+            let label = match prompt {
+                Prompt::Create => "name",
+                Prompt::Rename(_) => "new name",
+                Prompt::Recolor(_) => "colour (#rrggbb)",
+            };
+            text.lines.push(Spans::from(""));
+            text.lines.push(Spans::from(vec![Span::styled(format!("{}: ", label), theme.header), Span::raw(format!("{}_", input))]));
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use std::{path::PathBuf, sync::Arc};
+
+use chrono::{Duration as ChronoDuration, Local, NaiveDate, NaiveTime, TimeZone};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    sync::{mpsc, RwLock},
+    time::Duration,
+};
+use tokio_util::sync::CancellationToken;
+use tui::text::Text;
+
+use crate::{
+    duration,
+    modal::{ConfirmAction, Modal},
+    notifications, presence, vault, AppMode, AppState, ClientEvent,
+};
+
+/// A message waiting to be sent later.
+#[derive(Deserialize, Serialize)]
+pub struct ScheduledMessage {
+    /// The id of the scheduled message, used to cancel it.
+    pub id: u64,
+
+    /// The guild the message is sent in.
+    pub guild_id: u64,
+
+    /// The channel the message is sent in.
+    pub channel_id: u64,
+
+    /// The unix timestamp to send the message at.
+    pub send_at: u64,
+
+    /// The contents of the message.
+    pub text: String,
+}
+
+/// A reminder about a message.
+#[derive(Deserialize, Serialize)]
+pub struct Reminder {
+    /// The guild the message is in.
+    pub guild_id: u64,
+
+    /// The channel the message is in.
+    pub channel_id: u64,
+
+    /// The id of the message.
+    pub message_id: u64,
+
+    /// The unix timestamp to remind the user at.
+    pub remind_at: u64,
+
+    /// A preview of the message shown in the reminder popup. Only the summary is saved, so it's shown instead after a
+    /// restart.
+    #[serde(skip)]
+    pub preview: Text<'static>,
+
+    /// A plain text summary of the message shown in the desktop notification.
+    pub summary: String,
+}
+
+/// Scheduled messages and reminders as they're saved, so they still go off after a restart.
+#[derive(Default, Deserialize)]
+struct Saved {
+    /// The messages waiting to be sent.
+    scheduled: Vec<ScheduledMessage>,
+
+    /// The reminders waiting to fire.
+    reminders: Vec<Reminder>,
+}
+
+/// Gets the path of the given user's scheduled messages and reminders.
+fn path(user_id: u64) -> Option<PathBuf> {
+    dirs::data_dir().map(|v| v.join(format!("ilo-toki/schedule-{}.json", user_id)))
+}
+
+/// Loads the current user's scheduled messages and reminders from the data directory. Messages whose time passed
+/// while the program wasn't running are sent right away.
+pub fn load(state: &mut AppState) {
+    let saved: Saved = path(state.current_user)
+        .and_then(|v| vault::read_to_string(&v).ok())
+        .and_then(|v| serde_json::from_str(&v).ok())
+        .unwrap_or_default();
+
+    state.scheduled_count = saved.scheduled.iter().map(|v| v.id).max().unwrap_or(0);
+    state.scheduled = saved.scheduled;
+    state.reminders = saved.reminders;
+    for reminder in state.reminders.iter_mut() {
+        reminder.preview = Text::from(reminder.summary.clone());
+    }
+}
+
+/// Saves the current user's scheduled messages and reminders to the data directory.
+pub fn save(state: &AppState) {
+    /// The same as [`Saved`], borrowing from the app state.
+    #[derive(Serialize)]
+    struct SavedRef<'a> {
+        scheduled: &'a [ScheduledMessage],
+        reminders: &'a [Reminder],
+    }
+
+    let saved = SavedRef {
+        scheduled: &state.scheduled,
+        reminders: &state.reminders,
+    };
+    if let (Some(data_dir), Some(path)) = (dirs::data_dir(), path(state.current_user)) {
+        if let Ok(json) = serde_json::to_string(&saved) {
+            std::fs::create_dir(data_dir.join("ilo-toki/")).ok();
+            vault::write(&path, &json).ok();
+        }
+    }
+}
+
+/// Parses when to send a message, either a duration from now such as `15m` or a time of day such as `18:30`.
+/// Returns the unix timestamp to send at.
+pub fn parse_time(text: &str) -> Option<u64> {
+    if let Ok(time) = NaiveTime::parse_from_str(text, "%H:%M") {
+        // The next time the clock shows this time
+        let now = Local::now().naive_local();
+        let mut at = now.date().and_time(time);
+        if at <= now {
+            at += ChronoDuration::days(1);
+        }
+
+        return Local.from_local_datetime(&at).earliest().map(|v| v.timestamp() as u64);
+    }
+
+    duration::parse_duration(text).and_then(|v| presence::now().checked_add(v.as_secs()))
+}
+
+/// Parses a point in the past, either a date such as `2021-12-25` (its start in local time) or a duration ago such as `3d`.
+/// Returns the unix timestamp of that point.
+pub fn parse_past(text: &str) -> Option<u64> {
+    if let Ok(date) = NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+        return Local
+            .from_local_datetime(&date.and_hms(0, 0, 0))
+            .earliest()
+            .map(|v| v.timestamp().max(0) as u64);
+    }
+
+    duration::parse_duration(text).map(|v| presence::now().saturating_sub(v.as_secs()))
+}
+
+/// Background task that sends scheduled messages and fires reminders when they are due.
+pub async fn schedule_task(state: Arc<RwLock<AppState>>, tx: mpsc::Sender<ClientEvent>, shutdown: CancellationToken) {
+    while !shutdown.is_cancelled() {
+        let due: Vec<_> = {
+            let mut state = state.write().await;
+            let now = presence::now();
+            let (due, pending) = std::mem::take(&mut state.scheduled)
+                .into_iter()
+                .partition(|v| v.send_at <= now);
+            state.scheduled = pending;
+
+            // Fire reminders, holding back their popups while the user is typing so keys don't go to them
+            let (reminders, pending): (Vec<_>, _) = std::mem::take(&mut state.reminders)
+                .into_iter()
+                .partition(|v| v.remind_at <= now);
+            state.reminders = pending;
+            let fired = !reminders.is_empty();
+            if fired || !due.is_empty() {
+                save(&state);
+            }
+            for reminder in reminders {
+                if !state.dnd_active {
+                    notifications::notify(&state.config.notifications, String::from("reminder"), reminder.summary.clone());
+                }
+                state.due_reminders.push(reminder);
+            }
+
+            // Let the user jump back to the messages once they've stopped typing
+            if matches!(state.mode, AppMode::TextInsert | AppMode::Command) {
+                if fired {
+                    state.status = Some(format!("{} reminder(s) due; shown when you stop typing", state.due_reminders.len()));
+                }
+            } else {
+                for reminder in std::mem::take(&mut state.due_reminders) {
+                    let action = ConfirmAction::JumpToMessage(reminder.guild_id, reminder.channel_id, reminder.message_id);
+                    state.modals.push(Modal::confirm("reminder: jump to this message?", reminder.preview, action));
+                }
+            }
+
+            due
+        };
+
+        for message in due {
+            let _ = tx.send(ClientEvent::SendTo(message.guild_id, message.channel_id, message.text)).await;
+        }
+
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+}
@@ -0,0 +1,82 @@
+use tui::text::Text;
+
+use crate::{
+    backend::SearchHit,
+    message_author, message_snippet,
+    modal::{ListKind, Modal},
+    AppState, Guild, Message,
+};
+
+/// The most results shown for one search.
+const MAX_RESULTS: usize = 200;
+
+/// The id of the rows naming a guild and channel above its results, which can't be opened.
+pub const GROUP_HEADER: u64 = u64::MAX;
+
+/// Searches the messages loaded in every guild for text, ignoring case, newest first.
+pub fn search_local(state: &AppState, query: &str) -> Vec<SearchHit> {
+    let query = query.to_lowercase();
+    find_loaded(state, |_, message| message_snippet(message, usize::MAX).to_lowercase().contains(&query))
+}
+
+/// Finds the messages loaded in every guild that match a predicate, newest first.
+pub fn find_loaded(state: &AppState, mut matches: impl FnMut(&Guild, &Message) -> bool) -> Vec<SearchHit> {
+    let mut hits = vec![];
+    for guild in state.guilds_list.iter().filter_map(|v| state.guilds_map.get(v)) {
+        for channel in guild.channels_list.iter().filter_map(|v| guild.channels_map.get(v)) {
+            for message in channel.messages_map.values() {
+                if matches(guild, message) {
+                    hits.push(SearchHit {
+                        guild_id: guild.id,
+                        channel_id: channel.id,
+                        message_id: message.id,
+                        author: message_author(state, message),
+                        snippet: message_snippet(message, 80),
+                        timestamp: message.timestamp,
+                    });
+                }
+            }
+        }
+    }
+
+    hits.sort_by_key(|v| std::cmp::Reverse(v.timestamp));
+    hits.truncate(MAX_RESULTS);
+    hits
+}
+
+/// Creates the popup listing search results grouped by guild and channel, in sidebar order, newest first in each.
+/// Results are keyed by their index in the list the popup keeps, and each group starts with a header row.
+pub fn results_popup(state: &AppState, query: &str, mut hits: Vec<SearchHit>, local: bool) -> Modal {
+    let items = grouped_items(state, &mut hits);
+    let title = format!("search: {} ({} results{})", query, hits.len(), if local { ", loaded messages only" } else { "" });
+    Modal::list(title, Text::default(), items, ListKind::Search(hits))
+}
+
+/// Sorts hits into groups by guild and channel and gets the popup rows for them, keyed by their index in the sorted
+/// hits, with a header row starting each group.
+pub fn grouped_items(state: &AppState, hits: &mut Vec<SearchHit>) -> Vec<(u64, String)> {
+    let guild_position = |id: u64| state.guilds_list.iter().position(|&v| v == id).unwrap_or(usize::MAX);
+    let channel_position = |guild_id: u64, channel_id: u64| {
+        state
+            .guilds_map
+            .get(&guild_id)
+            .and_then(|v| v.channels_list.iter().position(|&v| v == channel_id))
+            .unwrap_or(usize::MAX)
+    };
+    hits.truncate(MAX_RESULTS);
+    hits.sort_by_key(|v| (guild_position(v.guild_id), channel_position(v.guild_id, v.channel_id), std::cmp::Reverse(v.timestamp)));
+
+    let mut items = vec![];
+    let mut group = None;
+    for (i, hit) in hits.iter().enumerate() {
+        if group != Some((hit.guild_id, hit.channel_id)) {
+            group = Some((hit.guild_id, hit.channel_id));
+            let label = state
+                .channel_label(hit.guild_id, hit.channel_id)
+                .unwrap_or_else(|| String::from("<unknown channel>"));
+            items.push((GROUP_HEADER, format!("── {}", label)));
+        }
+        items.push((i as u64, format!("   {}: {}", hit.author, hit.snippet)));
+    }
+    items
+}
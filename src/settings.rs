@@ -0,0 +1,329 @@
+use std::io;
+
+use crossterm::event::{KeyCode, KeyEvent};
+use toml_edit::{Document, TableLike};
+use tui::text::{Span, Spans, Text};
+
+use crate::{
+    config::{Config, GuildSort, NotifyLevel, Timestamps},
+    modal::ModalEvent,
+    theme::Theme,
+};
+
+/// How a setting is edited.
+enum Kind {
+    /// Switched on and off.
+    Toggle,
+
+    /// One of a few choices, cycled through.
+    Choice(&'static [&'static str]),
+
+    /// A whole number that isn't negative.
+    Number,
+
+    /// Any text, or nothing to unset it.
+    Text,
+}
+
+/// A setting that can be changed from the editor.
+struct Setting {
+    /// The heading the setting is listed under.
+    category: &'static str,
+
+    /// The setting's key in the config file, with its table before a dot.
+    key: &'static str,
+
+    /// How the setting is edited.
+    kind: Kind,
+}
+
+/// The settings shown in the editor, in order. The rest (such as snippets and media handlers) are edited in the file.
+const SETTINGS: &[Setting] = &[
+    Setting { category: "general", key: "theme", kind: Kind::Choice(&["default", "high-contrast", "colorblind"]) },
+    Setting { category: "general", key: "guild_sort", kind: Kind::Choice(&["manual", "activity"]) },
+    Setting { category: "general", key: "low_data", kind: Kind::Toggle },
+    Setting { category: "messages", key: "messages.page_size", kind: Kind::Number },
+    Setting { category: "messages", key: "messages.prefetch", kind: Kind::Number },
+    Setting { category: "messages", key: "messages.relative_numbers", kind: Kind::Toggle },
+    Setting { category: "messages", key: "messages.max_width", kind: Kind::Number },
+    Setting { category: "messages", key: "messages.timestamps", kind: Kind::Choice(&["inline", "column"]) },
+    Setting { category: "messages", key: "messages.header", kind: Kind::Toggle },
+    Setting { category: "messages", key: "messages.read_receipts", kind: Kind::Toggle },
+    Setting { category: "notifications", key: "notifications.enabled", kind: Kind::Toggle },
+    Setting { category: "notifications", key: "notifications.default_level", kind: Kind::Choice(&["all", "mentions", "nothing"]) },
+    Setting { category: "notifications", key: "notifications.sound", kind: Kind::Text },
+    Setting { category: "notifications", key: "notifications.sound_command", kind: Kind::Text },
+    Setting { category: "presence", key: "dnd.schedule", kind: Kind::Text },
+    Setting { category: "presence", key: "idle.timeout", kind: Kind::Text },
+    Setting { category: "presence", key: "rich_presence.command", kind: Kind::Text },
+    Setting { category: "presence", key: "rich_presence.interval", kind: Kind::Text },
+    Setting { category: "translation", key: "translate.url", kind: Kind::Text },
+    Setting { category: "translation", key: "translate.language", kind: Kind::Text },
+    Setting { category: "media", key: "media.cache_size", kind: Kind::Text },
+];
+
+/// The value of a setting.
+#[derive(Clone)]
+pub enum Value {
+    /// A setting that's on or off.
+    Bool(bool),
+
+    /// A number.
+    Integer(i64),
+
+    /// Text, including the choice made for a setting with choices.
+    Text(String),
+
+    /// A setting left out of the config file.
+    Unset,
+}
+
+impl Value {
+    /// Gets the value as it's shown in the editor.
+    fn describe(&self) -> String {
+        match self {
+            Value::Bool(true) => String::from("on"),
+            Value::Bool(false) => String::from("off"),
+            Value::Integer(v) => v.to_string(),
+            Value::Text(v) => v.clone(),
+            Value::Unset => String::from("(not set)"),
+        }
+    }
+}
+
+/// Gets the current value of a setting.
+fn current(config: &Config, key: &str) -> Value {
+    let text = |v: &str| Value::Text(v.to_owned());
+    let optional = |v: &Option<String>| v.as_deref().map(text).unwrap_or(Value::Unset);
+
+    match key {
+        "theme" if config.theme.is_empty() => text("default"),
+        "theme" => text(&config.theme),
+        "guild_sort" => text(match config.guild_sort {
+            GuildSort::Manual => "manual",
+            GuildSort::Activity => "activity",
+        }),
+        "low_data" => Value::Bool(config.low_data),
+        "messages.page_size" => Value::Integer(config.messages.page_size as i64),
+        "messages.prefetch" => Value::Integer(config.messages.prefetch as i64),
+        "messages.relative_numbers" => Value::Bool(config.messages.relative_numbers),
+        "messages.max_width" => config.messages.max_width.map(|v| Value::Integer(v as i64)).unwrap_or(Value::Unset),
+        "messages.timestamps" => text(match config.messages.timestamps {
+            Timestamps::Inline => "inline",
+            Timestamps::Column => "column",
+        }),
+        "messages.header" => Value::Bool(config.messages.header),
+        "messages.read_receipts" => Value::Bool(config.messages.read_receipts),
+        "notifications.enabled" => Value::Bool(config.notifications.enabled),
+        "notifications.default_level" => text(match config.notifications.default_level {
+            NotifyLevel::All => "all",
+            NotifyLevel::Mentions => "mentions",
+            NotifyLevel::Nothing => "nothing",
+        }),
+        "notifications.sound" => optional(&config.notifications.sound),
+        "notifications.sound_command" => optional(&config.notifications.sound_command),
+        "dnd.schedule" => optional(&config.dnd.schedule),
+        "idle.timeout" => text(&config.idle.timeout),
+        "rich_presence.command" => optional(&config.rich_presence.command),
+        "rich_presence.interval" => text(&config.rich_presence.interval),
+        "translate.url" => text(&config.translate.url),
+        "translate.language" => text(&config.translate.language),
+        "media.cache_size" => text(&config.media.cache_size),
+        _ => Value::Unset,
+    }
+}
+
+/// Writes a setting to the config file, keeping the rest of the file (including comments) as it is.
+pub fn save(key: &str, value: &Value) -> Result<(), String> {
+    let path = Config::path().ok_or("no config directory")?;
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.to_string()),
+    };
+    let mut document = text.parse::<Document>().map_err(|e| e.to_string())?;
+
+    let (table, name) = match key.split_once('.') {
+        Some((table, name)) => {
+            let item = document.entry(table).or_insert(toml_edit::table());
+            let table = item.as_table_like_mut().ok_or_else(|| format!("{} isn't a table", table))?;
+            (table, name)
+        }
+        None => (document.as_table_mut() as &mut dyn TableLike, key),
+    };
+
+    match value {
+        Value::Bool(v) => table.insert(name, toml_edit::value(*v)),
+        Value::Integer(v) => table.insert(name, toml_edit::value(*v)),
+        Value::Text(v) => table.insert(name, toml_edit::value(v.as_str())),
+        Value::Unset => table.remove(name),
+    };
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    std::fs::write(&path, document.to_string()).map_err(|e| e.to_string())
+}
+
+/// A pane listing the settings by category, which saves each change to the config file as it's made.
+pub struct SettingsEditor {
+    /// The value of each setting, in the same order as `SETTINGS`.
+    values: Vec<Value>,
+
+    /// The index of the selected setting.
+    selected: usize,
+
+    /// The text being typed for the selected setting, if any.
+    input: Option<String>,
+}
+
+impl SettingsEditor {
+    /// Creates a new settings editor showing the given config.
+    pub fn new(config: &Config) -> SettingsEditor {
+        SettingsEditor {
+            values: SETTINGS.iter().map(|v| current(config, v.key)).collect(),
+            selected: 0,
+            input: None,
+        }
+    }
+
+    /// Sets the selected setting, asking for it to be saved.
+    fn set(&mut self, value: Value) -> ModalEvent {
+        self.values[self.selected] = value.clone();
+        ModalEvent::Setting(SETTINGS[self.selected].key, value)
+    }
+
+    /// Moves the selected choice forward or back.
+    fn cycle(&mut self, choices: &[&str], forward: bool) -> ModalEvent {
+        let position = match &self.values[self.selected] {
+            Value::Text(v) => choices.iter().position(|c| c == v),
+            _ => None,
+        };
+        let next = match position {
+            Some(i) if forward => (i + 1) % choices.len(),
+            Some(i) => (i + choices.len() - 1) % choices.len(),
+            None => 0,
+        };
+        self.set(Value::Text(choices[next].to_owned()))
+    }
+
+    /// Handles a key press.
+    pub fn handle_key(&mut self, key: KeyEvent) -> ModalEvent {
+        // Typing a new value
+        if let Some(input) = &mut self.input {
+            match key.code {
+                KeyCode::Esc => self.input = None,
+
+                KeyCode::Backspace => {
+                    input.pop();
+                }
+
+                KeyCode::Char(c) => input.push(c),
+
+                KeyCode::Enter => {
+                    let text = input.trim().to_owned();
+                    let value = match SETTINGS[self.selected].kind {
+                        _ if text.is_empty() => Some(Value::Unset),
+                        Kind::Number => text.parse::<u32>().ok().map(|v| Value::Integer(v as i64)),
+                        _ => Some(Value::Text(text)),
+                    };
+
+                    // Keep typing if the number is invalid
+                    if let Some(value) = value {
+                        self.input = None;
+                        return self.set(value);
+                    }
+                }
+
+                _ => (),
+            }
+
+            return ModalEvent::None;
+        }
+
+        match key.code {
+            KeyCode::Esc | KeyCode::Char('q') => return ModalEvent::Close,
+
+            KeyCode::Char('j') | KeyCode::Down => {
+                if self.selected + 1 < SETTINGS.len() {
+                    self.selected += 1;
+                }
+            }
+
+            KeyCode::Char('k') | KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+
+            KeyCode::Char('h') | KeyCode::Left => {
+                if let Kind::Choice(choices) = SETTINGS[self.selected].kind {
+                    return self.cycle(choices, false);
+                }
+            }
+
+            KeyCode::Char('l') | KeyCode::Right => {
+                if let Kind::Choice(choices) = SETTINGS[self.selected].kind {
+                    return self.cycle(choices, true);
+                }
+            }
+
+            // Change the setting, or start typing a new value for it
+            KeyCode::Enter | KeyCode::Char(' ') => match SETTINGS[self.selected].kind {
+                Kind::Toggle => {
+                    let on = matches!(self.values[self.selected], Value::Bool(true));
+                    return self.set(Value::Bool(!on));
+                }
+
+                Kind::Choice(choices) => return self.cycle(choices, true),
+
+                Kind::Number | Kind::Text => {
+                    self.input = Some(match &self.values[self.selected] {
+                        Value::Unset => String::new(),
+                        value => value.describe(),
+                    });
+                }
+            },
+
+            // Remove the setting from the file so its default is used
+            KeyCode::Char('d') | KeyCode::Delete => return self.set(Value::Unset),
+
+            _ => (),
+        }
+
+        ModalEvent::None
+    }
+
+    /// Checks whether a new value is being typed, which escape cancels instead of closing the editor.
+    pub fn is_typing(&self) -> bool {
+        self.input.is_some()
+    }
+
+    /// Adds the contents of the editor to the text of its popup.
+    pub fn render(&self, text: &mut Text<'static>, theme: &Theme) {
+        let mut category = "";
+        for (i, (setting, value)) in SETTINGS.iter().zip(self.values.iter()).enumerate() {
+            if setting.category != category {
+                category = setting.category;
+                if i != 0 {
+                    text.lines.push(Spans::from(""));
+                }
+                text.lines.push(Spans::from(Span::styled(category, theme.header)));
+            }
+
+            let value = match &self.input {
+                Some(input) if i == self.selected => format!("{}_", input),
+                _ => value.describe(),
+            };
+            let line = format!("{}: {}", setting.key, value);
+            if i == self.selected {
+                text.lines.push(Spans::from(Span::styled(line, theme.selected)));
+            } else {
+                text.lines.push(Spans::from(line));
+            }
+        }
+
+        text.lines.push(Spans::from(""));
+        if self.input.is_some() {
+            text.lines.push(Spans::from("enter: save   esc: cancel   (empty to unset)"));
+        } else {
+            text.lines.push(Spans::from("j/k: move   enter: change   h/l: choices   d: unset   esc: close"));
+        }
+    }
+}
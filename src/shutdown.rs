@@ -0,0 +1,56 @@
+use tokio_util::sync::CancellationToken;
+use tui::{backend::CrosstermBackend, Terminal};
+
+/// Cancels the token when the process is asked to stop by a signal.
+/// Ctrl+C arrives as a key press in raw mode, so this mostly catches the terminal closing or `kill`.
+pub async fn watch_signals(shutdown: CancellationToken) {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let (mut terminate, mut hangup) = match (signal(SignalKind::terminate()), signal(SignalKind::hangup())) {
+            (Ok(terminate), Ok(hangup)) => (terminate, hangup),
+            _ => return,
+        };
+
+        tokio::select! {
+            _ = terminate.recv() => (),
+            _ = hangup.recv() => (),
+            _ = tokio::signal::ctrl_c() => (),
+            _ = shutdown.cancelled() => (),
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => (),
+            _ = shutdown.cancelled() => (),
+        }
+    }
+
+    shutdown.cancel();
+}
+
+/// Restores the terminal to how it was before the UI started.
+/// Errors are ignored since this also runs while panicking.
+pub fn restore_terminal() {
+    let _ = crossterm::terminal::disable_raw_mode();
+    if let Ok(mut terminal) = Terminal::new(CrosstermBackend::new(std::io::stdout())) {
+        let _ = terminal.clear();
+        let _ = terminal.set_cursor(0, 0);
+        let _ = terminal.show_cursor();
+    }
+}
+
+/// Restores the terminal before a panic on the main thread is printed, so it's readable and the shell isn't left in
+/// raw mode. Panics in background tasks aren't printed over the UI, since the supervisor reports them.
+pub fn install_panic_hook() {
+    let default = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        if std::thread::current().name() == Some("main") {
+            restore_terminal();
+            default(info);
+        }
+    }));
+}
@@ -0,0 +1,30 @@
+use chrono::Local;
+
+/// Where a snippet is being expanded, used to fill in its placeholders.
+pub struct SnippetContext<'a> {
+    /// The name of the current guild.
+    pub guild: &'a str,
+
+    /// The name of the current channel.
+    pub channel: &'a str,
+}
+
+/// Expands a snippet's placeholders, returning the text and where the cursor goes in it in bytes.
+/// `{date}`, `{time}`, `{guild}`, and `{channel}` are replaced, and `{cursor}` marks where the cursor goes,
+/// which is the end if there's no marker.
+pub fn expand(template: &str, context: &SnippetContext) -> (String, usize) {
+    let now = Local::now();
+    let text = template
+        .replace("{date}", &now.format("%x").to_string())
+        .replace("{time}", &now.format("%H:%M").to_string())
+        .replace("{guild}", context.guild)
+        .replace("{channel}", context.channel);
+
+    match text.find("{cursor}") {
+        Some(cursor) => (text.replacen("{cursor}", "", 1), cursor),
+        None => {
+            let len = text.len();
+            (text, len)
+        }
+    }
+}
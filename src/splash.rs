@@ -0,0 +1,94 @@
+use tui::{
+    backend::Backend,
+    layout::{Alignment, Rect},
+    text::{Span, Spans, Text},
+    widgets::{Block, Borders, Paragraph},
+    Frame,
+};
+
+use crate::{modal::centered_rect, theme::Theme};
+
+/// A step of getting ready after logging in, in the order they happen.
+#[derive(Copy, Clone)]
+pub enum Step {
+    /// Checking the session by going online.
+    SignIn,
+
+    /// Loading what was saved on disk last time.
+    SavedData,
+
+    /// Finding out what the homeserver supports.
+    Capabilities,
+
+    /// Fetching the list of guilds the user is in.
+    GuildList,
+
+    /// Fetching the name of each guild.
+    Guilds,
+}
+
+impl Step {
+    /// Every step, in order.
+    const ALL: [Step; 5] = [Step::SignIn, Step::SavedData, Step::Capabilities, Step::GuildList, Step::Guilds];
+
+    /// Gets the description of the step shown on the splash screen.
+    fn name(self) -> &'static str {
+        match self {
+            Step::SignIn => "signing in",
+            Step::SavedData => "loading saved data",
+            Step::Capabilities => "checking what the homeserver supports",
+            Step::GuildList => "fetching guild list",
+            Step::Guilds => "fetching guilds",
+        }
+    }
+}
+
+/// What's been done so far while getting ready, shown in place of the UI until everything is loaded.
+#[derive(Default)]
+pub struct Splash {
+    /// The number of steps finished.
+    finished: usize,
+
+    /// The progress of the current step as (done, total), if it counts.
+    progress: Option<(usize, usize)>,
+}
+
+impl Splash {
+    /// Marks every step before the given one as finished.
+    pub fn start(&mut self, step: Step) {
+        self.finished = step as usize;
+        self.progress = None;
+    }
+
+    /// Sets the progress of the current step.
+    pub fn progress(&mut self, done: usize, total: usize) {
+        self.progress = Some((done, total));
+    }
+
+    /// Renders the splash screen in the middle of the given area.
+    pub fn render<B: Backend>(&self, f: &mut Frame<B>, area: Rect, theme: &Theme) {
+        let mut lines = vec![Spans::from(Span::styled("ilo toki", theme.header)), Spans::from("")];
+        for (i, step) in Step::ALL.iter().enumerate() {
+            let line = if i < self.finished {
+                Span::styled(format!("✓ {}", step.name()), theme.connection_good)
+            } else if i == self.finished {
+                match self.progress {
+                    Some((done, total)) => Span::raw(format!("• {} ({}/{})", step.name(), done, total)),
+                    None => Span::raw(format!("• {}…", step.name())),
+                }
+            } else {
+                Span::raw(format!("  {}", step.name()))
+            };
+            lines.push(Spans::from(line));
+        }
+        lines.push(Spans::from(""));
+        lines.push(Spans::from("esc: quit"));
+
+        let width = 50.min(area.width);
+        let rect = centered_rect(width, lines.len() as u16 + 2, area);
+        let paragraph = Paragraph::new(Text::from(lines))
+            .block(Block::default().borders(Borders::ALL))
+            .alignment(Alignment::Left);
+        f.render_widget(paragraph, rect);
+    }
+}
@@ -0,0 +1,202 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use tokio_util::sync::CancellationToken;
+
+use crate::transfers::format_bytes;
+
+/// How often the event loop lag is measured.
+const LAG_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The upper bounds of the request latency histogram buckets exported as metrics, in seconds.
+const LATENCY_BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// How long the requests of one kind took, as a histogram.
+#[derive(Default)]
+struct Latency {
+    /// How many requests took no longer than each bucket's bound.
+    buckets: [u64; LATENCY_BUCKETS.len()],
+
+    /// How many requests there were.
+    count: u64,
+
+    /// How long they took altogether.
+    sum: Duration,
+
+    /// How many of them failed.
+    errors: u64,
+}
+
+/// Performance counters shown by `:stats`. They're updated while the state is only read, so they're atomic.
+#[derive(Default)]
+pub struct Stats {
+    /// How long the last frame took to render, in microseconds.
+    frame: AtomicU64,
+
+    /// A moving average of the frame render time, in microseconds.
+    frame_average: AtomicU64,
+
+    /// The slowest frame since the stats were last reset, in microseconds.
+    frame_slowest: AtomicU64,
+
+    /// How late the event loop last woke up, in microseconds.
+    lag: AtomicU64,
+
+    /// The worst event loop lag since the stats were last reset, in microseconds.
+    lag_worst: AtomicU64,
+
+    /// The number of requests to the homeserver that haven't finished.
+    pending: AtomicUsize,
+
+    /// The number of messages received from the homeserver as they were sent, not counting history.
+    messages_received: AtomicU64,
+
+    /// The number of messages sent successfully.
+    messages_sent: AtomicU64,
+
+    /// The number of times the event stream reconnected after breaking.
+    reconnects: AtomicU64,
+
+    /// How long requests to the homeserver took, by the name of the call.
+    requests: Mutex<BTreeMap<String, Latency>>,
+}
+
+impl Stats {
+    /// Records how long a frame took to render.
+    pub fn record_frame(&self, time: Duration) {
+        let time = time.as_micros() as u64;
+        self.frame.store(time, Ordering::Relaxed);
+        let average = self.frame_average.load(Ordering::Relaxed);
+        self.frame_average.store(if average == 0 { time } else { (average * 15 + time) / 16 }, Ordering::Relaxed);
+        self.frame_slowest.fetch_max(time, Ordering::Relaxed);
+    }
+
+    /// Records how late the event loop woke up.
+    fn record_lag(&self, lag: Duration) {
+        let lag = lag.as_micros() as u64;
+        self.lag.store(lag, Ordering::Relaxed);
+        self.lag_worst.fetch_max(lag, Ordering::Relaxed);
+    }
+
+    /// Counts a request to the homeserver as pending until the returned guard is dropped, so requests that are
+    /// cancelled are counted as finished too.
+    pub fn start_request(&self) -> PendingRequest<'_> {
+        self.pending.fetch_add(1, Ordering::Relaxed);
+        PendingRequest(self)
+    }
+
+    /// Records how long a finished request to the homeserver took and whether it worked.
+    pub fn record_request(&self, name: &str, time: Duration, ok: bool) {
+        let mut requests = self.requests.lock().unwrap();
+        let latency = requests.entry(name.to_owned()).or_default();
+        let seconds = time.as_secs_f64();
+        for (bucket, &bound) in latency.buckets.iter_mut().zip(LATENCY_BUCKETS.iter()) {
+            if seconds <= bound {
+                *bucket += 1;
+            }
+        }
+        latency.count += 1;
+        latency.sum += time;
+        latency.errors += u64::from(!ok);
+    }
+
+    /// Counts a message received as it was sent.
+    pub fn record_received(&self) {
+        self.messages_received.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a message sent successfully.
+    pub fn record_sent(&self) {
+        self.messages_sent.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts the event stream reconnecting.
+    pub fn record_reconnect(&self) {
+        self.reconnects.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Forgets the slowest frame and worst lag, so they only cover what happens from now on.
+    pub fn reset(&self) {
+        self.frame_slowest.store(0, Ordering::Relaxed);
+        self.lag_worst.store(0, Ordering::Relaxed);
+    }
+
+    /// Describes the stats, one per line.
+    pub fn describe(&self, cached_messages: usize, cache_bytes: usize) -> Vec<String> {
+        let millis = |v: &AtomicU64| v.load(Ordering::Relaxed) as f64 / 1000.0;
+        vec![
+            format!(
+                "frame: {:.2}ms (avg {:.2}ms, max {:.2}ms)",
+                millis(&self.frame),
+                millis(&self.frame_average),
+                millis(&self.frame_slowest)
+            ),
+            format!("event loop lag: {:.2}ms (max {:.2}ms)", millis(&self.lag), millis(&self.lag_worst)),
+            format!("message cache: {} messages, {}", cached_messages, format_bytes(cache_bytes as u64)),
+            format!("pending requests: {}", self.pending.load(Ordering::Relaxed)),
+        ]
+    }
+
+    /// Writes the counters in the Prometheus text format, for the metrics endpoint.
+    pub fn prometheus(&self) -> String {
+        let mut text = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, value: String| {
+            let _ = writeln!(text, "# HELP ilo_toki_{} {}\n# TYPE ilo_toki_{} {}\nilo_toki_{} {}", name, help, name, kind, name, value);
+        };
+
+        let count = |v: &AtomicU64| v.load(Ordering::Relaxed).to_string();
+        let seconds = |v: &AtomicU64| (v.load(Ordering::Relaxed) as f64 / 1_000_000.0).to_string();
+
+        metric("messages_received_total", "counter", "Messages received as they were sent.", count(&self.messages_received));
+        metric("messages_sent_total", "counter", "Messages sent successfully.", count(&self.messages_sent));
+        metric("reconnects_total", "counter", "Times the event stream reconnected after breaking.", count(&self.reconnects));
+        metric("pending_requests", "gauge", "Requests to the homeserver that haven't finished.", self.pending.load(Ordering::Relaxed).to_string());
+        metric("event_loop_lag_seconds", "gauge", "How late the event loop last woke up.", seconds(&self.lag));
+        metric("frame_seconds", "gauge", "Moving average of the time taken to render a frame.", seconds(&self.frame_average));
+
+        // Request latencies and errors, by call
+        let requests = self.requests.lock().unwrap();
+        text.push_str("# HELP ilo_toki_request_duration_seconds How long requests to the homeserver took.\n");
+        text.push_str("# TYPE ilo_toki_request_duration_seconds histogram\n");
+        for (name, latency) in requests.iter() {
+            for (bucket, bound) in latency.buckets.iter().zip(LATENCY_BUCKETS.iter()) {
+                let _ = writeln!(text, "ilo_toki_request_duration_seconds_bucket{{method=\"{}\",le=\"{}\"}} {}", name, bound, bucket);
+            }
+            let _ = writeln!(text, "ilo_toki_request_duration_seconds_bucket{{method=\"{}\",le=\"+Inf\"}} {}", name, latency.count);
+            let _ = writeln!(text, "ilo_toki_request_duration_seconds_sum{{method=\"{}\"}} {}", name, latency.sum.as_secs_f64());
+            let _ = writeln!(text, "ilo_toki_request_duration_seconds_count{{method=\"{}\"}} {}", name, latency.count);
+        }
+
+        text.push_str("# HELP ilo_toki_request_errors_total Requests to the homeserver that failed.\n");
+        text.push_str("# TYPE ilo_toki_request_errors_total counter\n");
+        for (name, latency) in requests.iter() {
+            let _ = writeln!(text, "ilo_toki_request_errors_total{{method=\"{}\"}} {}", name, latency.errors);
+        }
+        text
+    }
+}
+
+/// A request counted as pending until this is dropped.
+pub struct PendingRequest<'a>(&'a Stats);
+
+impl Drop for PendingRequest<'_> {
+    fn drop(&mut self) {
+        self.0.pending.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Background task that measures how late the event loop wakes up from sleeping, which shows how busy it is.
+pub async fn lag_task(stats: Arc<Stats>, shutdown: CancellationToken) {
+    while !shutdown.is_cancelled() {
+        let start = Instant::now();
+        tokio::time::sleep(LAG_INTERVAL).await;
+        stats.record_lag(start.elapsed().saturating_sub(LAG_INTERVAL));
+    }
+}
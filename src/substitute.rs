@@ -0,0 +1,137 @@
+use std::ops::Range;
+
+use regex::{Regex, RegexBuilder};
+
+/// A vim-style `:%s/pattern/replacement/flags` substitution over the text in the input box.
+pub struct Substitution {
+    /// What to replace, which can't span more than one line.
+    pattern: Regex,
+
+    /// What to replace it with, with `&` and `\1` … `\9` turned into the `${0}` … `${9}` the regex crate expands.
+    replacement: String,
+
+    /// Whether every match on a line is replaced (the `g` flag), rather than just the first.
+    global: bool,
+}
+
+/// Parses a substitution command, returning None if the command isn't one, or why it's invalid.
+/// Any punctuation can separate the parts, as in vim, and `\` before it keeps it as part of the pattern.
+pub fn parse(command: &str) -> Option<Result<Substitution, String>> {
+    let rest = command.strip_prefix("%s")?;
+    let delimiter = match rest.chars().next() {
+        Some(c) if c.is_ascii_punctuation() && c != '\\' => c,
+        _ => return Some(Err(String::from("usage: %s/pattern/replacement/[flags]"))),
+    };
+
+    // Split into the pattern, the replacement, and the flags after them
+    let mut parts = vec![String::new()];
+    let mut chars = rest[delimiter.len_utf8()..].chars();
+    while let Some(c) = chars.next() {
+        if c == delimiter && parts.len() < 3 {
+            parts.push(String::new());
+            continue;
+        }
+
+        let part = parts.last_mut()?;
+        match c {
+            '\\' => match chars.next() {
+                Some(c) if c == delimiter => part.push(c),
+                Some(c) => {
+                    part.push('\\');
+                    part.push(c);
+                }
+                None => part.push('\\'),
+            },
+            c => part.push(c),
+        }
+    }
+
+    parts.resize(3, String::new());
+    let (pattern, replacement, flags) = (&parts[0], &parts[1], &parts[2]);
+    if pattern.is_empty() {
+        return Some(Err(String::from("no pattern to replace")));
+    }
+
+    let mut global = false;
+    let mut builder = RegexBuilder::new(pattern);
+    for flag in flags.chars() {
+        match flag {
+            'g' => global = true,
+            'i' => {
+                builder.case_insensitive(true);
+            }
+            _ => return Some(Err(format!("unknown flag {}", flag))),
+        }
+    }
+
+    Some(match builder.build() {
+        Ok(pattern) => Ok(Substitution {
+            pattern,
+            replacement: expand_replacement(replacement),
+            global,
+        }),
+        Err(error) => Err(format!("invalid pattern: {}", error)),
+    })
+}
+
+/// Turns a vim replacement into one for the regex crate: `&` is the whole match, `\1` … `\9` are groups, `\n` and
+/// `\t` are a newline and a tab, and `\` before anything else keeps it as is.
+fn expand_replacement(replacement: &str) -> String {
+    let mut expanded = String::new();
+    let mut chars = replacement.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '&' => expanded.push_str("${0}"),
+            '$' => expanded.push_str("$$"),
+            '\\' => match chars.next() {
+                Some(group @ '0'..='9') => {
+                    expanded.push_str("${");
+                    expanded.push(group);
+                    expanded.push('}');
+                }
+                Some('n') => expanded.push('\n'),
+                Some('t') => expanded.push('\t'),
+                Some('$') => expanded.push_str("$$"),
+                Some(c) => expanded.push(c),
+                None => expanded.push('\\'),
+            },
+            c => expanded.push(c),
+        }
+    }
+    expanded
+}
+
+impl Substitution {
+    /// Finds the bytes that would be replaced in the text, with what each would be replaced by.
+    pub fn matches(&self, text: &str) -> Vec<(Range<usize>, String)> {
+        let mut matches = vec![];
+        let mut start = 0;
+        for line in text.split('\n') {
+            let limit = if self.global { usize::MAX } else { 1 };
+            for captures in self.pattern.captures_iter(line).take(limit) {
+                if let Some(whole) = captures.get(0) {
+                    let mut replaced = String::new();
+                    captures.expand(&self.replacement, &mut replaced);
+                    matches.push((start + whole.start()..start + whole.end(), replaced));
+                }
+            }
+            start += line.len() + 1;
+        }
+        matches
+    }
+
+    /// Replaces the matches in the text, returning the new text and how many there were.
+    /// The same matches are used as for the preview, so the result is always what was shown.
+    pub fn apply(&self, text: &str) -> (String, usize) {
+        let matches = self.matches(text);
+        let mut replaced = String::with_capacity(text.len());
+        let mut last = 0;
+        for (range, replacement) in &matches {
+            replaced.push_str(&text[last..range.start]);
+            replaced.push_str(replacement);
+            last = range.end;
+        }
+        replaced.push_str(&text[last..]);
+        (replaced, matches.len())
+    }
+}
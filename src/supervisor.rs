@@ -0,0 +1,111 @@
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use tokio::{
+    task::{JoinError, JoinHandle},
+    time::Duration,
+};
+use tokio_util::sync::CancellationToken;
+
+/// The longest a restarting task waits before starting again.
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// How long a task has to run for before its backoff resets.
+const HEALTHY_RUN: Duration = Duration::from_secs(60);
+
+/// Watches background tasks, restarting the ones that can recover and shutting down when one that can't stops.
+#[derive(Clone)]
+pub struct Supervisor {
+    /// Cancelled to shut everything down.
+    shutdown: CancellationToken,
+
+    /// Why the supervisor shut everything down, if it did.
+    failure: Arc<Mutex<Option<String>>>,
+}
+
+/// Describes how a task stopped.
+fn describe(result: Result<Result<(), String>, JoinError>) -> String {
+    match result {
+        Ok(Ok(())) => String::from("stopped unexpectedly"),
+        Ok(Err(e)) => format!("failed: {}", e),
+        Err(e) if e.is_panic() => {
+            let panic = e.into_panic();
+            let message = panic
+                .downcast_ref::<&str>()
+                .map(|v| v.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            format!("panicked: {}", message)
+        }
+        Err(_) => String::from("was cancelled"),
+    }
+}
+
+impl Supervisor {
+    /// Creates a supervisor that shuts down with the given token.
+    pub fn new(shutdown: CancellationToken) -> Supervisor {
+        Supervisor {
+            shutdown,
+            failure: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Gets why the supervisor shut everything down, if it did.
+    pub fn failure(&self) -> Option<String> {
+        self.failure.lock().unwrap().clone()
+    }
+
+    /// Runs a task the program can't work without. If it stops before shutdown, everything shuts down with an error.
+    /// The returned handle finishes once the task does.
+    pub fn critical<F>(&self, name: &'static str, task: F) -> JoinHandle<()>
+    where
+        F: Future<Output = Result<(), String>> + Send + 'static,
+    {
+        let handle = tokio::spawn(task);
+        let supervisor = self.clone();
+        tokio::spawn(async move {
+            let result = handle.await;
+            if !supervisor.shutdown.is_cancelled() {
+                *supervisor.failure.lock().unwrap() = Some(format!("{} {}", name, describe(result)));
+                supervisor.shutdown.cancel();
+            }
+        })
+    }
+
+    /// Runs a task that can recover by starting again, restarting it with increasing delays whenever it stops
+    /// before shutdown. The task is told whether it's a restart.
+    pub fn restarting<M, F>(&self, name: &'static str, make: M, on_stop: impl Fn(String) + Send + 'static)
+    where
+        M: Fn(bool) -> F + Send + 'static,
+        F: Future<Output = Result<(), String>> + Send + 'static,
+    {
+        let shutdown = self.shutdown.clone();
+        tokio::spawn(async move {
+            let mut backoff = Duration::from_secs(1);
+            let mut restart = false;
+            loop {
+                let started = Instant::now();
+                let result = tokio::spawn(make(restart)).await;
+                if shutdown.is_cancelled() {
+                    break;
+                }
+
+                if started.elapsed() >= HEALTHY_RUN {
+                    backoff = Duration::from_secs(1);
+                }
+
+                on_stop(format!("{} {}, restarting in {}s", name, describe(result), backoff.as_secs()));
+                tokio::select! {
+                    _ = tokio::time::sleep(backoff) => (),
+                    _ = shutdown.cancelled() => break,
+                }
+
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+                restart = true;
+            }
+        });
+    }
+}
@@ -0,0 +1,348 @@
+use std::ops::{Deref, Range};
+
+use unicode_normalization::UnicodeNormalization;
+use unicode_segmentation::GraphemeCursor;
+use unicode_width::UnicodeWidthStr;
+
+use crate::wrap::{self, Line};
+
+/// Text being typed with a cursor in it, such as the input box or the command prompt.
+/// The cursor moves and deletes by grapheme clusters, so accented letters and emoji made of several characters are
+/// edited as one, and text is kept in NFC so it's sent the same way however it was typed.
+#[derive(Clone, Default)]
+pub struct TextBuffer {
+    /// The text.
+    text: String,
+
+    /// The byte position of the cursor, which is always at the edge of a grapheme cluster.
+    cursor: usize,
+}
+
+impl TextBuffer {
+    /// Creates a buffer holding the given text, with the cursor at the end.
+    pub fn new(text: &str) -> TextBuffer {
+        let text: String = text.nfc().collect();
+        TextBuffer {
+            cursor: text.len(),
+            text,
+        }
+    }
+
+    /// Gets the text.
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    /// Gets the byte position of the cursor.
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Moves the cursor to a byte position, or the start of the grapheme cluster it's inside of.
+    pub fn set_cursor(&mut self, pos: usize) {
+        let mut pos = pos.min(self.text.len());
+        while !self.is_boundary(pos) {
+            pos -= 1;
+        }
+        self.cursor = pos;
+    }
+
+    /// Replaces the text, moving the cursor to the end.
+    pub fn set(&mut self, text: &str) {
+        *self = TextBuffer::new(text);
+    }
+
+    /// Empties the buffer.
+    pub fn clear(&mut self) {
+        self.text.clear();
+        self.cursor = 0;
+    }
+
+    /// Empties the buffer, returning what was in it.
+    pub fn take(&mut self) -> String {
+        std::mem::take(self).text
+    }
+
+    /// Checks whether a byte position is between two grapheme clusters.
+    fn is_boundary(&self, pos: usize) -> bool {
+        self.text.is_char_boundary(pos)
+            && GraphemeCursor::new(pos, self.text.len(), true)
+                .is_boundary(&self.text, 0)
+                .unwrap_or(false)
+    }
+
+    /// Gets the byte position of the grapheme cluster before the cursor.
+    fn previous_boundary(&self) -> Option<usize> {
+        GraphemeCursor::new(self.cursor, self.text.len(), true)
+            .prev_boundary(&self.text, 0)
+            .ok()
+            .flatten()
+    }
+
+    /// Gets the byte position of the end of the grapheme cluster after the cursor.
+    fn next_boundary(&self) -> Option<usize> {
+        GraphemeCursor::new(self.cursor, self.text.len(), true)
+            .next_boundary(&self.text, 0)
+            .ok()
+            .flatten()
+    }
+
+    /// Moves the cursor back a grapheme cluster.
+    pub fn move_left(&mut self) {
+        if let Some(pos) = self.previous_boundary() {
+            self.cursor = pos;
+        }
+    }
+
+    /// Moves the cursor forward a grapheme cluster.
+    pub fn move_right(&mut self) {
+        if let Some(pos) = self.next_boundary() {
+            self.cursor = pos;
+        }
+    }
+
+    /// Deletes the grapheme cluster before the cursor, returning whether there was one.
+    pub fn backspace(&mut self) -> bool {
+        match self.previous_boundary() {
+            Some(pos) => {
+                self.text.replace_range(pos..self.cursor, "");
+                self.cursor = pos;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes the given bytes, moving the cursor to where they were and returning them.
+    fn remove(&mut self, range: Range<usize>) -> String {
+        let removed = self.text[range.clone()].to_owned();
+        self.text.replace_range(range.clone(), "");
+        self.cursor = range.start;
+        removed
+    }
+
+    /// Deletes the grapheme cluster after the cursor, returning it if there was one.
+    pub fn delete(&mut self) -> Option<String> {
+        self.next_boundary().map(|end| self.remove(self.cursor..end))
+    }
+
+    /// Deletes everything from the cursor to the end, returning it.
+    pub fn delete_to_end(&mut self) -> String {
+        self.remove(self.cursor..self.text.len())
+    }
+
+    /// Deletes everything from the start to the cursor, returning it.
+    pub fn delete_to_start(&mut self) -> String {
+        self.remove(0..self.cursor)
+    }
+
+    /// Deletes the word before the cursor along with any spaces after it, returning them.
+    pub fn delete_word(&mut self) -> String {
+        let before = self.text[..self.cursor].trim_end();
+        let start = before
+            .char_indices()
+            .rev()
+            .find(|(_, c)| c.is_whitespace())
+            .map(|(i, c)| i + c.len_utf8())
+            .unwrap_or(0);
+        self.remove(start..self.cursor)
+    }
+
+    /// Types text at the cursor. Combining characters join what's before them, as they would in NFC.
+    pub fn insert(&mut self, text: &str) {
+        // The whole text is normalized since combining marks at the start of what follows can join the typed text, so
+        // the cursor goes before whatever is left of what follows unchanged
+        let after = &self.text[self.cursor..];
+        let text: String = self.text[..self.cursor].chars().chain(text.chars()).chain(after.chars()).nfc().collect();
+        let kept = text.bytes().rev().zip(after.bytes().rev()).take_while(|(a, b)| a == b).count();
+        let cursor = text.len() - kept;
+        self.text = text;
+        self.set_cursor(cursor);
+    }
+
+    /// Replaces the given bytes with text, moving the cursor to the end of it.
+    pub fn replace_range(&mut self, range: Range<usize>, text: &str) {
+        self.remove(range);
+        self.insert(text);
+    }
+
+    /// Gets how many columns the text before the cursor takes up.
+    pub fn column(&self) -> usize {
+        self.text[..self.cursor].width()
+    }
+
+    /// Splits the text into lines at most the given number of columns wide, breaking between words where it can.
+    pub fn lines(&self, width: usize) -> Vec<Line> {
+        wrap::wrap(&self.text, width)
+    }
+
+    /// Gets the column and line of the cursor once the text is split into lines of the given width.
+    /// A cursor at the end of a full line stays at the end of that line rather than starting the next.
+    pub fn cursor_position(&self, width: usize) -> (usize, usize) {
+        let lines = self.lines(width);
+        let line = lines.iter().position(|v| self.cursor <= v.range.end).unwrap_or(lines.len() - 1);
+        let start = lines[line].range.start.min(self.cursor);
+        (self.text[start..self.cursor].width().min(width), line)
+    }
+}
+
+impl Deref for TextBuffer {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.text
+    }
+}
+
+impl PartialEq<&str> for TextBuffer {
+    fn eq(&self, other: &&str) -> bool {
+        self.text == *other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A family emoji, three people joined by zero width joiners.
+    const FAMILY: &str = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}";
+
+    /// The flags of France and Germany, each a pair of regional indicators.
+    const FLAGS: &str = "\u{1F1EB}\u{1F1F7}\u{1F1E9}\u{1F1EA}";
+
+    /// A q with an acute accent, which has no precomposed form so it stays two characters in NFC.
+    const Q_ACUTE: &str = "q\u{301}";
+
+    #[test]
+    fn new_normalizes_to_nfc() {
+        let buffer = TextBuffer::new("e\u{301}");
+        assert_eq!(buffer.as_str(), "\u{E9}");
+        assert_eq!(buffer.cursor(), 2);
+    }
+
+    #[test]
+    fn moves_over_zwj_sequences() {
+        let mut buffer = TextBuffer::new(&format!("a{}b", FAMILY));
+        buffer.move_left();
+        assert_eq!(buffer.cursor(), 1 + FAMILY.len());
+        buffer.move_left();
+        assert_eq!(buffer.cursor(), 1);
+        buffer.move_right();
+        assert_eq!(buffer.cursor(), 1 + FAMILY.len());
+    }
+
+    #[test]
+    fn moves_over_regional_indicator_pairs() {
+        let mut buffer = TextBuffer::new(FLAGS);
+        buffer.move_left();
+        assert_eq!(buffer.cursor(), FLAGS.len() / 2);
+        buffer.move_left();
+        assert_eq!(buffer.cursor(), 0);
+        buffer.move_left();
+        assert_eq!(buffer.cursor(), 0);
+    }
+
+    #[test]
+    fn moves_over_combining_marks() {
+        let mut buffer = TextBuffer::new(&format!("{}x", Q_ACUTE));
+        buffer.set_cursor(0);
+        buffer.move_right();
+        assert_eq!(buffer.cursor(), Q_ACUTE.len());
+        buffer.move_right();
+        buffer.move_right();
+        assert_eq!(buffer.cursor(), Q_ACUTE.len() + 1);
+    }
+
+    #[test]
+    fn backspace_removes_whole_clusters() {
+        let mut buffer = TextBuffer::new(&format!("a{}{}", Q_ACUTE, FAMILY));
+        assert!(buffer.backspace());
+        assert_eq!(buffer.as_str(), format!("a{}", Q_ACUTE));
+        assert!(buffer.backspace());
+        assert_eq!(buffer.as_str(), "a");
+        assert!(buffer.backspace());
+        assert!(!buffer.backspace());
+    }
+
+    #[test]
+    fn delete_removes_whole_clusters() {
+        let mut buffer = TextBuffer::new(FLAGS);
+        buffer.set_cursor(0);
+        assert_eq!(buffer.delete().as_deref(), Some("\u{1F1EB}\u{1F1F7}"));
+        assert_eq!(buffer.as_str(), "\u{1F1E9}\u{1F1EA}");
+        assert_eq!(buffer.delete().as_deref(), Some("\u{1F1E9}\u{1F1EA}"));
+        assert_eq!(buffer.delete(), None);
+
+        let mut buffer = TextBuffer::new(FAMILY);
+        buffer.set_cursor(0);
+        assert_eq!(buffer.delete().as_deref(), Some(FAMILY));
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn set_cursor_snaps_to_cluster_start() {
+        let mut buffer = TextBuffer::new(&format!("a{}b", Q_ACUTE));
+        buffer.set_cursor(2);
+        assert_eq!(buffer.cursor(), 1);
+
+        let mut buffer = TextBuffer::new(&format!("a{}", FAMILY));
+        buffer.set_cursor(1 + "\u{1F468}\u{200D}".len());
+        assert_eq!(buffer.cursor(), 1);
+
+        let mut buffer = TextBuffer::new(FLAGS);
+        buffer.set_cursor(4);
+        assert_eq!(buffer.cursor(), 0);
+        buffer.set_cursor(usize::MAX);
+        assert_eq!(buffer.cursor(), FLAGS.len());
+    }
+
+    #[test]
+    fn insert_composes_with_text_before() {
+        let mut buffer = TextBuffer::new("e");
+        buffer.insert("\u{301}");
+        assert_eq!(buffer.as_str(), "\u{E9}");
+        assert_eq!(buffer.cursor(), 2);
+    }
+
+    #[test]
+    fn insert_composes_with_text_after() {
+        let mut buffer = TextBuffer::new("\u{301}x");
+        buffer.set_cursor(0);
+        buffer.insert("e");
+        assert_eq!(buffer.as_str(), "\u{E9}x");
+        assert_eq!(buffer.cursor(), 2);
+    }
+
+    #[test]
+    fn insert_keeps_clusters_after_the_cursor() {
+        let mut buffer = TextBuffer::new(FLAGS);
+        buffer.set_cursor(FLAGS.len() / 2);
+        buffer.insert("a");
+        assert_eq!(buffer.as_str(), "\u{1F1EB}\u{1F1F7}a\u{1F1E9}\u{1F1EA}");
+        assert_eq!(buffer.cursor(), FLAGS.len() / 2 + 1);
+
+        buffer.insert(FAMILY);
+        assert_eq!(buffer.cursor(), FLAGS.len() / 2 + 1 + FAMILY.len());
+    }
+
+    #[test]
+    fn replace_range_stays_normalized() {
+        let mut buffer = TextBuffer::new("ab\u{301}");
+        buffer.replace_range(0..2, "e");
+        assert_eq!(buffer.as_str(), "\u{E9}");
+        assert_eq!(buffer.cursor(), 2);
+    }
+
+    #[test]
+    fn cursor_position_counts_columns_not_bytes() {
+        let buffer = TextBuffer::new(&format!("{}{}", Q_ACUTE, Q_ACUTE));
+        assert_eq!(buffer.cursor_position(10), (2, 0));
+
+        let buffer = TextBuffer::new("a\u{1F44D}");
+        assert_eq!(buffer.cursor_position(10), (3, 0));
+
+        let mut buffer = TextBuffer::new(&format!("a{}", Q_ACUTE));
+        buffer.move_left();
+        assert_eq!(buffer.cursor_position(10), (1, 0));
+    }
+}
@@ -0,0 +1,268 @@
+use tui::style::{Color, Modifier, Style};
+
+use crate::config::Config;
+
+/// The colours a terminal is able to display.
+#[derive(Copy, Clone, PartialEq)]
+pub enum ColorSupport {
+    /// No colours at all (`NO_COLOR` is set or the terminal is dumb).
+    None,
+
+    /// The 8 basic ANSI colours.
+    Basic,
+
+    /// The 256 colour palette.
+    Indexed,
+
+    /// 24 bit colour.
+    TrueColor,
+}
+
+impl ColorSupport {
+    /// Detects the colour support of the current terminal from the environment.
+    pub fn detect() -> ColorSupport {
+        if std::env::var_os("NO_COLOR").map(|v| !v.is_empty()).unwrap_or(false) {
+            return ColorSupport::None;
+        }
+
+        if let Ok(colorterm) = std::env::var("COLORTERM") {
+            if colorterm == "truecolor" || colorterm == "24bit" {
+                return ColorSupport::TrueColor;
+            }
+        }
+
+        match std::env::var("TERM") {
+            Ok(term) if term == "dumb" => ColorSupport::None,
+            Ok(term) if term.contains("256color") => ColorSupport::Indexed,
+            Ok(term) if term == "linux" || term == "vt100" || term == "vt220" || term == "ansi" => ColorSupport::Basic,
+            Ok(_) => ColorSupport::Indexed,
+            Err(_) if cfg!(windows) => ColorSupport::TrueColor,
+            Err(_) => ColorSupport::Basic,
+        }
+    }
+}
+
+/// The styles used to draw the UI.
+#[derive(Copy, Clone)]
+pub struct Theme {
+    /// The highlight for the selected item in a list.
+    pub selected: Style,
+
+    /// The highlight for an item that is about to be deleted or left.
+    pub danger: Style,
+
+    /// The highlight for the message being edited.
+    pub editing: Style,
+
+    /// The header of a message (author, timestamp, etc).
+    pub header: Style,
+
+    /// Monospace text in messages.
+    pub monospace: Style,
+
+    /// Rendered math in messages.
+    pub math: Style,
+
+    /// Translations of messages.
+    pub translation: Style,
+
+    /// Notices in the message list, such as a message being pinned.
+    pub notice: Style,
+
+    /// The mark on the input box while others are being told we're typing.
+    pub typing: Style,
+
+    /// Guilds and channels with messages the user hasn't seen.
+    pub unseen: Style,
+
+    /// Messages containing a highlight word.
+    pub highlight: Style,
+
+    /// Words added in an edit.
+    pub diff_added: Style,
+
+    /// Words removed in an edit.
+    pub diff_removed: Style,
+
+    /// The connection indicator when the homeserver responds quickly.
+    pub connection_good: Style,
+
+    /// The connection indicator when the homeserver responds slowly.
+    pub connection_slow: Style,
+
+    /// The connection indicator when the homeserver or event stream is down.
+    pub connection_down: Style,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            selected: Style::default().bg(Color::Yellow),
+            danger: Style::default().bg(Color::Red),
+            editing: Style::default().bg(Color::Green),
+            header: Style::default().add_modifier(Modifier::BOLD),
+            monospace: Style::default().bg(Color::Gray),
+            math: Style::default().fg(Color::Cyan).add_modifier(Modifier::ITALIC),
+            translation: Style::default().add_modifier(Modifier::ITALIC | Modifier::DIM),
+            notice: Style::default().fg(Color::DarkGray),
+            typing: Style::default().fg(Color::Cyan).add_modifier(Modifier::ITALIC),
+            unseen: Style::default().add_modifier(Modifier::BOLD),
+            highlight: Style::default().fg(Color::LightYellow),
+            diff_added: Style::default().fg(Color::Green).add_modifier(Modifier::UNDERLINED),
+            diff_removed: Style::default().fg(Color::Red).add_modifier(Modifier::CROSSED_OUT),
+            connection_good: Style::default().fg(Color::Green),
+            connection_slow: Style::default().fg(Color::Yellow),
+            connection_down: Style::default().fg(Color::Red),
+        }
+    }
+}
+
+impl Theme {
+    /// A theme with maximum contrast between foreground and background.
+    pub fn high_contrast() -> Theme {
+        Theme {
+            selected: Style::default().fg(Color::Black).bg(Color::White).add_modifier(Modifier::BOLD),
+            danger: Style::default().fg(Color::White).bg(Color::Red).add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+            editing: Style::default().fg(Color::White).bg(Color::Blue).add_modifier(Modifier::BOLD),
+            header: Style::default().fg(Color::White).add_modifier(Modifier::BOLD),
+            monospace: Style::default().fg(Color::White).bg(Color::Black).add_modifier(Modifier::BOLD),
+            math: Style::default().fg(Color::White).add_modifier(Modifier::ITALIC | Modifier::BOLD),
+            translation: Style::default().fg(Color::White).add_modifier(Modifier::ITALIC),
+            notice: Style::default().fg(Color::White).add_modifier(Modifier::ITALIC),
+            typing: Style::default().fg(Color::White).add_modifier(Modifier::ITALIC | Modifier::BOLD),
+            unseen: Style::default().fg(Color::White).add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+            highlight: Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD),
+            diff_added: Style::default().fg(Color::White).add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+            diff_removed: Style::default().fg(Color::White).add_modifier(Modifier::DIM | Modifier::CROSSED_OUT),
+            connection_good: Style::default().fg(Color::Green).add_modifier(Modifier::BOLD),
+            connection_slow: Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD),
+            connection_down: Style::default().fg(Color::White).bg(Color::Red).add_modifier(Modifier::BOLD),
+        }
+    }
+
+    /// A theme safe for deuteranopia and protanopia, based on the Okabe-Ito palette.
+    /// Red and green are never used to tell things apart, and every highlight also differs by modifier.
+    pub fn colorblind() -> Theme {
+        Theme {
+            selected: Style::default().fg(Color::Black).bg(Color::Rgb(86, 180, 233)),
+            danger: Style::default().fg(Color::Black).bg(Color::Rgb(230, 159, 0)).add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+            editing: Style::default().fg(Color::White).bg(Color::Rgb(0, 114, 178)).add_modifier(Modifier::ITALIC),
+            header: Style::default().add_modifier(Modifier::BOLD),
+            monospace: Style::default().bg(Color::Rgb(64, 64, 64)),
+            math: Style::default().fg(Color::Rgb(204, 121, 167)).add_modifier(Modifier::ITALIC),
+            translation: Style::default().add_modifier(Modifier::ITALIC | Modifier::DIM),
+            notice: Style::default().add_modifier(Modifier::DIM),
+            typing: Style::default().fg(Color::Rgb(86, 180, 233)).add_modifier(Modifier::ITALIC),
+            unseen: Style::default().add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+            highlight: Style::default().fg(Color::Rgb(240, 228, 66)).add_modifier(Modifier::BOLD),
+            diff_added: Style::default().fg(Color::Rgb(86, 180, 233)).add_modifier(Modifier::UNDERLINED),
+            diff_removed: Style::default().fg(Color::Rgb(213, 94, 0)).add_modifier(Modifier::CROSSED_OUT),
+            connection_good: Style::default().fg(Color::Rgb(86, 180, 233)),
+            connection_slow: Style::default().fg(Color::Rgb(240, 228, 66)).add_modifier(Modifier::ITALIC),
+            connection_down: Style::default().fg(Color::Rgb(213, 94, 0)).add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+        }
+    }
+
+    /// Gets a built in theme by name.
+    pub fn from_name(name: &str) -> Option<Theme> {
+        match name {
+            "default" => Some(Theme::default()),
+            "high-contrast" => Some(Theme::high_contrast()),
+            "colorblind" | "deuteranopia" | "protanopia" => Some(Theme::colorblind()),
+            _ => None,
+        }
+    }
+
+    /// Gets the theme selected in the config, adjusted to what the terminal can display.
+    pub fn from_config(config: &Config) -> Theme {
+        Theme::from_name(&config.theme)
+            .unwrap_or_default()
+            .degrade(ColorSupport::detect())
+    }
+
+    /// Adjusts the theme to what the terminal can display.
+    pub fn degrade(self, support: ColorSupport) -> Theme {
+        let degrade = |style: Style, fallback: Modifier| degrade_style(style, support, fallback);
+        Theme {
+            selected: degrade(self.selected, Modifier::REVERSED),
+            danger: degrade(self.danger, Modifier::REVERSED | Modifier::BOLD | Modifier::UNDERLINED),
+            editing: degrade(self.editing, Modifier::REVERSED | Modifier::ITALIC),
+            header: degrade(self.header, Modifier::BOLD),
+            monospace: degrade(self.monospace, Modifier::empty()),
+            math: degrade(self.math, Modifier::ITALIC),
+            translation: degrade(self.translation, Modifier::DIM),
+            notice: degrade(self.notice, Modifier::DIM),
+            typing: degrade(self.typing, Modifier::ITALIC),
+            unseen: degrade(self.unseen, Modifier::BOLD),
+            highlight: degrade(self.highlight, Modifier::BOLD | Modifier::UNDERLINED),
+            diff_added: degrade(self.diff_added, Modifier::UNDERLINED),
+            diff_removed: degrade(self.diff_removed, Modifier::CROSSED_OUT),
+            connection_good: degrade(self.connection_good, Modifier::empty()),
+            connection_slow: degrade(self.connection_slow, Modifier::ITALIC),
+            connection_down: degrade(self.connection_down, Modifier::REVERSED | Modifier::BOLD),
+        }
+    }
+}
+
+/// Adjusts a style to the given colour support.
+/// If colours are unavailable, the fallback modifiers are used to keep the style distinguishable.
+fn degrade_style(style: Style, support: ColorSupport, fallback: Modifier) -> Style {
+    let convert = |color: Option<Color>| color.map(|v| degrade_color(v, support));
+    let mut result = Style::default()
+        .add_modifier(style.add_modifier)
+        .remove_modifier(style.sub_modifier);
+
+    if support == ColorSupport::None {
+        if style.fg.is_some() || style.bg.is_some() {
+            result = result.add_modifier(fallback);
+        }
+    } else {
+        if let Some(fg) = convert(style.fg) {
+            result = result.fg(fg);
+        }
+
+        if let Some(bg) = convert(style.bg) {
+            result = result.bg(bg);
+        }
+    }
+
+    result
+}
+
+/// Converts a colour to the closest one the terminal supports.
+fn degrade_color(color: Color, support: ColorSupport) -> Color {
+    match (support, color) {
+        (ColorSupport::TrueColor, _) | (ColorSupport::None, _) => color,
+
+        (ColorSupport::Indexed, Color::Rgb(r, g, b)) => {
+            let scale = |v: u8| ((v as u16 * 5 + 127) / 255) as u8;
+            Color::Indexed(16 + 36 * scale(r) + 6 * scale(g) + scale(b))
+        }
+        (ColorSupport::Indexed, _) => color,
+
+        (ColorSupport::Basic, Color::Rgb(r, g, b)) => {
+            let bit = |v: u8| v >= 128;
+            match (bit(r), bit(g), bit(b)) {
+                (false, false, false) => Color::Black,
+                (true, false, false) => Color::Red,
+                (false, true, false) => Color::Green,
+                (true, true, false) => Color::Yellow,
+                (false, false, true) => Color::Blue,
+                (true, false, true) => Color::Magenta,
+                (false, true, true) => Color::Cyan,
+                (true, true, true) => Color::White,
+            }
+        }
+        (ColorSupport::Basic, Color::Indexed(i)) if i < 8 => color,
+        (ColorSupport::Basic, Color::Indexed(_)) => Color::White,
+        (ColorSupport::Basic, Color::DarkGray) => Color::Black,
+        (ColorSupport::Basic, Color::Gray) => Color::White,
+        (ColorSupport::Basic, Color::LightRed) => Color::Red,
+        (ColorSupport::Basic, Color::LightGreen) => Color::Green,
+        (ColorSupport::Basic, Color::LightYellow) => Color::Yellow,
+        (ColorSupport::Basic, Color::LightBlue) => Color::Blue,
+        (ColorSupport::Basic, Color::LightMagenta) => Color::Magenta,
+        (ColorSupport::Basic, Color::LightCyan) => Color::Cyan,
+        (ColorSupport::Basic, _) => color,
+    }
+}
@@ -0,0 +1,162 @@
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Instant,
+};
+
+use tokio::time::Duration;
+
+/// What state a transfer is in.
+#[derive(Clone, PartialEq)]
+pub enum TransferStatus {
+    /// The transfer is in progress.
+    Running,
+
+    /// The transfer finished successfully.
+    Done,
+
+    /// The transfer was cancelled by the user.
+    Cancelled,
+
+    /// The transfer failed with the given error.
+    Failed(String),
+}
+
+/// An upload or download whose progress is shown in the transfers pane.
+pub struct Transfer {
+    /// The name of the file being transferred.
+    pub name: String,
+
+    /// Whether this is an upload rather than a download.
+    pub upload: bool,
+
+    /// When the transfer started.
+    started: Instant,
+
+    /// The size of the file in bytes, or 0 if unknown.
+    total: AtomicU64,
+
+    /// The number of bytes transferred so far.
+    done: AtomicU64,
+
+    /// Whether the transfer is paused.
+    paused: AtomicBool,
+
+    /// Whether the user cancelled the transfer.
+    cancelled: AtomicBool,
+
+    /// What state the transfer is in.
+    status: Mutex<TransferStatus>,
+}
+
+impl Transfer {
+    /// Creates a new running transfer.
+    pub fn new(name: impl Into<String>, upload: bool) -> Transfer {
+        Transfer {
+            name: name.into(),
+            upload,
+            started: Instant::now(),
+            total: AtomicU64::new(0),
+            done: AtomicU64::new(0),
+            paused: AtomicBool::new(false),
+            cancelled: AtomicBool::new(false),
+            status: Mutex::new(TransferStatus::Running),
+        }
+    }
+
+    /// Sets the size of the file in bytes.
+    pub fn set_total(&self, total: u64) {
+        self.total.store(total, Ordering::Relaxed);
+    }
+
+    /// Records that more bytes were transferred.
+    pub fn progress(&self, bytes: usize) {
+        self.done.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Waits while the transfer is paused, failing if it was cancelled.
+    /// Transfers call this between chunks.
+    pub async fn checkpoint(&self) -> io::Result<()> {
+        loop {
+            if self.cancelled.load(Ordering::Acquire) {
+                return Err(io::Error::new(io::ErrorKind::Interrupted, "cancelled"));
+            }
+
+            if !self.paused.load(Ordering::Acquire) {
+                return Ok(());
+            }
+
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+
+    /// Marks the transfer as finished with the given result.
+    pub fn finish<T>(&self, result: &io::Result<T>) {
+        let status = match result {
+            Ok(_) => TransferStatus::Done,
+            Err(_) if self.cancelled.load(Ordering::Acquire) => TransferStatus::Cancelled,
+            Err(e) => TransferStatus::Failed(e.to_string()),
+        };
+        *self.status.lock().unwrap() = status;
+    }
+
+    /// Checks whether the transfer is still in progress.
+    pub fn is_running(&self) -> bool {
+        *self.status.lock().unwrap() == TransferStatus::Running
+    }
+
+    /// Pauses the transfer, or resumes it if it's paused.
+    pub fn toggle_pause(&self) {
+        self.paused.fetch_xor(true, Ordering::AcqRel);
+    }
+
+    /// Cancels the transfer.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Release);
+    }
+
+    /// Describes the transfer's progress and speed in one line.
+    pub fn describe(&self) -> String {
+        let done = self.done.load(Ordering::Relaxed);
+        let total = self.total.load(Ordering::Relaxed);
+        let arrow = if self.upload { "↑" } else { "↓" };
+        let progress = if total == 0 {
+            format_bytes(done)
+        } else {
+            format!("{}% of {}", done * 100 / total, format_bytes(total))
+        };
+
+        let status = match &*self.status.lock().unwrap() {
+            TransferStatus::Running if self.paused.load(Ordering::Acquire) => String::from("paused"),
+            TransferStatus::Running => {
+                let elapsed = self.started.elapsed().as_secs_f64().max(0.001);
+                format!("{}/s", format_bytes((done as f64 / elapsed) as u64))
+            }
+            TransferStatus::Done => String::from("done"),
+            TransferStatus::Cancelled => String::from("cancelled"),
+            TransferStatus::Failed(e) => format!("failed: {}", e),
+        };
+
+        format!("{} {} - {} - {}", arrow, self.name, progress, status)
+    }
+}
+
+/// Formats a number of bytes with a binary unit.
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
@@ -0,0 +1,73 @@
+use std::{io, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{config::TranslateConfig, vault};
+
+/// The body of a request to LibreTranslate.
+#[derive(Serialize)]
+struct TranslateRequest<'a> {
+    q: &'a str,
+    source: &'a str,
+    target: &'a str,
+    format: &'a str,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    api_key: Option<&'a str>,
+}
+
+/// The response from LibreTranslate.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TranslateResponse {
+    translated_text: String,
+}
+
+/// Gets the path the api key set with `:translate-key` is saved at, encrypted with the rest of the saved data.
+fn key_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|v| v.join("ilo-toki/translate-key"))
+}
+
+/// Saves the api key, or forgets it if there's none.
+pub fn save_key(key: Option<&str>) -> io::Result<()> {
+    let path = key_path().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no data directory"))?;
+    match key {
+        Some(key) => vault::write(&path, key),
+        None => match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        },
+    }
+}
+
+/// Gets the api key from the config, or the one saved with `:translate-key`.
+fn api_key(config: &TranslateConfig) -> Option<String> {
+    config
+        .api_key
+        .clone()
+        .or_else(|| key_path().and_then(|v| vault::read_to_string(&v).ok()))
+        .filter(|v| !v.is_empty())
+}
+
+/// Translates the given text into the target language using the configured LibreTranslate instance.
+pub async fn translate(config: &TranslateConfig, text: &str, target: &str) -> reqwest::Result<String> {
+    let api_key = api_key(config);
+    let request = TranslateRequest {
+        q: text,
+        source: "auto",
+        target,
+        format: "text",
+        api_key: api_key.as_deref(),
+    };
+
+    let response: TranslateResponse = crate::net::client()
+        .post(format!("{}/translate", config.url.trim_end_matches('/')))
+        .json(&request)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    Ok(response.translated_text)
+}
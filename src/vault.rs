@@ -0,0 +1,348 @@
+use std::{
+    fs::OpenOptions,
+    io::{self, Write},
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use argon2::Argon2;
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use crossterm::event::{Event, KeyCode, KeyModifiers};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+use tui::{
+    backend::CrosstermBackend,
+    layout::Alignment,
+    text::{Span, Spans, Text},
+    widgets::{Block, Borders, Paragraph},
+    Terminal,
+};
+
+use crate::{modal::centered_rect, theme::Theme};
+
+/// What every encrypted file starts with, so files saved before encryption was turned on can still be read.
+const MAGIC: &[u8] = b"ilo-toki vault 1\n";
+
+/// The length of the random salt the key is derived with.
+const SALT_LENGTH: usize = 16;
+
+/// The length of the nonce before each encrypted file's contents.
+const NONCE_LENGTH: usize = 12;
+
+/// Encrypted into the vault file, so a wrong passphrase can be told apart from a right one.
+const CHECK: &[u8] = b"ilo toki";
+
+/// Added to the name of a file while it's being written.
+const PARTIAL: &str = ".part";
+
+/// The cipher saved data is encrypted with, once the passphrase has been typed.
+static CIPHER: OnceLock<ChaCha20Poly1305> = OnceLock::new();
+
+/// Gets the path of the vault file, which holds the salt and a check that the passphrase is right.
+fn vault_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|v| v.join("ilo-toki/vault"))
+}
+
+/// Derives the cipher for a passphrase with Argon2id, which is slow on purpose so passphrases can't be guessed quickly.
+fn derive(passphrase: &str, salt: &[u8]) -> Option<ChaCha20Poly1305> {
+    let mut key = [0; 32];
+    Argon2::default().hash_password_into(passphrase.as_bytes(), salt, &mut key).ok()?;
+    Some(ChaCha20Poly1305::new(Key::from_slice(&key)))
+}
+
+/// Encrypts bytes with a new nonce, giving the nonce followed by the ciphertext.
+fn seal(cipher: &ChaCha20Poly1305, plaintext: &[u8]) -> io::Result<Vec<u8>> {
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext)
+        .map_err(|_| io::Error::new(io::ErrorKind::Other, "could not encrypt"))?;
+    Ok([nonce.as_slice(), &ciphertext].concat())
+}
+
+/// Decrypts a nonce followed by ciphertext, or gives None if it was tampered with or the key is wrong.
+fn open(cipher: &ChaCha20Poly1305, sealed: &[u8]) -> Option<Vec<u8>> {
+    if sealed.len() < NONCE_LENGTH {
+        return None;
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LENGTH);
+    cipher.decrypt(Nonce::from_slice(nonce), ciphertext).ok()
+}
+
+/// Turns the bytes of a saved file into text, decrypting it if it's encrypted.
+fn decode(bytes: Vec<u8>, cipher: Option<&ChaCha20Poly1305>) -> io::Result<String> {
+    let bytes = match bytes.strip_prefix(MAGIC) {
+        Some(sealed) => cipher
+            .and_then(|v| open(v, sealed))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::PermissionDenied, "file is encrypted"))?,
+        None => bytes,
+    };
+    String::from_utf8(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Turns text into the bytes of a saved file, encrypting it if there's a cipher.
+fn encode(text: &str, cipher: Option<&ChaCha20Poly1305>) -> io::Result<Vec<u8>> {
+    match cipher {
+        Some(cipher) => Ok([MAGIC, &seal(cipher, text.as_bytes())?].concat()),
+        None => Ok(text.as_bytes().to_vec()),
+    }
+}
+
+/// Reads a file from the data directory, decrypting it if it was saved encrypted.
+pub fn read_to_string(path: &Path) -> io::Result<String> {
+    decode(std::fs::read(path)?, CIPHER.get())
+}
+
+/// Writes a file to the data directory, encrypted if encryption is turned on.
+pub fn write(path: &Path, text: &str) -> io::Result<()> {
+    replace(path, &encode(text, CIPHER.get())?)
+}
+
+/// Writes bytes next to a file and then moves them over it, so it's never left half written.
+/// The file is only readable by the user, whether or not it's encrypted.
+fn replace(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(PARTIAL);
+
+    // A write that never finished may have left the file behind with other permissions
+    std::fs::remove_file(&partial).ok();
+    let mut file = OpenOptions::new().write(true).create_new(true).mode(0o600).open(&partial)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    std::fs::rename(&partial, path)
+}
+
+/// Gets the files saved in the data directory, leaving out the vault file and writes that never finished.
+fn saved_files() -> Vec<PathBuf> {
+    let entries = match dirs::data_dir().and_then(|v| std::fs::read_dir(v.join("ilo-toki")).ok()) {
+        Some(entries) => entries,
+        None => return vec![],
+    };
+
+    let vault = vault_path();
+    entries
+        .filter_map(Result::ok)
+        .map(|v| v.path())
+        .filter(|v| v.is_file() && Some(v) != vault.as_ref() && !v.to_string_lossy().ends_with(PARTIAL))
+        .collect()
+}
+
+/// Rewrites every file in the data directory, decrypting them with one cipher and encrypting them with another.
+fn rewrite_all(from: &ChaCha20Poly1305, to: Option<&ChaCha20Poly1305>) {
+    for path in saved_files() {
+        std::fs::read(&path)
+            .and_then(|v| decode(v, Some(from)))
+            .and_then(|text| encode(&text, to))
+            .and_then(|bytes| replace(&path, &bytes))
+            .ok();
+    }
+}
+
+/// Checks whether any saved file is encrypted.
+fn any_encrypted() -> bool {
+    saved_files()
+        .iter()
+        .any(|v| std::fs::read(v).map(|v| v.starts_with(MAGIC)).unwrap_or(false))
+}
+
+/// The passphrase prompt shown before anything saved is read.
+#[derive(Default)]
+struct Prompt {
+    /// What's been typed so far.
+    input: String,
+
+    /// The passphrase typed the first time, while a new one is being confirmed.
+    first: Option<String>,
+
+    /// What went wrong with the last passphrase typed, if anything.
+    error: Option<&'static str>,
+}
+
+impl Prompt {
+    /// Renders the prompt in the middle of the screen.
+    fn render(&self, terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, theme: &Theme, choosing: bool) -> io::Result<()> {
+        let prompt = match (choosing, &self.first) {
+            (false, _) => "passphrase: ",
+            (true, None) => "choose a passphrase: ",
+            (true, Some(_)) => "type it again: ",
+        };
+        let mut lines = vec![
+            Spans::from(Span::styled("saved data is encrypted", theme.header)),
+            Spans::from(""),
+            Spans::from(format!("{}{}", prompt, "•".repeat(self.input.chars().count()))),
+        ];
+
+        if let Some(error) = self.error {
+            lines.push(Spans::from(""));
+            lines.push(Spans::from(Span::styled(error, theme.danger)));
+        }
+
+        terminal.draw(|f| {
+            let rect = centered_rect(44.min(f.size().width), lines.len() as u16 + 2, f.size());
+            let paragraph = Paragraph::new(Text::from(lines))
+                .block(Block::default().borders(Borders::ALL))
+                .alignment(Alignment::Center);
+            f.render_widget(paragraph, rect);
+        })?;
+        Ok(())
+    }
+}
+
+/// Asks for the passphrase saved data is encrypted with, if encryption is turned on or data was encrypted before.
+/// Everything saved is then encrypted or decrypted to match whether it's turned on, and turning it off forgets the
+/// passphrase. Returns false if the user quit instead of typing it.
+pub async fn unlock(
+    enabled: bool,
+    theme: &Theme,
+    shutdown: &CancellationToken,
+    input_events: &mut mpsc::Receiver<Event>,
+) -> io::Result<bool> {
+    let vault = match vault_path() {
+        Some(vault) => vault,
+        None => return Ok(true),
+    };
+    let existing = std::fs::read(&vault).ok().and_then(|v| v.strip_prefix(MAGIC).map(<[u8]>::to_vec));
+
+    // Choosing a new passphrase would make the data encrypted with the old one unreadable for good
+    if existing.is_none() && any_encrypted() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "saved data is encrypted but {} is missing; restore it, or delete the data directory and log in again",
+                vault.display()
+            ),
+        ));
+    }
+
+    if !enabled && existing.is_none() {
+        return Ok(true);
+    }
+
+    let mut terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
+    crossterm::terminal::enable_raw_mode()?;
+    terminal.clear()?;
+
+    let choosing = existing.is_none();
+    let mut prompt = Prompt::default();
+    let cipher = loop {
+        prompt.render(&mut terminal, theme, choosing)?;
+        let key = tokio::select! {
+            event = input_events.recv() => match event {
+                Some(Event::Key(key)) => key,
+                Some(_) => continue,
+                None => return Ok(false),
+            },
+            _ = shutdown.cancelled() => return Ok(false),
+        };
+
+        match key.code {
+            KeyCode::Esc => return Ok(false),
+            KeyCode::Char('c') if key.modifiers == KeyModifiers::CONTROL => return Ok(false),
+            KeyCode::Char(c) => prompt.input.push(c),
+            KeyCode::Backspace => {
+                prompt.input.pop();
+            }
+
+            KeyCode::Enter if !prompt.input.is_empty() => {
+                let passphrase = std::mem::take(&mut prompt.input);
+                prompt.error = None;
+                match (&existing, prompt.first.take()) {
+                    // Check the passphrase against what the vault was made with
+                    (Some(existing), _) => {
+                        let (salt, check) = existing.split_at(SALT_LENGTH.min(existing.len()));
+                        match derive(&passphrase, salt).filter(|v| open(v, check).as_deref() == Some(CHECK)) {
+                            Some(cipher) => break cipher,
+                            None => prompt.error = Some("wrong passphrase"),
+                        }
+                    }
+
+                    // Ask for a new passphrase twice, since there's no getting the data back if it's mistyped
+                    (None, None) => prompt.first = Some(passphrase),
+                    (None, Some(first)) if first != passphrase => prompt.error = Some("the passphrases don't match"),
+                    (None, Some(_)) => {
+                        let mut salt = [0; SALT_LENGTH];
+                        OsRng.fill_bytes(&mut salt);
+                        if let Some(cipher) = derive(&passphrase, &salt) {
+                            let header = [MAGIC, &salt, &seal(&cipher, CHECK)?].concat();
+                            if let Some(parent) = vault.parent() {
+                                std::fs::create_dir_all(parent)?;
+                            }
+                            replace(&vault, &header)?;
+                            break cipher;
+                        }
+                    }
+                }
+            }
+
+            _ => (),
+        }
+    };
+
+    if enabled {
+        rewrite_all(&cipher, Some(&cipher));
+        let _ = CIPHER.set(cipher);
+    } else {
+        rewrite_all(&cipher, None);
+        std::fs::remove_file(&vault).ok();
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cipher(key: u8) -> ChaCha20Poly1305 {
+        ChaCha20Poly1305::new(Key::from_slice(&[key; 32]))
+    }
+
+    #[test]
+    fn encrypted_files_round_trip() {
+        let cipher = cipher(1);
+        let bytes = encode("toki!", Some(&cipher)).unwrap();
+        assert!(bytes.starts_with(MAGIC));
+        assert!(!bytes.windows(5).any(|v| v == b"toki!"));
+        assert_eq!(decode(bytes, Some(&cipher)).unwrap(), "toki!");
+    }
+
+    #[test]
+    fn wrong_keys_are_refused() {
+        let bytes = encode("toki!", Some(&cipher(1))).unwrap();
+        assert_eq!(decode(bytes.clone(), Some(&cipher(2))).unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+        assert_eq!(decode(bytes, None).unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+    }
+
+    #[test]
+    fn tampered_files_are_refused() {
+        let cipher = cipher(1);
+        let mut bytes = encode("toki!", Some(&cipher)).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        assert!(decode(bytes, Some(&cipher)).is_err());
+        assert!(decode([MAGIC, b"short"].concat(), Some(&cipher)).is_err());
+    }
+
+    #[test]
+    fn plaintext_passes_through() {
+        assert_eq!(encode("toki!", None).unwrap(), b"toki!");
+        assert_eq!(decode(b"toki!".to_vec(), None).unwrap(), "toki!");
+        // Files saved before encryption was turned on are still read once it is
+        assert_eq!(decode(b"toki!".to_vec(), Some(&cipher(1))).unwrap(), "toki!");
+    }
+
+    #[test]
+    fn replaced_files_are_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("saved.json");
+        replace(&path, b"first").unwrap();
+        replace(&path, b"second").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"second");
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        assert!(!dir.path().join("saved.json.part").exists());
+    }
+}
@@ -0,0 +1,233 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use cpal::{
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+    InputCallbackInfo, SampleFormat,
+};
+use ogg::{PacketWriteEndInfo, PacketWriter};
+use opus::{Application, Channels, Encoder};
+
+/// The sample rate voice messages are encoded at.
+const SAMPLE_RATE: u32 = 48000;
+
+/// The number of samples in an opus frame (20ms).
+const FRAME_SIZE: usize = 960;
+
+/// The number of samples the decoder should skip at the start of the stream.
+const PRE_SKIP: u16 = 312;
+
+/// Audio captured from the microphone.
+struct Captured {
+    /// The interleaved samples.
+    samples: Vec<f32>,
+
+    /// The sample rate of the samples.
+    sample_rate: u32,
+
+    /// The number of interleaved channels.
+    channels: u16,
+}
+
+/// A recording from the default microphone, running on its own thread.
+/// The recording is cancelled when dropped.
+pub struct Recording {
+    /// Tells the recording thread to stop.
+    stop: Arc<AtomicBool>,
+
+    /// The loudness of the most recent audio, stored as the bits of an `f32`.
+    level: Arc<AtomicU32>,
+
+    /// The recording thread.
+    handle: Option<JoinHandle<Result<Captured, String>>>,
+}
+
+impl Recording {
+    /// Starts recording from the default microphone.
+    pub fn start() -> Result<Recording, String> {
+        let stop = Arc::new(AtomicBool::new(false));
+        let level = Arc::new(AtomicU32::new(0));
+        let (ready_tx, ready_rx) = mpsc::channel();
+
+        let handle = {
+            let stop = stop.clone();
+            let level = level.clone();
+
+            // cpal streams can't be sent between threads, so the stream lives on its own thread
+            std::thread::spawn(move || {
+                let samples = Arc::new(Mutex::new(vec![]));
+                let stream = match open_stream(samples.clone(), level) {
+                    Ok(v) => v,
+                    Err(e) => {
+                        let _ = ready_tx.send(Err(e.clone()));
+                        return Err(e);
+                    }
+                };
+                let _ = ready_tx.send(Ok(()));
+
+                while !stop.load(Ordering::Acquire) {
+                    std::thread::sleep(Duration::from_millis(20));
+                }
+
+                let (sample_rate, channels) = stream.1;
+                drop(stream);
+                let samples = std::mem::take(&mut *samples.lock().unwrap());
+                Ok(Captured {
+                    samples,
+                    sample_rate,
+                    channels,
+                })
+            })
+        };
+
+        ready_rx.recv().map_err(|e| e.to_string())??;
+        Ok(Recording {
+            stop,
+            level,
+            handle: Some(handle),
+        })
+    }
+
+    /// Gets the loudness of the most recent audio, from 0 to 1.
+    pub fn level(&self) -> f32 {
+        f32::from_bits(self.level.load(Ordering::Relaxed)).min(1.0)
+    }
+
+    /// Stops recording and encodes the audio as ogg/opus. This blocks while encoding.
+    pub fn finish(mut self) -> Result<Vec<u8>, String> {
+        self.stop.store(true, Ordering::Release);
+        let captured = self
+            .handle
+            .take()
+            .ok_or_else(|| String::from("recording already finished"))?
+            .join()
+            .map_err(|_| String::from("recording thread panicked"))??;
+
+        encode(&captured)
+    }
+}
+
+impl Drop for Recording {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+    }
+}
+
+/// Opens an input stream on the default microphone that appends to the given samples.
+/// Returns the stream along with its sample rate and channel count.
+fn open_stream(samples: Arc<Mutex<Vec<f32>>>, level: Arc<AtomicU32>) -> Result<(cpal::Stream, (u32, u16)), String> {
+    let device = cpal::default_host()
+        .default_input_device()
+        .ok_or_else(|| String::from("no microphone found"))?;
+    let config = device.default_input_config().map_err(|e| e.to_string())?;
+    let format = (config.sample_rate().0, config.channels());
+    let on_error = |_| ();
+
+    let stream = match config.sample_format() {
+        SampleFormat::F32 => device.build_input_stream(
+            &config.into(),
+            move |data: &[f32], _: &InputCallbackInfo| push_samples(&samples, &level, data.iter().copied()),
+            on_error,
+        ),
+        SampleFormat::I16 => device.build_input_stream(
+            &config.into(),
+            move |data: &[i16], _: &InputCallbackInfo| push_samples(&samples, &level, data.iter().map(|&v| v as f32 / i16::MAX as f32)),
+            on_error,
+        ),
+        SampleFormat::U16 => device.build_input_stream(
+            &config.into(),
+            move |data: &[u16], _: &InputCallbackInfo| push_samples(&samples, &level, data.iter().map(|&v| (v as f32 - 32768.0) / 32768.0)),
+            on_error,
+        ),
+    }
+    .map_err(|e| e.to_string())?;
+
+    stream.play().map_err(|e| e.to_string())?;
+    Ok((stream, format))
+}
+
+/// Appends captured samples and updates the level meter with their loudness.
+fn push_samples(samples: &Mutex<Vec<f32>>, level: &AtomicU32, data: impl Iterator<Item = f32>) {
+    let mut samples = samples.lock().unwrap();
+    let start = samples.len();
+    samples.extend(data);
+
+    let chunk = &samples[start..];
+    if !chunk.is_empty() {
+        let rms = (chunk.iter().map(|v| v * v).sum::<f32>() / chunk.len() as f32).sqrt();
+        level.store((rms * 4.0).to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// Encodes captured audio as mono ogg/opus.
+fn encode(captured: &Captured) -> Result<Vec<u8>, String> {
+    // Mix down to mono
+    let channels = captured.channels.max(1) as usize;
+    let mono: Vec<f32> = captured
+        .samples
+        .chunks(channels)
+        .map(|v| v.iter().sum::<f32>() / v.len() as f32)
+        .collect();
+
+    // Resample to 48kHz
+    let ratio = captured.sample_rate as f64 / SAMPLE_RATE as f64;
+    let length = (mono.len() as f64 / ratio) as usize;
+    let mut pcm: Vec<i16> = (0..length)
+        .map(|i| {
+            let position = i as f64 * ratio;
+            let index = position as usize;
+            let next = mono.get(index + 1).copied().unwrap_or(0.0);
+            let current = mono.get(index).copied().unwrap_or(0.0);
+            let sample = current + (next - current) * (position - index as f64) as f32;
+            (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16
+        })
+        .collect();
+    let frames = pcm.len().div_ceil(FRAME_SIZE);
+    pcm.resize(frames.max(1) * FRAME_SIZE, 0);
+
+    let mut encoder = Encoder::new(SAMPLE_RATE, Channels::Mono, Application::Voip).map_err(|e| e.to_string())?;
+    let mut result = vec![];
+    let mut writer = PacketWriter::new(&mut result);
+    let serial = 0x696c6f;
+
+    // Identification header
+    let mut head = b"OpusHead".to_vec();
+    head.push(1);
+    head.push(1);
+    head.extend(PRE_SKIP.to_le_bytes());
+    head.extend(SAMPLE_RATE.to_le_bytes());
+    head.extend(0i16.to_le_bytes());
+    head.push(0);
+    writer.write_packet(head, serial, PacketWriteEndInfo::EndPage, 0).map_err(|e| e.to_string())?;
+
+    // Comment header
+    let vendor = b"ilo toki";
+    let mut tags = b"OpusTags".to_vec();
+    tags.extend((vendor.len() as u32).to_le_bytes());
+    tags.extend(vendor);
+    tags.extend(0u32.to_le_bytes());
+    writer.write_packet(tags, serial, PacketWriteEndInfo::EndPage, 0).map_err(|e| e.to_string())?;
+
+    // Audio
+    let mut buffer = [0; 4000];
+    let count = pcm.len() / FRAME_SIZE;
+    for (i, frame) in pcm.chunks(FRAME_SIZE).enumerate() {
+        let length = encoder.encode(frame, &mut buffer).map_err(|e| e.to_string())?;
+        let end = if i + 1 == count {
+            PacketWriteEndInfo::EndStream
+        } else {
+            PacketWriteEndInfo::NormalPacket
+        };
+        let granule = PRE_SKIP as u64 + ((i + 1) * FRAME_SIZE) as u64;
+        writer.write_packet(buffer[..length].to_vec(), serial, end, granule).map_err(|e| e.to_string())?;
+    }
+
+    drop(writer);
+    Ok(result)
+}
@@ -0,0 +1,90 @@
+use std::ops::Range;
+
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// A line of wrapped text.
+pub struct Line {
+    /// The bytes of the text on the line.
+    pub range: Range<usize>,
+
+    /// Whether the line ends partway through a word too long to fit on a line of its own, so a hyphen goes after it.
+    pub hyphen: bool,
+}
+
+/// Wraps text into lines at most the given number of columns wide, breaking between words where it can.
+/// The spaces a line breaks at stay at the end of it, words too long for a line are split with a hyphen, and newlines
+/// always start a new line.
+pub fn wrap(text: &str, width: usize) -> Vec<Line> {
+    let width = width.max(1);
+    let mut lines = vec![];
+    let mut start = 0;
+    for paragraph in text.split('\n') {
+        wrap_paragraph(text, start..start + paragraph.len(), width, &mut lines);
+        start += paragraph.len() + 1;
+    }
+    lines
+}
+
+/// Wraps a part of the text without newlines in it, adding its lines to the list.
+fn wrap_paragraph(text: &str, paragraph: Range<usize>, width: usize, lines: &mut Vec<Line>) {
+    let mut start = paragraph.start;
+    let mut columns = 0;
+    for (i, word) in words(&text[paragraph.clone()]) {
+        let i = paragraph.start + i;
+        let word_width = word.width();
+
+        // Spaces hang off the end of the line instead of starting the next one
+        if word.starts_with(char::is_whitespace) || columns + word_width <= width {
+            columns += word_width;
+            continue;
+        }
+
+        // Start a new line with the word
+        if i != start {
+            lines.push(Line { range: start..i, hyphen: false });
+            start = i;
+            columns = 0;
+        }
+        if word_width <= width {
+            columns = word_width;
+            continue;
+        }
+
+        // Split words that don't fit on a line, leaving room for the hyphen if there is any
+        let hyphen = width > 1;
+        let limit = if hyphen { width - 1 } else { width };
+        for (j, grapheme) in word.grapheme_indices(true) {
+            let j = i + j;
+            let grapheme_width = grapheme.width();
+            if columns + grapheme_width > limit && j != start {
+                lines.push(Line { range: start..j, hyphen });
+                start = j;
+                columns = 0;
+            }
+            columns += grapheme_width;
+        }
+    }
+
+    lines.push(Line { range: start..paragraph.end, hyphen: false });
+}
+
+/// Splits text into runs of whitespace and runs of everything else, with the byte position of each.
+fn words(text: &str) -> Vec<(usize, &str)> {
+    let mut words = vec![];
+    let mut start = 0;
+    let mut space = None;
+    for (i, c) in text.char_indices() {
+        let is_space = c.is_whitespace();
+        if space == Some(!is_space) {
+            words.push((start, &text[start..i]));
+            start = i;
+        }
+        space = Some(is_space);
+    }
+
+    if start < text.len() {
+        words.push((start, &text[start..]));
+    }
+    words
+}